@@ -2,6 +2,7 @@
 
 extern crate test;
 use test::Bencher;
+use xml::reader::XmlEvent;
 use xml::{EventReader, EventWriter};
 
 #[bench]
@@ -28,6 +29,64 @@ fn read_lots_attrs(bencher: &mut Bencher) {
     });
 }
 
+#[bench]
+fn read_svg_paths(bencher: &mut Bencher) {
+    let mut xml = String::from(r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 100 100">"#);
+    for i in 0..1000 {
+        xml += &format!(r#"<path d="M{i} 0L0 {i}Z" fill="red" opacity="0.{i}"/><circle cx="{i}" cy="5" r="2" stroke="red"/>"#);
+    }
+    xml += "</svg>";
+    bencher.iter(move || {
+        let parser = EventReader::new(xml.as_bytes());
+        for e in parser {
+            e.unwrap();
+        }
+    });
+}
+
+#[bench]
+fn read_ooxml_cells(bencher: &mut Bencher) {
+    let mut xml = String::from(r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheetData>"#);
+    for row in 1..200 {
+        xml += &format!(r#"<row r="{row}" spans="1:5">"#);
+        for col in ['A', 'B', 'C', 'D', 'E'] {
+            xml += &format!(r#"<c r="{col}{row}" s="1" t="n"><v>{row}</v></c>"#);
+        }
+        xml += "</row>";
+    }
+    xml += "</sheetData></worksheet>";
+    bencher.iter(move || {
+        let parser = EventReader::new(xml.as_bytes());
+        for e in parser {
+            e.unwrap();
+        }
+    });
+}
+
+#[bench]
+fn read_small_messages(bencher: &mut Bencher) {
+    let messages: Vec<_> = (0..100).map(|i| format!(r#"<msg id="{i}"><to>a</to><body>hello {i}</body></msg>"#)).collect();
+    bencher.iter(move || {
+        for msg in &messages {
+            for e in EventReader::new(msg.as_bytes()) {
+                e.unwrap();
+            }
+        }
+    });
+}
+
+#[bench]
+fn read_small_messages_reused(bencher: &mut Bencher) {
+    let messages: Vec<_> = (0..100).map(|i| format!(r#"<msg id="{i}"><to>a</to><body>hello {i}</body></msg>"#)).collect();
+    let mut reader = EventReader::new(&b""[..]);
+    bencher.iter(|| {
+        for msg in &messages {
+            reader.reuse(msg.as_bytes());
+            while reader.next().unwrap() != XmlEvent::EndDocument {}
+        }
+    });
+}
+
 #[bench]
 fn write(bencher: &mut Bencher) {
     let xml = std::fs::read("tests/documents/sample_1.xml").unwrap();
@@ -33,7 +33,7 @@ fn main() {
                         } else {
                             let attrs: Vec<_> = attributes
                                 .iter()
-                                .map(|a| format!("{}={:?}", &a.name, a.value))
+                                .map(|a| format!("{}={:?}", a.name, a.value))
                                 .collect();
                             println!("StartElement({name} [{}])", attrs.join(", "));
                         }
@@ -51,6 +51,10 @@ fn main() {
                     XmlEvent::Whitespace(data) => {
                         println!(r#"Whitespace("{}")"#, data.escape_debug());
                     },
+                    XmlEvent::Doctype { syntax, .. } => {
+                        println!(r#"Doctype("{}")"#, syntax.escape_debug());
+                    },
+                    other => println!("{other:?}"),
                 }
             },
             Err(e) => {
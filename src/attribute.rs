@@ -1,10 +1,12 @@
 //! Contains XML attributes manipulation types and functions.
 //!
 
+use std::borrow::Cow;
 use std::fmt;
 
-use crate::escape::{AttributeEscapes, Escaped};
+use crate::escape::{unescape_with_limits, AttributeEscapes, Escaped, ExpansionLimits, UnescapeError};
 use crate::name::{Name, OwnedName};
+use crate::reader::EntityTable;
 
 /// A borrowed version of an XML attribute.
 ///
@@ -55,6 +57,12 @@ pub struct OwnedAttribute {
     pub value: String,
 }
 
+/// Attributes of a start tag, as stored in [`XmlEvent::StartElement`](crate::reader::XmlEvent::StartElement).
+///
+/// Tags with up to four attributes keep them inline, without a separate allocation.
+/// It derefs to a slice; use `into_vec()` to get a `Vec`, or `.into()` to make one from a `Vec`.
+pub type Attributes = smallvec::SmallVec<[OwnedAttribute; 4]>;
+
 impl OwnedAttribute {
     /// Returns a borrowed `Attribute` out of this owned one.
     #[must_use]
@@ -82,6 +90,58 @@ impl fmt::Display for OwnedAttribute {
     }
 }
 
+/// How an attribute was written in the source document.
+///
+/// Recorded only when [`ParserConfig2::preserve_source_details`](crate::reader::ParserConfig2::preserve_source_details)
+/// is enabled, see [`EventReader::attribute_details`](crate::EventReader::attribute_details).
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct AttributeDetails {
+    /// Position of the attribute in its tag, counting namespace declarations too.
+    pub index: usize,
+
+    /// The quote character, `"` or `'`.
+    pub quote: char,
+
+    /// Attribute value as written, before references were expanded.
+    pub raw_value: String,
+
+    /// `max_attribute_length` of the parser
+    pub(crate) max_length: usize,
+}
+
+impl AttributeDetails {
+    /// Expands references in [`raw_value`](Self::raw_value), like the parser does, see [`unescape`](crate::escape::unescape).
+    /// Doesn't allocate if there aren't any.
+    ///
+    /// References in values of entities are kept as they are, and the value is limited
+    /// to the parser's `max_attribute_length`, so it's the same as the attribute's `value`.
+    ///
+    /// This can be done after the reader has moved on, e.g. by a tool that copies raw values
+    /// through, and decodes only those it inspects. Entities declared in the document's DTD
+    /// are in [`EventReader::declared_entities`](crate::EventReader::declared_entities).
+    ///
+    /// ```rust
+    /// use xml::reader::{EntityTable, ParserConfig2, XmlEvent};
+    ///
+    /// let mut reader = ParserConfig2::new().preserve_source_details(true)
+    ///     .create_reader(r#"<!DOCTYPE a [<!ENTITY e "&#xE9;">]><a title="caf&e; &amp; bar"/>"#.as_bytes());
+    /// while !matches!(reader.next().unwrap(), XmlEvent::StartElement { .. }) {}
+    ///
+    /// let details = reader.attribute_details().to_vec();
+    /// let mut entities = EntityTable::new();
+    /// for (name, value) in reader.declared_entities() {
+    ///     entities.insert(name, value);
+    /// }
+    /// reader.next().unwrap();
+    ///
+    /// assert_eq!(details[0].raw_value, "caf&e; &amp; bar");
+    /// assert_eq!(details[0].decode(&entities).unwrap(), "café & bar");
+    /// ```
+    pub fn decode(&self, entities: &EntityTable) -> Result<Cow<'_, str>, UnescapeError> {
+        unescape_with_limits(&self.raw_value, entities, ExpansionLimits { length: self.max_length, depth: 1, nested: false })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::Attribute;
@@ -0,0 +1,161 @@
+//! Base64 and hex encoding of binary data in text content
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum BinaryEncoding {
+    Base64,
+    Hex,
+}
+
+impl BinaryEncoding {
+    /// Number of input bytes that can be encoded without knowing what follows them
+    pub fn whole_len(self, len: usize) -> usize {
+        match self {
+            Self::Base64 => len - len % 3,
+            Self::Hex => len,
+        }
+    }
+
+    /// Appends encoded bytes. Base64 is padded if the length isn't a multiple of 3, so it must be the end.
+    pub fn encode(self, bytes: &[u8], out: &mut String) {
+        match self {
+            Self::Base64 => {
+                out.reserve((bytes.len() + 2) / 3 * 4);
+                for group in bytes.chunks(3) {
+                    let b = [group[0], group.get(1).copied().unwrap_or(0), group.get(2).copied().unwrap_or(0)];
+                    let n = u32::from(b[0]) << 16 | u32::from(b[1]) << 8 | u32::from(b[2]);
+                    for i in 0..4 {
+                        out.push(if i <= group.len() { char::from(BASE64[(n >> (18 - 6 * i) & 63) as usize]) } else { '=' });
+                    }
+                }
+            },
+            Self::Hex => {
+                out.reserve(bytes.len() * 2);
+                for &b in bytes {
+                    out.push(char::from(HEX[usize::from(b >> 4)]));
+                    out.push(char::from(HEX[usize::from(b & 15)]));
+                }
+            },
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Base64 => "base64",
+            Self::Hex => "hex",
+        }
+    }
+}
+
+/// Decodes text that may be split at any point. Whitespace is ignored.
+pub(crate) struct BinaryDecoder {
+    encoding: BinaryEncoding,
+    pending: [u8; 4],
+    pending_len: usize,
+    /// Base64 padding has been seen, so only more padding can follow
+    padded: bool,
+}
+
+impl BinaryDecoder {
+    pub fn new(encoding: BinaryEncoding) -> Self {
+        Self { encoding, pending: [0; 4], pending_len: 0, padded: false }
+    }
+
+    /// Appends decoded bytes, or fails on invalid characters
+    pub fn decode(&mut self, text: &str, out: &mut Vec<u8>) -> Result<(), ()> {
+        for b in text.bytes().filter(|b| !b.is_ascii_whitespace()) {
+            let value = match self.encoding {
+                BinaryEncoding::Base64 => match b {
+                    b'=' if self.pending_len >= 2 || self.padded => {
+                        self.padded = true;
+                        self.pending_len += 1;
+                        if self.pending_len == 4 {
+                            self.pending_len = 0;
+                        }
+                        continue;
+                    },
+                    _ if self.padded => return Err(()),
+                    b'A'..=b'Z' => b - b'A',
+                    b'a'..=b'z' => b - b'a' + 26,
+                    b'0'..=b'9' => b - b'0' + 52,
+                    b'+' => 62,
+                    b'/' => 63,
+                    _ => return Err(()),
+                },
+                BinaryEncoding::Hex => match b {
+                    b'0'..=b'9' => b - b'0',
+                    b'a'..=b'f' => b - b'a' + 10,
+                    b'A'..=b'F' => b - b'A' + 10,
+                    _ => return Err(()),
+                },
+            };
+            self.pending[self.pending_len] = value;
+            self.pending_len += 1;
+            match self.encoding {
+                BinaryEncoding::Base64 => {
+                    let p = self.pending;
+                    match self.pending_len {
+                        // bytes are complete after the 2nd, 3rd and 4th character
+                        2 => out.push(p[0] << 2 | p[1] >> 4),
+                        3 => out.push(p[1] << 4 | p[2] >> 2),
+                        4 => {
+                            out.push(p[2] << 6 | p[3]);
+                            self.pending_len = 0;
+                        },
+                        _ => {},
+                    }
+                },
+                BinaryEncoding::Hex => if self.pending_len == 2 {
+                    out.push(self.pending[0] << 4 | self.pending[1]);
+                    self.pending_len = 0;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the text didn't end in the middle of a group of characters
+    pub fn finish(&self) -> Result<(), ()> {
+        if self.pending_len == 0 { Ok(()) } else { Err(()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BinaryDecoder, BinaryEncoding};
+
+    #[test]
+    fn round_trip() {
+        for encoding in [BinaryEncoding::Base64, BinaryEncoding::Hex] {
+            for len in 0..10 {
+                let bytes: Vec<u8> = (0..len).map(|i| (i * 97 + 200) as u8).collect();
+                let mut text = String::new();
+                encoding.encode(&bytes, &mut text);
+                let mut decoder = BinaryDecoder::new(encoding);
+                let mut decoded = Vec::new();
+                // split at every character
+                for c in text.chars() {
+                    decoder.decode(c.encode_utf8(&mut [0; 4]), &mut decoded).unwrap();
+                }
+                decoder.finish().unwrap();
+                assert_eq!(bytes, decoded, "{text}");
+            }
+        }
+
+        let mut text = String::new();
+        BinaryEncoding::Base64.encode(b"hello", &mut text);
+        assert_eq!(text, "aGVsbG8=");
+        text.clear();
+        BinaryEncoding::Hex.encode(b"\x01\xfe", &mut text);
+        assert_eq!(text, "01FE");
+
+        let mut out = Vec::new();
+        assert!(BinaryDecoder::new(BinaryEncoding::Base64).decode("aG=V", &mut out).is_err());
+        assert!(BinaryDecoder::new(BinaryEncoding::Hex).decode("0g", &mut out).is_err());
+        let mut decoder = BinaryDecoder::new(BinaryEncoding::Base64);
+        decoder.decode("aGV", &mut out).unwrap();
+        assert!(decoder.finish().is_err());
+    }
+}
@@ -0,0 +1,290 @@
+//! Contains a builder for small documents, as an alternative to writing events one by one.
+//!
+//! ```rust
+//! use xml::build::Element;
+//! use xml::EmitterConfig;
+//!
+//! let doc = Element::new("a")
+//!     .attr("k", "v")
+//!     .child(Element::new("b").text("x"))
+//!     .comment(" done ");
+//!
+//! let mut out = Vec::new();
+//! let mut writer = EmitterConfig::new().write_document_declaration(false).create_writer(&mut out);
+//! doc.write_to(&mut writer).unwrap();
+//! assert_eq!(String::from_utf8(out).unwrap(), r#"<a k="v"><b>x</b><!-- done --></a>"#);
+//! ```
+
+use std::borrow::Cow;
+use std::io::{Read, Write};
+
+use crate::attribute::{Attribute, Attributes, OwnedAttribute};
+use crate::name::{Name, OwnedName, SharedStr};
+use crate::namespace::{Namespace, NamespaceStack, NS_EMPTY_URI, NS_NO_PREFIX, NS_XMLNS_PREFIX, NS_XML_PREFIX};
+use crate::reader::{self, EventReader};
+use crate::writer::{self, EventWriter};
+
+/// A node of an [`Element`]'s content
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Node {
+    /// A child element
+    Element(Element),
+    /// Text, escaped when written
+    Text(String),
+    /// A CDATA section
+    CData(String),
+    /// A comment
+    Comment(String),
+    /// A processing instruction with a target name and optional data
+    ProcessingInstruction(String, Option<String>),
+}
+
+impl From<Element> for Node {
+    #[inline]
+    fn from(e: Element) -> Self {
+        Node::Element(e)
+    }
+}
+
+impl From<&str> for Node {
+    #[inline]
+    fn from(s: &str) -> Self {
+        Node::Text(s.into())
+    }
+}
+
+impl From<String> for Node {
+    #[inline]
+    fn from(s: String) -> Self {
+        Node::Text(s)
+    }
+}
+
+/// An element with its attributes and content, built with chained method calls.
+///
+/// Names can have a prefix, like `"svg:path"`, which must be declared with [`Element::ns`]
+/// on the element or one of its parents.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Element {
+    /// Name of the element. Its namespace is ignored when writing.
+    pub name: OwnedName,
+    /// Attributes in order
+    pub attributes: Vec<OwnedAttribute>,
+    /// Namespaces declared on this element
+    pub namespace: Namespace,
+    /// Content in order
+    pub children: Vec<Node>,
+}
+
+impl Element {
+    /// Creates an empty element, e.g. `Element::new("p:name")`
+    pub fn new<'a>(name: impl Into<Name<'a>>) -> Self {
+        Element {
+            name: name.into().to_owned(),
+            attributes: Vec::new(),
+            namespace: Namespace::empty(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Adds an attribute. The value is escaped when written.
+    #[must_use]
+    pub fn attr<'a>(mut self, name: impl Into<Name<'a>>, value: impl Into<String>) -> Self {
+        self.attributes.push(OwnedAttribute::new(name.into().to_owned(), value));
+        self
+    }
+
+    /// Declares a namespace prefix on this element
+    #[must_use]
+    pub fn ns(mut self, prefix: impl Into<String>, uri: impl Into<String>) -> Self {
+        self.namespace.put(prefix, uri);
+        self
+    }
+
+    /// Declares the default namespace on this element
+    #[must_use]
+    pub fn default_ns(self, uri: impl Into<String>) -> Self {
+        self.ns(NS_NO_PREFIX, uri)
+    }
+
+    /// Appends a child element or other node
+    #[must_use]
+    pub fn child(mut self, child: impl Into<Node>) -> Self {
+        self.children.push(child.into());
+        self
+    }
+
+    /// Appends all the nodes
+    #[must_use]
+    pub fn children<N: Into<Node>>(mut self, children: impl IntoIterator<Item = N>) -> Self {
+        self.children.extend(children.into_iter().map(Into::into));
+        self
+    }
+
+    /// Appends text
+    #[must_use]
+    pub fn text(self, text: impl Into<String>) -> Self {
+        self.child(Node::Text(text.into()))
+    }
+
+    /// Appends a CDATA section
+    #[must_use]
+    pub fn cdata(self, data: impl Into<String>) -> Self {
+        self.child(Node::CData(data.into()))
+    }
+
+    /// Appends a comment
+    #[must_use]
+    pub fn comment(self, comment: impl Into<String>) -> Self {
+        self.child(Node::Comment(comment.into()))
+    }
+
+    /// Writer events for this element and its content, from its `StartElement` to `EndElement`
+    #[must_use]
+    pub fn events(&self) -> Vec<writer::XmlEvent<'_>> {
+        let mut events = Vec::new();
+        self.push_events(&mut events);
+        events
+    }
+
+    fn push_events<'a>(&'a self, events: &mut Vec<writer::XmlEvent<'a>>) {
+        events.push(writer::XmlEvent::StartElement {
+            name: self.name.borrow(),
+            attributes: self.attributes.iter().map(|a| Attribute::new(a.name.borrow(), &a.value)).collect(),
+            namespace: Cow::Borrowed(&self.namespace),
+        });
+        for child in &self.children {
+            match child {
+                Node::Element(e) => e.push_events(events),
+                Node::Text(text) => events.push(writer::XmlEvent::Characters(text)),
+                Node::CData(data) => events.push(writer::XmlEvent::CData(data)),
+                Node::Comment(comment) => events.push(writer::XmlEvent::Comment(comment)),
+                Node::ProcessingInstruction(name, data) => {
+                    events.push(writer::XmlEvent::processing_instruction(name, data.as_deref()));
+                },
+            }
+        }
+        events.push(writer::XmlEvent::EndElement { name: Some(self.name.borrow()) });
+    }
+
+    /// Writes this element and its content
+    pub fn write_to<W: Write>(&self, writer: &mut EventWriter<W>) -> writer::Result<()> {
+        self.events().into_iter().try_for_each(|e| writer.write(e))
+    }
+
+    /// Reads the whole document, and returns its root element.
+    ///
+    /// Namespaces are declared on the elements that declared them in the document.
+    /// Comments and processing instructions outside of the root element, and the doctype, are not kept.
+    pub fn from_reader<R: Read>(mut reader: EventReader<R>) -> reader::Result<Self> {
+        let mut root = None;
+        loop {
+            match reader.next()? {
+                reader::XmlEvent::StartElement { name, attributes, namespace } => {
+                    root = Some(Self::read_subtree(&mut reader, name, attributes, namespace)?);
+                },
+                reader::XmlEvent::EndDocument => break,
+                _ => {},
+            }
+        }
+        // the parser doesn't end a document without a root element
+        root.ok_or_else(|| (&reader, "no root element").into())
+    }
+
+    /// Reads the content of an element whose `StartElement` has just been read, up to its `EndElement`.
+    ///
+    /// Namespaces in scope that aren't predefined are declared on the returned element, so it can be written on its own.
+    pub(crate) fn read_subtree<R: Read>(reader: &mut EventReader<R>, name: OwnedName, attributes: Attributes, namespace: Namespace) -> reader::Result<Self> {
+        let declared = |namespace: &Namespace, parent: Option<&Namespace>| {
+            let mut declared = Namespace::empty();
+            for (prefix, uri) in &namespace.0 {
+                let inherited = match parent {
+                    Some(parent) => parent.get(prefix) == Some(uri),
+                    None => prefix == NS_XML_PREFIX || prefix == NS_XMLNS_PREFIX || (prefix == NS_NO_PREFIX && uri == NS_EMPTY_URI),
+                };
+                if !inherited {
+                    declared.put(prefix.as_str(), uri.as_str());
+                }
+            }
+            declared
+        };
+        let root = Self { name, attributes: attributes.into_vec(), namespace: declared(&namespace, None), children: Vec::new() };
+        // unfinished elements with all the namespaces in their scope
+        let mut stack = vec![(root, namespace)];
+        loop {
+            let event = reader.next()?;
+            let (element, scope) = match stack.last_mut() {
+                Some(open) => open,
+                None => unreachable!(),
+            };
+            match event {
+                reader::XmlEvent::StartElement { name, attributes, namespace } => {
+                    let element = Self { name, attributes: attributes.into_vec(), namespace: declared(&namespace, Some(scope)), children: Vec::new() };
+                    stack.push((element, namespace));
+                },
+                reader::XmlEvent::EndElement { .. } => {
+                    let element = match stack.pop() {
+                        Some((element, _)) => element,
+                        None => unreachable!(),
+                    };
+                    match stack.last_mut() {
+                        Some((parent, _)) => parent.children.push(Node::Element(element)),
+                        None => return Ok(element),
+                    }
+                },
+                reader::XmlEvent::Characters(text) | reader::XmlEvent::Whitespace(text) => match element.children.last_mut() {
+                    Some(Node::Text(last)) => last.push_str(&text),
+                    _ => element.children.push(Node::Text(text)),
+                },
+                reader::XmlEvent::CData(data) => element.children.push(Node::CData(data)),
+                reader::XmlEvent::Comment(comment) => element.children.push(Node::Comment(comment)),
+                reader::XmlEvent::ProcessingInstruction { name, data } => element.children.push(Node::ProcessingInstruction(name, data)),
+                reader::XmlEvent::EndDocument => return Err((&*reader, "the document ended inside an element").into()),
+                _ => {},
+            }
+        }
+    }
+
+    /// Reader events for this element, as if it was parsed from a document.
+    ///
+    /// Prefixes are resolved to namespace URIs, like the parser does, so the events can be given
+    /// to anything that consumes parsed documents, such as a tree builder.
+    /// Prefixes that aren't declared keep `None` as their namespace.
+    #[must_use]
+    pub fn to_reader_events(&self) -> Vec<reader::XmlEvent> {
+        let mut events = Vec::new();
+        self.push_reader_events(&NamespaceStack::default().squash(), &mut events);
+        events
+    }
+
+    fn push_reader_events(&self, parent_namespace: &Namespace, events: &mut Vec<reader::XmlEvent>) {
+        let mut namespace = parent_namespace.clone();
+        namespace.0.extend(self.namespace.0.iter().map(|(prefix, uri)| (prefix.clone(), uri.clone())));
+        let resolve = |name: &OwnedName, is_attribute: bool| {
+            let mut name = OwnedName { namespace: None, ..name.clone() };
+            // unprefixed attributes are not in the default namespace
+            if name.prefix.is_some() || !is_attribute {
+                name.namespace = namespace.get(name.borrow().prefix_repr()).filter(|uri| !uri.is_empty()).map(SharedStr::from);
+            }
+            name
+        };
+        let name = resolve(&self.name, false);
+        events.push(reader::XmlEvent::StartElement {
+            name: name.clone(),
+            attributes: self.attributes.iter().map(|a| OwnedAttribute { name: resolve(&a.name, true), value: a.value.clone() }).collect(),
+            namespace: namespace.clone(),
+        });
+        for child in &self.children {
+            match child {
+                Node::Element(e) => e.push_reader_events(&namespace, events),
+                Node::Text(text) => events.push(reader::XmlEvent::Characters(text.clone())),
+                Node::CData(data) => events.push(reader::XmlEvent::CData(data.clone())),
+                Node::Comment(comment) => events.push(reader::XmlEvent::Comment(comment.clone())),
+                Node::ProcessingInstruction(name, data) => {
+                    events.push(reader::XmlEvent::ProcessingInstruction { name: name.clone(), data: data.clone() });
+                },
+            }
+        }
+        events.push(reader::XmlEvent::EndElement { name });
+    }
+}
@@ -0,0 +1,493 @@
+//! Generation of Rust types from a schema, for reading documents into structs instead of handling events.
+//!
+//! [`Codegen`] writes a struct for every element of an [`InferredSchema`], which can be read
+//! from an XML Schema with [`InferredSchema::from_xsd`] or inferred from sample documents.
+//! Elements with only text and no attributes become plain values: `bool`, `i64`, `f64` or `String`.
+//! The structs implement [`FromElement`], and optionally serde's `Serialize`/`Deserialize`
+//! with field names in the convention of serde XML formats, where attributes start with `@`
+//! and text is `$text`.
+//!
+//! The code is meant to be generated in a build script:
+//!
+//! ```rust,no_run
+//! // build.rs
+//! use std::{env, fs, path::Path};
+//! use xml::codegen::Codegen;
+//! use xml::infer::InferredSchema;
+//!
+//! let xsd = fs::File::open("feed.xsd").unwrap();
+//! let schema = InferredSchema::from_xsd(xml::EventReader::new(xsd)).unwrap();
+//! let code = Codegen::new().serde(true).generate(&schema);
+//! fs::write(Path::new(&env::var("OUT_DIR").unwrap()).join("feed.rs"), code).unwrap();
+//! println!("cargo:rerun-if-changed=feed.xsd");
+//! ```
+//!
+//! and included in the crate with `include!(concat!(env!("OUT_DIR"), "/feed.rs"));`.
+//! Documents are then read with [`from_reader`]:
+//!
+//! ```rust,ignore
+//! let feed: Feed = xml::codegen::from_reader(xml::EventReader::new(file))?;
+//! for entry in &feed.entry {
+//!     println!("{}: {}", entry.id, entry.title);
+//! }
+//! ```
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fmt::Write;
+use std::io::Read;
+use std::str::FromStr;
+
+use crate::infer::{ElementDecl, InferredSchema, SimpleType};
+use crate::reader::{self, EventReader};
+use crate::value::{self, Element, Value};
+
+/// Writes Rust source code for the elements of a schema
+#[derive(Clone, Debug, Default)]
+pub struct Codegen {
+    serde: bool,
+}
+
+/// Names that would shadow the types used by the generated code
+const RESERVED_TYPE_NAMES: [&str; 10] = ["Self", "String", "Vec", "Option", "Result", "Box", "Some", "None", "Ok", "Err"];
+
+const KEYWORDS: [&str; 48] = [
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false", "fn", "for", "if",
+    "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "static", "struct", "trait",
+    "true", "type", "unsafe", "use", "where", "while", "abstract", "become", "box", "do", "final", "macro", "override",
+    "priv", "try", "typeof", "unsized", "virtual", "yield", "gen",
+];
+
+/// A field of a generated struct
+struct Field {
+    name: String,
+    ty: String,
+    /// Name of the attribute or element, or `$text`
+    xml_name: String,
+    /// Value is `Option` or `Vec` that may be missing
+    skip_if: Option<&'static str>,
+    init: String,
+}
+
+impl Codegen {
+    /// Generates plain structs that implement [`FromElement`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also derive `serde::Serialize` and `serde::Deserialize`. The crate using the code needs `serde` with the `derive` feature.
+    #[must_use]
+    pub fn serde(mut self, serde: bool) -> Self {
+        self.serde = serde;
+        self
+    }
+
+    /// Source code of the types, to be written to a file and `include!`d.
+    ///
+    /// Struct names are the element names in `CamelCase`, and field names are attribute or child names in `snake_case`.
+    /// Children that can occur more than once are `Vec`s, and optional attributes and children are `Option`s.
+    pub fn generate(&self, schema: &InferredSchema) -> String {
+        let elements = &schema.elements;
+        let mut used = HashSet::new();
+        let type_names: Vec<String> = elements.iter().map(|e| unique(type_name(&e.name), &mut used)).collect();
+        let ty_of = |name: &str| match elements.iter().position(|e| e.name == name) {
+            Some(i) => value_type(&elements[i]).map_or_else(|| type_names[i].clone(), |ty| ty.into()),
+            None => "String".into(),
+        };
+
+        let mut out = String::from("// Generated by xml::codegen. Do not edit.\n");
+        for (i, decl) in elements.iter().enumerate() {
+            if value_type(decl).is_some() {
+                continue;
+            }
+            let mut used = HashSet::new();
+            let text_ty = decl.text.map(|ty| if decl.children.is_empty() { rust_type(ty) } else { "String" });
+            if text_ty.is_some() {
+                used.insert("text".to_string());
+            }
+            let mut fields = Vec::new();
+            for a in &decl.attributes {
+                let ty = rust_type(a.ty);
+                let (ty, init, skip_if) = if a.required {
+                    (ty.to_string(), format!("xml::codegen::attribute(element, {:?})?", a.name), None)
+                } else {
+                    (format!("Option<{ty}>"), format!("xml::codegen::optional_attribute(element, {:?})?", a.name), Some("Option::is_none"))
+                };
+                fields.push(Field { name: unique(field_name(&a.name), &mut used), ty, xml_name: format!("@{}", a.name), skip_if, init });
+            }
+            for c in &decl.children {
+                let mut ty = ty_of(&c.name);
+                let (ty, init, skip_if) = if c.max_occurs > 1 {
+                    (format!("Vec<{ty}>"), format!("xml::codegen::children(element, {:?})?", c.name), Some("Vec::is_empty"))
+                } else {
+                    if is_recursive(elements, &c.name, &decl.name) {
+                        ty = format!("Box<{ty}>");
+                    }
+                    if c.min_occurs == 0 {
+                        (format!("Option<{ty}>"), format!("xml::codegen::optional_child(element, {:?})?", c.name), Some("Option::is_none"))
+                    } else {
+                        (ty, format!("xml::codegen::child(element, {:?})?", c.name), None)
+                    }
+                };
+                fields.push(Field { name: unique(field_name(&c.name), &mut used), ty, xml_name: c.name.clone(), skip_if, init });
+            }
+            if let Some(ty) = text_ty {
+                fields.push(Field { name: "text".into(), ty: ty.into(), xml_name: "$text".into(), skip_if: None, init: "xml::codegen::text(element)?".into() });
+            }
+            self.write_struct(&mut out, decl, &type_names[i], &fields);
+        }
+        out
+    }
+
+    fn write_struct(&self, out: &mut String, decl: &ElementDecl, type_name: &str, fields: &[Field]) {
+        let _ = writeln!(out, "\n/// The `{}` element", decl.name);
+        out.push_str("#[derive(Clone, Debug, Default, PartialEq)]\n");
+        if self.serde {
+            out.push_str("#[derive(serde::Serialize, serde::Deserialize)]\n");
+        }
+        let _ = writeln!(out, "pub struct {type_name} {{");
+        for field in fields {
+            if self.serde {
+                let mut args = Vec::new();
+                if field.name.trim_start_matches("r#") != field.xml_name {
+                    args.push(format!("rename = {:?}", field.xml_name));
+                }
+                if let Some(skip_if) = field.skip_if {
+                    args.push(format!("default, skip_serializing_if = {skip_if:?}"));
+                }
+                if !args.is_empty() {
+                    let _ = writeln!(out, "    #[serde({})]", args.join(", "));
+                }
+            }
+            let _ = writeln!(out, "    pub {}: {},", field.name, field.ty);
+        }
+        out.push_str("}\n");
+
+        let element = if fields.is_empty() { "_element" } else { "element" };
+        let _ = writeln!(out, "\nimpl xml::codegen::FromElement for {type_name} {{");
+        let _ = writeln!(out, "    fn from_element({element}: &xml::value::Element) -> Result<Self, xml::codegen::BindError> {{");
+        out.push_str("        Ok(Self {\n");
+        for field in fields {
+            let _ = writeln!(out, "            {}: {},", field.name, field.init);
+        }
+        out.push_str("        })\n    }\n}\n");
+    }
+}
+
+/// Text-only elements without attributes are plain values
+fn value_type(decl: &ElementDecl) -> Option<&'static str> {
+    if decl.children.is_empty() && decl.attributes.is_empty() {
+        decl.text.map(rust_type)
+    } else {
+        None
+    }
+}
+
+fn rust_type(ty: SimpleType) -> &'static str {
+    match ty {
+        SimpleType::Boolean => "bool",
+        SimpleType::Integer => "i64",
+        SimpleType::Decimal => "f64",
+        _ => "String",
+    }
+}
+
+/// The element can contain the parent without a `Vec` in between, so the field needs a `Box`
+fn is_recursive(elements: &[ElementDecl], child: &str, parent: &str) -> bool {
+    let mut visited = HashSet::new();
+    let mut stack = vec![child];
+    while let Some(name) = stack.pop() {
+        if name == parent {
+            return true;
+        }
+        if !visited.insert(name) {
+            continue;
+        }
+        if let Some(decl) = elements.iter().find(|e| e.name == name) {
+            stack.extend(decl.children.iter().filter(|c| c.max_occurs <= 1).map(|c| c.name.as_str()));
+        }
+    }
+    false
+}
+
+fn type_name(name: &str) -> String {
+    let mut out = String::new();
+    for word in name.split(|c: char| !c.is_alphanumeric()) {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            out.extend(first.to_uppercase());
+            out.push_str(chars.as_str());
+        }
+    }
+    if out.is_empty() || out.starts_with(|c: char| c.is_numeric()) {
+        out.insert_str(0, "Element");
+    } else if RESERVED_TYPE_NAMES.contains(&out.as_str()) {
+        out.push_str("Element");
+    }
+    out
+}
+
+fn field_name(name: &str) -> String {
+    let mut out = String::new();
+    let mut after_lowercase = false;
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            if c.is_uppercase() && after_lowercase {
+                out.push('_');
+            }
+            after_lowercase = c.is_lowercase() || c.is_numeric();
+            out.extend(c.to_lowercase());
+        } else {
+            if !out.ends_with('_') {
+                out.push('_');
+            }
+            after_lowercase = false;
+        }
+    }
+    let out = out.trim_matches('_');
+    if out.is_empty() {
+        "field".into()
+    } else if out.starts_with(|c: char| c.is_numeric()) {
+        format!("_{out}")
+    } else if KEYWORDS.contains(&out) {
+        format!("r#{out}")
+    } else if ["self", "super", "crate"].contains(&out) {
+        format!("{out}_")
+    } else {
+        out.into()
+    }
+}
+
+fn unique(name: String, used: &mut HashSet<String>) -> String {
+    let mut unique = name.clone();
+    let mut n = 1;
+    while !used.insert(unique.clone()) {
+        n += 1;
+        unique = format!("{name}_{n}");
+    }
+    unique
+}
+
+/// Error from reading a document into generated types
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BindError {
+    /// The document couldn't be parsed
+    Reader(reader::Error),
+    /// A required attribute or child element is missing
+    Missing {
+        /// Local name of the element
+        element: String,
+        /// Name of the attribute or child element
+        name: String,
+    },
+    /// Text or an attribute value can't be parsed as the type of the field
+    InvalidValue {
+        /// Local name of the element
+        element: String,
+        /// Name of the attribute, or `text`
+        name: String,
+        /// The text as written
+        value: String,
+    },
+    /// The document's value is text rather than a root element
+    NoRootElement,
+}
+
+impl fmt::Display for BindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Reader(e) => e.fmt(f),
+            Self::Missing { element, name } => write!(f, "<{element}> is missing {name}"),
+            Self::InvalidValue { element, name, value } => write!(f, "invalid value of {name} in <{element}>: {value:?}"),
+            Self::NoRootElement => f.write_str("the document has no root element"),
+        }
+    }
+}
+
+impl std::error::Error for BindError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Reader(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reader::Error> for BindError {
+    fn from(e: reader::Error) -> Self {
+        Self::Reader(e)
+    }
+}
+
+/// A type that can be read from an element of a [`value`] tree. Implemented by the generated structs.
+pub trait FromElement: Sized {
+    /// Reads the element's attributes and content. The name of the element isn't checked.
+    fn from_element(element: &Element) -> Result<Self, BindError>;
+}
+
+macro_rules! from_text {
+    ($($t:ty)*) => {$(
+        impl FromElement for $t {
+            fn from_element(element: &Element) -> Result<Self, BindError> {
+                parse(element, "text", &element.text())
+            }
+        }
+    )*};
+}
+
+from_text! { String bool i64 f64 }
+
+impl<T: FromElement> FromElement for Box<T> {
+    fn from_element(element: &Element) -> Result<Self, BindError> {
+        T::from_element(element).map(Box::new)
+    }
+}
+
+/// Reads the whole document, and the root element as `T`
+pub fn from_reader<T: FromElement, R: Read>(reader: EventReader<R>) -> Result<T, BindError> {
+    match value::to_value(reader)? {
+        Value::Element(root) => T::from_element(&root),
+        Value::Text(_) => Err(BindError::NoRootElement),
+    }
+}
+
+/// Parses the value, also without the surrounding whitespace, which isn't significant in numbers and booleans
+fn parse<T: FromStr>(element: &Element, name: &str, value: &str) -> Result<T, BindError> {
+    value.parse().or_else(|_| value.trim().parse()).map_err(|_| BindError::InvalidValue {
+        element: element.name.clone(),
+        name: name.into(),
+        value: value.into(),
+    })
+}
+
+fn missing(element: &Element, name: &str) -> BindError {
+    BindError::Missing { element: element.name.clone(), name: name.into() }
+}
+
+/// Value of a required attribute
+pub fn attribute<T: FromStr>(element: &Element, name: &str) -> Result<T, BindError> {
+    optional_attribute(element, name)?.ok_or_else(|| missing(element, name))
+}
+
+/// Value of an attribute, if present
+pub fn optional_attribute<T: FromStr>(element: &Element, name: &str) -> Result<Option<T>, BindError> {
+    element.attributes.get(name).map(|value| parse(element, name, value)).transpose()
+}
+
+/// The first child element with the local name, which is required
+pub fn child<T: FromElement>(element: &Element, name: &str) -> Result<T, BindError> {
+    optional_child(element, name)?.ok_or_else(|| missing(element, name))
+}
+
+/// The first child element with the local name, if any
+pub fn optional_child<T: FromElement>(element: &Element, name: &str) -> Result<Option<T>, BindError> {
+    element.elements(name).next().map(T::from_element).transpose()
+}
+
+/// All child elements with the local name
+pub fn children<T: FromElement>(element: &Element, name: &str) -> Result<Vec<T>, BindError> {
+    element.elements(name).map(T::from_element).collect()
+}
+
+/// Text directly in the element, without the text of child elements
+pub fn text<T: FromStr>(element: &Element) -> Result<T, BindError> {
+    let text: String = element.children.iter().filter_map(Value::as_text).collect();
+    parse(element, "text", &text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{field_name, type_name, Codegen};
+    use crate::infer::{schema_from_samples, InferredSchema};
+    use crate::reader::EventReader;
+
+    #[test]
+    fn names() {
+        assert_eq!(type_name("feed-entry"), "FeedEntry");
+        assert_eq!(type_name("dateTime"), "DateTime");
+        assert_eq!(type_name("string"), "StringElement");
+        assert_eq!(type_name("3d"), "Element3d");
+        assert_eq!(field_name("dateTime"), "date_time");
+        assert_eq!(field_name("HTTP-Header"), "http_header");
+        assert_eq!(field_name("type"), "r#type");
+        assert_eq!(field_name("self"), "self_");
+        assert_eq!(field_name("_"), "field");
+    }
+
+    #[test]
+    fn generate() {
+        let schema = schema_from_samples([
+            EventReader::from_str(r#"<feed><entry id="1" type="a"><title>A</title><entry id="2"><title>B</title></entry></entry></feed>"#),
+            EventReader::from_str(r#"<feed><entry id="3"><title>C</title></entry><entry id="4"><title>D</title></entry></feed>"#),
+        ]).unwrap();
+        let code = Codegen::new().serde(true).generate(&schema);
+        assert_eq!(code, r#"// Generated by xml::codegen. Do not edit.
+
+/// The `feed` element
+#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Feed {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entry: Vec<Entry>,
+}
+
+impl xml::codegen::FromElement for Feed {
+    fn from_element(element: &xml::value::Element) -> Result<Self, xml::codegen::BindError> {
+        Ok(Self {
+            entry: xml::codegen::children(element, "entry")?,
+        })
+    }
+}
+
+/// The `entry` element
+#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+    #[serde(rename = "@id")]
+    pub id: i64,
+    #[serde(rename = "@type", default, skip_serializing_if = "Option::is_none")]
+    pub r#type: Option<String>,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entry: Option<Box<Entry>>,
+}
+
+impl xml::codegen::FromElement for Entry {
+    fn from_element(element: &xml::value::Element) -> Result<Self, xml::codegen::BindError> {
+        Ok(Self {
+            id: xml::codegen::attribute(element, "id")?,
+            r#type: xml::codegen::optional_attribute(element, "type")?,
+            title: xml::codegen::child(element, "title")?,
+            entry: xml::codegen::optional_child(element, "entry")?,
+        })
+    }
+}
+"#);
+
+        let xsd = r#"<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema" xmlns:t="urn:t" targetNamespace="urn:t">
+            <xs:simpleType name="Price"><xs:restriction base="xs:decimal"><xs:minInclusive value="0"/></xs:restriction></xs:simpleType>
+            <xs:complexType name="Named"><xs:attribute name="name" type="xs:string" use="required"/></xs:complexType>
+            <xs:element name="order">
+                <xs:complexType><xs:complexContent><xs:extension base="t:Named"><xs:sequence>
+                    <xs:element name="note" type="xs:string" minOccurs="0"/>
+                    <xs:element ref="t:item" maxOccurs="unbounded"/>
+                </xs:sequence></xs:extension></xs:complexContent></xs:complexType>
+            </xs:element>
+            <xs:element name="item">
+                <xs:complexType><xs:simpleContent><xs:extension base="t:Price">
+                    <xs:attribute name="sku" type="xs:token"/>
+                </xs:extension></xs:simpleContent></xs:complexType>
+            </xs:element>
+        </xs:schema>"#;
+        let schema = InferredSchema::from_xsd(EventReader::from_str(xsd)).unwrap();
+        assert_eq!(schema.roots, ["order", "item"]);
+        let code = Codegen::new().generate(&schema);
+        assert!(code.contains("pub struct Order {\n    pub name: String,\n    pub note: Option<String>,\n    pub item: Vec<Item>,\n}\n"), "{code}");
+        assert!(code.contains("pub struct Item {\n    pub sku: Option<String>,\n    pub text: f64,\n}\n"), "{code}");
+        assert!(code.contains("            text: xml::codegen::text(element)?,\n"), "{code}");
+
+        assert!(InferredSchema::from_xsd(EventReader::from_str("<schema/>")).is_err());
+    }
+}
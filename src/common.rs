@@ -3,12 +3,18 @@
 use std::fmt;
 
 /// Represents a position inside some textual document.
-#[derive(Copy, Clone, PartialEq, Eq)]
+///
+/// Positions are equal if they're in the same row and column. The offsets aren't compared.
+#[derive(Copy, Clone)]
 pub struct TextPosition {
     /// Row, counting from 0
     pub row: u64,
-    /// Column, counting from 0
+    /// Column, counting from 0. Tabs may count as more than one column, see `ParserConfig2::tab_width`.
     pub column: u64,
+    /// Number of bytes of the source before this position
+    pub byte_offset: u64,
+    /// Number of characters of the source before this position
+    pub char_offset: u64,
 }
 
 impl TextPosition {
@@ -16,7 +22,21 @@ impl TextPosition {
     #[inline]
     #[must_use]
     pub fn new() -> TextPosition {
-        TextPosition { row: 0, column: 0 }
+        TextPosition { row: 0, column: 0, byte_offset: 0, char_offset: 0 }
+    }
+
+    /// Line number, counting from 1, as used by editors and the LSP
+    #[inline]
+    #[must_use]
+    pub fn line(&self) -> u64 {
+        self.row + 1
+    }
+
+    /// Column number, counting from 1, as used by editors and the LSP
+    #[inline]
+    #[must_use]
+    pub fn column_number(&self) -> u64 {
+        self.column + 1
     }
 
     /// Advances the position in a line
@@ -38,6 +58,54 @@ impl TextPosition {
         self.column = 0;
         self.row += 1;
     }
+
+    /// Accounts for a character that has been read from the source, ending at `byte_offset`
+    ///
+    /// `\r\n` and a lone `\r` are line breaks too, so `prev` has to be the previous character.
+    #[inline]
+    pub fn advance_char(&mut self, c: char, prev: char, byte_offset: u64, tab_width: u8) {
+        match c {
+            '\r' => self.new_line(),
+            '\n' if prev != '\r' => self.new_line(),
+            '\n' => {},
+            '\t' if tab_width > 1 => self.advance_to_tab(tab_width),
+            _ => self.advance(1),
+        }
+        self.char_offset += 1;
+        self.byte_offset = byte_offset;
+    }
+}
+
+impl PartialEq for TextPosition {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.row == other.row && self.column == other.column
+    }
+}
+
+impl Eq for TextPosition {}
+
+impl Default for TextPosition {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Number of events of each kind that have been read or written
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct EventCounts {
+    /// `StartElement` events
+    pub elements: u64,
+    /// `Characters` events, including `Whitespace` in the reader
+    pub characters: u64,
+    /// `CData` events
+    pub cdata: u64,
+    /// `Comment` events
+    pub comments: u64,
+    /// `ProcessingInstruction` events
+    pub processing_instructions: u64,
 }
 
 impl fmt::Debug for TextPosition {
@@ -127,8 +195,19 @@ pub fn is_xml11_char(c: char) -> bool {
 /// Is it a valid character in XML 1.1 but not part of the restricted character set
 #[must_use]
 pub fn is_xml11_char_not_restricted(c: char) -> bool {
-    is_xml11_char(c) &&
-        !matches!(c, '\u{01}'..='\u{08}' | '\u{0B}'..='\u{0C}' | '\u{0E}'..='\u{1F}' | '\u{7F}'..='\u{84}' | '\u{86}'..='\u{9F}')
+    is_xml11_char(c) && !is_xml11_restricted_char(c)
+}
+
+/// Is it one of the 66 Unicode noncharacters, U+FDD0 to U+FDEF and the last two code points of every plane
+#[must_use]
+pub(crate) fn is_noncharacter(c: char) -> bool {
+    matches!(c, '\u{FDD0}'..='\u{FDEF}') || (c as u32) & 0xFFFE == 0xFFFE
+}
+
+/// Is it in the restricted character set of XML 1.1, which can only appear as character references
+#[must_use]
+pub(crate) fn is_xml11_restricted_char(c: char) -> bool {
+    matches!(c, '\u{01}'..='\u{08}' | '\u{0B}'..='\u{0C}' | '\u{0E}'..='\u{1F}' | '\u{7F}'..='\u{84}' | '\u{86}'..='\u{9F}')
 }
 
 /// Checks whether the given character is a name start character (`NameStartChar`)
@@ -162,3 +241,120 @@ pub fn is_name_char(c: char) -> bool {
         '\u{300}'..='\u{36F}' | '\u{203F}'..='\u{2040}'
     )
 }
+
+/// Checks whether the string is a name without a colon (`NCName`)
+/// as is defined by the Namespaces in XML specification.
+#[must_use]
+pub fn is_ncname(s: &str) -> bool {
+    s.starts_with(|c| c != ':' && is_name_start_char(c))
+        && s.chars().all(|c| c != ':' && is_name_char(c))
+}
+
+/// Pseudo-attributes of the `<?xml-stylesheet?>` processing instruction
+///
+/// See [Associating Style Sheets with XML documents](https://www.w3.org/TR/xml-stylesheet/).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct XmlStylesheet {
+    /// URI of the style sheet
+    pub href: String,
+    /// The `type` pseudo-attribute, e.g. `text/xsl`
+    pub mime_type: Option<String>,
+    /// Media query
+    pub media: Option<String>,
+    /// Name of the style sheet
+    pub title: Option<String>,
+    /// Character encoding of the style sheet
+    pub charset: Option<String>,
+    /// `alternate="yes"`
+    pub alternate: bool,
+}
+
+impl XmlStylesheet {
+    /// Parses data of a processing instruction with the `xml-stylesheet` target.
+    ///
+    /// Returns `None` if the pseudo-attributes are malformed or `href` is missing.
+    ///
+    /// ```rust
+    /// use xml::common::XmlStylesheet;
+    ///
+    /// let s = XmlStylesheet::parse(r#"href="style.xsl" type='text/xsl'"#).unwrap();
+    /// assert_eq!(s.href, "style.xsl");
+    /// assert_eq!(s.mime_type.as_deref(), Some("text/xsl"));
+    /// ```
+    #[must_use]
+    pub fn parse(data: &str) -> Option<Self> {
+        let mut s = XmlStylesheet {
+            href: String::new(),
+            mime_type: None,
+            media: None,
+            title: None,
+            charset: None,
+            alternate: false,
+        };
+        let mut has_href = false;
+        for (name, value) in pseudo_attributes(data)? {
+            match name {
+                "href" => { s.href = value; has_href = true; },
+                "type" => s.mime_type = Some(value),
+                "media" => s.media = Some(value),
+                "title" => s.title = Some(value),
+                "charset" => s.charset = Some(value),
+                "alternate" => s.alternate = value == "yes",
+                _ => {},
+            }
+        }
+        if has_href { Some(s) } else { None }
+    }
+}
+
+/// `name="value"` pairs used in data of some processing instructions
+fn pseudo_attributes(mut data: &str) -> Option<Vec<(&str, String)>> {
+    let mut attrs = Vec::new();
+    loop {
+        data = data.trim_start_matches(is_whitespace_char);
+        if data.is_empty() {
+            return Some(attrs);
+        }
+        let name_len = data.find(|c: char| !is_name_char(c)).unwrap_or(data.len());
+        let (name, rest) = data.split_at(name_len);
+        if name.is_empty() {
+            return None;
+        }
+        let rest = rest.trim_start_matches(is_whitespace_char).strip_prefix('=')?;
+        let rest = rest.trim_start_matches(is_whitespace_char);
+        let quote = rest.chars().next().filter(|&q| q == '"' || q == '\'')?;
+        let (value, rest) = rest[1..].split_once(quote)?;
+        attrs.push((name, unescape_pseudo_attribute(value)?));
+        if !rest.is_empty() && !rest.starts_with(is_whitespace_char) {
+            return None;
+        }
+        data = rest;
+    }
+}
+
+/// Only predefined entities and character references are allowed
+fn unescape_pseudo_attribute(mut value: &str) -> Option<String> {
+    let mut out = String::with_capacity(value.len());
+    while let Some((before, rest)) = value.split_once('&') {
+        out.push_str(before);
+        let (name, rest) = rest.split_once(';')?;
+        out.push(match name {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "apos" => '\'',
+            "quot" => '"',
+            _ => {
+                let num = name.strip_prefix('#')?;
+                let num = match num.strip_prefix('x') {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => num.parse().ok()?,
+                };
+                char::from_u32(num)?
+            },
+        });
+        value = rest;
+    }
+    out.push_str(value);
+    Some(out)
+}
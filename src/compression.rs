@@ -0,0 +1,197 @@
+//! Reading and writing of compressed documents, like `.xml.gz` files.
+//!
+//! Gzip needs the `flate2` feature, and zstd needs the `zstd` feature.
+
+use std::io::{self, Read, Write};
+
+/// Format of compressed data
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum Compression {
+    /// Not compressed
+    None,
+    /// Gzip, with one or more members
+    Gzip,
+    /// Zstandard
+    Zstd,
+}
+
+impl Compression {
+    /// Recognizes the format by the first bytes of the data
+    #[must_use]
+    pub fn detect(magic: &[u8]) -> Self {
+        if magic.starts_with(&[0x1F, 0x8B]) {
+            Self::Gzip
+        } else if magic.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+            Self::Zstd
+        } else {
+            Self::None
+        }
+    }
+}
+
+/// The first bytes that have been read for detection, followed by the rest of the source
+type Source<R> = io::Chain<io::Take<io::Cursor<[u8; 4]>>, R>;
+
+enum Decoder<R: Read> {
+    Plain(Source<R>),
+    #[cfg(feature = "flate2")]
+    Gzip(flate2::read::MultiGzDecoder<Source<R>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::read::Decoder<'static, io::BufReader<Source<R>>>),
+}
+
+/// A source that decompresses gzip or zstd data if it starts with their magic bytes,
+/// and passes anything else through unchanged.
+///
+/// ```rust,no_run
+/// use std::fs::File;
+/// use xml::compression::DecompressingReader;
+/// use xml::EventReader;
+///
+/// let source = DecompressingReader::new(File::open("sitemap.xml.gz")?)?;
+/// for event in EventReader::new(std::io::BufReader::new(source)) {
+///     println!("{:?}", event?);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct DecompressingReader<R: Read> {
+    decoder: Decoder<R>,
+    compression: Compression,
+}
+
+impl<R: Read> DecompressingReader<R> {
+    /// Reads the first bytes of the source to detect the compression.
+    ///
+    /// Fails if the data is compressed in a format that isn't enabled by crate features.
+    pub fn new(mut source: R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        let mut len = 0;
+        while len < magic.len() {
+            match source.read(&mut magic[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+        let compression = Compression::detect(&magic[..len]);
+        let source = io::Cursor::new(magic).take(len as u64).chain(source);
+        let decoder = match compression {
+            Compression::None => Decoder::Plain(source),
+            #[cfg(feature = "flate2")]
+            Compression::Gzip => Decoder::Gzip(flate2::read::MultiGzDecoder::new(source)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Decoder::Zstd(zstd::stream::read::Decoder::new(source)?),
+            #[allow(unreachable_patterns)]
+            _ => return Err(unsupported(compression)),
+        };
+        Ok(Self { decoder, compression })
+    }
+
+    /// The detected format
+    #[must_use]
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+}
+
+impl<R: Read> Read for DecompressingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.decoder {
+            Decoder::Plain(r) => r.read(buf),
+            #[cfg(feature = "flate2")]
+            Decoder::Gzip(r) => r.read(buf),
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(r) => r.read(buf),
+        }
+    }
+}
+
+enum Encoder<W: Write> {
+    Plain(W),
+    #[cfg(feature = "flate2")]
+    Gzip(flate2::write::GzEncoder<W>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+}
+
+/// A sink that compresses everything written to it, e.g. for an [`EventWriter`](crate::EventWriter)
+/// that writes `.xml.gz` files.
+///
+/// Flushing ends a compressed block, so that everything written so far can be decompressed.
+/// This happens at the writer's [`auto_flush_depth`](crate::EmitterConfig::auto_flush_depth)
+/// and [`auto_flush_bytes`](crate::EmitterConfig::auto_flush_bytes) points, but frequent flushing makes compression worse.
+///
+/// [`finish`](Self::finish) must be called at the end to write the rest of the data.
+///
+/// ```rust,no_run
+/// use xml::compression::{Compression, CompressingWriter};
+/// use xml::writer::{EmitterConfig, XmlEvent};
+///
+/// let sink = CompressingWriter::new(Vec::new(), Compression::Gzip)?;
+/// let mut writer = EmitterConfig::new().create_writer(sink);
+/// writer.write(XmlEvent::start_element("urlset"))?;
+/// writer.write(XmlEvent::end_element())?;
+/// let gz: Vec<u8> = writer.into_inner().finish()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct CompressingWriter<W: Write> {
+    encoder: Encoder<W>,
+}
+
+impl<W: Write> CompressingWriter<W> {
+    /// Compresses with the default level of the format. `Compression::None` writes the data unchanged.
+    ///
+    /// Fails if the format isn't enabled by crate features.
+    pub fn new(sink: W, compression: Compression) -> io::Result<Self> {
+        let encoder = match compression {
+            Compression::None => Encoder::Plain(sink),
+            #[cfg(feature = "flate2")]
+            Compression::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(sink, flate2::Compression::default())),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Encoder::Zstd(zstd::stream::write::Encoder::new(sink, 0)?),
+            #[allow(unreachable_patterns)]
+            _ => return Err(unsupported(compression)),
+        };
+        Ok(Self { encoder })
+    }
+
+    /// Writes the end of the compressed data, and returns the sink
+    pub fn finish(self) -> io::Result<W> {
+        match self.encoder {
+            Encoder::Plain(w) => Ok(w),
+            #[cfg(feature = "flate2")]
+            Encoder::Gzip(w) => w.finish(),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(w) => w.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for CompressingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.encoder {
+            Encoder::Plain(w) => w.write(buf),
+            #[cfg(feature = "flate2")]
+            Encoder::Gzip(w) => w.write(buf),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.encoder {
+            Encoder::Plain(w) => w.flush(),
+            #[cfg(feature = "flate2")]
+            Encoder::Gzip(w) => w.flush(),
+            #[cfg(feature = "zstd")]
+            Encoder::Zstd(w) => w.flush(),
+        }
+    }
+}
+
+#[cold]
+fn unsupported(compression: Compression) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, format!("{compression:?} compression isn't enabled in xml-rs features"))
+}
@@ -0,0 +1,286 @@
+//! Runs the [W3C XML Conformance Test Suite](https://www.w3.org/XML/Test/) with [`EventReader`].
+//!
+//! This module is enabled by the `conformance` feature. It reads the suite's manifest files,
+//! parses every test document with the given configuration, and reports which tests
+//! had the expected outcome. The crate uses the same rules for its own tests, so the results
+//! can be compared to show how another configuration changes conformance.
+//!
+//! ```rust,no_run
+//! use xml::conformance;
+//! use xml::reader::ParserConfig2;
+//!
+//! let config = ParserConfig2::new().allow_multiple_root_elements(false);
+//! let report = conformance::run_suite("xmlconf/xmlconf.xml".as_ref(), &config).unwrap();
+//! println!("{report}");
+//! for failure in report.failures() {
+//!     println!("{} ({}): {}", failure.case.id, failure.case.path.display(), failure.error.as_deref().unwrap_or(""));
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::reader::{EntityTable, EventReader, ParserConfig2, Result, XmlEvent};
+
+/// Expected outcome of a test, from the `TYPE` attribute
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum TestType {
+    /// Well-formed and valid document
+    Valid,
+    /// Well-formed document that is not valid. Non-validating parsers must accept it.
+    Invalid,
+    /// Document that is not well-formed, and must be rejected
+    NotWellFormed,
+    /// Document with an error that parsers may report. It's expected to be rejected.
+    Error,
+}
+
+impl TestType {
+    const ALL: [TestType; 4] = [TestType::Valid, TestType::Invalid, TestType::NotWellFormed, TestType::Error];
+
+    fn from_attribute(value: &str) -> Option<Self> {
+        Some(match value {
+            "valid" => TestType::Valid,
+            "invalid" => TestType::Invalid,
+            "not-wf" => TestType::NotWellFormed,
+            "error" => TestType::Error,
+            _ => return None,
+        })
+    }
+
+    fn expects_error(self) -> bool {
+        matches!(self, TestType::NotWellFormed | TestType::Error)
+    }
+}
+
+impl fmt::Display for TestType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TestType::Valid => "valid",
+            TestType::Invalid => "invalid",
+            TestType::NotWellFormed => "not-wf",
+            TestType::Error => "error",
+        })
+    }
+}
+
+/// A `TEST` entry of the manifest
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct TestCase {
+    /// `ID` of the test, or the file name if it has none
+    pub id: String,
+    /// Expected outcome
+    pub test_type: TestType,
+    /// Location of the test document
+    pub path: PathBuf,
+    /// Text of the `TEST` element
+    pub description: String,
+    /// `VERSION` of XML the test applies to, if limited
+    pub version: Option<String>,
+    /// `EDITION`s of the XML spec the test applies to, if limited
+    pub edition: Option<String>,
+    /// `NAMESPACE="no"` tests are for parsers without namespace support
+    pub namespace: bool,
+}
+
+/// Reads the list of tests from a manifest, such as `xmlconf.xml` of the suite or one of its parts.
+///
+/// Parts of the manifest included as external entities are read too, one level deep,
+/// and `xml:base` attributes are applied to paths of the tests.
+pub fn read_manifest(path: &Path) -> Result<Vec<TestCase>> {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let entity_table = external_entities(path, dir)?;
+    let reader = ParserConfig2::new()
+        .entity_table(entity_table)
+        .max_entity_expansion_length(usize::MAX)
+        .create_reader(BufReader::new(File::open(path)?));
+
+    let mut bases = vec![dir.to_path_buf()];
+    let mut cases = Vec::new();
+    let mut test = None;
+    for event in reader {
+        match event? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                let attr = |name: &str| attributes.iter().find(|a| a.name.local_name == name && a.name.prefix.is_none()).map(|a| a.value.clone());
+                let xml_base = attributes.iter().find(|a| a.name.local_name == "base" && a.name.prefix.as_deref() == Some("xml"));
+                let base = match xml_base {
+                    Some(a) => bases[bases.len() - 1].join(&a.value),
+                    None => bases[bases.len() - 1].clone(),
+                };
+                if name.local_name == "TEST" {
+                    let uri = attr("URI").unwrap_or_default();
+                    let path = base.join(&uri);
+                    test = Some(TestCase {
+                        id: attr("ID").unwrap_or_else(|| path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or(uri)),
+                        test_type: attr("TYPE").as_deref().and_then(TestType::from_attribute).unwrap_or(TestType::Error),
+                        path,
+                        description: String::new(),
+                        version: attr("VERSION"),
+                        edition: attr("EDITION"),
+                        namespace: attr("NAMESPACE").as_deref() != Some("no"),
+                    });
+                }
+                bases.push(base);
+            },
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some(test) = &mut test {
+                    test.description.push_str(&text);
+                }
+            },
+            XmlEvent::EndElement { name } => {
+                bases.pop();
+                if name.local_name == "TEST" {
+                    if let Some(mut test) = test.take() {
+                        test.description = test.description.split_whitespace().collect::<Vec<_>>().join(" ");
+                        cases.push(test);
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+    Ok(cases)
+}
+
+/// Loads `<!ENTITY name SYSTEM "file">` declarations of the manifest, since the parser doesn't read external entities
+fn external_entities(path: &Path, dir: &Path) -> Result<EntityTable> {
+    let reader = ParserConfig2::new()
+        .capture_doctype_raw(true)
+        .create_reader(BufReader::new(File::open(path)?));
+    let mut doctype = String::new();
+    for event in reader {
+        match event? {
+            XmlEvent::Doctype { syntax, .. } => doctype = syntax,
+            XmlEvent::StartElement { .. } => break,
+            _ => {},
+        }
+    }
+
+    let mut table = EntityTable::new();
+    for declaration in doctype.split("<!ENTITY").skip(1) {
+        let mut parts = declaration.split_whitespace();
+        let (name, keyword) = match (parts.next(), parts.next()) {
+            (Some(name), Some(keyword)) => (name, keyword),
+            _ => continue,
+        };
+        if name == "%" || keyword != "SYSTEM" {
+            continue;
+        }
+        let rest = declaration.trim_start()[name.len()..].trim_start()[keyword.len()..].trim_start();
+        let system_id = match rest.chars().next() {
+            Some(q @ ('"' | '\'')) => rest[1..].split(q).next().unwrap_or_default(),
+            _ => continue,
+        };
+        let text = String::from_utf8(fs::read(dir.join(system_id))?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        table.insert(name, strip_text_declaration(&text));
+    }
+    Ok(table)
+}
+
+/// External parsed entities can start with `<?xml encoding="…"?>`
+fn strip_text_declaration(text: &str) -> &str {
+    let text = text.trim_start_matches('\u{feff}');
+    if text.starts_with("<?xml") && text[5..].starts_with(|c: char| c.is_ascii_whitespace()) {
+        if let Some(end) = text.find("?>") {
+            return &text[end + 2..];
+        }
+    }
+    text
+}
+
+/// Outcome of a single test
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct TestResult {
+    /// The test
+    pub case: TestCase,
+    /// Why the test failed, or `None` if the parser behaved as expected
+    pub error: Option<String>,
+}
+
+impl TestResult {
+    /// The parser behaved as expected
+    #[must_use]
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Parses the test document with the configuration, and checks the outcome.
+///
+/// Documents of `valid` and `invalid` tests must be parsed without errors, and contain an element.
+/// `not-wf` and `error` tests must fail. The configuration must not ignore the end of stream.
+#[must_use]
+pub fn run_test(case: &TestCase, config: &ParserConfig2) -> TestResult {
+    let error = match parse(&case.path, config) {
+        Ok(()) if case.test_type.expects_error() => Some("the document was accepted".into()),
+        Err(e) if !case.test_type.expects_error() => Some(e),
+        _ => None,
+    };
+    TestResult { case: case.clone(), error }
+}
+
+fn parse(path: &Path, config: &ParserConfig2) -> Result<(), String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let reader = EventReader::new_with_config(BufReader::new(file), config.clone());
+    let mut seen_element = false;
+    for event in reader {
+        if let XmlEvent::StartElement { .. } = event.map_err(|e| e.to_string())? {
+            seen_element = true;
+        }
+    }
+    if seen_element { Ok(()) } else { Err("no elements found".into()) }
+}
+
+/// Runs all tests from the manifest, see [`read_manifest`] and [`run_test`]
+pub fn run_suite(manifest: &Path, config: &ParserConfig2) -> Result<Report> {
+    let results = read_manifest(manifest)?.iter().map(|case| run_test(case, config)).collect();
+    Ok(Report { results })
+}
+
+/// Results of [`run_suite`]. `Display` shows counts by test type.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct Report {
+    /// Results in the order of the manifest
+    pub results: Vec<TestResult>,
+}
+
+impl Report {
+    /// Numbers of passed and failed tests of the given type
+    #[must_use]
+    pub fn counts(&self, test_type: TestType) -> (usize, usize) {
+        let (passed, failed): (Vec<_>, Vec<_>) = self.results.iter()
+            .filter(|r| r.case.test_type == test_type)
+            .partition(|r| r.passed());
+        (passed.len(), failed.len())
+    }
+
+    /// Tests that didn't have the expected outcome
+    pub fn failures(&self) -> impl Iterator<Item = &TestResult> {
+        self.results.iter().filter(|r| !r.passed())
+    }
+
+    /// Failed tests by their id, e.g. to compare with a list of known failures
+    #[must_use]
+    pub fn failures_by_id(&self) -> HashMap<&str, &TestResult> {
+        self.failures().map(|r| (r.case.id.as_str(), r)).collect()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for test_type in TestType::ALL {
+            let (passed, failed) = self.counts(test_type);
+            if passed + failed > 0 {
+                writeln!(f, "{test_type}: {passed} passed, {failed} failed")?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,340 @@
+//! Edits of a document that keep everything else exactly as it was written, e.g. to change
+//! a setting in a configuration file without reformatting it.
+//!
+//! The source is read twice: first to find the elements to edit, and then to copy the bytes
+//! between the edits to the output. Only the start tags of edited elements are written again,
+//! and in them only the changed attributes. Comments, whitespace, entity references and quotes
+//! elsewhere stay as they were.
+//!
+//! Elements are selected with paths of local names separated by `/`, like in
+//! [`TransformRules`](crate::filter::TransformRules). `item` matches all `item` elements,
+//! `/config/server` only children of the root, and `*` matches any name.
+//!
+//! ```rust
+//! use std::io::Cursor;
+//! use xml::build::Element;
+//! use xml::edit::Edits;
+//!
+//! let config = "<config>\n  <!-- ports -->\n  <server port='80'  host=\"a\"/>\n  <legacy>x</legacy>\n</config>\n";
+//! let mut out = Vec::new();
+//! Edits::new()
+//!     .set_attribute("/config/server", "port", "8080")
+//!     .delete("legacy")
+//!     .insert_child("/config", Element::new("cache").attr("size", "1M"))
+//!     .apply(Cursor::new(config), &mut out)
+//!     .unwrap();
+//! assert_eq!(String::from_utf8(out).unwrap(),
+//!     "<config>\n  <!-- ports -->\n  <server port='8080'  host=\"a\"/>\n  \n<cache size=\"1M\" /></config>\n");
+//! ```
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+
+use crate::build::Element;
+use crate::escape::escape_str_attribute;
+use crate::filter::Pattern;
+use crate::reader::{self, EventReader, ParserConfig2, XmlEvent};
+use crate::util::Encoding;
+use crate::EmitterConfig;
+
+/// A list of changes to elements selected by paths, see the [module docs](self)
+#[derive(Clone, Debug, Default)]
+pub struct Edits {
+    edits: Vec<(Pattern, Edit)>,
+}
+
+#[derive(Clone, Debug)]
+enum Edit {
+    SetAttribute(String, String),
+    InsertChild(Element),
+    Delete,
+}
+
+/// A part of the source that is written differently
+#[derive(Debug)]
+struct Change {
+    /// Bytes of the source that are replaced
+    range: Range<u64>,
+    kind: ChangeKind,
+}
+
+#[derive(Debug)]
+enum ChangeKind {
+    /// Attribute values to set in the start tag, and content to add if it's an empty-element tag
+    StartTag { attributes: Vec<(String, String)>, content: Option<Vec<u8>> },
+    /// Content inserted before the end tag
+    Insert(Vec<u8>),
+    Delete,
+}
+
+impl Edits {
+    /// Creates an empty list of edits, which copies documents unchanged
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the value of the attribute of matching elements, or adds the attribute if they don't have it.
+    ///
+    /// The name is matched as it's written in the tag, including its prefix, like `xml:lang`.
+    /// The value is escaped, and an existing attribute keeps its quotes.
+    #[must_use]
+    pub fn set_attribute(mut self, path: &str, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.edits.push((Pattern::new(path), Edit::SetAttribute(name.into(), value.into())));
+        self
+    }
+
+    /// Adds the element as the last child of matching elements.
+    ///
+    /// It's written on its own, so it has only the namespace declarations that are in its [`Element::namespace`],
+    /// and isn't indented. An empty-element tag like `<a/>` becomes a start and an end tag.
+    #[must_use]
+    pub fn insert_child(mut self, path: &str, child: Element) -> Self {
+        self.edits.push((Pattern::new(path), Edit::InsertChild(child)));
+        self
+    }
+
+    /// Removes matching elements with their content. Whitespace around them is kept.
+    ///
+    /// Other edits of the removed elements and of their descendants are ignored.
+    #[must_use]
+    pub fn delete(mut self, path: &str) -> Self {
+        self.edits.push((Pattern::new(path), Edit::Delete));
+        self
+    }
+
+    /// Writes the edited document from the source to the output.
+    ///
+    /// The source is read from its current position, and then read again from the same position.
+    /// Only UTF-8 and ASCII documents can be edited, because the unchanged bytes are copied as they are.
+    pub fn apply<R: Read + Seek, W: Write>(&self, mut source: R, mut out: W) -> reader::Result<()> {
+        let start = source.stream_position()?;
+        let changes = self.find_changes(EventReader::new_with_config(&mut source, ParserConfig2::new()))?;
+
+        source.seek(SeekFrom::Start(start))?;
+        let mut pos = 0;
+        for change in changes {
+            io::copy(&mut (&mut source).take(change.range.start - pos), &mut out)?;
+            let len = change.range.end - change.range.start;
+            match change.kind {
+                ChangeKind::StartTag { attributes, content } => {
+                    let mut tag = Vec::new();
+                    (&mut source).take(len).read_to_end(&mut tag)?;
+                    out.write_all(&edit_start_tag(&String::from_utf8_lossy(&tag), &attributes, content.as_deref()))?;
+                },
+                ChangeKind::Insert(content) => out.write_all(&content)?,
+                ChangeKind::Delete => {
+                    io::copy(&mut (&mut source).take(len), &mut io::sink())?;
+                },
+            }
+            pos = change.range.end;
+        }
+        io::copy(&mut source, &mut out)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Reads the document, and returns the changes in the order of their positions in the source
+    fn find_changes<R: Read>(&self, mut reader: EventReader<R>) -> reader::Result<Vec<Change>> {
+        let mut changes = Vec::new();
+        let mut path = Vec::new();
+        // depth and change of the edited elements that haven't ended yet
+        let mut open: Vec<(usize, usize)> = Vec::new();
+        // depth of a deleted element, in which nothing else is edited
+        let mut deleted_depth = None;
+        loop {
+            match reader.next()? {
+                XmlEvent::StartDocument { encoding, .. }
+                    if !matches!(encoding.parse(), Ok(Encoding::Utf8 | Encoding::Default | Encoding::Ascii)) => {
+                    return Err((&reader, format!("Documents in {encoding} can't be edited, only UTF-8")).into());
+                },
+                XmlEvent::StartElement { name, .. } => {
+                    path.push(name.local_name);
+                    if deleted_depth.is_some() {
+                        continue;
+                    }
+                    let edits: Vec<_> = self.edits.iter().filter(|(pattern, _)| pattern.matches(&path)).map(|(_, edit)| edit).collect();
+                    if edits.is_empty() {
+                        continue;
+                    }
+                    let range = reader.event_byte_range();
+                    open.push((path.len(), changes.len()));
+                    if edits.iter().any(|edit| matches!(edit, Edit::Delete)) {
+                        deleted_depth = Some(path.len());
+                        changes.push(Change { range, kind: ChangeKind::Delete });
+                        continue;
+                    }
+                    let mut attributes: Vec<(String, String)> = Vec::new();
+                    let mut content = Vec::new();
+                    for edit in edits {
+                        match edit {
+                            Edit::SetAttribute(name, value) => match attributes.iter_mut().find(|(n, _)| n == name) {
+                                Some(attribute) => attribute.1.clone_from(value),
+                                None => attributes.push((name.clone(), value.clone())),
+                            },
+                            Edit::InsertChild(child) => {
+                                let mut writer = EmitterConfig::new().write_document_declaration(false).create_writer(&mut content);
+                                child.write_to(&mut writer).map_err(|e| reader::Error::from((&reader, e.to_string())))?;
+                            },
+                            Edit::Delete => {},
+                        }
+                    }
+                    changes.push(Change { range, kind: ChangeKind::StartTag { attributes, content: Some(content) } });
+                },
+                XmlEvent::EndElement { .. } => {
+                    if open.last().map_or(false, |&(depth, _)| depth == path.len()) {
+                        let (_, i) = open.pop().unwrap();
+                        let range = reader.event_byte_range();
+                        let change = &mut changes[i];
+                        match &mut change.kind {
+                            ChangeKind::Delete => {
+                                change.range.end = range.end;
+                                deleted_depth = None;
+                            },
+                            ChangeKind::StartTag { content, .. } => {
+                                // an empty-element tag has the same range as its start
+                                if range.start != change.range.start {
+                                    let content = content.take().unwrap_or_default();
+                                    if !content.is_empty() {
+                                        changes.push(Change { range: range.start..range.start, kind: ChangeKind::Insert(content) });
+                                    }
+                                }
+                            },
+                            ChangeKind::Insert(_) => {},
+                        }
+                    }
+                    path.pop();
+                },
+                XmlEvent::EndDocument => break,
+                _ => {},
+            }
+        }
+        changes.sort_by_key(|change| change.range.start);
+        Ok(changes)
+    }
+}
+
+/// Sets attributes in the source of a start tag, and if it's an empty-element tag with new content,
+/// turns it into a start tag, the content and an end tag
+fn edit_start_tag(tag: &str, attributes: &[(String, String)], content: Option<&[u8]>) -> Vec<u8> {
+    let bytes = tag.as_bytes();
+    let name_end = bytes.iter().position(|&b| b.is_ascii_whitespace() || b == b'/' || b == b'>').unwrap_or(bytes.len());
+    let name = &tag[1..name_end];
+
+    // names and value ranges without quotes of the attributes in the tag
+    let mut existing = Vec::new();
+    let mut i = name_end;
+    let mut attributes_end = name_end;
+    loop {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i >= bytes.len() || bytes[i] == b'/' || bytes[i] == b'>' {
+            break;
+        }
+        let name_start = i;
+        while i < bytes.len() && bytes[i] != b'=' && !bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        let attribute_name = &tag[name_start..i];
+        while i < bytes.len() && bytes[i] != b'"' && bytes[i] != b'\'' {
+            i += 1;
+        }
+        let quote = match bytes.get(i) {
+            Some(&quote) => quote,
+            None => break,
+        };
+        let value_start = i + 1;
+        i = value_start + bytes[value_start..].iter().position(|&b| b == quote).unwrap_or(bytes.len() - value_start);
+        existing.push((attribute_name, value_start..i));
+        i += 1;
+        attributes_end = i;
+    }
+
+    let mut out = String::with_capacity(tag.len());
+    let mut copied = 0;
+    for (attribute_name, range) in &existing {
+        if let Some((_, value)) = attributes.iter().find(|(name, _)| name == attribute_name) {
+            out.push_str(&tag[copied..range.start]);
+            out.push_str(&escape_str_attribute(value));
+            copied = range.end;
+        }
+    }
+    out.push_str(&tag[copied..attributes_end]);
+    for (attribute_name, value) in attributes {
+        if !existing.iter().any(|(name, _)| name == attribute_name) {
+            out.push_str(&format!(" {attribute_name}=\"{}\"", escape_str_attribute(value)));
+        }
+    }
+    let rest = &tag[attributes_end..];
+    let mut out = out.into_bytes();
+    match content {
+        Some(content) if !content.is_empty() && rest.ends_with("/>") => {
+            out.extend_from_slice(rest[..rest.len() - 2].trim_end().as_bytes());
+            out.push(b'>');
+            out.extend_from_slice(content);
+            out.extend_from_slice(format!("</{name}>").as_bytes());
+        },
+        _ => out.extend_from_slice(rest.as_bytes()),
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{edit_start_tag, Edits};
+    use crate::build::Element;
+
+    fn apply(edits: &Edits, doc: &[u8]) -> Result<String, String> {
+        let mut out = Vec::new();
+        edits.apply(Cursor::new(doc), &mut out).map_err(|e| e.to_string())?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn start_tags() {
+        let set = |tag, content: &str| {
+            let attributes = [("a".to_string(), "it's <1>".to_string()), ("x:b".to_string(), "2".to_string())];
+            String::from_utf8(edit_start_tag(tag, &attributes, Some(content.as_bytes()))).unwrap()
+        };
+        assert_eq!(set("<e a = '0' c=\"/>\">", ""), "<e a = 'it&apos;s &lt;1&gt;' c=\"/>\" x:b=\"2\">");
+        assert_eq!(set("<e\n/>", ""), "<e a=\"it&apos;s &lt;1&gt;\" x:b=\"2\"\n/>");
+        assert_eq!(set("<p:e x:b='1' />", "<c />"), "<p:e x:b='2' a=\"it&apos;s &lt;1&gt;\"><c /></p:e>");
+    }
+
+    #[test]
+    fn unchanged_bytes() {
+        let doc = "\u{feff}<?xml version='1.0'?>\r\n<!DOCTYPE a [<!ENTITY e 'é'>]>\r\n<a  k = \"&e;\" ><!--c--><b><c x='1'/></b>&e;<![CDATA[<]]><b/></a>\r\n";
+        assert_eq!(apply(&Edits::new(), doc.as_bytes()).unwrap(), doc);
+
+        let edits = Edits::new()
+            .set_attribute("c", "x", "2")
+            .delete("/a/b")
+            .insert_child("/a/b", Element::new("d"))
+            .set_attribute("a", "k", "&")
+            .insert_child("a", Element::new("z").text("t"));
+        assert_eq!(apply(&edits, doc.as_bytes()).unwrap(),
+            "\u{feff}<?xml version='1.0'?>\r\n<!DOCTYPE a [<!ENTITY e 'é'>]>\r\n<a  k = \"&amp;\" ><!--c-->&e;<![CDATA[<]]><z>t</z></a>\r\n");
+
+        let edits = Edits::new().insert_child("b", Element::new("d")).set_attribute("/a/b/c", "x", "2");
+        assert_eq!(apply(&edits, b"<a><b><c x='1'/></b><b/></a>").unwrap(), "<a><b><c x='2'/><d /></b><b><d /></b></a>");
+    }
+
+    #[test]
+    fn source_position() {
+        let mut source = Cursor::new(b"junk<a><b/></a>".to_vec());
+        source.set_position(4);
+        let mut out = Vec::new();
+        Edits::new().delete("b").apply(&mut source, &mut out).unwrap();
+        assert_eq!(out, b"<a></a>");
+    }
+
+    #[test]
+    fn errors() {
+        let doc = b"\xFF\xFE<\0a\0/\0>\0";
+        assert_eq!(apply(&Edits::new(), doc).unwrap_err(), "1:1 Documents in UTF-16 can't be edited, only UTF-8");
+        assert!(apply(&Edits::new().delete("b"), b"<a><b></a>").is_err());
+    }
+}
@@ -1,8 +1,25 @@
-//! Contains functions for performing XML special characters escaping.
+//! Contains functions for performing XML special characters escaping, and for expanding references.
+//!
+//! The escaping functions are the same that the writer uses, so text escaped with them and written
+//! with [`XmlEvent::raw_markup`](crate::writer::XmlEvent::raw_markup) matches the writer's own output.
+//!
+//! ```rust
+//! use xml::escape::{escape_str_attribute, unescape};
+//! use xml::reader::EntityTable;
+//!
+//! let escaped = escape_str_attribute("\"Tom\" & <Jerry>");
+//! assert_eq!(escaped, "&quot;Tom&quot; &amp; &lt;Jerry&gt;");
+//! assert_eq!(unescape(&escaped, &EntityTable::new()).unwrap(), "\"Tom\" & <Jerry>");
+//! ```
 
 use std::borrow::Cow;
-use std::fmt::{Display, Formatter, Result};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result, Write};
 use std::marker::PhantomData;
+use std::ops::Range;
+
+use crate::common::{is_name_char, is_name_start_char, is_xml10_char, is_xml11_restricted_char};
+use crate::reader::{EntityTable, DEFAULT_MAX_ENTITY_EXPANSION_DEPTH, DEFAULT_MAX_ENTITY_EXPANSION_LENGTH};
 
 pub(crate) trait Escapes {
     fn escape(c: u8) -> Option<&'static str>;
@@ -19,6 +36,10 @@ pub(crate) trait Escapes {
 pub(crate) struct Escaped<'a, E: Escapes> {
     _escape_phantom: PhantomData<E>,
     to_escape: &'a str,
+    /// XML 1.1 allows control characters, but only as character references
+    escape_restricted: bool,
+    /// Characters written as the given references
+    entities: &'a [(char, String)],
 }
 
 impl<'a, E: Escapes> Escaped<'a, E> {
@@ -26,12 +47,51 @@ impl<'a, E: Escapes> Escaped<'a, E> {
         Escaped {
             _escape_phantom: PhantomData,
             to_escape: s,
+            escape_restricted: false,
+            entities: &[],
+        }
+    }
+
+    /// Also escapes characters from the XML 1.1 restricted set
+    pub fn new_xml11(s: &'a str) -> Self {
+        Escaped {
+            escape_restricted: true,
+            ..Self::new(s)
+        }
+    }
+
+    /// Writes these characters as references, see `EmitterConfig::entity_map`
+    pub fn with_entities(self, entities: &'a [(char, String)]) -> Self {
+        Escaped { entities, ..self }
+    }
+
+    fn entity(&self, c: char) -> Option<&'a str> {
+        self.entities.iter().find(|&&(e, _)| e == c).map(|(_, r)| r.as_str())
+    }
+
+    #[cold]
+    fn fmt_by_char(&self, f: &mut Formatter<'_>) -> Result {
+        for c in self.to_escape.chars() {
+            let escaped = if c.is_ascii() { E::escape(c as u8) } else { None };
+            if let Some(replacement) = self.entity(c).or(escaped) {
+                f.write_str(replacement)?;
+            } else if self.escape_restricted && is_xml11_restricted_char(c) {
+                write!(f, "&#x{:X};", c as u32)?;
+            } else {
+                f.write_char(c)?;
+            }
         }
+        Ok(())
     }
 }
 
 impl<'a, E: Escapes> Display for Escaped<'a, E> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        if (self.escape_restricted && self.to_escape.chars().any(is_xml11_restricted_char)) ||
+            (!self.entities.is_empty() && self.to_escape.chars().any(|c| self.entity(c).is_some())) {
+            return self.fmt_by_char(f);
+        }
+
         let mut total_remaining = self.to_escape;
 
         // find the next occurence
@@ -96,6 +156,12 @@ escapes!(
     b'&' => "&amp;",
 );
 
+escapes!(
+    HtmlTextEscapes,
+    b'<' => "&lt;",
+    b'&' => "&amp;",
+);
+
 /// Performs escaping of common XML characters inside an attribute value.
 ///
 /// This function replaces several important markup characters with their
@@ -138,9 +204,171 @@ pub fn escape_str_pcdata(s: &str) -> Cow<'_, str> {
     escape_str::<PcDataEscapes>(s)
 }
 
+/// Expands character references, references to the predefined entities like `&lt;`,
+/// and to entities in the table, like the parser does in text and attribute values.
+///
+/// Values of entities from the table can refer to other entities. Markup in them is kept as text.
+/// Expansions are limited like the parser's default `max_entity_expansion_length` and `max_entity_expansion_depth`.
+///
+/// Does not perform allocations if the given string does not contain references.
+///
+/// ```rust
+/// use xml::escape::{unescape, UnescapeErrorKind};
+/// use xml::reader::EntityTable;
+///
+/// let mut entities = EntityTable::new();
+/// entities.insert("product", "xml-rs");
+/// assert_eq!(unescape("&product; &#x2764; &amp; you", &entities).unwrap(), "xml-rs ❤ & you");
+///
+/// let err = unescape("a &amp; b &c; d", &entities).unwrap_err();
+/// assert_eq!((err.offset, &*err.reference, err.kind), (10, "&c;", UnescapeErrorKind::UnknownEntity));
+/// ```
+pub fn unescape<'a>(s: &'a str, entities: &EntityTable) -> std::result::Result<Cow<'a, str>, UnescapeError> {
+    unescape_with_limits(s, entities, ExpansionLimits::default())
+}
+
+pub(crate) fn unescape_with_limits<'a>(s: &'a str, entities: &EntityTable, limits: ExpansionLimits) -> std::result::Result<Cow<'a, str>, UnescapeError> {
+    if !s.contains('&') {
+        return Ok(Cow::Borrowed(s));
+    }
+    let mut out = String::with_capacity(s.len());
+    unescape_into(&mut out, s, entities, limits, &mut Vec::new()).map_err(|(range, kind)| UnescapeError {
+        offset: range.start,
+        reference: s[range].into(),
+        kind,
+    })?;
+    Ok(Cow::Owned(out))
+}
+
+/// Limits of entity expansions, like `max_entity_expansion_length` and `max_entity_expansion_depth` of the parser
+#[derive(Copy, Clone)]
+pub(crate) struct ExpansionLimits {
+    /// Bytes of the unescaped text, checked before expanding an entity, like the parser checks its buffer
+    pub length: usize,
+    /// Entities that can be expanded inside each other
+    pub depth: u8,
+    /// Whether references in values of entities are expanded. The parser keeps them in attribute values.
+    pub nested: bool,
+}
+
+impl Default for ExpansionLimits {
+    fn default() -> Self {
+        Self {
+            length: DEFAULT_MAX_ENTITY_EXPANSION_LENGTH,
+            depth: DEFAULT_MAX_ENTITY_EXPANSION_DEPTH,
+            nested: true,
+        }
+    }
+}
+
+/// Errors are the byte range of the reference in `s`. For errors in values of entities, it's the range of the outermost reference.
+fn unescape_into(out: &mut String, s: &str, entities: &EntityTable, limits: ExpansionLimits, expanding: &mut Vec<String>)
+    -> std::result::Result<(), (Range<usize>, UnescapeErrorKind)> {
+    let mut rest = 0;
+    while let Some(found) = s[rest..].find('&') {
+        let start = rest + found;
+        out.push_str(&s[rest..start]);
+        let name_start = start + 1;
+        let mut chars = s[name_start..].char_indices();
+        let end = loop {
+            match chars.next() {
+                Some((i, ';')) if i > 0 => break name_start + i,
+                Some((0, c)) if is_name_start_char(c) || c == '#' => {},
+                Some((i, c)) if i > 0 && is_name_char(c) => {},
+                Some((i, _)) => return Err((start..name_start + i, UnescapeErrorKind::Unterminated)),
+                None => return Err((start..s.len(), UnescapeErrorKind::Unterminated)),
+            }
+        };
+        rest = end + 1;
+        let name = &s[name_start..end];
+        let reference = start..rest;
+        match name {
+            "lt"   => out.push('<'),
+            "gt"   => out.push('>'),
+            "amp"  => out.push('&'),
+            "apos" => out.push('\''),
+            "quot" => out.push('"'),
+            _ if name.starts_with('#') => {
+                let code = match name[1..].strip_prefix('x') {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => name[1..].parse().ok(),
+                };
+                match code.and_then(char::from_u32) {
+                    Some(c) if is_xml10_char(c) => out.push(c),
+                    _ => return Err((reference, UnescapeErrorKind::InvalidCharacter)),
+                }
+            },
+            _ => {
+                let value = entities.get(name).ok_or((reference.clone(), UnescapeErrorKind::UnknownEntity))?;
+                if expanding.iter().any(|n| n == name) {
+                    return Err((reference, UnescapeErrorKind::Recursive));
+                }
+                if out.len() > limits.length {
+                    return Err((reference, UnescapeErrorKind::TooBig));
+                }
+                if !limits.nested {
+                    out.push_str(value);
+                } else if expanding.len() >= usize::from(limits.depth) {
+                    return Err((reference, UnescapeErrorKind::TooBig));
+                } else {
+                    expanding.push(name.into());
+                    unescape_into(out, value, entities, limits, expanding).map_err(|(_, kind)| (reference.clone(), kind))?;
+                    expanding.pop();
+                }
+            },
+        }
+    }
+    out.push_str(&s[rest..]);
+    Ok(())
+}
+
+/// A reference that [`unescape`] couldn't expand
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct UnescapeError {
+    /// Byte offset of the `&` in the unescaped string
+    pub offset: usize,
+    /// The reference as written, up to the `;` or the character that can't be in it
+    pub reference: String,
+    /// What's wrong with it. If the reference is to an entity whose value has a bad reference, it's that reference's problem.
+    pub kind: UnescapeErrorKind,
+}
+
+/// Problem with a reference, see [`UnescapeError`]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum UnescapeErrorKind {
+    /// The entity isn't predefined nor in the table
+    UnknownEntity,
+    /// The number isn't a character allowed in XML
+    InvalidCharacter,
+    /// A `&` that isn't followed by a name and `;`
+    Unterminated,
+    /// The entity's value refers to the entity itself
+    Recursive,
+    /// The entity expands to too much text, or to too many nested entities
+    TooBig,
+}
+
+impl Display for UnescapeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let problem = match self.kind {
+            UnescapeErrorKind::UnknownEntity => "unknown entity",
+            UnescapeErrorKind::InvalidCharacter => "invalid character reference",
+            UnescapeErrorKind::Unterminated => "unterminated reference",
+            UnescapeErrorKind::Recursive => "recursive entity",
+            UnescapeErrorKind::TooBig => "entity expansion is too big",
+        };
+        write!(f, "{problem} {:?} at byte {}", self.reference, self.offset)
+    }
+}
+
+impl Error for UnescapeError {}
+
 #[cfg(test)]
 mod tests {
-    use super::{escape_str_attribute, escape_str_pcdata};
+    use super::{escape_str_attribute, escape_str_pcdata, unescape, UnescapeErrorKind};
+    use crate::reader::EntityTable;
 
     #[test]
     fn test_escape_str_attribute() {
@@ -159,4 +387,46 @@ mod tests {
         assert_eq!(escape_str_attribute("☃<"), "☃&lt;");
         assert_eq!(escape_str_pcdata("☃<"), "☃&lt;");
     }
+
+    #[test]
+    fn test_unescape() {
+        let mut entities = EntityTable::new();
+        entities.insert("a", "&b;&#65;");
+        entities.insert("b", "<b>");
+        entities.insert("loop", "x&loop;");
+        entities.insert("bad", "&#0;");
+
+        assert!(matches!(unescape("no_references", &entities).unwrap(), std::borrow::Cow::Borrowed(_)));
+        assert_eq!(unescape(&escape_str_attribute("<>'\"&\n\r"), &entities).unwrap(), "<>'\"&\n\r");
+        assert_eq!(unescape("[&a;&#x263A;&#9731;]", &entities).unwrap(), "[<b>A☺☃]");
+
+        let error = |s| {
+            let e = unescape(s, &entities).unwrap_err();
+            (e.offset, e.reference, e.kind)
+        };
+        assert_eq!(error("☃ &"), (4, "&".into(), UnescapeErrorKind::Unterminated));
+        assert_eq!(error("&; "), (0, "&".into(), UnescapeErrorKind::Unterminated));
+        assert_eq!(error("x & y;"), (2, "&".into(), UnescapeErrorKind::Unterminated));
+        assert_eq!(error("&ab;"), (0, "&ab;".into(), UnescapeErrorKind::UnknownEntity));
+        assert_eq!(error("&#xD800;"), (0, "&#xD800;".into(), UnescapeErrorKind::InvalidCharacter));
+        assert_eq!(error("&#x;"), (0, "&#x;".into(), UnescapeErrorKind::InvalidCharacter));
+        assert_eq!(error("1&loop;"), (1, "&loop;".into(), UnescapeErrorKind::Recursive));
+        assert_eq!(error("&a;&bad;"), (3, "&bad;".into(), UnescapeErrorKind::InvalidCharacter));
+
+        let mut laughs = EntityTable::new();
+        laughs.insert("l0", "ha");
+        for i in 1..10 {
+            laughs.insert(format!("l{i}"), format!("&l{};", i - 1).repeat(10));
+        }
+        assert_eq!(unescape("&l5;", &laughs).unwrap().len(), 200_000);
+        assert_eq!(unescape("&l9;", &laughs).unwrap_err().kind, UnescapeErrorKind::TooBig);
+
+        let mut nested = EntityTable::new();
+        nested.insert("n0", "x");
+        for i in 1..12 {
+            nested.insert(format!("n{i}"), format!("&n{};", i - 1));
+        }
+        assert_eq!(unescape("&n9;", &nested).unwrap(), "x");
+        assert_eq!(unescape("&n10;", &nested).unwrap_err().kind, UnescapeErrorKind::TooBig);
+    }
 }
@@ -0,0 +1,191 @@
+//! Contains composable transformations of XML event streams.
+//!
+//! An [`EventFilter`] gets events from an [`EventReader`] one by one, and decides which events
+//! to pass on. Filters can be chained, and [`copy`] writes the result to an [`EventWriter`].
+//!
+//! ```rust
+//! use xml::filter::{self, EventFilter, FilterOutput};
+//! use xml::reader::XmlEvent;
+//! use xml::{EmitterConfig, EventReader};
+//!
+//! let reader = EventReader::from_str("<a><!-- note --><b>text</b></a>");
+//! let no_comments = |e: XmlEvent| match e {
+//!     XmlEvent::Comment(_) => FilterOutput::Drop,
+//!     e => FilterOutput::Emit(e),
+//! };
+//! let upper = |e: XmlEvent| match e {
+//!     XmlEvent::Characters(text) => FilterOutput::Emit(XmlEvent::Characters(text.to_uppercase())),
+//!     e => FilterOutput::Emit(e),
+//! };
+//!
+//! let mut out = Vec::new();
+//! let mut writer = EmitterConfig::new().create_writer(&mut out);
+//! filter::copy(reader, &mut writer, no_comments.chain(upper)).unwrap();
+//! assert_eq!(String::from_utf8(out).unwrap(), r#"<?xml version="1.0" encoding="UTF-8"?><a><b>TEXT</b></a>"#);
+//! ```
+
+use std::io::{Read, Write};
+use std::{error, fmt, vec};
+
+use crate::reader::{self, EventReader, XmlEvent};
+use crate::writer::{self, EventWriter};
+
+pub use self::namespaces::NamespaceRewriter;
+pub use self::rules::{Action, RulesError, TransformRules};
+pub use self::sanitizer::Sanitizer;
+pub(crate) use self::rules::Pattern;
+
+mod namespaces;
+mod rules;
+mod sanitizer;
+
+/// What a filter does with an event
+#[derive(Clone, PartialEq, Debug)]
+#[allow(clippy::large_enum_variant)] // as large as `XmlEvent`, which is moved anyway
+pub enum FilterOutput {
+    /// Pass this event on. It may be the original event or a modified one.
+    Emit(XmlEvent),
+    /// Remove the event from the stream
+    Drop,
+    /// Pass on any number of events instead
+    Replace(Vec<XmlEvent>),
+}
+
+impl IntoIterator for FilterOutput {
+    type IntoIter = vec::IntoIter<XmlEvent>;
+    type Item = XmlEvent;
+
+    fn into_iter(self) -> Self::IntoIter {
+        match self {
+            FilterOutput::Emit(e) => vec![e].into_iter(),
+            FilterOutput::Drop => Vec::new().into_iter(),
+            FilterOutput::Replace(events) => events.into_iter(),
+        }
+    }
+}
+
+/// A step of a streaming transformation, e.g. a sanitizer or a namespace rewriter.
+///
+/// Closures taking an `XmlEvent` and returning [`FilterOutput`] are filters too.
+pub trait EventFilter {
+    /// Decides what to do with the next event of the stream.
+    fn transform(&mut self, event: XmlEvent) -> FilterOutput;
+
+    /// Creates a filter that passes the output of this filter through the `next` one.
+    fn chain<F: EventFilter>(self, next: F) -> Chain<Self, F> where Self: Sized {
+        Chain { first: self, second: next }
+    }
+
+    /// Applies this filter to an iterator of events, such as [`EventReader::into_iter`].
+    ///
+    /// Errors are passed through unchanged.
+    fn filter_events<I, E>(self, events: I) -> FilteredEvents<I::IntoIter, Self>
+    where I: IntoIterator<Item = Result<XmlEvent, E>>, Self: Sized {
+        FilteredEvents { events: events.into_iter(), filter: self, pending: Vec::new().into_iter() }
+    }
+}
+
+impl<F: FnMut(XmlEvent) -> FilterOutput> EventFilter for F {
+    #[inline]
+    fn transform(&mut self, event: XmlEvent) -> FilterOutput {
+        self(event)
+    }
+}
+
+/// Two filters applied one after another, see [`EventFilter::chain`]
+#[derive(Clone, Debug)]
+pub struct Chain<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A: EventFilter, B: EventFilter> EventFilter for Chain<A, B> {
+    fn transform(&mut self, event: XmlEvent) -> FilterOutput {
+        match self.first.transform(event) {
+            FilterOutput::Emit(e) => self.second.transform(e),
+            FilterOutput::Drop => FilterOutput::Drop,
+            FilterOutput::Replace(events) => FilterOutput::Replace(
+                events.into_iter().flat_map(|e| self.second.transform(e)).collect()
+            ),
+        }
+    }
+}
+
+/// An iterator of filtered events, see [`EventFilter::filter_events`]
+pub struct FilteredEvents<I, F> {
+    events: I,
+    filter: F,
+    pending: vec::IntoIter<XmlEvent>,
+}
+
+impl<I, E, F> Iterator for FilteredEvents<I, F> where I: Iterator<Item = Result<XmlEvent, E>>, F: EventFilter {
+    type Item = Result<XmlEvent, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(e) = self.pending.next() {
+                return Some(Ok(e));
+            }
+            match self.events.next()? {
+                Ok(e) => self.pending = self.filter.transform(e).into_iter(),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Reads all events from the reader, and writes the ones that pass the filter to the writer.
+///
+/// `EndDocument` goes through the filter too, so filters can emit events at the end,
+/// but it isn't written.
+pub fn copy<R: Read, W: Write>(reader: EventReader<R>, writer: &mut EventWriter<W>, filter: impl EventFilter) -> Result<(), Error> {
+    for event in filter.filter_events(reader) {
+        let event = event.map_err(Error::Reader)?;
+        if let Some(e) = event.as_writer_event() {
+            writer.write(e).map_err(Error::Writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Failure of [`copy`]
+#[derive(Debug)]
+pub enum Error {
+    /// The source document couldn't be parsed
+    Reader(reader::Error),
+    /// The filtered events couldn't be written
+    Writer(writer::Error),
+}
+
+impl fmt::Display for Error {
+    #[cold]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Reader(e) => e.fmt(f),
+            Error::Writer(e) => e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Reader(e) => Some(e),
+            Error::Writer(e) => Some(e),
+        }
+    }
+}
+
+impl From<reader::Error> for Error {
+    #[cold]
+    fn from(e: reader::Error) -> Self {
+        Error::Reader(e)
+    }
+}
+
+impl From<writer::Error> for Error {
+    #[cold]
+    fn from(e: writer::Error) -> Self {
+        Error::Writer(e)
+    }
+}
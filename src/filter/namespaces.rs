@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use crate::name::OwnedName;
+use crate::namespace::{Namespace, NS_EMPTY_URI, NS_XMLNS_PREFIX, NS_XML_PREFIX};
+use crate::reader::XmlEvent;
+
+use super::{EventFilter, FilterOutput};
+
+/// A filter that changes namespace URIs and prefixes throughout the document.
+///
+/// Names of elements and attributes, and namespace declarations are all updated.
+/// If a new prefix would clash with another prefix in scope, a number is appended to it.
+/// Values of attributes listed with [`qname_attribute`](Self::qname_attribute), such as `xsi:type`,
+/// are rewritten to use the new prefixes too.
+///
+/// ```rust
+/// use xml::filter::{self, NamespaceRewriter};
+/// use xml::{EmitterConfig, EventReader};
+///
+/// let reader = EventReader::from_str(r#"<v1:doc xmlns:v1="urn:schema:1"><v1:item/></v1:doc>"#);
+/// let rewriter = NamespaceRewriter::new()
+///     .rename_namespace("urn:schema:1", "urn:schema:2")
+///     .rename_prefix("v1", "v2");
+///
+/// let mut out = Vec::new();
+/// let mut writer = EmitterConfig::new().write_document_declaration(false).create_writer(&mut out);
+/// filter::copy(reader, &mut writer, rewriter).unwrap();
+/// assert!(String::from_utf8(out).unwrap().ends_with(r#"<v2:doc xmlns:v2="urn:schema:2"><v2:item /></v2:doc>"#));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct NamespaceRewriter {
+    uris: HashMap<String, String>,
+    prefixes: HashMap<String, String>,
+    qname_attributes: Vec<OwnedName>,
+    /// Renamed names of the open elements, for their end tags
+    elements: Vec<OwnedName>,
+}
+
+impl NamespaceRewriter {
+    /// Creates a filter that doesn't change anything yet
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the namespace URI `from` with `to`
+    #[must_use]
+    pub fn rename_namespace(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.uris.insert(from.into(), to.into());
+        self
+    }
+
+    /// Uses the prefix `to` instead of `from`. An empty string is the default namespace.
+    ///
+    /// The reserved `xml` and `xmlns` prefixes can't be renamed.
+    #[must_use]
+    pub fn rename_prefix(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.prefixes.insert(from.into(), to.into());
+        self
+    }
+
+    /// Treats values of attributes with this name (and namespace, if any) as `prefix:name`,
+    /// and updates their prefixes, e.g. `{http://www.w3.org/2001/XMLSchema-instance}type`.
+    #[must_use]
+    pub fn qname_attribute(mut self, name: OwnedName) -> Self {
+        self.qname_attributes.push(name);
+        self
+    }
+
+    fn map_uri<'a>(&'a self, uri: &'a str) -> &'a str {
+        self.uris.get(uri).map_or(uri, |s| s.as_str())
+    }
+
+    /// Returns the rewritten namespace, and new prefixes for the old ones in scope
+    fn rewrite_namespace(&self, namespace: &Namespace) -> (Namespace, HashMap<String, String>) {
+        let mut new_namespace = Namespace::empty();
+        let mut new_prefixes = HashMap::new();
+
+        let is_renamed = |prefix: &str, uri: &str| {
+            // undeclared default namespace has nothing to rename
+            !matches!(prefix, NS_XML_PREFIX | NS_XMLNS_PREFIX) && uri != NS_EMPTY_URI && self.prefixes.contains_key(prefix)
+        };
+        // prefixes that stay the same have priority, so that only renamed ones get numbered
+        let (kept, renamed): (Vec<_>, Vec<_>) = namespace.iter().partition(|&(prefix, uri)| !is_renamed(prefix, uri));
+        for (prefix, uri) in kept.into_iter().chain(renamed) {
+            let wanted = if is_renamed(prefix, uri) { &self.prefixes[prefix] } else { prefix };
+            let uri = self.map_uri(uri);
+            let mut new_prefix = wanted.to_owned();
+            let mut n = 1;
+            while new_namespace.get(&new_prefix).map_or(false, |bound| bound != uri) {
+                n += 1;
+                new_prefix = format!("{}{n}", if wanted.is_empty() { "ns" } else { wanted });
+            }
+            new_namespace.put(new_prefix.as_str(), uri);
+            new_prefixes.insert(prefix.to_owned(), new_prefix);
+        }
+        (new_namespace, new_prefixes)
+    }
+
+    fn rewrite_name(&self, name: &mut OwnedName, new_prefixes: &HashMap<String, String>) {
+        if let Some(namespace) = &mut name.namespace {
+            *namespace = self.map_uri(namespace).into();
+            if let Some(new_prefix) = new_prefixes.get(name.prefix.as_deref().unwrap_or("")) {
+                name.prefix = Some(new_prefix.as_str()).filter(|p| !p.is_empty()).map(From::from);
+            }
+        }
+    }
+}
+
+/// Updates prefix of `prefix:local`
+fn rewrite_qname(value: &str, new_prefixes: &HashMap<String, String>) -> Option<String> {
+    let value = value.trim();
+    let (prefix, local_name) = value.split_once(':').unwrap_or(("", value));
+    let new_prefix = new_prefixes.get(prefix).filter(|&p| p != prefix)?;
+    Some(if new_prefix.is_empty() { local_name.to_owned() } else { format!("{new_prefix}:{local_name}") })
+}
+
+impl EventFilter for NamespaceRewriter {
+    fn transform(&mut self, event: XmlEvent) -> FilterOutput {
+        match event {
+            XmlEvent::StartElement { mut name, mut attributes, namespace } => {
+                let (namespace, new_prefixes) = self.rewrite_namespace(&namespace);
+                for attr in &mut attributes {
+                    // compares the names before they're renamed
+                    let is_qname = self.qname_attributes.iter()
+                        .any(|n| n.local_name == attr.name.local_name && n.namespace == attr.name.namespace);
+                    if is_qname {
+                        if let Some(value) = rewrite_qname(&attr.value, &new_prefixes) {
+                            attr.value = value;
+                        }
+                    }
+                    self.rewrite_name(&mut attr.name, &new_prefixes);
+                }
+                self.rewrite_name(&mut name, &new_prefixes);
+                self.elements.push(name.clone());
+                FilterOutput::Emit(XmlEvent::StartElement { name, attributes, namespace })
+            },
+            XmlEvent::EndElement { name } => {
+                FilterOutput::Emit(XmlEvent::EndElement { name: self.elements.pop().unwrap_or(name) })
+            },
+            event => FilterOutput::Emit(event),
+        }
+    }
+}
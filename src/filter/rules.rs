@@ -0,0 +1,237 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::attribute::{Attributes, OwnedAttribute};
+use crate::name::{OwnedName, SharedStr};
+use crate::namespace::Namespace;
+use crate::reader::XmlEvent;
+
+use super::{EventFilter, FilterOutput};
+
+/// What to do with an element matched by a [`TransformRules`] pattern
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Action {
+    /// Change the local name of the element
+    Rename(String),
+    /// Put the element inside a new element with this local name
+    Wrap(String),
+    /// Remove the element with all its content
+    Drop,
+    /// Remove the tags of the element, but keep its content
+    Unwrap,
+    /// Remove the attribute with this local name, and add its value as the first child element of the same name
+    AttributeToElement(String),
+    /// Add an attribute, or replace its value
+    SetAttribute(String, String),
+}
+
+/// A filter that applies simple declarative rules to a stream, e.g. for one-off document migrations.
+///
+/// Patterns are paths of local names separated by `/`, such as `book/price`, which match
+/// `price` elements inside `book`. A pattern starting with `/` is matched from the root element,
+/// and `*` matches any name. Elements are matched by their original names, and
+/// all rules matching an element are applied in order.
+///
+/// Rules can be parsed from text, one rule per line: a pattern, an action and its arguments.
+/// Actions are `rename NAME`, `wrap NAME`, `drop`, `unwrap`, `attribute-to-element NAME`
+/// and `set-attribute NAME VALUE`. Lines starting with `#` are comments.
+///
+/// ```rust
+/// use xml::filter::{self, TransformRules};
+/// use xml::{EmitterConfig, EventReader};
+///
+/// let rules: TransformRules = "
+///     /catalog  rename  books
+///     book  attribute-to-element  title
+///     book/draft  drop
+///     book  set-attribute  status done
+/// ".parse().unwrap();
+///
+/// let reader = EventReader::from_str(r#"<catalog><book title="Rust"><draft/></book></catalog>"#);
+/// let mut out = Vec::new();
+/// let mut writer = EmitterConfig::new().create_writer(&mut out);
+/// filter::copy(reader, &mut writer, rules).unwrap();
+/// assert!(String::from_utf8(out).unwrap().ends_with(r#"<books><book status="done"><title>Rust</title></book></books>"#));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TransformRules {
+    rules: Vec<(Pattern, Action)>,
+    /// Original names of the open elements
+    path: Vec<SharedStr>,
+    /// Events to emit at the end of each open element
+    ends: Vec<Vec<XmlEvent>>,
+    /// Nesting inside a dropped element
+    skip_depth: usize,
+}
+
+/// A path of local names, see [`TransformRules`] for the syntax
+#[derive(Clone, Debug)]
+pub(crate) struct Pattern {
+    absolute: bool,
+    names: Vec<String>,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Self {
+        let absolute = pattern.starts_with('/');
+        let names = pattern.split('/').filter(|s| !s.is_empty()).map(String::from).collect();
+        Self { absolute, names }
+    }
+
+    pub fn matches(&self, path: &[SharedStr]) -> bool {
+        if path.len() < self.names.len() || (self.absolute && path.len() != self.names.len()) {
+            return false;
+        }
+        path[path.len() - self.names.len()..].iter().zip(&self.names)
+            .all(|(name, pattern)| pattern == "*" || pattern == name)
+    }
+}
+
+impl TransformRules {
+    /// Creates a filter without any rules
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a rule for elements matching the pattern, see [`TransformRules`] for the syntax
+    #[must_use]
+    pub fn rule(mut self, pattern: &str, action: Action) -> Self {
+        self.rules.push((Pattern::new(pattern), action));
+        self
+    }
+
+    fn start_element(&mut self, mut name: OwnedName, mut attributes: Attributes, namespace: Namespace) -> FilterOutput {
+        self.path.push(name.local_name.clone());
+        let actions: Vec<_> = self.rules.iter()
+            .filter(|(pattern, _)| pattern.matches(&self.path))
+            .map(|(_, action)| action)
+            .collect();
+
+        let mut wrappers = Vec::new();
+        let mut children = Vec::new();
+        let mut unwrap = false;
+        for action in actions {
+            match action {
+                // whatever the other actions have changed is dropped with the element
+                Action::Drop => {
+                    self.path.pop();
+                    self.skip_depth = 1;
+                    return FilterOutput::Drop;
+                },
+                Action::Rename(new_name) => name.local_name = new_name.as_str().into(),
+                Action::Wrap(wrapper) => wrappers.push(wrapper),
+                Action::Unwrap => unwrap = true,
+                Action::AttributeToElement(attr_name) => {
+                    if let Some(i) = attributes.iter().position(|a| &a.name.local_name == attr_name) {
+                        children.push(attributes.remove(i));
+                    }
+                },
+                Action::SetAttribute(attr_name, value) => {
+                    match attributes.iter_mut().find(|a| a.name.prefix.is_none() && &a.name.local_name == attr_name) {
+                        Some(attr) => attr.value.clone_from(value),
+                        None => attributes.push(OwnedAttribute::new(OwnedName::local(attr_name.as_str()), value.as_str())),
+                    }
+                },
+            }
+        }
+
+        // new elements are in the same namespace as the matched one
+        let named = |local_name: &str| OwnedName { local_name: local_name.into(), ..name.clone() };
+        let mut events = Vec::new();
+        let mut ends = Vec::new();
+        for wrapper in wrappers {
+            events.push(XmlEvent::StartElement { name: named(wrapper), attributes: Attributes::new(), namespace: namespace.clone() });
+            ends.push(XmlEvent::EndElement { name: named(wrapper) });
+        }
+        if !unwrap {
+            ends.push(XmlEvent::EndElement { name: name.clone() });
+        }
+        ends.reverse();
+        let child_events = children.into_iter().flat_map(|attr| [
+            XmlEvent::StartElement { name: named(&attr.name.local_name), attributes: Attributes::new(), namespace: namespace.clone() },
+            XmlEvent::Characters(attr.value),
+            XmlEvent::EndElement { name: named(&attr.name.local_name) },
+        ]).collect::<Vec<_>>();
+        if !unwrap {
+            events.push(XmlEvent::StartElement { name, attributes, namespace });
+        }
+        events.extend(child_events);
+        self.ends.push(ends);
+        FilterOutput::Replace(events)
+    }
+}
+
+impl EventFilter for TransformRules {
+    fn transform(&mut self, event: XmlEvent) -> FilterOutput {
+        if self.skip_depth > 0 {
+            match event {
+                XmlEvent::StartElement { .. } => self.skip_depth += 1,
+                XmlEvent::EndElement { .. } => self.skip_depth -= 1,
+                _ => {},
+            }
+            return FilterOutput::Drop;
+        }
+        match event {
+            XmlEvent::StartElement { name, attributes, namespace } => self.start_element(name, attributes, namespace),
+            XmlEvent::EndElement { .. } => {
+                self.path.pop();
+                match self.ends.pop() {
+                    Some(ends) => FilterOutput::Replace(ends),
+                    None => FilterOutput::Emit(event),
+                }
+            },
+            event => FilterOutput::Emit(event),
+        }
+    }
+}
+
+impl FromStr for TransformRules {
+    type Err = RulesError;
+
+    fn from_str(s: &str) -> Result<Self, RulesError> {
+        let mut rules = TransformRules::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message| RulesError { line: i + 1, message };
+            let mut parts = line.split_whitespace();
+            let (pattern, action) = match (parts.next(), parts.next()) {
+                (Some(pattern), Some(action)) => (pattern, action),
+                _ => return Err(error("expected a pattern and an action")),
+            };
+            let args = line[pattern.len()..].trim_start()[action.len()..].trim();
+            let action = match (action, args.split_once(char::is_whitespace)) {
+                ("drop", _) if args.is_empty() => Action::Drop,
+                ("unwrap", _) if args.is_empty() => Action::Unwrap,
+                ("rename", None) if !args.is_empty() => Action::Rename(args.into()),
+                ("wrap", None) if !args.is_empty() => Action::Wrap(args.into()),
+                ("attribute-to-element", None) if !args.is_empty() => Action::AttributeToElement(args.into()),
+                ("set-attribute", Some((name, value))) => Action::SetAttribute(name.into(), value.trim_start().into()),
+                ("drop" | "unwrap" | "rename" | "wrap" | "attribute-to-element" | "set-attribute", _) => return Err(error("wrong number of arguments")),
+                _ => return Err(error("unknown action")),
+            };
+            rules = rules.rule(pattern, action);
+        }
+        Ok(rules)
+    }
+}
+
+/// Invalid line in the text of [`TransformRules`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RulesError {
+    /// Line number, counting from 1
+    pub line: usize,
+    message: &'static str,
+}
+
+impl fmt::Display for RulesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for RulesError {}
@@ -0,0 +1,142 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::attribute::OwnedAttribute;
+use crate::reader::XmlEvent;
+
+use super::{EventFilter, FilterOutput};
+
+/// A filter that keeps only allowed elements and attributes, e.g. to make untrusted SVG or XHTML safe to display.
+///
+/// Elements and attributes are matched by their local names. Elements that aren't allowed are removed
+/// together with all their content. URLs in attributes like `href` must be relative or use an allowed scheme.
+/// Comments, processing instructions and the DOCTYPE are removed too.
+///
+/// ```rust
+/// use xml::filter::{self, Sanitizer};
+/// use xml::{EmitterConfig, EventReader};
+///
+/// let reader = EventReader::from_str(r#"<svg onload="x()"><script>x()</script><a href="javascript:x()"><rect width="1"/></a></svg>"#);
+/// let sanitizer = Sanitizer::new()
+///     .allow_element("svg", [])
+///     .allow_element("a", ["href"])
+///     .allow_element("rect", ["width", "height"]);
+///
+/// let mut out = Vec::new();
+/// let mut writer = EmitterConfig::new().create_writer(&mut out);
+/// filter::copy(reader, &mut writer, sanitizer).unwrap();
+/// assert!(String::from_utf8(out).unwrap().ends_with(r#"<svg><a><rect width="1" /></a></svg>"#));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Sanitizer {
+    /// Allowed attributes by element name
+    elements: HashMap<String, HashSet<String>>,
+    global_attributes: HashSet<String>,
+    url_attributes: HashSet<String>,
+    url_schemes: HashSet<String>,
+    keep_comments: bool,
+    /// Nesting inside a removed element
+    skip_depth: usize,
+}
+
+impl Default for Sanitizer {
+    fn default() -> Self {
+        Self {
+            elements: HashMap::new(),
+            global_attributes: HashSet::new(),
+            url_attributes: ["href", "src", "action", "formaction", "poster", "background"].iter().map(|&s| s.into()).collect(),
+            url_schemes: ["http", "https", "mailto"].iter().map(|&s| s.into()).collect(),
+            keep_comments: false,
+            skip_depth: 0,
+        }
+    }
+}
+
+impl Sanitizer {
+    /// Creates a sanitizer that doesn't allow any elements yet.
+    ///
+    /// `http`, `https` and `mailto` URLs are allowed by default.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows the element with the given local name, and these attributes on it
+    #[must_use]
+    pub fn allow_element<'a>(mut self, name: impl Into<String>, attributes: impl IntoIterator<Item = &'a str>) -> Self {
+        self.elements.entry(name.into()).or_default().extend(attributes.into_iter().map(String::from));
+        self
+    }
+
+    /// Allows the attribute on all allowed elements, e.g. `class`
+    #[must_use]
+    pub fn allow_global_attribute(mut self, name: impl Into<String>) -> Self {
+        self.global_attributes.insert(name.into());
+        self
+    }
+
+    /// Checks the URL scheme in values of this attribute. `href`, `src` and similar are checked by default.
+    #[must_use]
+    pub fn url_attribute(mut self, name: impl Into<String>) -> Self {
+        self.url_attributes.insert(name.into());
+        self
+    }
+
+    /// Allows absolute URLs with this scheme, e.g. `data`. Relative URLs are always allowed.
+    #[must_use]
+    pub fn allow_url_scheme(mut self, scheme: impl Into<String>) -> Self {
+        self.url_schemes.insert(scheme.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Keeps comments inside allowed elements
+    #[must_use]
+    pub fn keep_comments(mut self, keep: bool) -> Self {
+        self.keep_comments = keep;
+        self
+    }
+
+    fn is_allowed_attribute(&self, allowed: &HashSet<String>, attr: &OwnedAttribute) -> bool {
+        let name = attr.name.local_name.as_str();
+        if !allowed.contains(name) && !self.global_attributes.contains(name) {
+            return false;
+        }
+        !self.url_attributes.contains(name) || self.is_allowed_url(&attr.value)
+    }
+
+    fn is_allowed_url(&self, url: &str) -> bool {
+        // browsers ignore whitespace and control characters in schemes, like `java\tscript:`
+        let url: String = url.chars().filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control()).collect();
+        let scheme = match url.find([':', '/', '?', '#']) {
+            Some(end) if url[end..].starts_with(':') => &url[..end],
+            _ => return true, // relative
+        };
+        self.url_schemes.contains(&scheme.to_ascii_lowercase())
+    }
+}
+
+impl EventFilter for Sanitizer {
+    fn transform(&mut self, event: XmlEvent) -> FilterOutput {
+        if self.skip_depth > 0 {
+            match event {
+                XmlEvent::StartElement { .. } => self.skip_depth += 1,
+                XmlEvent::EndElement { .. } => self.skip_depth -= 1,
+                _ => {},
+            }
+            return FilterOutput::Drop;
+        }
+        match event {
+            XmlEvent::StartElement { name, mut attributes, namespace } => {
+                if let Some(allowed) = self.elements.get(name.local_name.as_str()) {
+                    attributes.retain(|attr| self.is_allowed_attribute(allowed, attr));
+                    FilterOutput::Emit(XmlEvent::StartElement { name, attributes, namespace })
+                } else {
+                    self.skip_depth = 1;
+                    FilterOutput::Drop
+                }
+            },
+            XmlEvent::Comment(_) if self.keep_comments => FilterOutput::Emit(event),
+            XmlEvent::Comment(_) | XmlEvent::ProcessingInstruction { .. } | XmlEvent::Doctype { .. } => FilterOutput::Drop,
+            event => FilterOutput::Emit(event),
+        }
+    }
+}
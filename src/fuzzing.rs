@@ -0,0 +1,362 @@
+//! Structured document generator for round-trip fuzzing of the writer and the reader.
+//!
+//! [`XmlDocumentModel`] implements [`Arbitrary`], and only generates documents that the writer
+//! can represent, so for every model `XmlDocumentModel::parse(&model.to_xml())` is expected
+//! to be equal to `model.normalized()`.
+//!
+//! ```rust,ignore
+//! fuzz_target!(|model: xml::fuzzing::XmlDocumentModel| {
+//!     let parsed = xml::fuzzing::XmlDocumentModel::parse(&model.to_xml()).unwrap();
+//!     assert_eq!(parsed, model.normalized());
+//! });
+//! ```
+
+use std::io::Write;
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::common::{is_xml10_char, XmlVersion};
+use crate::reader::{self, ParserConfig};
+use crate::writer::{self, EmitterConfig, EventWriter};
+
+const MAX_DEPTH: usize = 8;
+const MAX_CHILDREN: usize = 6;
+
+/// A complete document
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct XmlDocumentModel {
+    /// `standalone` in the XML declaration
+    pub standalone: Option<bool>,
+    /// Comments and processing instructions before the root element
+    pub prolog: Vec<MiscModel>,
+    /// The root element
+    pub root: ElementModel,
+    /// Comments and processing instructions after the root element
+    pub epilog: Vec<MiscModel>,
+}
+
+/// An element with its content
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ElementModel {
+    /// Element name, without a namespace prefix
+    pub name: String,
+    /// Attribute names and values, names are unique
+    pub attributes: Vec<(String, String)>,
+    /// Content of the element
+    pub children: Vec<NodeModel>,
+}
+
+/// Content of an element
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum NodeModel {
+    /// A child element
+    Element(ElementModel),
+    /// Character data, escaped by the writer
+    Text(String),
+    /// A CDATA section
+    CData(String),
+    /// Comment or processing instruction
+    Misc(MiscModel),
+}
+
+/// Markup allowed outside of the root element
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MiscModel {
+    /// A comment
+    Comment(String),
+    /// A processing instruction
+    ProcessingInstruction {
+        /// PI target
+        target: String,
+        /// PI content
+        data: Option<String>,
+    },
+}
+
+impl<'a> Arbitrary<'a> for XmlDocumentModel {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            standalone: u.arbitrary()?,
+            prolog: misc_list(u)?,
+            root: element(u, 0)?,
+            epilog: misc_list(u)?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for ElementModel {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        element(u, 0)
+    }
+}
+
+impl<'a> Arbitrary<'a> for MiscModel {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(if u.arbitrary()? {
+            MiscModel::Comment(comment_text(u)?)
+        } else {
+            let data = pi_data(u)?;
+            MiscModel::ProcessingInstruction {
+                target: name(u)?,
+                data: if data.is_empty() { None } else { Some(data) },
+            }
+        })
+    }
+}
+
+fn misc_list(u: &mut Unstructured<'_>) -> Result<Vec<MiscModel>> {
+    let len = u.int_in_range(0..=2)?;
+    (0..len).map(|_| MiscModel::arbitrary(u)).collect()
+}
+
+fn element(u: &mut Unstructured<'_>, depth: usize) -> Result<ElementModel> {
+    let name = name(u)?;
+
+    let mut attributes: Vec<(String, String)> = Vec::new();
+    for _ in 0..u.int_in_range(0..=3)? {
+        let attr_name = self::name(u)?;
+        let value = text(u)?;
+        if attributes.iter().all(|(n, _)| *n != attr_name) {
+            attributes.push((attr_name, value));
+        }
+    }
+
+    let mut children = Vec::new();
+    let max_children = if depth < MAX_DEPTH { MAX_CHILDREN } else { 0 };
+    for _ in 0..u.int_in_range(0..=max_children)? {
+        children.push(match u.int_in_range(0..=3)? {
+            0 => NodeModel::Element(element(u, depth + 1)?),
+            1 => NodeModel::Text(text(u)?),
+            2 => NodeModel::CData(cdata_text(u)?),
+            _ => NodeModel::Misc(MiscModel::arbitrary(u)?),
+        });
+    }
+
+    Ok(ElementModel { name, attributes, children })
+}
+
+/// Valid name that doesn't need namespaces and can't start with the reserved `xml`
+fn name(u: &mut Unstructured<'_>) -> Result<String> {
+    const START: &[char] = &['a', 'b', 'x', 'Z', '_', 'é', 'Ж', '中'];
+    const REST: &[char] = &['a', 'b', 'x', 'Z', '_', 'é', 'Ж', '中', '-', '.', '0', '9', '·'];
+
+    let mut name = String::new();
+    name.push(*u.choose(START)?);
+    for _ in 0..u.int_in_range(0..=6)? {
+        name.push(*u.choose(REST)?);
+    }
+    Ok(name)
+}
+
+/// Any XML 1.0 characters, biased towards ones that need escaping or normalization
+fn text(u: &mut Unstructured<'_>) -> Result<String> {
+    const SPECIAL: &[char] = &['<', '>', '&', '"', '\'', ']', ' ', '\t', '\n', '\r', '-', '?'];
+
+    let mut text = String::new();
+    for _ in 0..u.int_in_range(0..=12)? {
+        let c = if u.arbitrary()? { *u.choose(SPECIAL)? } else { u.arbitrary()? };
+        if is_xml10_char(c) {
+            text.push(c);
+        }
+    }
+    Ok(text)
+}
+
+/// CDATA can't escape anything, and the parser normalizes line endings
+fn cdata_text(u: &mut Unstructured<'_>) -> Result<String> {
+    let mut text = text(u)?.replace('\r', "");
+    while text.contains("]]>") {
+        text = text.replace("]]>", "]]");
+    }
+    Ok(text)
+}
+
+fn comment_text(u: &mut Unstructured<'_>) -> Result<String> {
+    let mut text = text(u)?.replace('\r', "");
+    while text.contains("--") {
+        text = text.replace("--", "-");
+    }
+    while text.ends_with('-') {
+        text.pop();
+    }
+    Ok(text)
+}
+
+/// The parser skips whitespace between the target and the data
+fn pi_data(u: &mut Unstructured<'_>) -> Result<String> {
+    let mut text = text(u)?.replace('\r', "");
+    while text.contains("?>") {
+        text = text.replace("?>", "?");
+    }
+    Ok(text.trim_start_matches([' ', '\t', '\n']).to_owned())
+}
+
+impl XmlDocumentModel {
+    /// Writes the whole document, including the XML declaration
+    pub fn write<W: Write>(&self, w: &mut EventWriter<W>) -> writer::Result<()> {
+        w.write(writer::XmlEvent::StartDocument {
+            version: XmlVersion::Version10,
+            encoding: None,
+            standalone: self.standalone,
+        })?;
+        for misc in &self.prolog {
+            misc.write(w)?;
+        }
+        self.root.write(w)?;
+        for misc in &self.epilog {
+            misc.write(w)?;
+        }
+        Ok(())
+    }
+
+    /// Serializes the document with a writer configuration that doesn't change the content
+    #[must_use]
+    pub fn to_xml(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut w = EmitterConfig::new()
+            .autopad_comments(false)
+            .create_writer(&mut out);
+        self.write(&mut w).expect("writing to Vec");
+        out
+    }
+
+    /// Parses a document into a model, keeping comments and CDATA sections
+    pub fn parse(xml: &[u8]) -> reader::Result<Self> {
+        use crate::reader::XmlEvent;
+
+        let reader = ParserConfig::new()
+            .ignore_comments(false)
+            .whitespace_to_characters(true)
+            .coalesce_characters(true)
+            .create_reader(xml);
+
+        let mut standalone = None;
+        let mut prolog = Vec::new();
+        let mut epilog = Vec::new();
+        let mut root = None;
+        let mut stack: Vec<ElementModel> = Vec::new();
+
+        for e in reader {
+            let node = match e? {
+                XmlEvent::StartDocument { standalone: s, .. } => { standalone = s; continue; },
+                XmlEvent::EndDocument => break,
+                XmlEvent::Doctype { .. } => continue,
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    stack.push(ElementModel {
+                        name: name.local_name.into(),
+                        attributes: attributes.into_iter().map(|a| (a.name.local_name.into(), a.value)).collect(),
+                        children: Vec::new(),
+                    });
+                    continue;
+                },
+                XmlEvent::EndElement { .. } => {
+                    let el = stack.pop().expect("balanced");
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(NodeModel::Element(el)),
+                        None => root = Some(el),
+                    }
+                    continue;
+                },
+                XmlEvent::Characters(s) | XmlEvent::Whitespace(s) => NodeModel::Text(s),
+                XmlEvent::CData(s) => NodeModel::CData(s),
+                XmlEvent::Comment(s) => NodeModel::Misc(MiscModel::Comment(s)),
+                XmlEvent::ProcessingInstruction { name, data } => {
+                    NodeModel::Misc(MiscModel::ProcessingInstruction { target: name, data })
+                },
+            };
+            match (stack.last_mut(), node) {
+                (Some(parent), node) => parent.children.push(node),
+                (None, NodeModel::Misc(misc)) => if root.is_none() { prolog.push(misc) } else { epilog.push(misc) },
+                (None, _) => {}, // ignorable whitespace
+            }
+        }
+
+        Ok(Self {
+            standalone,
+            prolog,
+            root: root.expect("the reader requires a root element"),
+            epilog,
+        })
+    }
+
+    /// The model as the parser sees it: adjacent text merged, and empty text removed
+    #[must_use]
+    pub fn normalized(&self) -> Self {
+        Self {
+            root: self.root.normalized(),
+            ..self.clone()
+        }
+    }
+}
+
+impl ElementModel {
+    fn write<W: Write>(&self, w: &mut EventWriter<W>) -> writer::Result<()> {
+        let mut start = writer::XmlEvent::start_element(&*self.name);
+        for (name, value) in &self.attributes {
+            start = start.attr(&**name, value);
+        }
+        w.write(start)?;
+        for child in &self.children {
+            match child {
+                NodeModel::Element(el) => el.write(w)?,
+                NodeModel::Text(text) => w.write(writer::XmlEvent::characters(text))?,
+                NodeModel::CData(text) => w.write(writer::XmlEvent::cdata(text))?,
+                NodeModel::Misc(misc) => misc.write(w)?,
+            }
+        }
+        w.write(writer::XmlEvent::end_element())
+    }
+
+    fn normalized(&self) -> Self {
+        let mut children: Vec<NodeModel> = Vec::with_capacity(self.children.len());
+        for child in &self.children {
+            match child {
+                NodeModel::Text(text) if text.is_empty() => {},
+                NodeModel::Text(text) => {
+                    if let Some(NodeModel::Text(prev)) = children.last_mut() {
+                        prev.push_str(text);
+                    } else {
+                        children.push(NodeModel::Text(text.clone()));
+                    }
+                },
+                NodeModel::Element(el) => children.push(NodeModel::Element(el.normalized())),
+                other => children.push(other.clone()),
+            }
+        }
+        Self { children, ..self.clone() }
+    }
+}
+
+impl MiscModel {
+    fn write<W: Write>(&self, w: &mut EventWriter<W>) -> writer::Result<()> {
+        match self {
+            MiscModel::Comment(text) => w.write(writer::XmlEvent::comment(text)),
+            MiscModel::ProcessingInstruction { target, data } => {
+                w.write(writer::XmlEvent::processing_instruction(target, data.as_deref()))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::XmlDocumentModel;
+    use arbitrary::{Arbitrary, Unstructured};
+
+    #[test]
+    fn roundtrip() {
+        let mut seed = 0x1234_5678_u32;
+        let mut data = vec![0; 4096];
+        for _ in 0..1000 {
+            for b in &mut data {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                *b = (seed >> 16) as u8;
+            }
+            let model = XmlDocumentModel::arbitrary(&mut Unstructured::new(&data)).unwrap();
+            let xml = model.to_xml();
+            let parsed = XmlDocumentModel::parse(&xml)
+                .unwrap_or_else(|e| panic!("{e} in {}", String::from_utf8_lossy(&xml)));
+            assert_eq!(parsed, model.normalized(), "{}", String::from_utf8_lossy(&xml));
+        }
+    }
+}
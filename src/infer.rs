@@ -0,0 +1,755 @@
+//! Inference of a schema from sample documents, for feeds and exports that come without one.
+//!
+//! The schema describes every element by its name: its attributes, child elements with how many
+//! times they occur, and the type of its text, guessed from the values in the samples.
+//! It can be written as [XML Schema](https://www.w3.org/TR/xmlschema-1/) or [RELAX NG](https://relaxng.org),
+//! as a starting point to be edited by hand.
+//!
+//! Elements are told apart by their local names, and the namespace of the root element is used
+//! as the target namespace. Attributes with a namespace, like `xml:lang`, are left out.
+//!
+//! ```rust
+//! use xml::infer::{schema_from_samples, SimpleType};
+//! use xml::{EmitterConfig, EventReader};
+//!
+//! let samples = [
+//!     r#"<feed><entry id="1"><title>A</title><updated>2024-01-02</updated></entry></feed>"#,
+//!     r#"<feed><entry id="2" draft="true"><title>B</title></entry><entry id="3"><title>C</title></entry></feed>"#,
+//! ];
+//! let schema = schema_from_samples(samples.iter().map(|s| EventReader::from_str(s))).unwrap();
+//!
+//! let entry = schema.element("entry").unwrap();
+//! assert_eq!(entry.attributes[0].ty, SimpleType::Integer);
+//! assert!(entry.attributes[0].required && !entry.attributes[1].required);
+//! assert_eq!(entry.children[1].min_occurs, 0);
+//! assert_eq!(schema.element("updated").unwrap().text, Some(SimpleType::Date));
+//!
+//! let mut xsd = Vec::new();
+//! let mut writer = EmitterConfig::pretty().create_writer(&mut xsd);
+//! schema.to_xsd().write_to(&mut writer).unwrap();
+//! ```
+
+use std::io::Read;
+
+use crate::build::Element;
+use crate::common::TextPosition;
+use crate::reader::{self, EventReader, XmlEvent};
+use crate::value;
+
+/// Namespace of XML Schema
+pub const NS_XSD_URI: &str = "http://www.w3.org/2001/XMLSchema";
+/// Namespace of RELAX NG
+pub const NS_RELAX_NG_URI: &str = "http://relaxng.org/ns/structure/1.0";
+/// Datatype library of XML Schema types, used in RELAX NG
+const XSD_DATATYPES_URI: &str = "http://www.w3.org/2001/XMLSchema-datatypes";
+
+/// Reads all the samples, and infers a schema that they all conform to
+pub fn schema_from_samples<R: Read>(samples: impl IntoIterator<Item = EventReader<R>>) -> reader::Result<InferredSchema> {
+    let mut schema = InferredSchema::default();
+    for sample in samples {
+        schema.add_sample(sample)?;
+    }
+    Ok(schema)
+}
+
+/// Structure of the sample documents, see [`schema_from_samples`]
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct InferredSchema {
+    /// Namespace of the first root element, which is used as the target namespace
+    pub namespace: Option<String>,
+    /// Names of the root elements of the samples
+    pub roots: Vec<String>,
+    /// All elements, in the order they were first seen
+    pub elements: Vec<ElementDecl>,
+}
+
+/// An element of an [`InferredSchema`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct ElementDecl {
+    /// Local name
+    pub name: String,
+    /// Attributes, in the order they were first seen
+    pub attributes: Vec<AttributeDecl>,
+    /// Child elements. If `ordered` is true, they always come in this order.
+    pub children: Vec<ChildDecl>,
+    /// Children always come in the order of `children`, with every element's occurrences next to each other
+    pub ordered: bool,
+    /// Type of the text, if any instance has text other than whitespace.
+    /// Text mixed with child elements is always a string.
+    pub text: Option<SimpleType>,
+    /// Number of instances in the samples
+    pub count: usize,
+    /// Some instance has no text nor children, so the text type must allow an empty string
+    empty_instances: bool,
+    /// Number of instances whose children have been counted. Nested instances end before their ancestors.
+    ended: usize,
+}
+
+/// An attribute of an [`ElementDecl`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct AttributeDecl {
+    /// Local name
+    pub name: String,
+    /// The narrowest type of all the values
+    pub ty: SimpleType,
+    /// Every instance of the element has the attribute
+    pub required: bool,
+}
+
+/// A child element of an [`ElementDecl`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct ChildDecl {
+    /// Local name
+    pub name: String,
+    /// The least number of times it occurs in an instance of the parent
+    pub min_occurs: usize,
+    /// The most number of times it occurs in an instance of the parent
+    pub max_occurs: usize,
+}
+
+/// Type of a text or an attribute value, named after the XML Schema type
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[non_exhaustive]
+pub enum SimpleType {
+    /// `true` or `false`
+    Boolean,
+    /// Whole number, like `-12`
+    Integer,
+    /// Number with a fraction, like `1.5`
+    Decimal,
+    /// Date like `2024-01-31`
+    Date,
+    /// Date and time like `2024-01-31T12:00:00Z`
+    DateTime,
+    /// Anything else
+    String,
+}
+
+impl SimpleType {
+    /// The narrowest type of the value
+    #[must_use]
+    pub fn of(value: &str) -> Self {
+        if value == "true" || value == "false" {
+            Self::Boolean
+        } else if is_integer(value) {
+            Self::Integer
+        } else if is_decimal(value) {
+            Self::Decimal
+        } else if is_date(value) {
+            Self::Date
+        } else if value.is_ascii() && value.len() > 10 && is_date(&value[..10]) && is_time(&value[10..]) {
+            Self::DateTime
+        } else {
+            Self::String
+        }
+    }
+
+    /// The narrowest type of values of both types
+    #[must_use]
+    pub fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Self::Integer, Self::Decimal) | (Self::Decimal, Self::Integer) => Self::Decimal,
+            _ => Self::String,
+        }
+    }
+
+    /// Name of the type in XML Schema, without a prefix, like `integer`
+    #[must_use]
+    pub fn xsd_name(self) -> &'static str {
+        match self {
+            Self::Boolean => "boolean",
+            Self::Integer => "integer",
+            Self::Decimal => "decimal",
+            Self::Date => "date",
+            Self::DateTime => "dateTime",
+            Self::String => "string",
+        }
+    }
+}
+
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn is_integer(s: &str) -> bool {
+    is_digits(s.strip_prefix(['-', '+']).unwrap_or(s))
+}
+
+fn is_decimal(s: &str) -> bool {
+    match s.strip_prefix(['-', '+']).unwrap_or(s).split_once('.') {
+        Some((int, fract)) => (int.is_empty() || is_digits(int)) && (fract.is_empty() || is_digits(fract)) && int.len() + fract.len() > 0,
+        None => false,
+    }
+}
+
+fn is_date(s: &str) -> bool {
+    let b = s.as_bytes();
+    b.len() == 10 && b[4] == b'-' && b[7] == b'-' && is_digits(&s[..4]) && is_digits(&s[5..7]) && is_digits(&s[8..])
+}
+
+/// `Thh:mm:ss`, optional fraction of seconds, and optional time zone
+fn is_time(s: &str) -> bool {
+    let b = s.as_bytes();
+    if b.len() < 9 || b[0] != b'T' || b[3] != b':' || b[6] != b':' || !is_digits(&s[1..3]) || !is_digits(&s[4..6]) || !is_digits(&s[7..9]) {
+        return false;
+    }
+    let mut rest = &s[9..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let len = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if len == 0 {
+            return false;
+        }
+        rest = &fraction[len..];
+    }
+    match rest.strip_prefix(['+', '-']) {
+        Some(zone) => zone.len() == 5 && zone.as_bytes()[2] == b':' && is_digits(&zone[..2]) && is_digits(&zone[3..]),
+        None => rest.is_empty() || rest == "Z",
+    }
+}
+
+/// An element that hasn't ended yet
+struct Open {
+    decl: usize,
+    children: Vec<usize>,
+    text: String,
+}
+
+impl InferredSchema {
+    /// Reads the document, and changes the schema to allow it too
+    pub fn add_sample<R: Read>(&mut self, mut sample: EventReader<R>) -> reader::Result<()> {
+        let mut stack: Vec<Open> = Vec::new();
+        loop {
+            match sample.next()? {
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    if stack.is_empty() {
+                        if self.roots.is_empty() {
+                            self.namespace = name.namespace.as_deref().map(String::from);
+                        }
+                        if !self.roots.iter().any(|root| *root == name.local_name) {
+                            self.roots.push(name.local_name.as_str().to_owned());
+                        }
+                    }
+                    let index = self.decl_index(&name.local_name);
+                    if let Some(parent) = stack.last_mut() {
+                        parent.children.push(index);
+                    }
+                    let decl = &mut self.elements[index];
+                    decl.count += 1;
+                    let first = decl.count == 1;
+                    for attr in decl.attributes.iter_mut() {
+                        if !attributes.iter().any(|a| a.name.namespace.is_none() && a.name.local_name == attr.name) {
+                            attr.required = false;
+                        }
+                    }
+                    for a in attributes.iter().filter(|a| a.name.namespace.is_none()) {
+                        let ty = SimpleType::of(&a.value);
+                        match decl.attributes.iter_mut().find(|attr| attr.name == a.name.local_name) {
+                            Some(attr) => attr.ty = attr.ty.merge(ty),
+                            None => decl.attributes.push(AttributeDecl { name: a.name.local_name.as_str().to_owned(), ty, required: first }),
+                        }
+                    }
+                    stack.push(Open { decl: index, children: Vec::new(), text: String::new() });
+                },
+                XmlEvent::EndElement { .. } => {
+                    if let Some(open) = stack.pop() {
+                        self.end_instance(open);
+                    }
+                },
+                XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                    if let Some(open) = stack.last_mut() {
+                        open.text.push_str(&text);
+                    }
+                },
+                XmlEvent::EndDocument => return Ok(()),
+                _ => {},
+            }
+        }
+    }
+
+    /// Reads the element declarations of an XML Schema document, e.g. to generate code from it.
+    ///
+    /// Global elements are the roots. Named and anonymous complex types, sequences, choices, `all` groups,
+    /// simple content and extensions of complex types are supported, and simple types are narrowed
+    /// to the [`SimpleType`] of their base type. Other constructs, imports and includes are ignored.
+    /// Local elements are told apart only by their names, like in inferred schemas, so the first
+    /// declaration of a name wins. The `count` of elements is 0.
+    pub fn from_xsd<R: Read>(reader: EventReader<R>) -> reader::Result<Self> {
+        let root = value::to_value(reader)?;
+        let schema = match root.as_element() {
+            Some(schema) if schema.name == "schema" && schema.namespace.as_deref() == Some(NS_XSD_URI) => schema,
+            _ => return Err((&TextPosition::new(), "not an XML Schema document").into()),
+        };
+        let mut xsd = XsdReader {
+            schema,
+            out: Self { namespace: schema.attributes.get("targetNamespace").cloned(), ..Self::default() },
+        };
+        for element in xs_children(schema, "element") {
+            if let Some(name) = element.attributes.get("name") {
+                xsd.out.roots.push(name.clone());
+                xsd.element_decl(element);
+            }
+        }
+        Ok(xsd.out)
+    }
+
+    /// The element with the local name
+    #[must_use]
+    pub fn element(&self, name: &str) -> Option<&ElementDecl> {
+        self.elements.iter().find(|e| e.name == name)
+    }
+
+    fn decl_index(&mut self, name: &str) -> usize {
+        if let Some(index) = self.elements.iter().position(|e| e.name == name) {
+            return index;
+        }
+        self.elements.push(ElementDecl {
+            name: name.into(),
+            attributes: Vec::new(),
+            children: Vec::new(),
+            ordered: true,
+            text: None,
+            count: 0,
+            empty_instances: false,
+            ended: 0,
+        });
+        self.elements.len() - 1
+    }
+
+    fn end_instance(&mut self, open: Open) {
+        // runs of the same child element, in order
+        let mut runs: Vec<(&str, usize)> = Vec::new();
+        for &child in &open.children {
+            let name = self.elements[child].name.as_str();
+            match runs.last_mut() {
+                Some((last, n)) if *last == name => *n += 1,
+                _ => runs.push((name, 1)),
+            }
+        }
+        let runs: Vec<(String, usize)> = runs.into_iter().map(|(name, n)| (name.to_owned(), n)).collect();
+
+        let decl = &mut self.elements[open.decl];
+        decl.ended += 1;
+        let mut previous = None;
+        for (i, (name, _)) in runs.iter().enumerate() {
+            if runs[..i].iter().any(|(n, _)| n == name) {
+                decl.ordered = false;
+                continue;
+            }
+            let position = match decl.children.iter().position(|c| &c.name == name) {
+                Some(position) => position,
+                None => {
+                    let position = previous.map_or(0, |p| p + 1);
+                    let min_occurs = if decl.ended == 1 { usize::MAX } else { 0 };
+                    decl.children.insert(position, ChildDecl { name: name.clone(), min_occurs, max_occurs: 0 });
+                    position
+                },
+            };
+            // new children are inserted after the last one so far
+            match previous {
+                Some(p) if p >= position => decl.ordered = false,
+                _ => previous = Some(position),
+            }
+        }
+        for child in &mut decl.children {
+            let n = runs.iter().filter(|(name, _)| *name == child.name).map(|(_, n)| n).sum();
+            child.min_occurs = child.min_occurs.min(n);
+            child.max_occurs = child.max_occurs.max(n);
+        }
+
+        let text = open.text.trim();
+        if !text.is_empty() {
+            let ty = if open.children.is_empty() { SimpleType::of(text) } else { SimpleType::String };
+            decl.text = Some(decl.text.map_or(ty, |t| t.merge(ty)));
+        } else if open.children.is_empty() {
+            decl.empty_instances = true;
+        }
+        if decl.empty_instances && decl.text.is_some() {
+            decl.text = Some(SimpleType::String);
+        }
+    }
+
+    /// The schema as an XML Schema document, with a global declaration of every element
+    #[must_use]
+    pub fn to_xsd(&self) -> Element {
+        let mut schema = Element::new("xs:schema").ns("xs", NS_XSD_URI).attr("elementFormDefault", "qualified");
+        if let Some(namespace) = &self.namespace {
+            schema = schema.attr("targetNamespace", namespace.as_str()).default_ns(namespace.as_str());
+        }
+        schema.children(self.elements.iter().map(ElementDecl::to_xsd))
+    }
+
+    /// The schema as a RELAX NG grammar in the XML syntax, with a definition of every element
+    #[must_use]
+    pub fn to_relax_ng(&self) -> Element {
+        let mut grammar = Element::new("grammar").default_ns(NS_RELAX_NG_URI).attr("datatypeLibrary", XSD_DATATYPES_URI);
+        if let Some(namespace) = &self.namespace {
+            grammar = grammar.attr("ns", namespace.as_str());
+        }
+        let roots = self.roots.iter().map(|name| Element::new("ref").attr("name", name.as_str()));
+        let start = if self.roots.len() == 1 {
+            Element::new("start").children(roots)
+        } else {
+            Element::new("start").child(Element::new("choice").children(roots))
+        };
+        grammar.child(start).children(self.elements.iter().map(|e| {
+            Element::new("define").attr("name", e.name.as_str()).child(e.to_relax_ng())
+        }))
+    }
+}
+
+impl ElementDecl {
+    fn to_xsd(&self) -> Element {
+        let element = Element::new("xs:element").attr("name", self.name.as_str());
+        let attributes = self.attributes.iter().map(|a| {
+            let attribute = Element::new("xs:attribute").attr("name", a.name.as_str()).attr("type", format!("xs:{}", a.ty.xsd_name()));
+            if a.required { attribute.attr("use", "required") } else { attribute }
+        });
+        if self.children.is_empty() {
+            return match self.text {
+                Some(ty) if self.attributes.is_empty() => element.attr("type", format!("xs:{}", ty.xsd_name())),
+                Some(ty) => element.child(Element::new("xs:complexType").child(Element::new("xs:simpleContent").child(
+                    Element::new("xs:extension").attr("base", format!("xs:{}", ty.xsd_name())).children(attributes),
+                ))),
+                None => element.child(Element::new("xs:complexType").children(attributes)),
+            };
+        }
+
+        let occurs = |e: Element, min: usize, max: usize| {
+            let e = if min == 1 { e } else { e.attr("minOccurs", min.to_string()) };
+            if max > 1 { e.attr("maxOccurs", "unbounded") } else { e }
+        };
+        let refs = self.children.iter().map(|c| Element::new("xs:element").attr("ref", c.name.as_str()));
+        let particle = if self.ordered {
+            Element::new("xs:sequence").children(self.children.iter().map(|c| {
+                occurs(Element::new("xs:element").attr("ref", c.name.as_str()), c.min_occurs, c.max_occurs)
+            }))
+        } else if self.children.iter().all(|c| c.max_occurs <= 1) {
+            Element::new("xs:all").children(self.children.iter().map(|c| {
+                occurs(Element::new("xs:element").attr("ref", c.name.as_str()), c.min_occurs, 1)
+            }))
+        } else {
+            occurs(Element::new("xs:choice"), 0, usize::MAX).children(refs)
+        };
+        let mut complex_type = Element::new("xs:complexType");
+        if self.text.is_some() {
+            complex_type = complex_type.attr("mixed", "true");
+        }
+        element.child(complex_type.child(particle).children(attributes))
+    }
+
+    fn to_relax_ng(&self) -> Element {
+        let element = Element::new("element").attr("name", self.name.as_str());
+        let data = |ty: SimpleType| match ty {
+            SimpleType::String => Element::new("text"),
+            ty => Element::new("data").attr("type", ty.xsd_name()),
+        };
+        let element = element.children(self.attributes.iter().map(|a| {
+            let attribute = Element::new("attribute").attr("name", a.name.as_str()).child(data(a.ty));
+            if a.required { attribute } else { Element::new("optional").child(attribute) }
+        }));
+        if self.children.is_empty() {
+            return match self.text {
+                Some(ty) => element.child(data(ty)),
+                None if self.attributes.is_empty() => element.child(Element::new("empty")),
+                None => element,
+            };
+        }
+
+        let occurs = |c: &ChildDecl, max: usize| {
+            let r = Element::new("ref").attr("name", c.name.as_str());
+            match (c.min_occurs, max) {
+                (0, 0 | 1) => Element::new("optional").child(r),
+                (0, _) => Element::new("zeroOrMore").child(r),
+                (_, 0 | 1) => r,
+                _ => Element::new("oneOrMore").child(r),
+            }
+        };
+        let content: Vec<Element> = if self.ordered {
+            self.children.iter().map(|c| occurs(c, c.max_occurs)).collect()
+        } else if self.children.iter().all(|c| c.max_occurs <= 1) {
+            vec![Element::new("interleave").children(self.children.iter().map(|c| occurs(c, 1)))]
+        } else {
+            let refs = self.children.iter().map(|c| Element::new("ref").attr("name", c.name.as_str()));
+            vec![Element::new("zeroOrMore").child(Element::new("choice").children(refs))]
+        };
+        if self.text.is_some() {
+            element.child(Element::new("mixed").children(content))
+        } else {
+            element.children(content)
+        }
+    }
+}
+
+/// Child elements in the XML Schema namespace with the given local name
+fn xs_children<'a>(parent: &'a value::Element, name: &'a str) -> impl Iterator<Item = &'a value::Element> + 'a {
+    parent.elements(name).filter(|e| e.namespace.as_deref() == Some(NS_XSD_URI))
+}
+
+fn local_name(qname: &str) -> &str {
+    qname.rsplit(':').next().unwrap_or(qname)
+}
+
+/// `minOccurs` and `maxOccurs`, with `unbounded` as `usize::MAX`
+fn occurs(particle: &value::Element) -> (usize, usize) {
+    let parse = |name, default| match particle.attributes.get(name).map(|v| v.trim()) {
+        Some("unbounded") => usize::MAX,
+        Some(v) => v.parse().unwrap_or(default),
+        None => default,
+    };
+    (parse("minOccurs", 1), parse("maxOccurs", 1))
+}
+
+struct XsdReader<'a> {
+    schema: &'a value::Element,
+    out: InferredSchema,
+}
+
+impl<'a> XsdReader<'a> {
+    /// Top-level definition of the kind, like `complexType`, with the qualified name
+    fn named(&self, kind: &'a str, qname: &str) -> Option<&'a value::Element> {
+        let name = local_name(qname);
+        xs_children(self.schema, kind).find(|e| e.attributes.get("name").map_or(false, |n| n == name))
+    }
+
+    /// The name refers to a built-in type of XML Schema
+    fn is_builtin(&self, qname: &str) -> bool {
+        let prefix = qname.split_once(':').map_or("", |(prefix, _)| prefix);
+        self.schema.namespaces.get(prefix).map_or(false, |uri| uri == NS_XSD_URI)
+    }
+
+    fn simple_type(&self, qname: &str) -> SimpleType {
+        let mut qname = qname;
+        // a chain of restrictions can't be longer than the number of types, unless it's invalid
+        for _ in 0..=self.schema.children.len() {
+            if self.is_builtin(qname) {
+                break;
+            }
+            match self.named("simpleType", qname).and_then(|t| self.restriction_base(t)) {
+                Some(base) => qname = base,
+                None => break,
+            }
+        }
+        match local_name(qname) {
+            "boolean" => SimpleType::Boolean,
+            "integer" | "int" | "long" | "short" | "byte" | "nonNegativeInteger" | "positiveInteger" | "negativeInteger"
+            | "nonPositiveInteger" | "unsignedLong" | "unsignedInt" | "unsignedShort" | "unsignedByte" => SimpleType::Integer,
+            "decimal" | "float" | "double" => SimpleType::Decimal,
+            "date" => SimpleType::Date,
+            "dateTime" => SimpleType::DateTime,
+            _ => SimpleType::String,
+        }
+    }
+
+    /// Base of a `simpleType` defined by a restriction. Lists and unions are strings.
+    fn restriction_base(&self, simple_type: &'a value::Element) -> Option<&'a str> {
+        xs_children(simple_type, "restriction").next()?.attributes.get("base").map(|b| b.as_str())
+    }
+
+    fn inline_simple_type(&self, parent: &value::Element) -> Option<SimpleType> {
+        let simple_type = xs_children(parent, "simpleType").next()?;
+        Some(self.restriction_base(simple_type).map_or(SimpleType::String, |base| self.simple_type(base)))
+    }
+
+    /// Declares the element if there's no element with the same name yet, and returns the name
+    fn element_decl(&mut self, element: &'a value::Element) -> Option<String> {
+        if let Some(r) = element.attributes.get("ref") {
+            let name = local_name(r);
+            if self.out.element(name).is_none() {
+                if let Some(global) = self.named("element", name) {
+                    self.element_decl(global);
+                }
+            }
+            return Some(name.into());
+        }
+        let name = element.attributes.get("name")?;
+        if self.out.element(name).is_some() {
+            return Some(name.clone());
+        }
+        // declared before the content, so that recursive types end
+        let index = self.out.decl_index(name);
+        if let Some(ty) = element.attributes.get("type") {
+            if self.is_builtin(ty) {
+                self.out.elements[index].text = Some(self.simple_type(ty));
+            } else if let Some(complex_type) = self.named("complexType", ty) {
+                self.complex_type(index, complex_type, 0);
+            } else {
+                self.out.elements[index].text = Some(self.simple_type(ty));
+            }
+        } else if let Some(complex_type) = xs_children(element, "complexType").next() {
+            self.complex_type(index, complex_type, 0);
+        } else if let Some(ty) = self.inline_simple_type(element) {
+            self.out.elements[index].text = Some(ty);
+        }
+        Some(name.clone())
+    }
+
+    fn complex_type(&mut self, index: usize, complex_type: &'a value::Element, depth: usize) {
+        if depth > self.schema.children.len() {
+            return;
+        }
+        if complex_type.attributes.get("mixed").map_or(false, |m| m == "true" || m == "1") {
+            self.out.elements[index].text = Some(SimpleType::String);
+        }
+        self.content(index, complex_type);
+        for simple in xs_children(complex_type, "simpleContent") {
+            for derived in xs_children(simple, "extension").chain(xs_children(simple, "restriction")) {
+                if let Some(base) = derived.attributes.get("base") {
+                    match self.named("complexType", base).filter(|_| !self.is_builtin(base)) {
+                        Some(base) => self.complex_type(index, base, depth + 1),
+                        None => self.out.elements[index].text = Some(self.simple_type(base)),
+                    }
+                }
+                self.content(index, derived);
+            }
+        }
+        for complex in xs_children(complex_type, "complexContent") {
+            if complex.attributes.get("mixed").map_or(false, |m| m == "true" || m == "1") {
+                self.out.elements[index].text = Some(SimpleType::String);
+            }
+            for extension in xs_children(complex, "extension") {
+                if let Some(base) = extension.attributes.get("base").and_then(|base| self.named("complexType", base)) {
+                    self.complex_type(index, base, depth + 1);
+                }
+                self.content(index, extension);
+            }
+            for restriction in xs_children(complex, "restriction") {
+                self.content(index, restriction);
+            }
+        }
+    }
+
+    /// Particles and attributes of a complex type or its derivation
+    fn content(&mut self, index: usize, parent: &'a value::Element) {
+        for child in parent.children.iter().filter_map(value::Value::as_element) {
+            if child.namespace.as_deref() != Some(NS_XSD_URI) {
+                continue;
+            }
+            match child.name.as_str() {
+                "sequence" | "choice" | "all" => self.particle(index, child, 1, 1),
+                "attribute" => self.attribute(index, child),
+                _ => {},
+            }
+        }
+    }
+
+    fn particle(&mut self, index: usize, particle: &'a value::Element, min: usize, max: usize) {
+        let (own_min, own_max) = occurs(particle);
+        let mut min = min.saturating_mul(own_min);
+        let max = max.saturating_mul(own_max);
+        match particle.name.as_str() {
+            "choice" => {
+                min = 0;
+                if max > 1 {
+                    self.out.elements[index].ordered = false;
+                }
+            },
+            "all" => self.out.elements[index].ordered = false,
+            _ => {},
+        }
+        for child in particle.children.iter().filter_map(value::Value::as_element) {
+            if child.namespace.as_deref() != Some(NS_XSD_URI) {
+                continue;
+            }
+            match child.name.as_str() {
+                "element" => {
+                    let (child_min, child_max) = occurs(child);
+                    let (child_min, child_max) = (min.saturating_mul(child_min), max.saturating_mul(child_max));
+                    if let Some(name) = self.element_decl(child) {
+                        let children = &mut self.out.elements[index].children;
+                        match children.iter_mut().find(|c| c.name == name) {
+                            Some(c) => {
+                                c.min_occurs = c.min_occurs.saturating_add(child_min);
+                                c.max_occurs = c.max_occurs.saturating_add(child_max);
+                            },
+                            None => children.push(ChildDecl { name, min_occurs: child_min, max_occurs: child_max }),
+                        }
+                    }
+                },
+                "sequence" | "choice" | "all" => self.particle(index, child, min, max),
+                _ => {},
+            }
+        }
+    }
+
+    fn attribute(&mut self, index: usize, attribute: &'a value::Element) {
+        let global = attribute.attributes.get("ref").and_then(|r| self.named("attribute", r));
+        let decl = global.unwrap_or(attribute);
+        let name = match decl.attributes.get("name") {
+            Some(name) => name.clone(),
+            None => return,
+        };
+        let ty = match decl.attributes.get("type") {
+            Some(ty) => self.simple_type(ty),
+            None => self.inline_simple_type(decl).unwrap_or(SimpleType::String),
+        };
+        let required = attribute.attributes.get("use").map_or(false, |u| u == "required");
+        let attributes = &mut self.out.elements[index].attributes;
+        if !attributes.iter().any(|a| a.name == name) {
+            attributes.push(AttributeDecl { name, ty, required });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{schema_from_samples, SimpleType};
+    use crate::reader::EventReader;
+    use crate::EmitterConfig;
+
+    #[test]
+    fn simple_types() {
+        let types: Vec<_> = ["true", "-12", "+1.5", ".5", "2024-01-31", "2024-01-31T12:00:00.5+01:00", "2024-01-31T12:00:00Z", "", "1e3", "2024-1-31", "2024-01-31T12:00:0é"]
+            .iter().map(|v| SimpleType::of(v)).collect();
+        assert_eq!(types, [SimpleType::Boolean, SimpleType::Integer, SimpleType::Decimal, SimpleType::Decimal, SimpleType::Date,
+            SimpleType::DateTime, SimpleType::DateTime, SimpleType::String, SimpleType::String, SimpleType::String, SimpleType::String]);
+        assert_eq!(SimpleType::Integer.merge(SimpleType::Decimal), SimpleType::Decimal);
+        assert_eq!(SimpleType::Integer.merge(SimpleType::Boolean), SimpleType::String);
+    }
+
+    #[test]
+    fn structure() {
+        let samples = [
+            r#"<r xmlns="urn:r" xmlns:x="urn:x" v="1"><a/><b>1</b><b>2.5</b><c x:n="1">t<i>x</i></c></r>"#,
+            r#"<r xmlns="urn:r" v="2"><b>3</b><d/><a/><e>x</e><e/></r>"#,
+        ];
+        let schema = schema_from_samples(samples.iter().map(|s| EventReader::from_str(s))).unwrap();
+        assert_eq!(schema.namespace.as_deref(), Some("urn:r"));
+        assert_eq!(schema.roots, ["r"]);
+        let r = schema.element("r").unwrap();
+        assert_eq!(r.count, 2);
+        assert!(!r.ordered);
+        let children: Vec<_> = r.children.iter().map(|c| (&*c.name, c.min_occurs, c.max_occurs)).collect();
+        assert_eq!(children, [("a", 1, 1), ("b", 1, 2), ("d", 0, 1), ("e", 0, 2), ("c", 0, 1)]);
+        assert_eq!(schema.element("b").unwrap().text, Some(SimpleType::Decimal));
+        assert_eq!(schema.element("c").unwrap().text, Some(SimpleType::String));
+        assert!(schema.element("c").unwrap().attributes.is_empty());
+        assert_eq!(schema.element("e").unwrap().text, Some(SimpleType::String));
+        assert_eq!(schema.element("a").unwrap().text, None);
+
+        let write = |e: crate::build::Element| {
+            let mut out = Vec::new();
+            e.write_to(&mut EmitterConfig::new().write_document_declaration(false).create_writer(&mut out)).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let xsd = write(schema.to_xsd());
+        assert!(xsd.starts_with(r#"<xs:schema xmlns="urn:r" xmlns:xs="http://www.w3.org/2001/XMLSchema" elementFormDefault="qualified" targetNamespace="urn:r">"#), "{xsd}");
+        assert!(xsd.contains(r#"<xs:element name="r"><xs:complexType><xs:choice minOccurs="0" maxOccurs="unbounded"><xs:element ref="a" /><xs:element ref="b" />"#), "{xsd}");
+        assert!(xsd.contains(r#"<xs:attribute name="v" type="xs:integer" use="required" />"#), "{xsd}");
+        assert!(xsd.contains(r#"<xs:element name="b" type="xs:decimal" />"#), "{xsd}");
+        assert!(xsd.contains(r#"<xs:element name="c"><xs:complexType mixed="true"><xs:sequence><xs:element ref="i" /></xs:sequence></xs:complexType></xs:element>"#), "{xsd}");
+
+        let rng = write(schema.to_relax_ng());
+        assert!(rng.starts_with(r#"<grammar xmlns="http://relaxng.org/ns/structure/1.0" datatypeLibrary="http://www.w3.org/2001/XMLSchema-datatypes" ns="urn:r"><start><ref name="r" /></start>"#), "{rng}");
+        assert!(rng.contains(r#"<define name="b"><element name="b"><data type="decimal" /></element></define>"#), "{rng}");
+        assert!(rng.contains(r#"<define name="a"><element name="a"><empty /></element></define>"#), "{rng}");
+        assert!(rng.contains(r#"<element name="c"><mixed><ref name="i" /></mixed></element>"#), "{rng}");
+
+        let schema = schema_from_samples([EventReader::from_str("<r><a/><b/><b/></r>"), EventReader::from_str("<r><b/></r>")]).unwrap();
+        let rng = write(schema.to_relax_ng());
+        assert!(rng.contains(r#"<element name="r"><optional><ref name="a" /></optional><oneOrMore><ref name="b" /></oneOrMore></element>"#), "{rng}");
+    }
+}
@@ -0,0 +1,200 @@
+//! Conversions to and from the trees of DOM crates, to read documents with this crate's parser,
+//! which handles encodings, entities and large inputs, and then use an existing tree API.
+//!
+//! Trees are converted through [`build::Element`](crate::build::Element), which can be written with an [`EventWriter`].
+//! [`minidom`](https://lib.rs/crates/minidom) needs the `minidom` feature, and
+//! [`roxmltree`](https://lib.rs/crates/roxmltree) needs the `roxmltree` feature.
+//!
+//! `roxmltree` documents can only be parsed from a string, so [`roxmltree_source`] reads a document
+//! into a string that it can parse.
+
+use std::io::{Read, Write};
+
+use crate::build::{Element, Node};
+#[cfg(feature = "minidom")]
+use crate::namespace::Namespace;
+use crate::namespace::NS_NO_PREFIX;
+use crate::reader::{self, EventReader};
+use crate::writer::{self, EventWriter};
+
+/// Reads the whole document into a `minidom` tree. Comments and processing instructions are not kept.
+#[cfg(feature = "minidom")]
+pub fn read_minidom<R: Read>(reader: EventReader<R>) -> reader::Result<minidom::Element> {
+    Element::from_reader(reader).map(|root| minidom::Element::from(&root))
+}
+
+/// Writes the `minidom` element and its content
+#[cfg(feature = "minidom")]
+pub fn write_minidom<W: Write>(element: &minidom::Element, writer: &mut EventWriter<W>) -> writer::Result<()> {
+    Element::from(element).write_to(writer)
+}
+
+#[cfg(feature = "minidom")]
+impl From<&minidom::Element> for Element {
+    /// Namespaces of the element names are declared where they change. Comments are not kept.
+    fn from(element: &minidom::Element) -> Self {
+        from_minidom(element, &Namespace::empty())
+    }
+}
+
+#[cfg(feature = "minidom")]
+fn from_minidom(element: &minidom::Element, scope: &Namespace) -> Element {
+    let mut declared = Namespace::empty();
+    for (prefix, uri) in element.prefixes.declared_prefixes() {
+        declared.0.insert(prefix.as_deref().unwrap_or(NS_NO_PREFIX).into(), uri.clone());
+    }
+    let mut scope = scope.clone();
+    scope.0.extend(declared.0.iter().map(|(prefix, uri)| (prefix.clone(), uri.clone())));
+
+    // minidom doesn't keep prefixes of elements, so it's any prefix declared for the namespace,
+    // or the namespace becomes the default one
+    let uri = element.ns();
+    let prefix = if scope.get(NS_NO_PREFIX).unwrap_or(NS_NO_PREFIX) == uri {
+        None
+    } else if let Some((prefix, _)) = scope.0.iter().find(|&(prefix, u)| *u == uri && !prefix.is_empty()) {
+        Some(prefix.clone())
+    } else {
+        declared.0.insert(NS_NO_PREFIX.into(), uri.clone());
+        scope.0.insert(NS_NO_PREFIX.into(), uri.clone());
+        None
+    };
+    let mut out = match prefix {
+        Some(prefix) => Element::new(format!("{prefix}:{}", element.name()).as_str()),
+        None => Element::new(element.name()),
+    };
+    out.name.namespace = Some(uri).filter(|uri| !uri.is_empty()).map(From::from);
+    out.namespace = declared;
+    for (name, value) in element.attrs() {
+        out = out.attr(name, value);
+    }
+    for node in element.nodes() {
+        if let Some(child) = node.as_element() {
+            out.children.push(Node::Element(from_minidom(child, &scope)));
+        } else if let Some(text) = node.as_text() {
+            out.children.push(Node::Text(text.into()));
+        }
+    }
+    out
+}
+
+#[cfg(feature = "minidom")]
+impl From<&Element> for minidom::Element {
+    /// Comments and processing instructions are not kept, and CDATA becomes text.
+    fn from(element: &Element) -> Self {
+        to_minidom(element, &Namespace::empty())
+    }
+}
+
+/// `scope` has all namespaces declared so far
+#[cfg(feature = "minidom")]
+fn to_minidom(element: &Element, scope: &Namespace) -> minidom::Element {
+    let mut scope = scope.clone();
+    scope.0.extend(element.namespace.0.iter().map(|(prefix, uri)| (prefix.clone(), uri.clone())));
+    let prefix = element.name.prefix.as_deref().unwrap_or(NS_NO_PREFIX);
+    let uri = scope.get(prefix).unwrap_or_default();
+
+    let mut builder = minidom::Element::builder(&element.name.local_name, uri);
+    for a in &element.attributes {
+        builder = builder.attr(a.name.borrow().to_repr(), a.value.as_str());
+    }
+    for child in &element.children {
+        match child {
+            Node::Element(child) => builder = builder.append(to_minidom(child, &scope)),
+            Node::Text(text) | Node::CData(text) => builder = builder.append(text.as_str()),
+            Node::Comment(_) | Node::ProcessingInstruction(..) => {},
+        }
+    }
+    let mut out = builder.build();
+    out.prefixes = element.namespace.0.iter()
+        .map(|(prefix, uri)| (Some(prefix.clone()).filter(|p| !p.is_empty()), uri.clone()))
+        .collect::<std::collections::BTreeMap<_, _>>()
+        .into();
+    out
+}
+
+/// Reads the whole document, and writes it again in UTF-8 for `roxmltree::Document::parse`.
+///
+/// Entities are expanded, and the doctype and XML declaration are left out, because `roxmltree` doesn't
+/// support all DTDs and encodings that this crate does.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let bytes = b"<?xml version='1.0' encoding='ISO-8859-1'?><!DOCTYPE p [<!ENTITY n 'na\xEFve'>]><p>&n;</p>";
+/// let source = xml::interop::roxmltree_source(xml::EventReader::new(&bytes[..]))?;
+/// let doc = roxmltree::Document::parse(&source)?;
+/// assert_eq!(doc.root_element().text(), Some("naïve"));
+/// # Ok(()) }
+/// ```
+#[cfg(feature = "roxmltree")]
+pub fn roxmltree_source<R: Read>(mut reader: EventReader<R>) -> reader::Result<String> {
+    let mut out = Vec::new();
+    let mut writer = crate::EmitterConfig::new().write_document_declaration(false).create_writer(&mut out);
+    loop {
+        let event = reader.next()?;
+        match event {
+            reader::XmlEvent::EndDocument => break,
+            reader::XmlEvent::StartDocument { .. } | reader::XmlEvent::Doctype { .. } => {},
+            _ => if let Some(event) = event.as_writer_event() {
+                writer.write(event).map_err(|e| reader::Error::from((&reader, e.to_string())))?;
+            },
+        }
+    }
+    Ok(String::from_utf8(out).unwrap_or_default())
+}
+
+/// Writes the `roxmltree` element and its content. If the node isn't an element, its document's root element is written.
+#[cfg(feature = "roxmltree")]
+pub fn write_roxmltree<W: Write>(node: roxmltree::Node<'_, '_>, writer: &mut EventWriter<W>) -> writer::Result<()> {
+    Element::from(node).write_to(writer)
+}
+
+#[cfg(feature = "roxmltree")]
+impl From<roxmltree::Node<'_, '_>> for Element {
+    /// Converts the element, or the root element of the node's document if the node isn't an element.
+    ///
+    /// `roxmltree` doesn't keep the prefixes of names, so a prefix declared for the namespace is used.
+    fn from(node: roxmltree::Node<'_, '_>) -> Self {
+        let node = if node.is_element() { node } else { node.document().root_element() };
+        // the root element declares everything in scope
+        from_roxmltree(node, &[])
+    }
+}
+
+#[cfg(feature = "roxmltree")]
+fn from_roxmltree(node: roxmltree::Node<'_, '_>, parent_scope: &[roxmltree::Namespace<'_>]) -> Element {
+    let scope: Vec<_> = node.namespaces().cloned().collect();
+    let prefixed = |namespace: Option<&str>, local_name: &str, is_attribute: bool| {
+        let prefix = namespace.and_then(|uri| {
+            if uri == crate::namespace::NS_XML_URI {
+                return Some(crate::namespace::NS_XML_PREFIX);
+            }
+            // unprefixed attributes are not in the default namespace
+            scope.iter().filter(|ns| ns.uri() == uri && (ns.name().is_some() || !is_attribute)).map(|ns| ns.name()).next().flatten()
+        });
+        match prefix {
+            Some(prefix) => format!("{prefix}:{local_name}"),
+            None => local_name.to_string(),
+        }
+    };
+    let tag = node.tag_name();
+    let mut out = Element::new(prefixed(tag.namespace(), tag.name(), false).as_str());
+    out.name.namespace = tag.namespace().filter(|uri| !uri.is_empty()).map(From::from);
+    for ns in scope.iter().filter(|ns| ns.name() != Some(crate::namespace::NS_XML_PREFIX) && !parent_scope.contains(ns)) {
+        out.namespace.0.insert(ns.name().unwrap_or(NS_NO_PREFIX).into(), ns.uri().into());
+    }
+    for a in node.attributes() {
+        out = out.attr(prefixed(a.namespace(), a.name(), true).as_str(), a.value());
+    }
+    for child in node.children() {
+        if child.is_element() {
+            out.children.push(Node::Element(from_roxmltree(child, &scope)));
+        } else if child.is_text() {
+            out.children.push(Node::Text(child.text().unwrap_or_default().into()));
+        } else if child.is_comment() {
+            out.children.push(Node::Comment(child.text().unwrap_or_default().into()));
+        } else if let Some(pi) = child.pi() {
+            out.children.push(Node::ProcessingInstruction(pi.target.into(), pi.value.map(String::from)));
+        }
+    }
+    out
+}
@@ -1,5 +1,6 @@
 #![warn(missing_docs)]
-#![forbid(non_camel_case_types)]
+#![cfg_attr(not(feature = "serde"), forbid(non_camel_case_types))]
+#![cfg_attr(feature = "serde", deny(non_camel_case_types))] // serde_derive allows it in its generated code
 #![forbid(unsafe_code)]
 #![allow(clippy::redundant_closure_for_method_calls)]
 #![allow(clippy::module_name_repetitions)]
@@ -14,15 +15,35 @@
 
 pub use crate::reader::{EventReader, ParserConfig};
 pub use crate::util::Encoding;
+pub use crate::value::{to_value, Value};
 pub use crate::writer::{EmitterConfig, EventWriter};
 
 pub mod attribute;
+mod binary;
+pub mod build;
+#[cfg(feature = "codegen")]
+pub mod codegen;
 pub mod common;
+#[cfg(any(feature = "flate2", feature = "zstd"))]
+pub mod compression;
+#[cfg(feature = "conformance")]
+pub mod conformance;
+pub mod edit;
 pub mod escape;
+pub mod filter;
+#[cfg(feature = "arbitrary")]
+pub mod fuzzing;
+pub mod infer;
+#[cfg(any(feature = "minidom", feature = "roxmltree"))]
+pub mod interop;
 #[doc(hidden)] // FIXME: not supposed to be public
 pub mod macros;
 pub mod name;
 pub mod namespace;
 pub mod reader;
+pub mod scan;
+pub mod schematron;
+pub mod testing;
 mod util;
+pub mod value;
 pub mod writer;
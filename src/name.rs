@@ -1,9 +1,15 @@
 //! Contains XML qualified names manipulation types and functions.
 //!
 
+use std::borrow::{Borrow, Cow};
+use std::collections::HashSet;
+use std::error::Error;
 use std::fmt;
+use std::ops::Deref;
 use std::str::FromStr;
+use std::sync::Arc;
 
+use crate::common::{is_name_char, is_name_start_char};
 use crate::namespace::NS_NO_PREFIX;
 
 /// Represents a qualified XML name.
@@ -158,8 +164,120 @@ impl<'a> Name<'a> {
     pub fn prefix_repr(&self) -> &str {
         self.prefix.unwrap_or(NS_NO_PREFIX)
     }
+
+    /// Compares names ignoring the case of local names. Prefixes and namespaces must be equal.
+    #[must_use]
+    pub fn eq_ignore_case(&self, other: &Name<'_>) -> bool {
+        self.namespace == other.namespace && self.prefix == other.prefix &&
+            self.local_name.chars().flat_map(char::to_lowercase).eq(other.local_name.chars().flat_map(char::to_lowercase))
+    }
+
+    /// Returns an owned name with lowercase local name. The prefix and namespace are unchanged.
+    #[must_use]
+    pub fn to_lowercase(&self) -> OwnedName {
+        let mut name = self.to_owned();
+        name.make_local_name_lowercase();
+        name
+    }
+
+    /// Parses `prefix:local` or `local`, and checks that both parts are valid names without colons.
+    ///
+    /// Unlike `From<&str>`, this rejects names that would make the document ill-formed.
+    ///
+    /// ```rust
+    /// # use xml::name::{Name, NameError};
+    /// assert_eq!(Name::parse_qualified("svg:path"), Ok(Name::prefixed("path", "svg")));
+    /// assert_eq!(Name::parse_qualified("2d"), Err(NameError::InvalidStartChar('2')));
+    /// ```
+    pub fn parse_qualified(name: &'a str) -> Result<Name<'a>, NameError> {
+        match name.split_once(':') {
+            Some((prefix, local_name)) => Name::try_prefixed(local_name, prefix).map_err(|e| match e {
+                NameError::InvalidChar(':') | NameError::InvalidStartChar(':') => NameError::TooManyColons,
+                e => e,
+            }),
+            None => Name::try_local(name),
+        }
+    }
+
+    /// Same as [`Name::local`], but fails if the name isn't a valid `NCName`
+    pub fn try_local(local_name: &'a str) -> Result<Name<'a>, NameError> {
+        check_ncname(local_name)?;
+        Ok(Name::local(local_name))
+    }
+
+    /// Same as [`Name::prefixed`], but fails if the local name or prefix isn't a valid `NCName`
+    pub fn try_prefixed(local_name: &'a str, prefix: &'a str) -> Result<Name<'a>, NameError> {
+        check_ncname(prefix)?;
+        check_ncname(local_name)?;
+        Ok(Name::prefixed(local_name, prefix))
+    }
+
+    /// Same as [`Name::qualified`], but fails if the local name or prefix isn't a valid `NCName`.
+    /// The namespace URI isn't checked.
+    pub fn try_qualified(local_name: &'a str, namespace: &'a str, prefix: Option<&'a str>) -> Result<Name<'a>, NameError> {
+        if let Some(prefix) = prefix {
+            check_ncname(prefix)?;
+        }
+        check_ncname(local_name)?;
+        Ok(Name::qualified(local_name, namespace, prefix))
+    }
+
+    /// Checks that the local name and the prefix, if any, are valid names without colons
+    pub fn validate(&self) -> Result<(), NameError> {
+        if let Some(prefix) = self.prefix {
+            check_ncname(prefix)?;
+        }
+        check_ncname(self.local_name)
+    }
+}
+
+/// Checks whether the string is a valid name without a colon (`NCName`),
+/// which can be used as a local name or a prefix.
+#[must_use]
+pub fn is_valid_ncname(name: &str) -> bool {
+    check_ncname(name).is_ok()
+}
+
+fn check_ncname(name: &str) -> Result<(), NameError> {
+    let mut chars = name.chars();
+    match chars.next() {
+        None => return Err(NameError::Empty),
+        Some(c) if c == ':' || !is_name_start_char(c) => return Err(NameError::InvalidStartChar(c)),
+        Some(_) => {},
+    }
+    match chars.find(|&c| c == ':' || !is_name_char(c)) {
+        Some(c) => Err(NameError::InvalidChar(c)),
+        None => Ok(()),
+    }
+}
+
+/// Why a name isn't valid in XML, see [`Name::parse_qualified`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum NameError {
+    /// The name, its prefix or local name is empty
+    Empty,
+    /// A name can't start with this character, e.g. a digit or `-`
+    InvalidStartChar(char),
+    /// The character isn't allowed in names
+    InvalidChar(char),
+    /// A qualified name can have only one colon
+    TooManyColons,
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameError::Empty => f.write_str("empty name"),
+            NameError::InvalidStartChar(c) => write!(f, "a name can't start with {c:?}"),
+            NameError::InvalidChar(c) => write!(f, "{c:?} is not allowed in names"),
+            NameError::TooManyColons => f.write_str("more than one colon in a qualified name"),
+        }
+    }
 }
 
+impl Error for NameError {}
+
 /// A wrapper around `Name` whose `Display` implementation prints the wrapped name as it is
 /// displayed in an XML document.
 pub struct ReprDisplay<'a, 'b>(&'a Name<'b>);
@@ -173,19 +291,190 @@ impl<'a, 'b: 'a> fmt::Display for ReprDisplay<'a, 'b> {
     }
 }
 
+/// An immutable string of an [`OwnedName`], which is cheap to clone.
+///
+/// The parser gives the same string to all names that have the same local name, prefix
+/// or namespace URI, so that repeated names don't allocate. It derefs to `str`.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SharedStr(Arc<str>);
+
+impl SharedStr {
+    /// The string
+    #[inline]
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for SharedStr {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for SharedStr {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for SharedStr {
+    #[inline]
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for SharedStr {
+    #[inline]
+    fn default() -> Self {
+        Self::from("")
+    }
+}
+
+impl fmt::Debug for SharedStr {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl fmt::Display for SharedStr {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&*self.0, f)
+    }
+}
+
+impl From<&str> for SharedStr {
+    #[inline]
+    fn from(s: &str) -> Self {
+        Self(s.into())
+    }
+}
+
+impl From<String> for SharedStr {
+    #[inline]
+    fn from(s: String) -> Self {
+        Self(s.into())
+    }
+}
+
+impl From<&String> for SharedStr {
+    #[inline]
+    fn from(s: &String) -> Self {
+        Self(s.as_str().into())
+    }
+}
+
+impl From<Cow<'_, str>> for SharedStr {
+    #[inline]
+    fn from(s: Cow<'_, str>) -> Self {
+        Self(s.into())
+    }
+}
+
+impl From<SharedStr> for String {
+    #[inline]
+    fn from(s: SharedStr) -> Self {
+        s.0.as_ref().into()
+    }
+}
+
+impl PartialEq<str> for SharedStr {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        *self.0 == *other
+    }
+}
+
+impl PartialEq<&str> for SharedStr {
+    #[inline]
+    fn eq(&self, other: &&str) -> bool {
+        *self.0 == **other
+    }
+}
+
+impl PartialEq<String> for SharedStr {
+    #[inline]
+    fn eq(&self, other: &String) -> bool {
+        *self.0 == **other
+    }
+}
+
+impl PartialEq<SharedStr> for str {
+    #[inline]
+    fn eq(&self, other: &SharedStr) -> bool {
+        *self == *other.0
+    }
+}
+
+impl PartialEq<SharedStr> for &str {
+    #[inline]
+    fn eq(&self, other: &SharedStr) -> bool {
+        **self == *other.0
+    }
+}
+
+impl PartialEq<SharedStr> for String {
+    #[inline]
+    fn eq(&self, other: &SharedStr) -> bool {
+        **self == *other.0
+    }
+}
+
+/// Strings of names that have been seen, for reusing them in names that repeat
+#[derive(Clone, Default)]
+pub(crate) struct NameTable {
+    strings: HashSet<SharedStr>,
+}
+
+/// Documents with more distinct names don't get more memory for the table
+const MAX_SHARED_NAMES: usize = 1000;
+
+impl NameTable {
+    pub fn get(&mut self, s: &str) -> SharedStr {
+        if let Some(shared) = self.strings.get(s) {
+            return shared.clone();
+        }
+        let shared = SharedStr::from(s);
+        if self.strings.len() < MAX_SHARED_NAMES {
+            self.strings.insert(shared.clone());
+        }
+        shared
+    }
+
+    /// Parses a qualified name like `OwnedName::from_str`, sharing the strings
+    pub fn name(&mut self, s: &str) -> Option<OwnedName> {
+        match s.split_once(':') {
+            None if !s.is_empty() => Some(OwnedName { local_name: self.get(s), namespace: None, prefix: None }),
+            Some((prefix, local_name)) if !prefix.is_empty() && !local_name.is_empty() && !local_name.contains(':') => {
+                Some(OwnedName { local_name: self.get(local_name), namespace: None, prefix: Some(self.get(prefix)) })
+            },
+            _ => None,
+        }
+    }
+}
+
 /// An owned variant of `Name`.
 ///
 /// Everything about `Name` applies to this structure as well.
+/// The strings are [`SharedStr`], which are shared by names read from the same document.
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub struct OwnedName {
     /// A local name, e.g. `string` in `xsi:string`.
-    pub local_name: String,
+    pub local_name: SharedStr,
 
     /// A namespace URI, e.g. `http://www.w3.org/2000/xmlns/`.
-    pub namespace: Option<String>,
+    pub namespace: Option<SharedStr>,
 
     /// A name prefix, e.g. `xsi` in `xsi:string`.
-    pub prefix: Option<String>,
+    pub prefix: Option<SharedStr>,
 }
 
 impl fmt::Display for OwnedName {
@@ -209,7 +498,7 @@ impl OwnedName {
 
     /// Returns a new `OwnedName` instance representing a plain local name.
     #[inline]
-    pub fn local<S>(local_name: S) -> OwnedName where S: Into<String> {
+    pub fn local<S>(local_name: S) -> OwnedName where S: Into<SharedStr> {
         OwnedName {
             local_name: local_name.into(),
             namespace: None,
@@ -221,7 +510,7 @@ impl OwnedName {
     /// a prefix and with a namespace URI.
     #[inline]
     pub fn qualified<S1, S2, S3>(local_name: S1, namespace: S2, prefix: Option<S3>) -> OwnedName
-        where S1: Into<String>, S2: Into<String>, S3: Into<String>
+        where S1: Into<SharedStr>, S2: Into<SharedStr>, S3: Into<SharedStr>
     {
         OwnedName {
             local_name: local_name.into(),
@@ -245,6 +534,14 @@ impl OwnedName {
     pub fn namespace_ref(&self) -> Option<&str> {
         self.namespace.as_deref()
     }
+
+    /// Changes the local name to lowercase, keeping the prefix and namespace.
+    /// Doesn't allocate if the name is already lowercase.
+    pub fn make_local_name_lowercase(&mut self) {
+        if self.local_name.chars().any(char::is_uppercase) {
+            self.local_name = self.local_name.to_lowercase().into();
+        }
+    }
 }
 
 impl<'a> From<Name<'a>> for OwnedName {
@@ -287,7 +584,7 @@ impl FromStr for OwnedName {
 
 #[cfg(test)]
 mod tests {
-    use super::OwnedName;
+    use super::{is_valid_ncname, Name, NameError, OwnedName};
 
     #[test]
     fn test_owned_name_from_str() {
@@ -309,4 +606,36 @@ mod tests {
         assert_eq!("a:".parse(), Err::<OwnedName, ()>(()));
         assert_eq!("a:b:c".parse(), Err::<OwnedName, ()>(()));
     }
+
+    #[test]
+    fn test_name_validation() {
+        assert_eq!(Name::parse_qualified("a"), Ok(Name::local("a")));
+        assert_eq!(Name::parse_qualified("p:a-1"), Ok(Name::prefixed("a-1", "p")));
+        assert_eq!(Name::parse_qualified(""), Err(NameError::Empty));
+        assert_eq!(Name::parse_qualified(":a"), Err(NameError::Empty));
+        assert_eq!(Name::parse_qualified("a:"), Err(NameError::Empty));
+        assert_eq!(Name::parse_qualified("a:b:c"), Err(NameError::TooManyColons));
+        assert_eq!(Name::parse_qualified("a::c"), Err(NameError::TooManyColons));
+        assert_eq!(Name::parse_qualified("a b"), Err(NameError::InvalidChar(' ')));
+        assert_eq!(Name::parse_qualified("-a"), Err(NameError::InvalidStartChar('-')));
+
+        assert!(is_valid_ncname("élément.2"));
+        assert!(!is_valid_ncname("a:b"));
+        assert_eq!(Name::try_local("a:b"), Err(NameError::InvalidChar(':')));
+        assert!(Name::try_qualified("a", "urn:x", Some("1")).is_err());
+        assert!(Name::prefixed("a", "x y").validate().is_err());
+        assert!(Name::qualified("a", "not checked", Some("p")).validate().is_ok());
+    }
+
+    #[test]
+    fn test_name_case() {
+        let name = Name::prefixed("ÉLément", "P");
+        assert!(name.eq_ignore_case(&Name::prefixed("élÉMENT", "P")));
+        assert!(!name.eq_ignore_case(&Name::prefixed("élément", "p")));
+        assert_eq!(name.to_lowercase(), OwnedName {
+            local_name: "élément".into(),
+            namespace: None,
+            prefix: Some("P".into())
+        });
+    }
 }
@@ -37,6 +37,20 @@ pub const NS_XML_PREFIX: &str = "xml";
 /// See `NS_XML_PREFIX` documentation for more information.
 pub const NS_XML_URI: &str = "http://www.w3.org/XML/1998/namespace";
 
+/// Designates the namespace of `xop:Include` elements.
+///
+/// See [XML-binary Optimized Packaging][xop] for more information.
+///
+///   [xop]: https://www.w3.org/TR/xop10/
+pub const NS_XOP_URI: &str = "http://www.w3.org/2004/08/xop/include";
+
+/// Designates the namespace of `xsi:type`, `xsi:nil` and other attributes of XML Schema instances.
+///
+/// See [XML Schema Instance Attributes][xsi] for more information.
+///
+///   [xsi]: https://www.w3.org/TR/xmlschema-1/#Instance_Document_Constructions
+pub const NS_XSI_URI: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
 /// Designates the absence of prefix in a qualified name.
 ///
 /// This constant should be used to define or query default namespace which should be used
@@ -66,6 +80,10 @@ pub const NS_EMPTY_URI: &str = "";
 /// Namespace is a map from prefixes to namespace URIs.
 ///
 /// No prefix (i.e. default namespace) is designated by `NS_NO_PREFIX` constant.
+/// A prefix mapped to `NS_EMPTY_URI` has been undeclared with `xmlns:prefix=""`,
+/// which is allowed only in XML 1.1 documents (see [Namespaces in XML 1.1][ns11]).
+///
+///   [ns11]: https://www.w3.org/TR/xml-names11/#scoping
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct Namespace(pub BTreeMap<String, String>);
 
@@ -280,6 +298,7 @@ impl NamespaceStack {
     /// Returns a boolean flag indicating whether the insertion has completed successfully.
     /// Note that both key and value are matched and the mapping is inserted if either
     /// namespace prefix is not already mapped, or if it is mapped, but to a different URI.
+    /// Mapping a prefix that isn't in scope to `NS_EMPTY_URI` is not inserted, since it's undeclared already.
     ///
     /// # Parameters
     /// * `prefix` --- namespace prefix;
@@ -292,7 +311,8 @@ impl NamespaceStack {
         where P: Into<String> + AsRef<str>,
               U: Into<String> + AsRef<str>
     {
-        if self.0.iter().any(|ns| ns.get(&prefix) == Some(uri.as_ref())) {
+        // an unbound prefix is the same as an undeclared one
+        if self.get(&prefix).unwrap_or(NS_EMPTY_URI) == uri.as_ref() {
             false
         } else {
             self.put(prefix, uri);
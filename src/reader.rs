@@ -2,22 +2,49 @@
 //!
 //! The most important type in this module is `EventReader`, which provides an iterator
 //! view for events in XML document.
+//!
+//! ## Thread safety
+//!
+//! `EventReader<R>` is `Send` if `R` is, and `Sync` if `R` is, so readers can be moved to worker threads.
+//! Configs, [`EntityTable`], events and errors are `Send + Sync`. One config can be shared by many readers,
+//! e.g. in an `Arc`, and cloned for each of them: the entity table is reference-counted, so it isn't copied.
+//! A [`RawBytesObserver`] is called on the thread of the reader that uses it.
 
-use std::io::Read;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::iter::FusedIterator;
+use std::ops::Range;
 use std::result;
+use std::sync::Mutex;
+
+use crate::attribute::{AttributeDetails, OwnedAttribute};
+use crate::binary::{BinaryDecoder, BinaryEncoding};
+use crate::build;
+use crate::common::{EventCounts, Position, TextPosition};
+use crate::name::OwnedName;
 
-use crate::common::{Position, TextPosition};
+pub(crate) use self::config::{DEFAULT_MAX_ENTITY_EXPANSION_DEPTH, DEFAULT_MAX_ENTITY_EXPANSION_LENGTH};
+pub use self::config::{Callback, ConfigError, DocumentDelimiter, InvalidCharReferences, EntityExpansionCheck, ParserConfig, ParserConfig2, RawBytesObserver, ReaderConfigBuilder, TextHandling, TextHandlingHook, TextOutsideRoot, ValidatedConfig};
+pub use self::entities::{EntityTable, Notation, UnparsedEntity};
+pub use self::error::{CharReferenceKind, Error, ErrorContext, ErrorKind, Limit, Warning};
+pub use self::events::{EventMask, XmlEvent};
+pub use self::index::{IndexEntry, XmlIndex};
 
-pub use self::config::{ParserConfig, ParserConfig2};
-pub use self::error::{Error, ErrorKind};
-pub use self::events::XmlEvent;
+pub use self::lexer::Token;
 
-use self::parser::PullParser;
+use self::error::SyntaxError;
+use self::parser::{FedToken, PullParser, Tokens};
+pub(crate) use self::lexer::Lexer;
 
 mod config;
+mod entities;
 mod error;
 mod events;
+mod index;
+#[cfg(feature = "html_entities")]
+mod html_entities;
 mod indexset;
 mod lexer;
 mod parser;
@@ -25,7 +52,51 @@ mod parser;
 /// A result type yielded by `XmlReader`.
 pub type Result<T, E = Error> = result::Result<T, E>;
 
+/// Statistics about the document parsed so far, returned by [`EventReader::stats`].
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct ReaderStats {
+    /// Bytes taken from the source
+    pub bytes_read: u64,
+    /// Events returned by the reader
+    pub events: EventCounts,
+    /// Deepest nesting of elements
+    pub max_depth: usize,
+    /// References to entities from the DTD or [`ParserConfig::add_entity`] that were replaced
+    pub entity_expansions: u64,
+    /// The expansions by the entity name
+    pub entities: HashMap<String, EntityStats>,
+}
+
+/// Expansions of one entity, see [`ReaderStats::entities`]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct EntityStats {
+    /// References replaced
+    pub count: u64,
+    /// Length of the replacement text in bytes, summed over all the references
+    pub bytes: u64,
+}
+
 /// A wrapper around an `std::io::Read` instance which provides pull-based XML parsing.
+///
+/// A reader can be cloned if its source can, e.g. to parse ahead speculatively from the current
+/// state, and then discard the clone. Clones of a reader over `&[u8]` read the same bytes independently.
+/// A [`RawBytesObserver`] is shared by the clones.
+///
+/// ```rust
+/// use xml::reader::{EventReader, XmlEvent};
+///
+/// let mut reader = EventReader::new(&b"<a><b/></a>"[..]);
+/// reader.next().unwrap(); // StartDocument
+/// reader.next().unwrap(); // <a>
+/// let mut fork = reader.clone();
+/// assert!(matches!(fork.next().unwrap(), XmlEvent::StartElement { name, .. } if name.local_name == "b"));
+/// fork.next().unwrap();
+/// // the original is unaffected
+/// assert!(matches!(reader.next().unwrap(), XmlEvent::StartElement { name, .. } if name.local_name == "b"));
+/// ```
+#[derive(Clone)]
 pub struct EventReader<R: Read> {
     source: R,
     parser: PullParser,
@@ -53,6 +124,73 @@ impl<R: Read> EventReader<R> {
         self.parser.next(&mut self.source)
     }
 
+    /// Starts reading a new document from another source, and returns the previous source.
+    ///
+    /// The configuration and the parser's buffers are kept, which makes parsing of many small documents faster
+    /// than creating a reader for each of them. See also [`ReaderPool`].
+    ///
+    /// Changes made with [`controls`](Self::controls) are undone, and the configuration is the one the reader has been created with.
+    pub fn reuse(&mut self, source: R) -> R {
+        self.parser.reset();
+        std::mem::replace(&mut self.source, source)
+    }
+
+    /// Skips the rest of the current document, and continues with the next document from the same source.
+    ///
+    /// Returns `false` at the end of the stream. Documents are recognized only if
+    /// [`ParserConfig2::multiple_documents`] is enabled, otherwise there's always just one.
+    /// Positions and [`stats`](Self::stats) continue from the previous documents.
+    ///
+    /// ```rust
+    /// use xml::reader::{DocumentDelimiter, ParserConfig2, XmlEvent};
+    ///
+    /// let mut reader = ParserConfig2::new()
+    ///     .multiple_documents(Some(DocumentDelimiter::Declaration))
+    ///     .create_reader(&b"<?xml version='1.0'?><a/>\n<?xml version='1.0'?><b/>"[..]);
+    /// let mut names = Vec::new();
+    /// loop {
+    ///     assert!(matches!(reader.next().unwrap(), XmlEvent::StartDocument { .. }));
+    ///     if let XmlEvent::StartElement { name, .. } = reader.next().unwrap() {
+    ///         names.push(name.local_name);
+    ///     }
+    ///     // skips to the end of the current document
+    ///     if !reader.next_document().unwrap() {
+    ///         break;
+    ///     }
+    /// }
+    /// assert_eq!(names, ["a", "b"]);
+    /// ```
+    pub fn next_document(&mut self) -> Result<bool> {
+        while self.parser.next_unmasked(&mut self.source)? != XmlEvent::EndDocument {}
+        Ok(self.parser.start_next_document())
+    }
+
+    /// Events of all documents in the source, with `EndDocument` after each of them.
+    ///
+    /// See [`next_document`](Self::next_document).
+    #[inline]
+    #[must_use]
+    pub fn documents(self) -> DocumentStream<R> {
+        DocumentStream { reader: self, finished: false }
+    }
+
+    /// Like [`into_iter`](IntoIterator::into_iter), but each event comes with the position where it starts.
+    ///
+    /// ```rust
+    /// use xml::reader::{EventReader, XmlEvent};
+    ///
+    /// let events = EventReader::from_str("<a>\n  <b/></a>").into_events_with_positions();
+    /// let (pos, _) = events.map(Result::unwrap)
+    ///     .find(|(_, event)| matches!(event, XmlEvent::StartElement { name, .. } if name.local_name == "b"))
+    ///     .unwrap();
+    /// assert_eq!((pos.row, pos.column), (1, 2));
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn into_events_with_positions(self) -> PositionedEvents<R> {
+        PositionedEvents { reader: self, finished: false }
+    }
+
     /// Skips all XML events until the next end tag at the current level.
     ///
     /// Convenience function that is useful for the case where you have
@@ -63,7 +201,8 @@ impl<R: Read> EventReader<R> {
         let mut depth = 1;
 
         while depth > 0 {
-            match self.next()? {
+            // elements may be excluded from the events
+            match self.parser.next_unmasked(&mut self.source)? {
                 XmlEvent::StartElement { .. } => depth += 1,
                 XmlEvent::EndElement { .. } => depth -= 1,
                 XmlEvent::EndDocument => unreachable!(),
@@ -74,6 +213,273 @@ impl<R: Read> EventReader<R> {
         Ok(())
     }
 
+    /// Reads the content of an element into a tree, for handling small elements of a large document as a whole.
+    ///
+    /// `start` must be the `StartElement` event that [`next`](Self::next) has just returned. The events are read
+    /// up to and including the element's `EndElement`. Namespaces in scope are declared on the returned element,
+    /// so it can be written on its own.
+    ///
+    /// ```rust
+    /// use xml::reader::{EventReader, XmlEvent};
+    ///
+    /// let mut reader = EventReader::from_str(r#"<feed xmlns:m="urn:m"><entry m:id="1"><title>A</title></entry><entry m:id="2"/></feed>"#);
+    /// let mut entries = Vec::new();
+    /// loop {
+    ///     let event = reader.next().unwrap();
+    ///     match &event {
+    ///         XmlEvent::StartElement { name, .. } if name.local_name == "entry" => {
+    ///             entries.push(reader.build_element_tree(event).unwrap());
+    ///         },
+    ///         XmlEvent::EndDocument => break,
+    ///         _ => {},
+    ///     }
+    /// }
+    /// assert_eq!(entries.len(), 2);
+    /// assert_eq!(entries[0].namespace.get("m"), Some("urn:m"));
+    /// assert_eq!(entries[0].children.len(), 1);
+    /// ```
+    pub fn build_element_tree(&mut self, start: XmlEvent) -> Result<build::Element> {
+        match start {
+            XmlEvent::StartElement { name, attributes, namespace } => build::Element::read_subtree(self, name, attributes, namespace),
+            _ => Err((&self.parser, "build_element_tree needs a StartElement event").into()),
+        }
+    }
+
+    /// Decodes base64 text content of the current element, and writes the bytes to `out`.
+    ///
+    /// It must be called right after the element's `StartElement`, and it reads events up to and including its `EndElement`.
+    /// Whitespace, comments and processing instructions are ignored, and the text can be split into many events,
+    /// so it's decoded in chunks. Child elements are an error. Returns the number of bytes written.
+    ///
+    /// ```rust
+    /// use xml::reader::{EventReader, XmlEvent};
+    ///
+    /// let mut reader = EventReader::from_str("<blob>aGVs\n  bG8=</blob>");
+    /// reader.next().unwrap(); // StartDocument
+    /// reader.next().unwrap(); // <blob>
+    /// let mut bytes = Vec::new();
+    /// reader.read_base64_to(&mut bytes).unwrap();
+    /// assert_eq!(bytes, b"hello");
+    /// assert_eq!(reader.next().unwrap(), XmlEvent::EndDocument);
+    /// ```
+    pub fn read_base64_to<W: Write>(&mut self, out: W) -> Result<u64> {
+        self.read_binary_to(out, BinaryEncoding::Base64)
+    }
+
+    /// Decodes hex text content of the current element (like `xs:hexBinary`), and writes the bytes to `out`.
+    ///
+    /// Works like [`read_base64_to`](Self::read_base64_to).
+    pub fn read_hex_to<W: Write>(&mut self, out: W) -> Result<u64> {
+        self.read_binary_to(out, BinaryEncoding::Hex)
+    }
+
+    fn read_binary_to<W: Write>(&mut self, mut out: W, encoding: BinaryEncoding) -> Result<u64> {
+        let invalid = |parser: &PullParser| Error::from((parser, SyntaxError::InvalidBinaryContent(encoding.name()).to_cow()));
+        let mut decoder = BinaryDecoder::new(encoding);
+        let mut bytes = Vec::new();
+        let mut total = 0;
+        loop {
+            match self.parser.next_unmasked(&mut self.source)? {
+                XmlEvent::Characters(text) | XmlEvent::CData(text) | XmlEvent::Whitespace(text) => {
+                    bytes.clear();
+                    decoder.decode(&text, &mut bytes).map_err(|_| invalid(&self.parser))?;
+                    out.write_all(&bytes).map_err(|e| Error { pos: self.parser.position(), kind: ErrorKind::Io(e), context: None })?;
+                    total += bytes.len() as u64;
+                },
+                XmlEvent::EndElement { .. } => break,
+                XmlEvent::Comment(_) | XmlEvent::ProcessingInstruction { .. } => {},
+                _ => return Err(invalid(&self.parser)),
+            }
+        }
+        decoder.finish().map_err(|_| invalid(&self.parser))?;
+        Ok(total)
+    }
+
+    /// Reads the rest of the current text as a stream of UTF-8 bytes.
+    ///
+    /// Use it after [`next`](Self::next) has returned `Characters` or `CData`. It reads the following
+    /// `Characters`, `Whitespace` and `CData` events, and stops before any other event. With [`ParserConfig2::lazy_text`]
+    /// the text is never held in memory all at once.
+    ///
+    /// ```rust
+    /// use std::io::Read;
+    /// use xml::reader::{ParserConfig2, XmlEvent};
+    ///
+    /// let mut reader = ParserConfig2::new().lazy_text(true)
+    ///     .create_reader(&b"<doc>text <![CDATA[and more]]></doc>"[..]);
+    /// reader.next().unwrap(); // StartDocument
+    /// reader.next().unwrap(); // <doc>
+    /// let mut text = match reader.next().unwrap() {
+    ///     XmlEvent::Characters(text) => text,
+    ///     _ => unreachable!(),
+    /// };
+    /// reader.text_reader().read_to_string(&mut text).unwrap();
+    /// assert_eq!(text, "text and more");
+    /// assert!(matches!(reader.next().unwrap(), XmlEvent::EndElement { .. }));
+    /// ```
+    pub fn text_reader(&mut self) -> TextReader<'_, R> {
+        TextReader { reader: self, chunk: String::new(), offset: 0, done: false }
+    }
+
+    /// Reads the rest of the document, and counts its events.
+    ///
+    /// Together with [`ParserConfig2::events_only`] this is a fast way to scan the structure of a document:
+    ///
+    /// ```rust
+    /// use xml::reader::{EventMask, ParserConfig2};
+    ///
+    /// let mut reader = ParserConfig2::new()
+    ///     .events_only(EventMask::START_ELEMENT)
+    ///     .create_reader(&b"<list><item a='1'>x</item><item/></list>"[..]);
+    /// assert_eq!(reader.drain_count().unwrap().elements, 3);
+    /// ```
+    pub fn drain_count(&mut self) -> Result<EventCounts> {
+        let mut counts = EventCounts::default();
+        loop {
+            match self.next()? {
+                XmlEvent::StartElement { .. } => counts.elements += 1,
+                XmlEvent::Characters(_) | XmlEvent::Whitespace(_) => counts.characters += 1,
+                XmlEvent::CData(_) => counts.cdata += 1,
+                XmlEvent::Comment(_) => counts.comments += 1,
+                XmlEvent::ProcessingInstruction { .. } => counts.processing_instructions += 1,
+                XmlEvent::EndDocument => return Ok(counts),
+                _ => {},
+            }
+        }
+    }
+
+    /// Counts of bytes, events and entity expansions, e.g. for monitoring or capacity planning
+    #[must_use]
+    pub fn stats(&self) -> ReaderStats {
+        self.parser.stats()
+    }
+
+    /// Source details of the attributes of the last `StartElement` event, in the same order as its `attributes`.
+    ///
+    /// This is empty unless [`ParserConfig2::preserve_source_details`] is enabled.
+    /// Namespace declarations have no details, but they're counted in [`AttributeDetails::index`].
+    #[must_use]
+    pub fn attribute_details(&self) -> &[AttributeDetails] {
+        self.parser.attribute_details()
+    }
+
+    /// Attributes of the last `StartElement` event, if [`ParserConfig2::lazy_attributes`] is enabled.
+    ///
+    /// Their values are decoded only when [`LazyAttribute::value`] is called.
+    pub fn lazy_attributes(&self) -> impl ExactSizeIterator<Item = LazyAttribute<'_>> {
+        let parser = &self.parser;
+        parser.lazy_attributes().iter().zip(parser.lazy_attribute_positions())
+            .map(move |(attr, &pos)| LazyAttribute { attr, pos, parser })
+    }
+
+    /// Entities declared so far in the document's DTD or in the [`EntityTable`], with their replacement text
+    pub fn declared_entities(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.parser.declared_entities()
+    }
+
+    /// Options that can be changed while the document is being read
+    pub fn controls(&mut self) -> ParserControls<'_> {
+        ParserControls { parser: &mut self.parser }
+    }
+
+    /// Notations declared so far in the document's DTD, in order of declaration
+    #[must_use]
+    pub fn notations(&self) -> &[Notation] {
+        self.parser.notations()
+    }
+
+    /// Unparsed entities declared so far in the document's DTD, in order of declaration.
+    ///
+    /// They can't be referenced in the text, only named by attributes of `ENTITY` type.
+    #[must_use]
+    pub fn unparsed_entities(&self) -> &[UnparsedEntity] {
+        self.parser.unparsed_entities()
+    }
+
+    /// The last event is `Whitespace`, or `Characters` or `CData` that is only whitespace.
+    ///
+    /// Text that has been coalesced from several parts, like CDATA or character references, can be
+    /// whitespace-only `Characters`. This flag saves checking every string with `trim()`.
+    /// An empty `CData` counts as whitespace.
+    #[must_use]
+    pub fn is_whitespace_text(&self) -> bool {
+        self.parser.is_whitespace_text()
+    }
+
+    /// The last event is `Whitespace` or whitespace-only `Characters` in an element that
+    /// the DTD declares with element content, like `<!ELEMENT list (item*)>`.
+    ///
+    /// Such whitespace is only formatting, and isn't part of the text of the document.
+    #[must_use]
+    pub fn is_element_content_whitespace(&self) -> bool {
+        self.parser.is_element_content_whitespace()
+    }
+
+    /// The last event is `Characters` or `Whitespace` with text from a CDATA section, which has been
+    /// merged into it because [`ParserConfig::cdata_to_characters`] is enabled.
+    ///
+    /// Filters can use it to write the text as CDATA again. The text may also have parts that weren't CDATA.
+    #[must_use]
+    pub fn text_contains_cdata(&self) -> bool {
+        self.parser.text_contains_cdata()
+    }
+
+    /// Problems in the document that the parser has worked around, because the configuration allows them.
+    ///
+    /// At most [`ParserConfig2::max_warnings`] are kept, see [`EventReader::take_warnings`].
+    #[must_use]
+    pub fn warnings(&self) -> &[Warning] {
+        self.parser.warnings()
+    }
+
+    /// Removes the warnings collected so far, e.g. to handle them after every event of a long document
+    /// without reaching [`ParserConfig2::max_warnings`]
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        self.parser.take_warnings()
+    }
+
+    /// Content-id of the attachment, if the last event is `StartElement` of an `<xop:Include href="cid:…"/>` element
+    /// of [XOP](https://www.w3.org/TR/xop10/) package, like a SOAP message with MTOM.
+    ///
+    /// The `cid:` scheme is removed, and percent-encoding is decoded. This requires [`ParserConfig2::xop_includes`].
+    #[must_use]
+    pub fn xop_content_id(&self) -> Option<&str> {
+        self.parser.xop_content_id()
+    }
+
+    /// Index of the element of the last `StartElement` or `EndElement` event, in document order.
+    ///
+    /// Elements are numbered from 0 in the order of their start tags, so the index
+    /// is stable and can be used to refer to nodes without building a tree.
+    #[must_use]
+    pub fn element_index(&self) -> Option<usize> {
+        self.parser.element_index()
+    }
+
+    /// Index of the parent of the element of the last `StartElement` or `EndElement` event.
+    ///
+    /// `None` for the root element. See [`element_index`](Self::element_index).
+    #[must_use]
+    pub fn parent_element_index(&self) -> Option<usize> {
+        self.parser.parent_element_index()
+    }
+
+    /// Elements with an `xml:id` attribute seen so far, with positions of their start tags.
+    ///
+    /// This is empty unless [`ParserConfig2::track_xml_ids`] is enabled. It's complete at `EndDocument`.
+    #[must_use]
+    pub fn xml_ids(&self) -> &HashMap<String, TextPosition> {
+        self.parser.xml_ids()
+    }
+
+    /// IDREF and IDREFS values that don't match any ID seen so far, with positions of their elements.
+    ///
+    /// This is empty unless [`ParserConfig2::check_idrefs`] is enabled. References can point forward,
+    /// so the list is final only at the end of the document, where the first of them is reported as an error.
+    pub fn unresolved_idrefs(&self) -> impl Iterator<Item = (&str, TextPosition)> {
+        self.parser.unresolved_idrefs()
+    }
+
     /// Access underlying reader
     ///
     /// Using it directly while the event reader is parsing is not recommended
@@ -84,6 +490,83 @@ impl<R: Read> EventReader<R> {
     /// Using it directly while the event reader is parsing is not recommended
     pub fn source_mut(&mut self) -> &mut R { &mut self.source }
 
+    /// Name of the encoding declared in `<?xml encoding="…"?>` that the parser can't decode.
+    ///
+    /// This is set only when [`ParserConfig2::defer_unsupported_encoding`] is enabled,
+    /// and `next()` has returned an unsupported encoding error. Parsing can continue after
+    /// calling [`resume_with_utf8_source`](Self::resume_with_utf8_source).
+    #[must_use]
+    pub fn unsupported_encoding(&self) -> Option<&str> {
+        self.parser.deferred_encoding()
+    }
+
+    /// Continues parsing from a source transcoded to UTF-8 by the application.
+    ///
+    /// The closure gets the original source, positioned right after the `<?xml … ?>` declaration,
+    /// and should return a reader that decodes the rest of the document to UTF-8.
+    /// The next event will be `StartDocument` with the originally declared encoding name.
+    ///
+    /// ```rust
+    /// use std::io::Read;
+    /// use xml::reader::{ParserConfig2, XmlEvent};
+    ///
+    /// let mut reader = ParserConfig2::new()
+    ///     .defer_unsupported_encoding(true)
+    ///     .create_reader(&b"<?xml version='1.0' encoding='x-upper'?><A/>"[..]);
+    ///
+    /// assert!(reader.next().is_err());
+    /// assert_eq!(Some("x-upper"), reader.unsupported_encoding());
+    ///
+    /// let mut reader = reader.resume_with_utf8_source(|mut source| {
+    ///     let mut s = String::new();
+    ///     source.read_to_string(&mut s).unwrap();
+    ///     std::io::Cursor::new(s.to_lowercase())
+    /// });
+    /// assert!(matches!(reader.next().unwrap(), XmlEvent::StartDocument { .. }));
+    /// assert!(matches!(reader.next().unwrap(), XmlEvent::StartElement { name, .. } if name.local_name == "a"));
+    /// ```
+    pub fn resume_with_utf8_source<S: Read>(self, transcode: impl FnOnce(R) -> S) -> EventReader<S> {
+        let mut parser = self.parser;
+        parser.resume_as_utf8();
+        EventReader { source: transcode(self.source), parser }
+    }
+
+    /// Copies the input to `sink` exactly as it's read by the parser, e.g. to store the original document
+    /// while validating it. Bytes that have been parsed already are not copied.
+    ///
+    /// ```rust
+    /// use xml::reader::{EventReader, XmlEvent};
+    ///
+    /// let input = "<doc><item/></doc>";
+    /// let mut reader = EventReader::from_str(input).tee(Vec::new());
+    /// let mut tags = Vec::new();
+    /// loop {
+    ///     match reader.next().unwrap() {
+    ///         XmlEvent::StartElement { .. } => {
+    ///             let range = reader.event_byte_range();
+    ///             tags.push(&input[range.start as usize..range.end as usize]);
+    ///         },
+    ///         XmlEvent::EndDocument => break,
+    ///         _ => {},
+    ///     }
+    /// }
+    /// assert_eq!(tags, ["<doc>", "<item/>"]);
+    /// assert_eq!(reader.source().sink(), input.as_bytes());
+    /// ```
+    pub fn tee<W: Write>(self, sink: W) -> EventReader<Tee<R, W>> {
+        EventReader { source: Tee { source: self.source, sink }, parser: self.parser }
+    }
+
+    /// Byte offsets in the source where the last event starts and ends.
+    ///
+    /// The end is exact for markup. Text is followed by markup, so its end may be known
+    /// only after the next event has been read, and until then it's the number of bytes read so far.
+    /// `StartElement` and `EndElement` of an empty-element tag like `<b/>` have the same range.
+    #[must_use]
+    pub fn event_byte_range(&self) -> Range<u64> {
+        self.parser.event_byte_range()
+    }
+
     /// Unwraps this `EventReader`, returning the underlying reader.
     ///
     /// Note that this operation is destructive; unwrapping the reader and wrapping it
@@ -94,6 +577,469 @@ impl<R: Read> EventReader<R> {
     }
 }
 
+/// An attribute with an undecoded value, see [`EventReader::lazy_attributes`]
+#[derive(Clone, Copy)]
+pub struct LazyAttribute<'a> {
+    attr: &'a OwnedAttribute,
+    pos: TextPosition,
+    parser: &'a PullParser,
+}
+
+impl<'a> LazyAttribute<'a> {
+    /// Name of the attribute, with its namespace
+    #[must_use]
+    pub fn name(&self) -> &'a OwnedName {
+        &self.attr.name
+    }
+
+    /// The value as written in the document, with entity and character references
+    #[must_use]
+    pub fn raw_value(&self) -> &'a str {
+        &self.attr.value
+    }
+
+    /// Expands references in the value. Doesn't allocate if there aren't any.
+    ///
+    /// Fails on undefined entities and malformed references. The error has the position of the value.
+    pub fn value(&self) -> Result<Cow<'a, str>> {
+        self.parser.unescape_lazy_value(&self.attr.value).map_err(|e| Error {
+            pos: self.pos,
+            kind: e.into(),
+            context: None,
+        })
+    }
+
+    /// Decodes the value, and makes a regular attribute
+    pub fn to_owned_attribute(&self) -> Result<OwnedAttribute> {
+        Ok(OwnedAttribute::new(self.attr.name.clone(), self.value()?))
+    }
+}
+
+impl fmt::Debug for LazyAttribute<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LazyAttribute")
+            .field("name", &self.attr.name)
+            .field("raw_value", &self.attr.value)
+            .finish()
+    }
+}
+
+/// Text that is read from the document on demand, see [`EventReader::text_reader`]
+pub struct TextReader<'a, R: Read> {
+    reader: &'a mut EventReader<R>,
+    chunk: String,
+    offset: usize,
+    done: bool,
+}
+
+impl<R: Read> Read for TextReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.offset >= self.chunk.len() {
+            if self.done {
+                return Ok(0);
+            }
+            let reader = &mut *self.reader;
+            match reader.parser.next(&mut reader.source) {
+                // whitespace-only parts are a part of the text too
+                Ok(XmlEvent::Characters(text) | XmlEvent::Whitespace(text) | XmlEvent::CData(text)) => {
+                    self.chunk = text;
+                    self.offset = 0;
+                },
+                Ok(event) => {
+                    // it belongs to the next call to EventReader::next
+                    reader.parser.push_back(event);
+                    self.done = true;
+                },
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+            }
+        }
+        let rest = &self.chunk.as_bytes()[self.offset..];
+        let n = rest.len().min(buf.len());
+        buf[..n].copy_from_slice(&rest[..n]);
+        self.offset += n;
+        Ok(n)
+    }
+}
+
+impl<R: Read> fmt::Debug for TextReader<'_, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TextReader")
+            .field("buffered", &(self.chunk.len() - self.offset))
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+/// A source that copies all bytes read from it to a sink, see [`EventReader::tee`]
+pub struct Tee<R, W> {
+    source: R,
+    sink: W,
+}
+
+impl<R, W> Tee<R, W> {
+    /// The copy of the input
+    pub fn sink(&self) -> &W { &self.sink }
+
+    /// The copy of the input
+    pub fn sink_mut(&mut self) -> &mut W { &mut self.sink }
+
+    /// Returns the original source and the sink
+    pub fn into_inner(self) -> (R, W) {
+        (self.source, self.sink)
+    }
+}
+
+impl<R: Read, W: Write> Read for Tee<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.source.read(buf)?;
+        self.sink.write_all(&buf[..n])?;
+        Ok(n)
+    }
+}
+
+/// Options of a reader that can be changed while it's reading, returned by [`EventReader::controls`].
+///
+/// Changes take effect from the next event. Markup or text that has been partially read
+/// for the next event may have been checked against the old limits.
+///
+/// The changes last until the end of the current document. The next document of the stream,
+/// [`EventReader::reuse`] and [`ReaderPool::recycle`] start with the configuration the reader has been created with.
+///
+/// ```rust
+/// use xml::reader::{EventReader, XmlEvent};
+///
+/// let mut events = EventReader::from_str("<feed><title/><entry>&nbsp;</entry></feed>").into_iter();
+/// events.by_ref().take(3).for_each(drop); // StartDocument, <feed>, <title>
+/// // e.g. after learning the dialect of the document
+/// events.controls().add_entity("nbsp", "\u{a0}").set_max_data_length(1000);
+/// assert!(events.any(|e| e.unwrap() == XmlEvent::Characters("\u{a0}".into())));
+/// ```
+pub struct ParserControls<'a> {
+    parser: &'a mut PullParser,
+}
+
+impl ParserControls<'_> {
+    /// The options in effect
+    #[must_use]
+    pub fn config(&self) -> &ParserConfig2 {
+        self.parser.config()
+    }
+
+    /// Defines an entity like [`ParserConfig::add_entity`]
+    pub fn add_entity(&mut self, name: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.parser.config_mut().c.extra_entities.insert(name.into(), value.into());
+        self
+    }
+
+    /// See [`ParserConfig2::max_name_length`]
+    pub fn set_max_name_length(&mut self, max: usize) -> &mut Self {
+        self.parser.config_mut().max_name_length = max;
+        self
+    }
+
+    /// See [`ParserConfig2::max_attributes`]
+    pub fn set_max_attributes(&mut self, max: usize) -> &mut Self {
+        self.parser.config_mut().max_attributes = max;
+        self
+    }
+
+    /// See [`ParserConfig2::max_attribute_length`]
+    pub fn set_max_attribute_length(&mut self, max: usize) -> &mut Self {
+        self.parser.config_mut().max_attribute_length = max;
+        self
+    }
+
+    /// See [`ParserConfig2::max_data_length`]
+    pub fn set_max_data_length(&mut self, max: usize) -> &mut Self {
+        self.parser.config_mut().max_data_length = max;
+        self
+    }
+
+    /// See [`ParserConfig2::lazy_text`]
+    pub fn set_lazy_text(&mut self, lazy: bool) -> &mut Self {
+        self.parser.config_mut().lazy_text = lazy;
+        self
+    }
+
+    /// See [`ParserConfig2::lazy_attributes`]
+    pub fn set_lazy_attributes(&mut self, lazy: bool) -> &mut Self {
+        self.parser.config_mut().lazy_attributes = lazy;
+        self
+    }
+}
+
+impl fmt::Debug for ParserControls<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ParserControls").field("config", self.parser.config()).finish()
+    }
+}
+
+impl<R: Read + Seek> EventReader<R> {
+    /// Saves the state of the parser and the position in the source, to continue from there later with [`rewind_to`](Self::rewind_to).
+    ///
+    /// This allows reading a document twice, e.g. to look ahead before deciding how to parse
+    /// the rest of it, without creating a new reader. The parser reads the source one byte
+    /// at a time, so the source position matches the parser's state exactly.
+    ///
+    /// ```rust
+    /// use std::io::Cursor;
+    /// use xml::reader::{EventReader, XmlEvent};
+    ///
+    /// let mut reader = EventReader::new(Cursor::new("<list><item/><item/></list>"));
+    /// reader.next().unwrap(); // StartDocument
+    /// reader.next().unwrap(); // <list>
+    /// let checkpoint = reader.checkpoint().unwrap();
+    /// let mut count = 0;
+    /// while let XmlEvent::StartElement { .. } | XmlEvent::EndElement { .. } = reader.next().unwrap() {
+    ///     count += 1;
+    /// }
+    /// assert_eq!(count, 5);
+    /// reader.rewind_to(&checkpoint).unwrap();
+    /// assert!(matches!(reader.next().unwrap(), XmlEvent::StartElement { name, .. } if name.local_name == "item"));
+    /// ```
+    pub fn checkpoint(&mut self) -> Result<Checkpoint> {
+        Ok(Checkpoint {
+            parser: self.parser.clone(),
+            source_position: self.source.stream_position()?,
+        })
+    }
+
+    /// Restores the state saved by [`checkpoint`](Self::checkpoint), and seeks the source back to its position.
+    ///
+    /// The checkpoint must be from this reader, or restored with [`Checkpoint::from_bytes`] for a reader
+    /// of the same source. It can be used more than once.
+    /// A [`RawBytesObserver`] sees the bytes after the checkpoint again.
+    pub fn rewind_to(&mut self, checkpoint: &Checkpoint) -> Result<()> {
+        self.source.seek(SeekFrom::Start(checkpoint.source_position))?;
+        self.parser = checkpoint.parser.clone();
+        Ok(())
+    }
+}
+
+/// Parsers that can be reused for many documents, also from multiple threads.
+///
+/// Creating a reader takes a parser from the pool, and [`recycle`](Self::recycle) returns it.
+/// Parsers keep their buffers, like with [`EventReader::reuse`], but the pool can be shared by readers of different types.
+///
+/// ```rust
+/// use xml::reader::{ParserConfig2, ReaderPool, XmlEvent};
+///
+/// let pool = ReaderPool::new(ParserConfig2::new().trim_whitespace(true));
+/// for message in ["<m>1</m>", "<m>2</m>"] {
+///     let mut reader = pool.reader(message.as_bytes());
+///     while reader.next().unwrap() != XmlEvent::EndDocument {}
+///     pool.recycle(reader);
+/// }
+/// ```
+pub struct ReaderPool {
+    config: ParserConfig2,
+    idle: Mutex<Vec<PullParser>>,
+}
+
+impl ReaderPool {
+    /// Creates an empty pool. Parsers are created as needed, using the config.
+    #[must_use]
+    pub fn new(config: impl Into<ParserConfig2>) -> Self {
+        Self { config: config.into(), idle: Mutex::new(Vec::new()) }
+    }
+
+    /// Creates a reader with a recycled parser, if there's one
+    pub fn reader<R: Read>(&self, source: R) -> EventReader<R> {
+        let parser = self.idle.lock().ok().and_then(|mut idle| idle.pop());
+        let parser = parser.unwrap_or_else(|| PullParser::new(self.config.clone()));
+        EventReader { source, parser }
+    }
+
+    /// Returns the reader's parser to the pool, and gives back the source.
+    ///
+    /// The parser gets the pool's config, even if the reader was made elsewhere, or its config has been changed with [`controls`](EventReader::controls).
+    pub fn recycle<R: Read>(&self, reader: EventReader<R>) -> R {
+        let EventReader { source, mut parser } = reader;
+        parser.reset_with_config(self.config.clone());
+        if let Ok(mut idle) = self.idle.lock() {
+            idle.push(parser);
+        }
+        source
+    }
+
+    /// The config of the readers
+    #[must_use]
+    pub fn config(&self) -> &ParserConfig2 {
+        &self.config
+    }
+}
+
+impl fmt::Debug for ReaderPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReaderPool").field("config", &self.config).finish_non_exhaustive()
+    }
+}
+
+/// A source of [`Token`]s for [`CoreParser`], e.g. a different lexer, or a stream of tokens saved earlier.
+///
+/// Any iterator of tokens is a source too.
+pub trait TokenSource {
+    /// Returns the next token, or `None` at the end of the document
+    fn next_token(&mut self) -> Result<Option<Token>>;
+
+    /// Position of the token returned last, used for events and errors
+    fn position(&self) -> TextPosition {
+        TextPosition::new()
+    }
+}
+
+impl<I: Iterator<Item = Token>> TokenSource for I {
+    #[inline]
+    fn next_token(&mut self) -> Result<Option<Token>> {
+        Ok(self.next())
+    }
+}
+
+/// The parser used by [`EventReader`], but without the `Read` source and its lexer.
+///
+/// It takes [`Token`]s from a [`TokenSource`] with [`advance`](Self::advance), or one by one with [`feed_token`](Self::feed_token).
+/// Replacement text of entities declared in the DTD is tokenized by the parser itself.
+///
+/// ```rust
+/// use xml::reader::{CoreParser, ParserConfig2, Token, XmlEvent};
+///
+/// let mut parser = CoreParser::new(ParserConfig2::new());
+/// let tokens = [Token::OpeningTagStart, Token::Character('a'), Token::EmptyTagEnd];
+/// let mut events = Vec::new();
+/// for token in tokens {
+///     events.extend(parser.feed_token(token).unwrap());
+/// }
+/// assert!(matches!(events[1], XmlEvent::StartElement { .. }));
+/// assert!(matches!(events[2], XmlEvent::EndElement { .. }));
+/// // the end of the document
+/// assert_eq!(parser.advance(&mut std::iter::empty()).unwrap(), XmlEvent::EndDocument);
+/// ```
+#[derive(Clone)]
+pub struct CoreParser {
+    parser: PullParser,
+}
+
+impl CoreParser {
+    /// Creates a parser with the configuration. Options that affect only reading of bytes, such as encoding, are ignored.
+    #[must_use]
+    pub fn new(config: impl Into<ParserConfig2>) -> Self {
+        Self { parser: PullParser::new(config) }
+    }
+
+    /// Takes tokens from the source until they complete an event.
+    ///
+    /// The end of the source ends the document. Errors of the source are returned,
+    /// and like other errors, they end parsing.
+    pub fn advance<S: TokenSource + ?Sized>(&mut self, tokens: &mut S) -> Result<XmlEvent> {
+        self.parser.next(&mut Tokens(tokens))
+    }
+
+    /// Parses one token, and returns the events that it has completed, if any.
+    ///
+    /// The document doesn't end until [`advance`](Self::advance) gets to the end of its source.
+    pub fn feed_token(&mut self, token: Token) -> Result<Vec<XmlEvent>> {
+        let mut input = FedToken::new(token);
+        let mut events = Vec::new();
+        loop {
+            match self.parser.next(&mut input) {
+                Ok(XmlEvent::EndDocument) => {
+                    events.push(XmlEvent::EndDocument);
+                    return Ok(events);
+                },
+                Ok(event) => events.push(event),
+                Err(e) if FedToken::is_need_more_tokens(&e) => return Ok(events),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Statistics of the document parsed so far
+    #[must_use]
+    pub fn stats(&self) -> ReaderStats {
+        self.parser.stats()
+    }
+
+    /// Prepares the parser for a new document, keeping the configuration
+    pub fn reset(&mut self) {
+        self.parser.reset();
+    }
+}
+
+impl Position for CoreParser {
+    /// Returns the position of the last event produced by the parser
+    #[inline]
+    fn position(&self) -> TextPosition {
+        self.parser.position()
+    }
+}
+
+impl fmt::Debug for CoreParser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CoreParser").field("position", &self.parser.position()).finish_non_exhaustive()
+    }
+}
+
+/// Saved state of an [`EventReader`], see [`EventReader::checkpoint`]
+#[derive(Clone)]
+pub struct Checkpoint {
+    parser: PullParser,
+    source_position: u64,
+}
+
+impl Checkpoint {
+    /// Offset in the source where reading continues after [`EventReader::rewind_to`]
+    #[must_use]
+    pub fn source_position(&self) -> u64 {
+        self.source_position
+    }
+
+    /// Encodes the state, so that parsing can be resumed later, e.g. after a restart of the program.
+    ///
+    /// Only the state between events inside the root element can be saved, and `None` is returned
+    /// when the checkpoint is in the middle of text or markup, or outside of the root element.
+    /// A checkpoint after `StartElement` or `EndElement` can always be saved, except after
+    /// the `StartElement` of an empty-element tag like `<b/>`, which is followed by its `EndElement`.
+    ///
+    /// The saved state has the open elements, namespaces, entities and the position in the source.
+    /// Statistics, warnings and other information from the DTD, such as notations and attribute types, aren't kept.
+    ///
+    /// ```rust
+    /// use std::io::Cursor;
+    /// use xml::reader::{Checkpoint, EventReader, ParserConfig2, XmlEvent};
+    ///
+    /// let doc = r#"<!DOCTYPE log [<!ENTITY me "xml-rs">]><log xmlns:x="urn:x"><run>1</run><run>&me;</run></log>"#;
+    /// let mut reader = EventReader::new(Cursor::new(doc));
+    /// while !matches!(reader.next().unwrap(), XmlEvent::EndElement { .. }) {}
+    /// let saved: Vec<u8> = reader.checkpoint().unwrap().to_bytes().unwrap();
+    ///
+    /// // later, with the same config
+    /// let mut reader = EventReader::new_with_config(Cursor::new(doc), ParserConfig2::new());
+    /// reader.rewind_to(&Checkpoint::from_bytes(&saved, ParserConfig2::new()).unwrap()).unwrap();
+    /// reader.next().unwrap(); // <run>
+    /// assert_eq!(reader.next().unwrap(), XmlEvent::Characters("xml-rs".into()));
+    /// ```
+    #[must_use]
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        self.parser.save_state(self.source_position)
+    }
+
+    /// Restores the state encoded by [`to_bytes`](Self::to_bytes), for use with [`EventReader::rewind_to`].
+    ///
+    /// The config should be the same as the one of the reader that has saved the state.
+    pub fn from_bytes(bytes: &[u8], config: impl Into<ParserConfig2>) -> Result<Self> {
+        let mut parser = PullParser::new(config);
+        let source_position = parser.restore_state(bytes)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid saved checkpoint"))?;
+        Ok(Self { parser, source_position })
+    }
+}
+
+impl fmt::Debug for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Checkpoint").field("source_position", &self.source_position).finish_non_exhaustive()
+    }
+}
+
 impl<B: Read> Position for EventReader<B> {
     /// Returns the position of the last event produced by the reader.
     #[inline]
@@ -115,6 +1061,7 @@ impl<R: Read> IntoIterator for EventReader<R> {
 ///
 /// When the next event is `xml::event::Error` or `xml::event::EndDocument`, then
 /// it will be returned by the iterator once, and then it will stop producing events.
+#[derive(Clone)]
 pub struct Events<R: Read> {
     reader: EventReader<R>,
     finished: bool,
@@ -136,6 +1083,16 @@ impl<R: Read> Events<R> {
     ///
     /// It's not recommended to use it while the events are still being parsed
     pub fn source_mut(&mut self) -> &mut R { &mut self.reader.source }
+
+    /// Entities declared so far in the document's DTD or in the [`EntityTable`], with their replacement text
+    pub fn declared_entities(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.reader.declared_entities()
+    }
+
+    /// Options that can be changed between events, see [`EventReader::controls`]
+    pub fn controls(&mut self) -> ParserControls<'_> {
+        self.reader.controls()
+    }
 }
 
 impl<R: Read> FusedIterator for Events<R> {
@@ -158,6 +1115,88 @@ impl<R: Read> Iterator for Events<R> {
     }
 }
 
+/// An iterator over XML events and their start positions, created by [`EventReader::into_events_with_positions`].
+///
+/// It stops after `EndDocument` or an error, like [`Events`]. Errors have their own position.
+#[derive(Clone)]
+pub struct PositionedEvents<R: Read> {
+    reader: EventReader<R>,
+    finished: bool,
+}
+
+impl<R: Read> PositionedEvents<R> {
+    /// Unwraps the iterator, returning the internal `EventReader`.
+    #[inline]
+    pub fn into_inner(self) -> EventReader<R> {
+        self.reader
+    }
+
+    /// Options that can be changed between events, see [`EventReader::controls`]
+    pub fn controls(&mut self) -> ParserControls<'_> {
+        self.reader.controls()
+    }
+}
+
+impl<R: Read> FusedIterator for PositionedEvents<R> {
+}
+
+impl<R: Read> Iterator for PositionedEvents<R> {
+    type Item = Result<(TextPosition, XmlEvent)>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished && !self.reader.parser.is_ignoring_end_of_stream() {
+            return None;
+        }
+        let ev = self.reader.next();
+        if let Ok(XmlEvent::EndDocument) | Err(_) = ev {
+            self.finished = true;
+        }
+        Some(ev.map(|ev| (self.reader.position(), ev)))
+    }
+}
+
+/// An iterator over events of concatenated documents, created by [`EventReader::documents`].
+///
+/// Each document ends with `EndDocument`. The iterator stops after the last document or the first error.
+pub struct DocumentStream<R: Read> {
+    reader: EventReader<R>,
+    finished: bool,
+}
+
+impl<R: Read> DocumentStream<R> {
+    /// Unwraps the iterator, returning the internal `EventReader`.
+    #[inline]
+    pub fn into_inner(self) -> EventReader<R> {
+        self.reader
+    }
+
+    /// Options that can be changed between events, see [`EventReader::controls`]
+    pub fn controls(&mut self) -> ParserControls<'_> {
+        self.reader.controls()
+    }
+}
+
+impl<R: Read> FusedIterator for DocumentStream<R> {
+}
+
+impl<R: Read> Iterator for DocumentStream<R> {
+    type Item = Result<XmlEvent>;
+
+    fn next(&mut self) -> Option<Result<XmlEvent>> {
+        if self.finished {
+            return None;
+        }
+        let ev = self.reader.next();
+        match ev {
+            Ok(XmlEvent::EndDocument) => self.finished = !self.reader.parser.start_next_document(),
+            Err(_) => self.finished = true,
+            _ => {},
+        }
+        Some(ev)
+    }
+}
+
 impl<'r> EventReader<&'r [u8]> {
     /// A convenience method to create an `XmlReader` from a string slice.
     #[inline]
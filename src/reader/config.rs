@@ -1,19 +1,22 @@
 //! Contains parser configuration structure.
 use std::collections::HashMap;
+use std::fmt;
 use std::io::Read;
+use std::sync::{Arc, Mutex};
 
-use crate::reader::EventReader;
+use crate::reader::{EntityStats, EntityTable, EventMask, EventReader, XmlEvent};
 use crate::util::Encoding;
 
 /// Limits to defend from billion laughs attack
-const DEFAULT_MAX_ENTITY_EXPANSION_LENGTH: usize = 1_000_000;
-const DEFAULT_MAX_ENTITY_EXPANSION_DEPTH: u8 = 10;
+pub(crate) const DEFAULT_MAX_ENTITY_EXPANSION_LENGTH: usize = 1_000_000;
+pub(crate) const DEFAULT_MAX_ENTITY_EXPANSION_DEPTH: u8 = 10;
 
 /// Parser configuration structure. **There are more config methods than public fileds — see methods below**.
 ///
 /// This structure contains various configuration options which affect
 /// behavior of the parser.
 #[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
 pub struct ParserConfig {
     /// Whether or not should whitespace in textual events be removed. Default is false.
     ///
@@ -26,6 +29,9 @@ pub struct ParserConfig {
     ///
     /// This option does not affect CDATA events, unless `cdata_to_characters`
     /// option is also set. In that case CDATA content will also be trimmed.
+    ///
+    /// [`ParserConfig2`] can trim text and drop whitespace-only text separately,
+    /// see [`ParserConfig2::trim_text`] and [`ParserConfig2::drop_whitespace_only_text`].
     pub trim_whitespace: bool,
 
     /// Whether or not should whitespace be converted to characters.
@@ -57,7 +63,8 @@ pub struct ParserConfig {
     /// a single event, that is, their data will be concatenated.
     ///
     /// Multiple sequential `Characters` events are only possible if either
-    /// `cdata_to_characters` or `ignore_comments` are set. Otherwise character
+    /// `cdata_to_characters` or `ignore_comments` are set, or
+    /// [`ParserConfig2::ignore_processing_instructions`]. Otherwise character
     /// events will always be separated by other events.
     pub coalesce_characters: bool,
 
@@ -190,28 +197,355 @@ gen_setters! { ParserConfig,
     ignore_root_level_whitespace: val bool
 }
 
+/// A callback in the config, shared by the clones of the config and the readers that use it.
+///
+/// Clones are equal to each other, since they call the same closure.
+pub struct Callback<F: ?Sized>(Arc<Mutex<F>>);
+
+impl<F: ?Sized> Callback<F> {
+    /// Calls the closure, unless it has panicked before
+    fn call<R>(&self, call: impl FnOnce(&mut F) -> R) -> Option<R> {
+        self.0.lock().ok().map(|mut callback| call(&mut callback))
+    }
+}
+
+impl<F: ?Sized> Clone for Callback<F> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<F: ?Sized> PartialEq for Callback<F> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<F: ?Sized> Eq for Callback<F> {}
+
+impl<F: ?Sized> fmt::Debug for Callback<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Callback")
+    }
+}
+
+/// Callback that gets the raw bytes of the document, see [`ParserConfig2::raw_bytes_observer`]
+pub type RawBytesObserver = Callback<dyn FnMut(&[u8]) + Send>;
+
+impl RawBytesObserver {
+    /// Wraps the callback
+    pub fn new(callback: impl FnMut(&[u8]) + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(callback)))
+    }
+
+    pub(crate) fn observe(&self, bytes: &[u8]) {
+        self.call(|callback| callback(bytes));
+    }
+}
+
+/// Callback that can reject expansions of entities, see [`ParserConfig2::check_entity_expansion`]
+pub type EntityExpansionCheck = Callback<dyn FnMut(&str, &EntityStats) -> bool + Send>;
+
+impl EntityExpansionCheck {
+    /// Wraps the callback
+    pub fn new(callback: impl FnMut(&str, &EntityStats) -> bool + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(callback)))
+    }
+
+    pub(crate) fn allows(&self, name: &str, stats: &EntityStats) -> bool {
+        self.call(|callback| callback(name, stats)).unwrap_or(true)
+    }
+}
+
+/// How the reader treats text and CDATA, as chosen by [`ParserConfig2::text_handling`]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum TextHandling {
+    /// Return the text as a whole, like without the hook
+    Collect,
+    /// Return the text in parts of bounded size, like [`ParserConfig2::lazy_text`]
+    Stream,
+    /// Don't return the text at all, and don't keep more than a small part of it in memory
+    Skip,
+}
+
+/// Callback that chooses how to read the following text, see [`ParserConfig2::text_handling`]
+pub type TextHandlingHook = Callback<dyn FnMut(&XmlEvent) -> TextHandling + Send>;
+
+impl TextHandlingHook {
+    /// Wraps the callback
+    pub fn new(callback: impl FnMut(&XmlEvent) -> TextHandling + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(callback)))
+    }
+
+    pub(crate) fn next(&self, event: &XmlEvent) -> TextHandling {
+        self.call(|callback| callback(event)).unwrap_or(TextHandling::Collect)
+    }
+}
+
+/// Where the next document starts in a stream of documents, see [`ParserConfig2::multiple_documents`]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum DocumentDelimiter {
+    /// Every document after the first one must start with `<?xml`
+    Declaration,
+    /// A new document starts with `<?xml` or a root element
+    DeclarationOrElement,
+}
+
+/// What to do with character references to characters that aren't allowed in XML,
+/// like `&#1;` in XML 1.0, see [`ParserConfig2::invalid_char_references`]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum InvalidCharReferences {
+    /// The document is ill-formed
+    Error,
+    /// Keep the character, and report it in [`EventReader::warnings`](crate::EventReader::warnings)
+    Warning,
+    /// Replace the character with U+FFFD
+    Replace,
+}
+
+/// What to do with text before or after the root element, like junk around a feed,
+/// see [`ParserConfig2::text_outside_root`]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum TextOutsideRoot {
+    /// The document is ill-formed
+    Error,
+    /// Skip the text, and report it in [`EventReader::warnings`](crate::EventReader::warnings)
+    Warning,
+    /// Skip the text silently
+    Ignore,
+}
+
 /// Backwards-compatible extension of `ParserConfig`, which will eventually be merged into the original `ParserConfig` struct
 #[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
 #[non_exhaustive]
 pub struct ParserConfig2 {
+    #[cfg_attr(feature = "serde", serde(flatten))]
     pub(crate) c: ParserConfig,
 
     /// Use this encoding as the default. Necessary for UTF-16 files without BOM.
+    /// Stored as the encoding name in config files.
+    #[cfg_attr(feature = "serde", serde(with = "encoding_name"))]
     pub override_encoding: Option<Encoding>,
 
     /// Allow `<?xml encoding="…">` to contain unsupported encoding names,
     /// and interpret them as Latin1 instead. This will mangle non-ASCII characters, but usually it won't fail parsing.
     pub ignore_invalid_encoding_declarations: bool,
 
+    /// Pause instead of failing when `<?xml encoding="…">` names an unsupported encoding.
+    ///
+    /// The parser will return a recoverable error, and the application can decode the rest of the
+    /// document itself. See [`EventReader::resume_with_utf8_source`].
+    pub defer_unsupported_encoding: bool,
+
     /// Documents with multiple root elements are ill-formed
     pub allow_multiple_root_elements: bool,
 
+    /// Accept tags that declare the same namespace prefix more than once, like `<a xmlns:p="1" xmlns:p="2">`,
+    /// and use the last declaration. Such documents are ill-formed, so by default they're an error.
+    /// When allowed, each repeated declaration is reported in [`EventReader::warnings`](crate::EventReader::warnings).
+    pub allow_duplicate_namespace_declarations: bool,
+
+    /// Accept documents that misuse the reserved `xml` and `xmlns` prefixes and namespace names,
+    /// like `<xmlns:a>` or `xmlns:p="http://www.w3.org/2000/xmlns/"`.
+    /// They're ill-formed under the Namespaces in XML spec, so by default they're an error.
+    /// When allowed, each misuse is reported in [`EventReader::warnings`](crate::EventReader::warnings).
+    pub allow_reserved_namespace_misuse: bool,
+
+    /// Record how attributes were written: their order in the tag, quote characters and unexpanded values.
+    /// See [`EventReader::attribute_details`](crate::EventReader::attribute_details).
+    pub preserve_source_details: bool,
+
+    /// Change local names of elements and attributes to lowercase, e.g. for documents that aren't consistent
+    /// about letter case. Prefixes and namespace declarations are not changed, and end tags
+    /// match start tags regardless of case.
+    pub lowercase_names: bool,
+
+    /// Emit `XmlEvent::Doctype` with the complete `<!DOCTYPE …>` declaration as it was written,
+    /// including the internal subset. By default the DOCTYPE is parsed, but not reported.
+    pub capture_doctype_raw: bool,
+
+    /// Check that `xml:id` attributes are valid and unique, and collect them.
+    /// See [`EventReader::xml_ids`](crate::EventReader::xml_ids).
+    pub track_xml_ids: bool,
+
+    /// Read ID, IDREF and IDREFS types from `<!ATTLIST>` declarations, and check that every
+    /// IDREF refers to an ID in the document. Unresolved references are reported at the end of the document.
+    /// `xml:id` attributes count as IDs too.
+    pub check_idrefs: bool,
+
+    /// Accept a literal `<` and a `&` that doesn't start a reference in attribute values, like
+    /// `href="?a=1&b=2"`, and report them in [`EventReader::warnings`](crate::EventReader::warnings)
+    /// instead of failing. Such documents are not well-formed, but are common in feeds with scraped HTML.
+    pub lenient_attribute_values: bool,
+
+    /// Handling of text outside the root element, which includes `>`, `&` and other characters that
+    /// don't start markup. Default is an error. Other modes skip it until the next markup,
+    /// which is useful for feeds with junk before or after the root element.
+    ///
+    /// Leading junk has to come after the XML declaration, see [`skip_leading_garbage_until_lt`](Self::skip_leading_garbage_until_lt)
+    /// for junk before it.
+    pub text_outside_root: TextOutsideRoot,
+
+    /// Skip processing instructions inside the root element, like `ignore_comments` skips comments.
+    /// With `coalesce_characters` the text around them is merged into one event. Default is false.
+    ///
+    /// Processing instructions outside the root element are always reported, because they may be
+    /// XML declarations of following documents.
+    pub ignore_processing_instructions: bool,
+
+    /// Called with bytes of the source as the parser consumes them, in chunks that end at character boundaries.
+    /// This allows computing a digest of exactly what has been parsed, regardless of buffering of the source.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub raw_bytes_observer: Option<RawBytesObserver>,
+
+    /// Called before every expansion of an entity declared in the DTD, added with [`ParserConfig::add_entity`],
+    /// or from `html_entities`, with the entity's statistics including this expansion.
+    /// Returning `false` stops parsing with an error.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub entity_expansion_check: Option<EntityExpansionCheck>,
+
+    /// Called with every `StartElement` and `EndElement` as it's read, to choose how to handle the text up to the next one.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub text_handling_hook: Option<TextHandlingHook>,
+
+    /// Number of columns a tab character advances to, for positions that match what editors display.
+    /// The default is 1, which counts tabs like any other character. Must not be 0.
+    pub tab_width: u8,
+
+    /// Number of most recently read characters of the source to keep for errors, so that they
+    /// can show the source around the error with a caret, even when the source can't be read again.
+    /// See [`Error::context`](crate::reader::Error::context). The default is 0, which doesn't keep any.
+    pub error_context: usize,
+
+    /// Kinds of events that the parser emits. Default is all of them.
+    ///
+    /// Other events are skipped, and attributes are left out of `StartElement` events unless
+    /// [`EventMask::ATTRIBUTES`] is included. Unwanted comments, processing instructions, whitespace
+    /// and DOCTYPEs aren't even buffered. This is useful for fast structural scans, e.g. counting elements of huge files.
+    pub events_only: EventMask,
+
+    /// Make `WouldBlock` and `TimedOut` errors of the source recoverable, e.g. for non-blocking sockets
+    /// or sockets with a read timeout. Such errors are returned from `next()`, and parsing continues
+    /// where it stopped when `next()` is called again.
+    ///
+    /// While the parser is inside markup, like a tag or a comment, it allows only this many such errors in a row,
+    /// and then the error is final. This detects peers that stalled in the middle of a tag.
+    /// Between markup the parser can wait indefinitely, e.g. for the next stanza of a stream.
+    ///
+    /// By default all I/O errors are final.
+    pub max_stalled_reads: Option<u32>,
+
+    /// Leave attributes out of `StartElement` events, and decode their values only when asked.
+    /// See [`EventReader::lazy_attributes`](crate::EventReader::lazy_attributes).
+    ///
+    /// Entity and character references in values aren't checked until the value is decoded.
+    /// Entity expansions are counted in the stats, and passed to [`entity_expansion_check`](Self::entity_expansion_check),
+    /// every time a value is decoded. Namespace declarations are always processed.
+    pub lazy_attributes: bool,
+
+    /// Skip anything before the first `<` of the document, such as leftovers of HTTP headers,
+    /// log prefixes, or a repeated byte order mark, instead of failing at the first byte.
+    ///
+    /// The number of skipped bytes is reported by [`EventReader::warnings`](crate::EventReader::warnings).
+    /// This works only for documents in ASCII-compatible encodings, or in UTF-16 with a byte order mark
+    /// and no garbage.
+    pub skip_leading_garbage_until_lt: bool,
+
+    /// Read concatenated documents from the same source, e.g. from a log file or a pipe.
+    ///
+    /// After the root element the next document can start, and the reader emits `EndDocument`
+    /// for the current one. [`EventReader::next_document`](crate::EventReader::next_document)
+    /// continues with the next document, and [`EventReader::documents`](crate::EventReader::documents)
+    /// reads all of them.
+    pub multiple_documents: Option<DocumentDelimiter>,
+
+    /// Return long text and CDATA in several `Characters` or `CData` events of up to 64KB each,
+    /// instead of collecting all of the text first. [`EventReader::text_reader`](crate::EventReader::text_reader)
+    /// reads such text as a stream.
+    ///
+    /// Text is split only before characters that aren't whitespace. With `trim_whitespace`,
+    /// whitespace is trimmed only at the start of the first part and the end of the last one.
+    pub lazy_text: bool,
+
+    /// Remove leading and trailing whitespace of `Characters` events. Text that is only whitespace isn't changed.
+    ///
+    /// Together with `drop_whitespace_only_text` this is the same as `trim_whitespace`.
+    pub trim_text: bool,
+
+    /// Replace every run of whitespace inside `Characters` events with a single space.
+    pub collapse_whitespace: bool,
+
+    /// Omit text that is only whitespace, which is otherwise reported as `Whitespace` events,
+    /// or as `Characters` if `whitespace_to_characters` is set.
+    pub drop_whitespace_only_text: bool,
+
+    /// Don't trim or collapse whitespace written as character references, like `&#xA;` or `&#32;`.
+    /// Text that contains them isn't whitespace-only. This is how the XML spec treats such references.
+    ///
+    /// By default they're trimmed like any other whitespace.
+    pub preserve_escaped_whitespace: bool,
+
+    /// Handling of character references to control characters and other characters that XML doesn't allow,
+    /// including surrogates like `&#xD800;`. Default is an error. Surrogates can't be kept in a string,
+    /// so `Warning` replaces them with U+FFFD. References to numbers above U+10FFFF are always an error,
+    /// unless `replace_unknown_entity_references` is set.
+    ///
+    /// Values of [`lazy_attributes`](Self::lazy_attributes) don't report warnings.
+    pub invalid_char_references: InvalidCharReferences,
+
+    /// Treat character references to Unicode noncharacters, like `&#xFDD0;` or `&#x1FFFF;`, as invalid,
+    /// and handle them as [`invalid_char_references`](Self::invalid_char_references) says.
+    ///
+    /// XML allows them, but they're not meant to be interchanged. Default is false.
+    pub reject_noncharacter_references: bool,
+
+    /// Convert names of elements and attributes to Unicode Normalization Form C, so that names
+    /// written with different forms of the same characters, like `é` and `e\u{301}`, are equal.
+    /// End tags are matched after the conversion.
+    #[cfg(feature = "unicode-normalization")]
+    pub normalize_names: bool,
+
+    /// Convert text, CDATA and attribute values to Unicode Normalization Form C.
+    /// Values of [`lazy_attributes`](Self::lazy_attributes) aren't converted.
+    #[cfg(feature = "unicode-normalization")]
+    pub normalize_text: bool,
+
+    /// Recognize the named character references of HTML5, like `&nbsp;`, `&mdash;` or `&rsquo;`,
+    /// without declaring them in a DTD. Entities declared in the document or in `extra_entities` take precedence.
+    #[cfg(feature = "html_entities")]
+    pub html_entities: bool,
+
+    /// Check `<xop:Include href="cid:…"/>` elements of [XOP](https://www.w3.org/TR/xop10/) (used by SOAP MTOM),
+    /// and make the content-id of the attachment available from [`EventReader::xop_content_id`](crate::EventReader::xop_content_id).
+    /// Their extension elements are skipped, so `StartElement` of `xop:Include` is followed by its `EndElement`.
+    pub xop_includes: bool,
+
+    /// Reject documents whose root element isn't in this namespace, see [`require_namespace`](Self::require_namespace)
+    pub required_namespace: Option<String>,
+    /// Reject documents with elements or attributes in these namespaces, see [`forbid_namespaces`](Self::forbid_namespaces)
+    pub forbidden_namespaces: Vec<String>,
+    /// Reject documents whose root element has a different local name, see [`require_root_element`](Self::require_root_element)
+    pub required_root_element: Option<String>,
+
+    /// Entities declared before the document, and replacement text of external DTDs.
+    /// Unlike `extra_entities`, these can contain markup.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub entity_table: EntityTable,
+
     /// Abort if custom entities create a string longer than this
     pub max_entity_expansion_length: usize,
     /// Entities can expand into other entities this many times (be careful about exponential cost!)
     pub max_entity_expansion_depth: u8,
 
-    /// Maximum length of tag name or attribute name
+    /// Maximum length of tag name or attribute name. Must not be 0.
     pub max_name_length: usize,
 
     /// Max number of attributes per element
@@ -222,6 +556,9 @@ pub struct ParserConfig2 {
 
     /// Maximum length of strings reprsenting characters, comments, and processing instructions
     pub max_data_length: usize,
+
+    /// Max number of [`warnings`](crate::EventReader::warnings) kept. Later ones are dropped.
+    pub max_warnings: usize,
 }
 
 impl Default for ParserConfig2 {
@@ -230,12 +567,52 @@ impl Default for ParserConfig2 {
             c: ParserConfig::default(),
             override_encoding: None,
             ignore_invalid_encoding_declarations: false,
+            defer_unsupported_encoding: false,
             allow_multiple_root_elements: true,
+            allow_duplicate_namespace_declarations: false,
+            allow_reserved_namespace_misuse: false,
+            preserve_source_details: false,
+            lowercase_names: false,
+            capture_doctype_raw: false,
+            track_xml_ids: false,
+            check_idrefs: false,
+            lenient_attribute_values: false,
+            text_outside_root: TextOutsideRoot::Error,
+            ignore_processing_instructions: false,
+            raw_bytes_observer: None,
+            entity_expansion_check: None,
+            text_handling_hook: None,
+            tab_width: 1,
+            error_context: 0,
+            events_only: EventMask::ALL,
+            max_stalled_reads: None,
+            lazy_attributes: false,
+            skip_leading_garbage_until_lt: false,
+            multiple_documents: None,
+            lazy_text: false,
+            trim_text: false,
+            collapse_whitespace: false,
+            drop_whitespace_only_text: false,
+            preserve_escaped_whitespace: false,
+            invalid_char_references: InvalidCharReferences::Error,
+            reject_noncharacter_references: false,
+            #[cfg(feature = "unicode-normalization")]
+            normalize_names: false,
+            #[cfg(feature = "unicode-normalization")]
+            normalize_text: false,
+            #[cfg(feature = "html_entities")]
+            html_entities: false,
+            xop_includes: false,
+            required_namespace: None,
+            forbidden_namespaces: Vec::new(),
+            required_root_element: None,
+            entity_table: EntityTable::new(),
             max_entity_expansion_length: DEFAULT_MAX_ENTITY_EXPANSION_LENGTH,
             max_entity_expansion_depth: DEFAULT_MAX_ENTITY_EXPANSION_DEPTH,
             max_attributes: 1 << 16,
             max_attribute_length: 1 << 30,
             max_data_length: 1 << 30,
+            max_warnings: 1 << 16,
             max_name_length: 1 << 18,
         }
     }
@@ -266,6 +643,127 @@ impl ParserConfig2 {
         self
     }
 
+    /// Stops the parser from emitting these kinds of events, in addition to the ones already excluded by
+    /// [`events_only`](Self::events_only).
+    ///
+    /// ```rust
+    /// use xml::reader::{EventMask, ParserConfig2, XmlEvent};
+    ///
+    /// let mut reader = ParserConfig2::new()
+    ///     .event_filter(EventMask::START_DOCUMENT | EventMask::PROCESSING_INSTRUCTION | EventMask::WHITESPACE)
+    ///     .create_reader(&b"<?pi data?><a> <b/></a>"[..]);
+    /// assert!(matches!(reader.next().unwrap(), XmlEvent::StartElement { .. }));
+    /// assert!(matches!(reader.next().unwrap(), XmlEvent::StartElement { .. }));
+    /// ```
+    #[must_use]
+    pub fn event_filter(mut self, excluded: EventMask) -> Self {
+        self.events_only = self.events_only & !excluded;
+        self
+    }
+
+    /// Calls the function with all bytes consumed by the parser, e.g. to hash or sign the document while parsing.
+    ///
+    /// ```rust
+    /// use std::sync::{Arc, Mutex};
+    /// use xml::reader::{ParserConfig2, XmlEvent};
+    ///
+    /// let seen = Arc::new(Mutex::new(Vec::new()));
+    /// let seen2 = seen.clone();
+    /// let mut reader = ParserConfig2::new()
+    ///     .raw_bytes_observer(move |bytes| seen2.lock().unwrap().extend_from_slice(bytes))
+    ///     .create_reader(&b"<a>\xC3\xA9</a>"[..]);
+    /// while reader.next().unwrap() != XmlEvent::EndDocument {}
+    /// assert_eq!(&seen.lock().unwrap()[..], "<a>é</a>".as_bytes());
+    /// ```
+    #[must_use]
+    pub fn raw_bytes_observer(mut self, callback: impl FnMut(&[u8]) + Send + 'static) -> Self {
+        self.raw_bytes_observer = Some(RawBytesObserver::new(callback));
+        self
+    }
+
+    /// Calls the function before expanding an entity, e.g. to limit the expansions of each entity separately.
+    /// Expansions so far are available from [`EventReader::stats`](crate::EventReader::stats).
+    ///
+    /// ```rust
+    /// use xml::reader::ParserConfig2;
+    ///
+    /// let mut reader = ParserConfig2::new()
+    ///     .check_entity_expansion(|name, stats| name != "big" || stats.bytes <= 1000)
+    ///     .create_reader(&br#"<!DOCTYPE d [<!ENTITY big "0123456789">]><d>&big;&big;</d>"#[..]);
+    /// while let Ok(event) = reader.next() {
+    ///     if event == xml::reader::XmlEvent::EndDocument { break; }
+    /// }
+    /// assert_eq!(reader.stats().entities["big"].count, 2);
+    /// ```
+    #[must_use]
+    pub fn check_entity_expansion(mut self, callback: impl FnMut(&str, &EntityStats) -> bool + Send + 'static) -> Self {
+        self.entity_expansion_check = Some(EntityExpansionCheck::new(callback));
+        self
+    }
+
+    /// Calls the function with every `StartElement` and `EndElement` as soon as it's read,
+    /// and handles the text that follows it, up to the next start or end tag, as the function returns.
+    ///
+    /// This lets a state machine that follows the structure of the document, e.g. one made from a schema,
+    /// decide which text is needed, and read huge documents in bounded memory without keeping a stack.
+    /// Elements are still checked for well-formedness, so the reader keeps their names.
+    ///
+    /// ```rust
+    /// use xml::reader::{ParserConfig2, TextHandling, XmlEvent};
+    ///
+    /// let reader = ParserConfig2::new()
+    ///     .text_handling(|event| match event {
+    ///         XmlEvent::StartElement { name, .. } if name.local_name == "title" => TextHandling::Collect,
+    ///         _ => TextHandling::Skip,
+    ///     })
+    ///     .create_reader(&b"<book><title>XML</title>a lot of text<page>more text</page></book>"[..]);
+    /// let text: Vec<_> = reader.into_iter().filter_map(|e| match e.unwrap() {
+    ///     XmlEvent::Characters(text) => Some(text),
+    ///     _ => None,
+    /// }).collect();
+    /// assert_eq!(text, ["XML"]);
+    /// ```
+    #[must_use]
+    pub fn text_handling(mut self, callback: impl FnMut(&XmlEvent) -> TextHandling + Send + 'static) -> Self {
+        self.text_handling_hook = Some(TextHandlingHook::new(callback));
+        self
+    }
+
+    /// Rejects documents whose root element isn't in this namespace, as soon as the root element is read.
+    ///
+    /// ```rust
+    /// use xml::reader::ParserConfig2;
+    ///
+    /// let config = ParserConfig2::new()
+    ///     .require_namespace("http://www.w3.org/2005/Atom")
+    ///     .require_root_element("feed");
+    /// let mut reader = config.create_reader(&b"<rss version='2.0'><channel/></rss>"[..]);
+    /// reader.next().unwrap();
+    /// assert!(reader.next().is_err());
+    /// ```
+    #[must_use]
+    pub fn require_namespace(mut self, uri: impl Into<String>) -> Self {
+        self.required_namespace = Some(uri.into());
+        self
+    }
+
+    /// Rejects documents with elements or attributes in any of these namespaces, e.g. to refuse XInclude or XSLT,
+    /// as soon as such element is read. Namespace declarations alone are allowed.
+    #[must_use]
+    pub fn forbid_namespaces<S: Into<String>>(mut self, uris: impl IntoIterator<Item = S>) -> Self {
+        self.forbidden_namespaces.extend(uris.into_iter().map(Into::into));
+        self
+    }
+
+    /// Rejects documents whose root element has a different local name, as soon as the root element is read.
+    ///
+    /// Use [`require_namespace`](Self::require_namespace) to check its namespace too.
+    #[must_use]
+    pub fn require_root_element(mut self, local_name: impl Into<String>) -> Self {
+        self.required_root_element = Some(local_name.into());
+        self
+    }
+
     /// Creates an XML reader with this configuration.
     ///
     /// This is a convenience method for configuring and creating a reader at the same time:
@@ -288,6 +786,13 @@ impl ParserConfig2 {
     pub fn create_reader<R: Read>(self, source: R) -> EventReader<R> {
         EventReader::new_with_config(source, self)
     }
+
+    /// See [`ParserConfig::add_entity`]
+    #[must_use]
+    pub fn add_entity<S: Into<String>, T: Into<String>>(mut self, entity: S, value: T) -> ParserConfig2 {
+        self.c.extra_entities.insert(entity.into(), value.into());
+        self
+    }
 }
 
 impl From<ParserConfig> for ParserConfig2 {
@@ -302,6 +807,67 @@ gen_setters! { ParserConfig2,
     override_encoding: val Option<Encoding>,
     /// Allows invalid documents. There should be only a single root element in XML.
     allow_multiple_root_elements: val bool,
+    /// Allows invalid documents. The last of repeated `xmlns` attributes wins, with a warning.
+    allow_duplicate_namespace_declarations: val bool,
+    /// Allows invalid documents that rebind or use the reserved `xml`/`xmlns` namespaces, with warnings.
+    allow_reserved_namespace_misuse: val bool,
+    /// Record attribute order, quotes and raw values for formatting-preserving tools
+    preserve_source_details: val bool,
+    /// Fold local names of elements and attributes to lowercase
+    lowercase_names: val bool,
+    /// Report the DOCTYPE verbatim in a `Doctype` event
+    capture_doctype_raw: val bool,
+    /// Columns per tab in `TextPosition`, e.g. 4 or 8
+    tab_width: val u8,
+    /// Characters of the source before an error to show in the error
+    error_context: val usize,
+    /// Emit only these kinds of events, see [`EventMask`]
+    events_only: val EventMask,
+    /// Recover from this many `WouldBlock`/`TimedOut` reads inside markup
+    max_stalled_reads: val Option<u32>,
+    /// Decode attribute values on demand, see [`EventReader::lazy_attributes`](crate::EventReader::lazy_attributes)
+    lazy_attributes: val bool,
+    /// Skip bytes before the first `<`, see [`EventReader::warnings`](crate::EventReader::warnings)
+    skip_leading_garbage_until_lt: val bool,
+    /// Read concatenated documents, see [`EventReader::next_document`](crate::EventReader::next_document)
+    multiple_documents: val Option<DocumentDelimiter>,
+    /// Split long text into several events, see [`EventReader::text_reader`](crate::EventReader::text_reader)
+    lazy_text: val bool,
+    /// Trim whitespace at the start and end of text, but keep whitespace-only text
+    trim_text: val bool,
+    /// Replace runs of whitespace in text with a single space
+    collapse_whitespace: val bool,
+    /// Skip whitespace between elements, but don't trim text
+    drop_whitespace_only_text: val bool,
+    /// Keep whitespace written as `&#xA;` etc. when trimming text
+    preserve_escaped_whitespace: val bool,
+    /// Fail, warn or replace references like `&#1;`
+    invalid_char_references: val InvalidCharReferences,
+    /// Treat references to noncharacters like `&#xFFFFE;` as invalid
+    reject_noncharacter_references: val bool,
+    /// Convert names to NFC
+    #[cfg(feature = "unicode-normalization")]
+    normalize_names: val bool,
+    /// Convert text and attribute values to NFC
+    #[cfg(feature = "unicode-normalization")]
+    normalize_text: val bool,
+    /// Expand `&nbsp;` and other HTML entities
+    #[cfg(feature = "html_entities")]
+    html_entities: val bool,
+    /// Report content-ids of `<xop:Include>` elements, see [`EventReader::xop_content_id`](crate::EventReader::xop_content_id)
+    xop_includes: val bool,
+    /// Validate `xml:id` attributes and collect them for lookups
+    track_xml_ids: val bool,
+    /// Check that IDREF and IDREFS attributes refer to existing IDs
+    check_idrefs: val bool,
+    /// Keep unescaped `<` and `&` in attribute values, with a warning
+    lenient_attribute_values: val bool,
+    /// Skip text before and after the root element, with or without a warning
+    text_outside_root: val TextOutsideRoot,
+    /// Skip processing instructions inside the root element, and merge the text around them
+    ignore_processing_instructions: val bool,
+    /// Entities known before parsing, see [`EntityTable`]
+    entity_table: val EntityTable,
     /// Abort if custom entities create a string longer than this
     max_entity_expansion_length: val usize,
     /// Entities can expand into other entities this many times (be careful about exponential cost!)
@@ -314,8 +880,12 @@ gen_setters! { ParserConfig2,
     max_attribute_length: val usize,
     /// Maximum length of strings reprsenting characters, comments, and processing instructions
     max_data_length: val usize,
+    /// Max number of warnings kept
+    max_warnings: val usize,
     /// Allow `<?xml encoding="bogus"?>`
-    ignore_invalid_encoding_declarations: val bool
+    ignore_invalid_encoding_declarations: val bool,
+    /// Let the application decode encodings that aren't supported by the parser
+    defer_unsupported_encoding: val bool
 }
 
 gen_setters! { ParserConfig,
@@ -323,8 +893,73 @@ gen_setters! { ParserConfig,
     override_encoding: c2 Option<Encoding>,
     /// Allow `<?xml encoding="bogus"?>`
     ignore_invalid_encoding_declarations: c2 bool,
+    /// Let the application decode encodings that aren't supported by the parser
+    defer_unsupported_encoding: c2 bool,
     /// Allows invalid documents. There should be only a single root element in XML.
     allow_multiple_root_elements: c2 bool,
+    /// Allows invalid documents. The last of repeated `xmlns` attributes wins, with a warning.
+    allow_duplicate_namespace_declarations: c2 bool,
+    /// Allows invalid documents that rebind or use the reserved `xml`/`xmlns` namespaces, with warnings.
+    allow_reserved_namespace_misuse: c2 bool,
+    /// Record attribute order, quotes and raw values for formatting-preserving tools
+    preserve_source_details: c2 bool,
+    /// Fold local names of elements and attributes to lowercase
+    lowercase_names: c2 bool,
+    /// Report the DOCTYPE verbatim in a `Doctype` event
+    capture_doctype_raw: c2 bool,
+    /// Columns per tab in `TextPosition`, e.g. 4 or 8
+    tab_width: c2 u8,
+    /// Characters of the source before an error to show in the error
+    error_context: c2 usize,
+    /// Emit only these kinds of events, see [`EventMask`]
+    events_only: c2 EventMask,
+    /// Don't emit these kinds of events, see [`EventMask`]
+    event_filter: c2 EventMask,
+    /// Recover from this many `WouldBlock`/`TimedOut` reads inside markup
+    max_stalled_reads: c2 Option<u32>,
+    /// Decode attribute values on demand, see [`EventReader::lazy_attributes`](crate::EventReader::lazy_attributes)
+    lazy_attributes: c2 bool,
+    /// Skip bytes before the first `<`, see [`EventReader::warnings`](crate::EventReader::warnings)
+    skip_leading_garbage_until_lt: c2 bool,
+    /// Read concatenated documents, see [`EventReader::next_document`](crate::EventReader::next_document)
+    multiple_documents: c2 Option<DocumentDelimiter>,
+    /// Split long text into several events, see [`EventReader::text_reader`](crate::EventReader::text_reader)
+    lazy_text: c2 bool,
+    /// Trim whitespace at the start and end of text, but keep whitespace-only text
+    trim_text: c2 bool,
+    /// Replace runs of whitespace in text with a single space
+    collapse_whitespace: c2 bool,
+    /// Skip whitespace between elements, but don't trim text
+    drop_whitespace_only_text: c2 bool,
+    /// Keep whitespace written as `&#xA;` etc. when trimming text
+    preserve_escaped_whitespace: c2 bool,
+    /// Fail, warn or replace references like `&#1;`
+    invalid_char_references: c2 InvalidCharReferences,
+    /// Treat references to noncharacters like `&#xFFFFE;` as invalid
+    reject_noncharacter_references: c2 bool,
+    /// Convert names to NFC
+    #[cfg(feature = "unicode-normalization")]
+    normalize_names: c2 bool,
+    /// Convert text and attribute values to NFC
+    #[cfg(feature = "unicode-normalization")]
+    normalize_text: c2 bool,
+    /// Expand `&nbsp;` and other HTML entities
+    #[cfg(feature = "html_entities")]
+    html_entities: c2 bool,
+    /// Report content-ids of `<xop:Include>` elements, see [`EventReader::xop_content_id`](crate::EventReader::xop_content_id)
+    xop_includes: c2 bool,
+    /// Validate `xml:id` attributes and collect them for lookups
+    track_xml_ids: c2 bool,
+    /// Check that IDREF and IDREFS attributes refer to existing IDs
+    check_idrefs: c2 bool,
+    /// Keep unescaped `<` and `&` in attribute values, with a warning
+    lenient_attribute_values: c2 bool,
+    /// Skip text before and after the root element, with or without a warning
+    text_outside_root: c2 TextOutsideRoot,
+    /// Skip processing instructions inside the root element, and merge the text around them
+    ignore_processing_instructions: c2 bool,
+    /// Entities known before parsing, see [`EntityTable`]
+    entity_table: c2 EntityTable,
 
     /// Abort if custom entities create a string longer than this
     max_entity_expansion_length: c2 usize,
@@ -338,6 +973,8 @@ gen_setters! { ParserConfig,
     max_attribute_length: c2 usize,
     /// Maximum length of strings reprsenting characters, comments, and processing instructions
     max_data_length: c2 usize,
+    /// Max number of warnings kept
+    max_warnings: c2 usize,
 
     /// Set encoding from the MIME type. Important for HTTP compatibility.
     content_type: c2 &str
@@ -355,6 +992,110 @@ gen_setters! { ParserConfig2,
     ignore_root_level_whitespace: delegate bool
 }
 
+/// All reader options in one place. This is the same type as [`ParserConfig2`],
+/// and [`ParserConfig2::build`] checks the options for mistakes before use.
+///
+/// With the `serde` feature enabled the options can be loaded from config files.
+/// Missing fields get default values. Callbacks and `entity_table` aren't stored.
+///
+/// ```rust
+/// use xml::reader::ReaderConfigBuilder;
+///
+/// let config = ReaderConfigBuilder::new()
+///     .trim_whitespace(true)
+///     .tab_width(4)
+///     .build()
+///     .unwrap();
+/// let reader = config.create_reader(&b"<a/>"[..]);
+///
+/// assert!(ReaderConfigBuilder::new().trim_whitespace(true).ignore_root_level_whitespace(false).build().is_err());
+/// ```
+pub type ReaderConfigBuilder = ParserConfig2;
+
+impl ParserConfig2 {
+    /// Checks that the options make sense together
+    pub fn build(self) -> Result<ValidatedConfig, ConfigError> {
+        if self.tab_width == 0 {
+            return Err(ConfigError::InvalidValue("tab_width"));
+        }
+        if self.max_name_length == 0 {
+            return Err(ConfigError::InvalidValue("max_name_length"));
+        }
+        if self.c.trim_whitespace && !self.c.ignore_root_level_whitespace {
+            // trimming drops all whitespace-only text, so root-level whitespace can't be preserved
+            return Err(ConfigError::Conflict("trim_whitespace", "ignore_root_level_whitespace(false)"));
+        }
+        if self.drop_whitespace_only_text && !self.c.ignore_root_level_whitespace {
+            return Err(ConfigError::Conflict("drop_whitespace_only_text", "ignore_root_level_whitespace(false)"));
+        }
+        if self.ignore_invalid_encoding_declarations && self.defer_unsupported_encoding {
+            return Err(ConfigError::Conflict("ignore_invalid_encoding_declarations", "defer_unsupported_encoding"));
+        }
+        Ok(ValidatedConfig(self))
+    }
+}
+
+/// Reader configuration that passed the checks of [`ReaderConfigBuilder::build`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ValidatedConfig(ParserConfig2);
+
+impl ValidatedConfig {
+    /// Creates an XML reader with this configuration
+    #[inline]
+    pub fn create_reader<R: Read>(self, source: R) -> EventReader<R> {
+        EventReader::new_with_config(source, self)
+    }
+}
+
+impl From<ValidatedConfig> for ParserConfig2 {
+    #[inline]
+    fn from(c: ValidatedConfig) -> Self {
+        c.0
+    }
+}
+
+/// Options rejected by [`ReaderConfigBuilder::build`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// The option has a value that can't work, e.g. zero `tab_width`
+    InvalidValue(&'static str),
+    /// The two options can't be enabled at the same time
+    Conflict(&'static str, &'static str),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::InvalidValue(option) => write!(f, "invalid value of {option}"),
+            ConfigError::Conflict(a, b) => write!(f, "{a} and {b} can't be used together"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(feature = "serde")]
+mod encoding_name {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use crate::util::Encoding;
+
+    pub fn serialize<S: Serializer>(encoding: &Option<Encoding>, s: S) -> Result<S::Ok, S::Error> {
+        match encoding {
+            Some(encoding) => s.collect_str(encoding),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Encoding>, D::Error> {
+        Option::<String>::deserialize(d)?
+            .map(|name| name.parse().map_err(|_| D::Error::custom(format!("unsupported encoding {name}"))))
+            .transpose()
+    }
+}
+
 #[test]
 fn mime_parse() {
     let c = ParserConfig2::new().content_type("text/xml;charset=Us-AScii").max_entity_expansion_length(1000);
@@ -363,3 +1104,17 @@ fn mime_parse() {
     let c = ParserConfig2::new().max_entity_expansion_depth(3).content_type("text/xml;charset = \"UTF-16\"");
     assert_eq!(c.override_encoding, Some(Encoding::Utf16));
 }
+
+#[test]
+fn builder_validation() {
+    let c: ParserConfig2 = ReaderConfigBuilder::new().lowercase_names(true).trim_whitespace(true).whitespace_to_characters(true).build().unwrap().into();
+    assert!(c.lowercase_names && c.c.trim_whitespace);
+    assert_eq!(ParserConfig2::from(ParserConfig::new()).build().unwrap(), ValidatedConfig(ParserConfig2::new()));
+
+    assert_eq!(ReaderConfigBuilder::new().trim_whitespace(true).ignore_root_level_whitespace(false).build(),
+        Err(ConfigError::Conflict("trim_whitespace", "ignore_root_level_whitespace(false)")));
+
+    assert_eq!(ReaderConfigBuilder::new().tab_width(0).build(), Err(ConfigError::InvalidValue("tab_width")));
+    assert_eq!(ReaderConfigBuilder::new().ignore_invalid_encoding_declarations(true).defer_unsupported_encoding(true).build(),
+        Err(ConfigError::Conflict("ignore_invalid_encoding_declarations", "defer_unsupported_encoding")));
+}
@@ -6,10 +6,12 @@ use std::error::Error as _;
 use std::{error, fmt, io, str};
 
 use crate::common::{Position, TextPosition};
+use crate::name::OwnedName;
 use crate::util;
 
 /// Failure reason
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum ErrorKind {
     /// This is an ill-formed XML document
     Syntax(Cow<'static, str>),
@@ -19,6 +21,75 @@ pub enum ErrorKind {
     Utf8(str::Utf8Error),
     /// The document ended while they were elements/comments/etc. still open
     UnexpectedEof,
+    /// A closing tag doesn't match the element that is open
+    UnexpectedClosingTag {
+        /// Name of the open element
+        expected: Box<OwnedName>,
+        /// Name in the closing tag
+        found: Box<OwnedName>,
+        /// Position of the opening tag of the `expected` element
+        opened_at: TextPosition,
+    },
+    /// The document ended inside the root element. These elements are still open,
+    /// starting from the root, with positions of their opening tags.
+    UnclosedElements(Vec<(OwnedName, TextPosition)>),
+    /// The document is larger or more complex than allowed by the parser's configuration
+    LimitExceeded {
+        /// Which limit
+        limit: Limit,
+        /// Value of the limit in the configuration
+        configured: usize,
+        /// Size of the part of the document that has exceeded the limit, when it has been noticed
+        observed: usize,
+        /// Name of what was being read, like a too long name, or the attribute of a too long value,
+        /// or the element of too long text or too many attributes, if it's known
+        name: Option<Box<str>>,
+    },
+    /// A character reference like `&#1;` to a character that isn't allowed,
+    /// see [`ParserConfig2::invalid_char_references`](crate::reader::ParserConfig2::invalid_char_references)
+    InvalidCharReference {
+        /// The referenced number
+        code: u32,
+        /// Why it isn't allowed
+        kind: CharReferenceKind,
+    },
+}
+
+/// Why a character reference is an error, see [`ErrorKind::InvalidCharReference`]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum CharReferenceKind {
+    /// A character that XML doesn't allow, like a control character, or a number above U+10FFFF
+    Invalid,
+    /// A UTF-16 surrogate, like `&#xD800;`
+    Surrogate,
+    /// A noncharacter, if `reject_noncharacter_references` is set
+    Noncharacter,
+}
+
+/// A size limit of [`ParserConfig2`](crate::reader::ParserConfig2), see [`ErrorKind::LimitExceeded`]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum Limit {
+    /// `max_name_length`
+    NameLength,
+    /// `max_attributes`
+    Attributes,
+    /// `max_attribute_length`
+    AttributeLength,
+    /// `max_data_length`
+    DataLength,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NameLength => "max_name_length",
+            Self::Attributes => "max_attributes",
+            Self::AttributeLength => "max_attribute_length",
+            Self::DataLength => "max_data_length",
+        })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -31,7 +102,6 @@ pub(crate) enum SyntaxError {
     EmptyEntity,
     NoRootElement,
     ProcessingInstructionWithoutName,
-    UnbalancedRootElement,
     UnexpectedEof,
     UnexpectedOpeningTag,
     /// Missing `]]>`
@@ -45,30 +115,57 @@ pub(crate) enum SyntaxError {
     InvalidQualifiedName(Box<str>),
     UnboundAttribute(Box<str>),
     UnboundElementPrefix(Box<str>),
-    UnexpectedClosingTag(Box<str>),
     UnexpectedName(Box<str>),
     /// Found <?xml-like PI not at the beginning of a document,
     /// which is an error, see section 2.6 of XML 1.1 spec
     UnexpectedProcessingInstruction(Box<str>, Token),
     CannotUndefinePrefix(Box<str>),
+    CannotBindToXmlNamespace(Box<str>),
+    CannotBindToXmlnsNamespace(Box<str>),
     InvalidCharacterEntity(u32),
+    /// Reference to a character that isn't allowed, reported as [`ErrorKind::InvalidCharReference`]
+    InvalidCharReference(u32, CharReferenceKind),
     InvalidDefaultNamespace(Box<str>),
     InvalidNamePrefix(Box<str>),
     InvalidNumericEntity(Box<str>),
     InvalidStandaloneDeclaration(Box<str>),
     InvalidXmlProcessingInstruction(Box<str>),
     RedefinedAttribute(Box<str>),
+    /// The same `xmlns` attribute twice in one tag
+    RedefinedNamespaceDeclaration(Box<str>),
     UndefinedEntity(Box<str>),
     UnexpectedEntity(Box<str>),
+    /// Stopped by [`ParserConfig2::check_entity_expansion`](crate::reader::ParserConfig2::check_entity_expansion)
+    EntityExpansionRejected(Box<str>),
     UnexpectedNameInsideXml(Box<str>),
     UnsupportedEncoding(Box<str>),
     /// In DTD
     UnknownMarkupDeclaration(Box<str>),
+    /// `<![` in DTD not followed by `INCLUDE[` or `IGNORE[`
+    InvalidConditionalSection(Box<str>),
+    /// `<![` in the document's DTD
+    ConditionalSectionInInternalSubset,
     UnexpectedXmlVersion(Box<str>),
+    /// `xml:id` value that isn't an `NCName`
+    InvalidXmlId(Box<str>),
+    /// The same `xml:id` on two elements
+    DuplicateXmlId(Box<str>),
+    /// The same value of an ID-typed attribute on two elements
+    DuplicateId(Box<str>),
+    /// IDREF to an ID that isn't in the document
+    UnresolvedIdref(Box<str>),
     ConflictingEncoding(Encoding, Encoding),
     UnexpectedTokenBefore(&'static str, char),
-    /// Document has more stuff than `ParserConfig` allows
-    ExceededConfiguredLimit,
+    /// Text that isn't valid base64 or hex, or elements in it
+    InvalidBinaryContent(&'static str),
+    /// `href` of `xop:Include` that isn't a `cid:` URL
+    InvalidXopHref(Box<str>),
+    /// Text or nested `xop:Include` in `xop:Include`
+    UnexpectedXopIncludeContent,
+    /// Root element other than `required_root_element`, or not in `required_namespace`
+    UnexpectedRootElement(Box<str>),
+    /// Element or attribute name in one of `forbidden_namespaces`
+    ForbiddenNamespace(Box<(Box<str>, Box<str>)>),
 }
 
 impl fmt::Display for SyntaxError {
@@ -88,13 +185,17 @@ impl SyntaxError {
             Self::EntityTooBig => "Entity too big".into(),
             Self::NoRootElement => "Unexpected end of stream: no root element found".into(),
             Self::ProcessingInstructionWithoutName => "Encountered processing instruction without a name".into(),
-            Self::UnbalancedRootElement => "Unexpected end of stream: still inside the root element".into(),
             Self::UnclosedCdata => "Unclosed <![CDATA[".into(),
             Self::UnexpectedEof => "Unexpected end of stream".into(),
             Self::UnexpectedOpeningTag => "'<' is not allowed in attributes".into(),
             Self::CannotUndefinePrefix(ref ln) => format!("Cannot undefine prefix '{ln}'").into(),
+            Self::CannotBindToXmlNamespace(ref ln) => format!("Prefix '{ln}' cannot be bound to the reserved xml namespace").into(),
+            Self::CannotBindToXmlnsNamespace(ref ln) => format!("Prefix '{ln}' cannot be bound to the reserved xmlns namespace").into(),
             Self::ConflictingEncoding(a, b) => format!("Declared encoding {a}, but uses {b}").into(),
             Self::InvalidCharacterEntity(num) => format!("Invalid character U+{num:04X}").into(),
+            Self::InvalidCharReference(num, CharReferenceKind::Invalid) => format!("Invalid character U+{num:04X}").into(),
+            Self::InvalidCharReference(num, CharReferenceKind::Surrogate) => format!("Reference to surrogate U+{num:04X}, which isn't a character").into(),
+            Self::InvalidCharReference(num, CharReferenceKind::Noncharacter) => format!("Reference to noncharacter U+{num:04X}").into(),
             Self::InvalidDefaultNamespace(ref name) => format!("Namespace '{name}' cannot be default").into(),
             Self::InvalidNamePrefix(ref prefix) => format!("'{prefix}' cannot be an element name prefix").into(),
             Self::InvalidNumericEntity(ref v) => format!("Invalid numeric entity: {v}").into(),
@@ -102,11 +203,12 @@ impl SyntaxError {
             Self::InvalidStandaloneDeclaration(ref value) => format!("Invalid standalone declaration value: {value}").into(),
             Self::InvalidXmlProcessingInstruction(ref name) => format!("Invalid processing instruction: <?{name} - \"<?xml\"-like PI is only valid at the beginning of the document").into(),
             Self::RedefinedAttribute(ref name) => format!("Attribute '{name}' is redefined").into(),
+            Self::RedefinedNamespaceDeclaration(ref name) => format!("Namespace declaration '{name}' is repeated in the same tag").into(),
             Self::UnboundAttribute(ref name) => format!("Attribute {name} prefix is unbound").into(),
             Self::UnboundElementPrefix(ref name) => format!("Element {name} prefix is unbound").into(),
             Self::UndefinedEntity(ref v) => format!("Undefined entity: {v}").into(),
-            Self::UnexpectedClosingTag(ref expected_got) => format!("Unexpected closing tag: {expected_got}").into(),
             Self::UnexpectedEntity(ref name) => format!("Unexpected entity: {name}").into(),
+            Self::EntityExpansionRejected(ref name) => format!("Expansion of entity '{name}' was rejected by the parser's configuration").into(),
             Self::UnexpectedName(ref name) => format!("Unexpected name: {name}").into(),
             Self::UnexpectedNameInsideXml(ref name) => format!("Unexpected name inside XML declaration: {name}").into(),
             Self::UnexpectedProcessingInstruction(ref buf, token) => format!("Unexpected token inside processing instruction: <?{buf}{token}").into(),
@@ -119,8 +221,18 @@ impl SyntaxError {
             Self::UnexpectedTokenOutsideRoot(token) => format!("Unexpected characters outside the root element: {token}").into(),
             Self::UnexpectedXmlVersion(ref version) => format!("Invalid XML version: {version}").into(),
             Self::UnknownMarkupDeclaration(ref v) => format!("Unknown markup declaration: {v}").into(),
+            Self::ConditionalSectionInInternalSubset => "Conditional sections are only allowed in the external DTD subset".into(),
+            Self::InvalidConditionalSection(ref v) => format!("Conditional section must be INCLUDE or IGNORE, found '{v}'").into(),
+            Self::InvalidXmlId(ref id) => format!("Invalid xml:id value: '{id}'").into(),
+            Self::DuplicateXmlId(ref id) => format!("Duplicate xml:id: '{id}'").into(),
+            Self::DuplicateId(ref id) => format!("Duplicate ID: '{id}'").into(),
+            Self::UnresolvedIdref(ref id) => format!("IDREF refers to a missing ID: '{id}'").into(),
             Self::UnsupportedEncoding(ref v) => format!("Unsupported encoding: {v}").into(),
-            Self::ExceededConfiguredLimit => "This document is larger/more complex than allowed by the parser's configuration".into(),
+            Self::InvalidBinaryContent(encoding) => format!("Invalid {encoding} content").into(),
+            Self::InvalidXopHref(ref href) => format!("xop:Include must have href=\"cid:…\", found '{href}'").into(),
+            Self::UnexpectedXopIncludeContent => "xop:Include can only contain extension elements".into(),
+            Self::UnexpectedRootElement(ref name) => format!("The document type is not allowed: unexpected root element {name}").into(),
+            Self::ForbiddenNamespace(ref name_uri) => format!("{} is in a forbidden namespace '{}'", name_uri.0, name_uri.1).into(),
         }
     }
 }
@@ -132,11 +244,45 @@ impl SyntaxError {
 pub struct Error {
     pub(crate) pos: TextPosition,
     pub(crate) kind: ErrorKind,
+    pub(crate) context: Option<Box<ErrorContext>>,
+}
+
+/// Source text around an error, see [`ParserConfig2::error_context`](crate::reader::ParserConfig2::error_context)
+///
+/// It's displayed as the lines of the text, with a caret under the position of the error.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct ErrorContext {
+    /// Source that has been read before the error, and a bit after it.
+    /// It may start in the middle of a line.
+    pub text: String,
+    /// Byte index in the `text` where the error is, if it's in the text
+    pub error_index: Option<usize>,
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut line_start = 0;
+        for line in self.text.split_inclusive('\n') {
+            if line_start > 0 {
+                f.write_str("\n")?;
+            }
+            let line_end = line_start + line.len();
+            write!(f, "    {}", line.trim_end_matches(['\r', '\n']))?;
+            if let Some(index) = self.error_index.filter(|&i| i >= line_start && (i < line_end || line_end == self.text.len())) {
+                // keeps tabs, so that the caret is aligned however they're displayed
+                let indent: String = self.text[line_start..index].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+                write!(f, "\n    {indent}^")?;
+            }
+            line_start = line_end;
+        }
+        Ok(())
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        use self::ErrorKind::{Io, Syntax, UnexpectedEof, Utf8};
+        use self::ErrorKind::{InvalidCharReference, Io, LimitExceeded, Syntax, UnclosedElements, UnexpectedClosingTag, UnexpectedEof, Utf8};
 
         write!(f, "{} ", self.pos)?;
         match &self.kind {
@@ -144,7 +290,28 @@ impl fmt::Display for Error {
             Utf8(reason) => reason.fmt(f),
             Syntax(msg) => f.write_str(msg),
             UnexpectedEof => f.write_str("Unexpected EOF"),
+            UnexpectedClosingTag { expected, found, opened_at } => write!(f, "Unexpected closing tag </{}>, expected </{}> of the element opened at {opened_at}",
+                found.borrow().to_repr(), expected.borrow().to_repr()),
+            UnclosedElements(open) => {
+                f.write_str("Unexpected end of stream: still inside the root element; unclosed")?;
+                for (name, pos) in open {
+                    write!(f, " <{}> at {pos}", name.borrow().to_repr())?;
+                }
+                Ok(())
+            },
+            LimitExceeded { limit, configured, observed, name } => {
+                write!(f, "The document exceeds {limit} = {configured} of the parser's configuration with {observed}")?;
+                if let Some(name) = name {
+                    write!(f, " in '{name}'")?;
+                }
+                Ok(())
+            },
+            InvalidCharReference { code, kind } => f.write_str(&SyntaxError::InvalidCharReference(*code, *kind).to_cow()),
+        }?;
+        if let Some(context) = &self.context {
+            write!(f, "\n{context}")?;
         }
+        Ok(())
     }
 }
 
@@ -160,21 +327,43 @@ impl Error {
     #[allow(deprecated)]
     #[must_use]
     pub fn msg(&self) -> &str {
-        use self::ErrorKind::{Io, Syntax, UnexpectedEof, Utf8};
+        use self::ErrorKind::{InvalidCharReference, Io, LimitExceeded, Syntax, UnclosedElements, UnexpectedClosingTag, UnexpectedEof, Utf8};
         match &self.kind {
             Io(io_error) => io_error.description(),
             Utf8(reason) => reason.description(),
             Syntax(msg) => msg.as_ref(),
             UnexpectedEof => "Unexpected EOF",
+            UnexpectedClosingTag { .. } => "Unexpected closing tag",
+            UnclosedElements(_) => "Unexpected end of stream: still inside the root element",
+            LimitExceeded { .. } => "This document is larger/more complex than allowed by the parser's configuration",
+            InvalidCharReference { kind: CharReferenceKind::Invalid, .. } => "Invalid character reference",
+            InvalidCharReference { kind: CharReferenceKind::Surrogate, .. } => "Reference to surrogate, which isn't a character",
+            InvalidCharReference { kind: CharReferenceKind::Noncharacter, .. } => "Reference to noncharacter",
         }
     }
 
+    /// The source around the error, if [`ParserConfig2::error_context`](crate::reader::ParserConfig2::error_context) is enabled
+    #[must_use]
+    pub fn context(&self) -> Option<&ErrorContext> {
+        self.context.as_deref()
+    }
+
     /// Failure reason
     #[must_use]
     #[inline]
     pub fn kind(&self) -> &ErrorKind {
         &self.kind
     }
+
+    /// The number and the problem of a disallowed character reference like `&#xD800;`,
+    /// if that's what this error is about
+    #[must_use]
+    pub fn invalid_char_reference(&self) -> Option<(u32, CharReferenceKind)> {
+        match self.kind {
+            ErrorKind::InvalidCharReference { code, kind } => Some((code, kind)),
+            _ => None,
+        }
+    }
 }
 
 impl error::Error for Error {
@@ -183,12 +372,87 @@ impl error::Error for Error {
     fn description(&self) -> &str { self.msg() }
 }
 
+/// A problem that the parser has worked around, see [`EventReader::warnings`](crate::EventReader::warnings)
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum Warning {
+    /// Bytes before the first `<` were skipped by [`skip_leading_garbage_until_lt`](crate::reader::ParserConfig2::skip_leading_garbage_until_lt)
+    LeadingGarbage {
+        /// Number of skipped bytes, including byte order marks after the first one
+        bytes: u64,
+    },
+    /// A character reference to a character that isn't allowed in XML has been kept,
+    /// see [`invalid_char_references`](crate::reader::ParserConfig2::invalid_char_references)
+    InvalidCharacterReference {
+        /// The referenced code point
+        code: u32,
+        /// Where the reference ends
+        position: TextPosition,
+    },
+    /// A `<` or `&` has been kept in an attribute value,
+    /// see [`lenient_attribute_values`](crate::reader::ParserConfig2::lenient_attribute_values)
+    UnescapedAttributeCharacter {
+        /// `<` or `&`
+        character: char,
+        /// Position of the `<`, or of the first character after the `&` that can't be in a reference
+        position: TextPosition,
+    },
+    /// Text outside the root element has been skipped,
+    /// see [`text_outside_root`](crate::reader::ParserConfig2::text_outside_root)
+    TextOutsideRoot {
+        /// Number of bytes of the skipped text, without trailing whitespace
+        length: usize,
+        /// Position of its first character
+        position: TextPosition,
+    },
+    /// A namespace prefix has been declared more than once in the same tag, and the last declaration is used,
+    /// see [`allow_duplicate_namespace_declarations`](crate::reader::ParserConfig2::allow_duplicate_namespace_declarations)
+    DuplicateNamespaceDeclaration {
+        /// Name of the repeated attribute, like `xmlns:p`
+        name: String,
+        /// Where the repeated declaration ends
+        position: TextPosition,
+    },
+    /// An element or a namespace declaration misuses the reserved `xml` or `xmlns` prefix or namespace,
+    /// see [`allow_reserved_namespace_misuse`](crate::reader::ParserConfig2::allow_reserved_namespace_misuse)
+    ReservedNamespaceMisuse {
+        /// Name of the element or attribute, like `xmlns:a`
+        name: String,
+        /// Where its name or value ends
+        position: TextPosition,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::LeadingGarbage { bytes } => write!(f, "skipped {bytes} bytes before the start of the document"),
+            Warning::InvalidCharacterReference { code, position } => write!(f, "{position}: kept invalid character reference &#x{code:x};"),
+            Warning::UnescapedAttributeCharacter { character, position } => write!(f, "{position}: kept unescaped '{character}' in an attribute value"),
+            Warning::TextOutsideRoot { length, position } => write!(f, "{position}: skipped {length} bytes of text outside the root element"),
+            Warning::DuplicateNamespaceDeclaration { name, position } => write!(f, "{position}: used the last of repeated '{name}' declarations"),
+            Warning::ReservedNamespaceMisuse { name, position } => write!(f, "{position}: accepted '{name}' that misuses a reserved namespace"),
+        }
+    }
+}
+
 impl<'a, P, M> From<(&'a P, M)> for Error where P: Position, M: Into<Cow<'static, str>> {
     #[cold]
     fn from(orig: (&'a P, M)) -> Self {
         Error {
             pos: orig.0.position(),
             kind: ErrorKind::Syntax(orig.1.into()),
+            context: None,
+        }
+    }
+}
+
+impl From<SyntaxError> for ErrorKind {
+    #[cold]
+    fn from(e: SyntaxError) -> Self {
+        match e {
+            SyntaxError::InvalidCharReference(code, kind) => ErrorKind::InvalidCharReference { code, kind },
+            e => ErrorKind::Syntax(e.to_cow()),
         }
     }
 }
@@ -204,6 +468,7 @@ impl From<util::CharReadError> for Error {
                 Utf8(reason) => ErrorKind::Utf8(reason),
                 Io(io_error) => ErrorKind::Io(io_error),
             },
+            context: None,
         }
     }
 }
@@ -214,6 +479,7 @@ impl From<io::Error> for Error {
         Error {
             pos: TextPosition::new(),
             kind: ErrorKind::Io(e),
+            context: None,
         }
     }
 }
@@ -221,19 +487,32 @@ impl From<io::Error> for Error {
 impl Clone for ErrorKind {
     #[cold]
     fn clone(&self) -> Self {
-        use self::ErrorKind::{Io, Syntax, UnexpectedEof, Utf8};
+        use self::ErrorKind::{InvalidCharReference, Io, LimitExceeded, Syntax, UnclosedElements, UnexpectedClosingTag, UnexpectedEof, Utf8};
         match self {
             UnexpectedEof => UnexpectedEof,
             Utf8(reason) => Utf8(*reason),
             Io(io_error) => Io(io::Error::new(io_error.kind(), io_error.to_string())),
             Syntax(msg) => Syntax(msg.clone()),
+            UnexpectedClosingTag { expected, found, opened_at } => UnexpectedClosingTag {
+                expected: expected.clone(),
+                found: found.clone(),
+                opened_at: *opened_at,
+            },
+            UnclosedElements(open) => UnclosedElements(open.clone()),
+            LimitExceeded { limit, configured, observed, name } => LimitExceeded {
+                limit: *limit,
+                configured: *configured,
+                observed: *observed,
+                name: name.clone(),
+            },
+            InvalidCharReference { code, kind } => InvalidCharReference { code: *code, kind: *kind },
         }
     }
 }
 impl PartialEq for ErrorKind {
     #[allow(deprecated)]
     fn eq(&self, other: &ErrorKind) -> bool {
-        use self::ErrorKind::{Io, Syntax, UnexpectedEof, Utf8};
+        use self::ErrorKind::{InvalidCharReference, Io, LimitExceeded, Syntax, UnclosedElements, UnexpectedClosingTag, UnexpectedEof, Utf8};
         match (self, other) {
             (UnexpectedEof, UnexpectedEof) => true,
             (Utf8(left), Utf8(right)) => left == right,
@@ -242,6 +521,13 @@ impl PartialEq for ErrorKind {
                 left.description() == right.description(),
             (Syntax(left), Syntax(right)) =>
                 left == right,
+            (UnexpectedClosingTag { expected, found, opened_at }, UnexpectedClosingTag { expected: e, found: f, opened_at: o }) =>
+                expected == e && found == f && opened_at == o,
+            (UnclosedElements(left), UnclosedElements(right)) => left == right,
+            (LimitExceeded { limit, configured, observed, name }, LimitExceeded { limit: l, configured: c, observed: o, name: n }) =>
+                limit == l && configured == c && observed == o && name == n,
+            (InvalidCharReference { code, kind }, InvalidCharReference { code: c, kind: k }) =>
+                code == c && kind == k,
 
             (_, _) => false,
         }
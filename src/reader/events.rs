@@ -1,16 +1,20 @@
 //! Contains `XmlEvent` datatype, instances of which are emitted by the parser.
 
-use crate::attribute::OwnedAttribute;
-use crate::common::XmlVersion;
+use crate::attribute::Attributes;
+use crate::common::{is_whitespace_char, XmlStylesheet, XmlVersion};
 use crate::name::OwnedName;
-use crate::namespace::Namespace;
+use crate::namespace::{Namespace, NS_EMPTY_URI, NS_XSI_URI};
+use std::borrow::Cow;
 use std::fmt;
+use std::ops::Range;
 
 /// An element of an XML input stream.
 ///
 /// Items of this enum are emitted by `reader::EventReader`. They correspond to different
 /// elements of an XML document.
 #[derive(PartialEq, Clone)]
+#[non_exhaustive]
+#[allow(clippy::large_enum_variant)] // attributes of `StartElement` are inline to save an allocation
 pub enum XmlEvent {
     /// Corresponds to XML document declaration.
     ///
@@ -55,6 +59,23 @@ pub enum XmlEvent {
         data: Option<String>,
     },
 
+    /// Denotes a document type declaration.
+    ///
+    /// This event is emitted only if `capture_doctype_raw` is enabled in `ParserConfig2`.
+    Doctype {
+        /// The complete `<!DOCTYPE …>` declaration, exactly as written in the document,
+        /// including the internal subset. Parameter entities are not expanded.
+        syntax: String,
+
+        /// The declaration has an internal subset in `[…]`, which can declare entities and attributes.
+        /// `DOCTYPE`s with only a public or system identifier don't.
+        has_internal_subset: bool,
+
+        /// Byte offsets of the internal subset in the source, from `[` to `]` inclusive.
+        /// `None` if there is no internal subset.
+        internal_subset_byte_range: Option<Range<u64>>,
+    },
+
     /// Denotes a beginning of an XML element.
     ///
     /// This event is emitted after parsing opening tags or after parsing bodiless tags. In the
@@ -66,7 +87,7 @@ pub enum XmlEvent {
         /// A list of attributes associated with the element.
         ///
         /// Currently attributes are not checked for duplicates (TODO)
-        attributes: Vec<OwnedAttribute>,
+        attributes: Attributes,
 
         /// Contents of the namespace mapping at this point of the document.
         namespace: Namespace,
@@ -125,6 +146,8 @@ impl fmt::Debug for XmlEvent {
                     Some(ref data) => format!(", {data}"),
                     None       => String::new()
                 }),
+            XmlEvent::Doctype { ref syntax, .. } =>
+                write!(f, "Doctype({syntax})"),
             XmlEvent::StartElement { ref name, ref attributes, namespace: Namespace(ref namespace) } =>
                 write!(f, "StartElement({}, {:?}{})", name, namespace, if attributes.is_empty() {
                     String::new()
@@ -143,12 +166,61 @@ impl fmt::Debug for XmlEvent {
             XmlEvent::Characters(ref data) =>
                 write!(f, "Characters({data})"),
             XmlEvent::Whitespace(ref data) =>
-                write!(f, "Whitespace({data})")
+                write!(f, "Whitespace({data})"),
         }
     }
 }
 
 impl XmlEvent {
+    /// Parsed data of an `<?xml-stylesheet?>` processing instruction.
+    ///
+    /// Returns `None` for all other events, and if the data is malformed.
+    #[must_use]
+    pub fn xml_stylesheet(&self) -> Option<XmlStylesheet> {
+        match self {
+            XmlEvent::ProcessingInstruction { name, data: Some(data) } if name == "xml-stylesheet" => XmlStylesheet::parse(data),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a `StartElement` with `xsi:nil="true"`, i.e. an element that XML Schema considers null.
+    ///
+    /// The attribute is recognized by its namespace, regardless of its prefix.
+    /// Values of [`lazy_attributes`](crate::reader::ParserConfig2::lazy_attributes) aren't checked.
+    #[must_use]
+    pub fn is_xsi_nil(&self) -> bool {
+        self.xsi_attribute("nil").map_or(false, |value| matches!(value.trim_matches(is_whitespace_char), "true" | "1"))
+    }
+
+    /// The type from the `xsi:type` attribute of a `StartElement`, with its prefix resolved
+    /// to a namespace in scope of the element.
+    ///
+    /// Returns `None` for all other events, and if the attribute is missing, malformed, or has an unbound prefix.
+    #[must_use]
+    pub fn xsi_type(&self) -> Option<OwnedName> {
+        let namespace = match self {
+            XmlEvent::StartElement { namespace, .. } => namespace,
+            _ => return None,
+        };
+        let mut name: OwnedName = self.xsi_attribute("type")?.trim_matches(is_whitespace_char).parse().ok()?;
+        name.namespace = match namespace.get(name.borrow().prefix_repr()) {
+            Some(NS_EMPTY_URI) => None,
+            Some(uri) => Some(uri.into()),
+            None if name.prefix.is_none() => None,
+            None => return None,
+        };
+        Some(name)
+    }
+
+    fn xsi_attribute(&self, local_name: &str) -> Option<&str> {
+        match self {
+            XmlEvent::StartElement { attributes, .. } => attributes.iter()
+                .find(|attr| attr.name.local_name == local_name && attr.name.namespace.as_deref() == Some(NS_XSI_URI))
+                .map(|attr| &*attr.value),
+            _ => None,
+        }
+    }
+
     /// Obtains a writer event from this reader event.
     ///
     /// This method is useful for streaming processing of XML documents where the output
@@ -199,8 +271,9 @@ impl XmlEvent {
             XmlEvent::ProcessingInstruction { ref name, ref data } =>
                 Some(crate::writer::events::XmlEvent::ProcessingInstruction {
                     name,
-                    data: data.as_ref().map(|s| &**s)
+                    data: data.as_deref().map(Cow::Borrowed)
                 }),
+            XmlEvent::Doctype { ref syntax, .. } => Some(crate::writer::events::XmlEvent::Doctype(syntax)),
             XmlEvent::StartElement { ref name, ref attributes, ref namespace } =>
                 Some(crate::writer::events::XmlEvent::StartElement {
                     name: name.borrow(),
@@ -217,3 +290,104 @@ impl XmlEvent {
         }
     }
 }
+
+/// A set of kinds of [`XmlEvent`]s, for choosing which events the parser emits.
+///
+/// Sets can be combined with `|`, and `!` gives all the other kinds.
+///
+/// ```rust
+/// use xml::reader::EventMask;
+///
+/// let elements = EventMask::START_ELEMENT | EventMask::END_ELEMENT;
+/// assert!(elements.contains(EventMask::END_ELEMENT));
+/// assert!(!elements.contains(EventMask::ATTRIBUTES));
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EventMask(u16);
+
+impl EventMask {
+    /// No events, except `EndDocument`, which is always emitted
+    pub const NONE: Self = Self(0);
+    /// `StartDocument`
+    pub const START_DOCUMENT: Self = Self(1 << 0);
+    /// `ProcessingInstruction`
+    pub const PROCESSING_INSTRUCTION: Self = Self(1 << 1);
+    /// `Doctype`
+    pub const DOCTYPE: Self = Self(1 << 2);
+    /// `StartElement`
+    pub const START_ELEMENT: Self = Self(1 << 3);
+    /// Attributes of `StartElement`. Without it, the events have no attributes.
+    pub const ATTRIBUTES: Self = Self(1 << 4);
+    /// `EndElement`
+    pub const END_ELEMENT: Self = Self(1 << 5);
+    /// `CData`
+    pub const CDATA: Self = Self(1 << 6);
+    /// `Comment`
+    pub const COMMENT: Self = Self(1 << 7);
+    /// `Characters`
+    pub const CHARACTERS: Self = Self(1 << 8);
+    /// `Whitespace`
+    pub const WHITESPACE: Self = Self(1 << 9);
+    /// All events
+    pub const ALL: Self = Self((1 << 10) - 1);
+
+    /// All kinds in `other` are in this set
+    #[inline]
+    #[must_use]
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether the event is of a kind in this set. `EndDocument` always is.
+    #[must_use]
+    pub fn matches(self, event: &XmlEvent) -> bool {
+        let kind = match event {
+            XmlEvent::StartDocument { .. } => Self::START_DOCUMENT,
+            XmlEvent::EndDocument => return true,
+            XmlEvent::ProcessingInstruction { .. } => Self::PROCESSING_INSTRUCTION,
+            XmlEvent::Doctype { .. } => Self::DOCTYPE,
+            XmlEvent::StartElement { .. } => Self::START_ELEMENT,
+            XmlEvent::EndElement { .. } => Self::END_ELEMENT,
+            XmlEvent::CData(_) => Self::CDATA,
+            XmlEvent::Comment(_) => Self::COMMENT,
+            XmlEvent::Characters(_) => Self::CHARACTERS,
+            XmlEvent::Whitespace(_) => Self::WHITESPACE,
+        };
+        self.contains(kind)
+    }
+}
+
+impl Default for EventMask {
+    #[inline]
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl std::ops::BitOr for EventMask {
+    type Output = Self;
+
+    #[inline]
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl std::ops::BitAnd for EventMask {
+    type Output = Self;
+
+    #[inline]
+    fn bitand(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+impl std::ops::Not for EventMask {
+    type Output = Self;
+
+    #[inline]
+    fn not(self) -> Self {
+        Self(!self.0 & Self::ALL.0)
+    }
+}
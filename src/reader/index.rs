@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom, Take};
+use std::ops::Range;
+
+use crate::filter::Pattern;
+use crate::namespace::{NS_EMPTY_URI, NS_NO_PREFIX, NS_XMLNS_PREFIX, NS_XML_PREFIX};
+
+use super::{EventReader, ParserConfig2, Result, XmlEvent};
+
+/// Byte offsets of elements matching some paths, for parsing them again later without reading
+/// the rest of the document, e.g. to process records of a large dump in parallel, or to resume work.
+///
+/// Paths are local names separated by `/`, like in [`TransformRules`](crate::filter::TransformRules).
+/// `page` matches all `page` elements, `/mediawiki/page` only children of the root, and `*` matches any name.
+///
+/// With the `serde` feature the index can be saved and loaded.
+///
+/// ```rust
+/// use std::io::Cursor;
+/// use xml::reader::{ParserConfig2, XmlEvent, XmlIndex};
+/// use xml::EventReader;
+///
+/// let dump = r#"<osm xmlns:x="urn:x"><node id="1"/><way><x:nd ref="1"/></way><node id="2"/></osm>"#;
+/// let index = XmlIndex::build(EventReader::from_str(dump), &["/osm/node", "way"]).unwrap();
+/// assert_eq!(index.entries().len(), 3);
+///
+/// let way = &index.entries()[1];
+/// assert_eq!(way.path, 1);
+/// let mut reader = way.reader(Cursor::new(dump), ParserConfig2::new()).unwrap();
+/// reader.next().unwrap(); // StartDocument
+/// assert!(matches!(reader.next().unwrap(), XmlEvent::StartElement { name, .. } if name.local_name == "way"));
+/// assert!(matches!(reader.next().unwrap(), XmlEvent::StartElement { name, .. } if name.namespace.as_deref() == Some("urn:x")));
+/// ```
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct XmlIndex {
+    entries: Vec<IndexEntry>,
+}
+
+/// An element found by [`XmlIndex::build`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IndexEntry {
+    /// Position of the matching path in the list given to [`XmlIndex::build`].
+    /// If more than one path matches an element, it has an entry for each.
+    pub path: usize,
+    /// Byte offsets in the source from the `<` of the start tag to the end of the end tag
+    pub byte_range: Range<u64>,
+    /// Namespace prefixes in scope at the element, needed to parse it without its ancestors
+    pub namespaces: BTreeMap<String, String>,
+}
+
+impl XmlIndex {
+    /// Reads the whole document, and records the elements matching any of the paths in document order.
+    ///
+    /// Matching elements can be nested, e.g. with paths `section` or `*`.
+    pub fn build<R: Read>(mut reader: EventReader<R>, paths: &[&str]) -> Result<Self> {
+        let patterns: Vec<_> = paths.iter().map(|p| Pattern::new(p)).collect();
+        let mut entries = Vec::new();
+        let mut path = Vec::new();
+        // depth and entry of the matched elements that haven't ended yet
+        let mut open: Vec<(usize, usize)> = Vec::new();
+        loop {
+            match reader.next()? {
+                XmlEvent::StartElement { name, namespace, .. } => {
+                    path.push(name.local_name);
+                    let start = reader.event_byte_range().start;
+                    let mut namespaces = None;
+                    for (i, pattern) in patterns.iter().enumerate() {
+                        if pattern.matches(&path) {
+                            open.push((path.len(), entries.len()));
+                            let namespaces = namespaces.get_or_insert_with(|| namespace.0.iter()
+                                .filter(|&(prefix, uri)| {
+                                    prefix != NS_XML_PREFIX && prefix != NS_XMLNS_PREFIX && !(prefix == NS_NO_PREFIX && uri == NS_EMPTY_URI)
+                                })
+                                .map(|(prefix, uri)| (prefix.clone(), uri.clone()))
+                                .collect::<BTreeMap<_, _>>());
+                            entries.push(IndexEntry { path: i, byte_range: start..start, namespaces: namespaces.clone() });
+                        }
+                    }
+                },
+                XmlEvent::EndElement { .. } => {
+                    let end = reader.event_byte_range().end;
+                    while let Some(&(depth, entry)) = open.last() {
+                        if depth != path.len() {
+                            break;
+                        }
+                        entries[entry].byte_range.end = end;
+                        open.pop();
+                    }
+                    path.pop();
+                },
+                XmlEvent::EndDocument => break,
+                _ => {},
+            }
+        }
+        Ok(Self { entries })
+    }
+
+    /// Elements in document order
+    #[must_use]
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+}
+
+impl IndexEntry {
+    /// Seeks to this element in a source with the same content as the indexed one,
+    /// and returns a reader that parses only this element, as if it was a document on its own.
+    ///
+    /// Namespace prefixes declared by the element's ancestors are known to the reader.
+    /// Entities declared in the DTD are not, but can be given in `config`, as well as
+    /// the encoding if the document isn't in UTF-8. Positions are relative to the start of the element.
+    pub fn reader<R: Read + Seek>(&self, mut source: R, config: impl Into<ParserConfig2>) -> Result<EventReader<Take<R>>> {
+        source.seek(SeekFrom::Start(self.byte_range.start))?;
+        let mut reader = EventReader::new_with_config(source.take(self.byte_range.end - self.byte_range.start), config);
+        reader.parser.inherit_namespaces(&self.namespaces);
+        Ok(reader)
+    }
+}
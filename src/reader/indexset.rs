@@ -1,4 +1,4 @@
-use crate::attribute::OwnedAttribute;
+use crate::attribute::{Attributes, OwnedAttribute};
 use crate::name::OwnedName;
 
 use std::collections::hash_map::RandomState;
@@ -6,12 +6,13 @@ use std::collections::HashSet;
 use std::hash::{BuildHasher, Hash, Hasher};
 
 /// An ordered set
+#[derive(Clone)]
 pub(crate) struct AttributesSet {
-    vec: Vec<OwnedAttribute>,
+    vec: Attributes,
     /// Uses a no-op hasher, because these u64s are hashes already
     may_contain: HashSet<u64, U64HasherBuilder>,
-    /// This is real hasher for the `OwnedName`
-    hasher: RandomState,
+    /// This is real hasher for the `OwnedName`, created only once there are many attributes
+    hasher: Option<RandomState>,
 }
 
 /// Use linear search and don't allocate `HashSet` if there are few attributes,
@@ -21,14 +22,14 @@ const HASH_THRESHOLD: usize = 8;
 impl AttributesSet {
     pub fn new() -> Self {
         Self {
-            vec: Vec::new(),
-            hasher: RandomState::new(),
+            vec: Attributes::new(),
+            hasher: None,
             may_contain: HashSet::default(),
         }
     }
 
-    fn hash(&self, val: &OwnedName) -> u64 {
-        let mut h = self.hasher.build_hasher();
+    fn hash(hasher: &RandomState, val: &OwnedName) -> u64 {
+        let mut h = hasher.build_hasher();
         val.hash(&mut h);
         h.finish()
     }
@@ -39,24 +40,28 @@ impl AttributesSet {
 
     pub fn contains(&self, name: &OwnedName) -> bool {
         // fall back to linear search only on duplicate or hash collision
-        (self.vec.len() < HASH_THRESHOLD || self.may_contain.contains(&self.hash(name))) &&
-            self.vec.iter().any(move |a| &a.name == name)
+        let may_contain = match &self.hasher {
+            Some(hasher) => self.may_contain.contains(&Self::hash(hasher, name)),
+            None => true,
+        };
+        may_contain && self.vec.iter().any(move |a| &a.name == name)
     }
 
     pub fn push(&mut self, attr: OwnedAttribute) {
         if self.vec.len() >= HASH_THRESHOLD {
+            let hasher = self.hasher.get_or_insert_with(RandomState::new);
             if self.vec.len() == HASH_THRESHOLD {
                 self.may_contain.reserve(HASH_THRESHOLD * 2);
                 for attr in &self.vec {
-                    self.may_contain.insert(self.hash(&attr.name));
+                    self.may_contain.insert(Self::hash(hasher, &attr.name));
                 }
             }
-            self.may_contain.insert(self.hash(&attr.name));
+            self.may_contain.insert(Self::hash(hasher, &attr.name));
         }
         self.vec.push(attr);
     }
 
-    pub fn into_vec(self) -> Vec<OwnedAttribute> {
+    pub fn into_attributes(self) -> Attributes {
         self.vec
     }
 }
@@ -73,7 +78,7 @@ fn indexset() {
     // this test will take a lot of time if the `contains()` is linear, and the loop is quadratic
     for i in 0..50000 {
         let name = OwnedName {
-            local_name: format!("attr{i}"), namespace: None, prefix: None,
+            local_name: format!("attr{i}").into(), namespace: None, prefix: None,
         };
         assert!(!s.contains(&name));
 
@@ -105,7 +110,7 @@ impl Hasher for U64Hasher {
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 struct U64HasherBuilder;
 
 impl BuildHasher for U64HasherBuilder {
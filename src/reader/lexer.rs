@@ -3,19 +3,22 @@
 //! This module is for internal use. Use `xml::pull` module to do parsing.
 
 use crate::common::{is_name_char, is_whitespace_char, is_xml10_char, is_xml11_char, Position, TextPosition};
-use crate::reader::error::SyntaxError;
+use crate::reader::error::{ErrorContext, SyntaxError};
 use crate::reader::{Error, ErrorKind};
 use crate::util::{CharReader, Encoding};
 use std::collections::VecDeque;
 use std::io::Read;
 use std::{fmt, result};
 
-use super::ParserConfig2;
+use super::{ParserConfig2, RawBytesObserver};
 
 /// `Token` represents a single lexeme of an XML document. These lexemes
 /// are used to perform actual parsing.
+///
+/// Whitespace and text are tokenized as individual `Character`s.
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
-pub(crate) enum Token {
+#[non_exhaustive]
+pub enum Token {
     /// `<?`
     ProcessingInstructionStart,
     /// `?>`
@@ -52,6 +55,8 @@ pub(crate) enum Token {
     ReferenceEnd,
     /// `<!` of `ENTITY`
     MarkupDeclarationStart,
+    /// `<![` of `INCLUDE` or `IGNORE` in DTD
+    ConditionalSectionStart,
 }
 
 impl fmt::Display for Token {
@@ -77,6 +82,7 @@ impl fmt::Display for Token {
                 Token::SingleQuote                => "'",
                 Token::DoubleQuote                => "\"",
                 Token::MarkupDeclarationStart     => "<!",
+                Token::ConditionalSectionStart    => "<![",
                 Token::Character(_)               => {
                     debug_assert!(false);
                     ""
@@ -87,7 +93,7 @@ impl fmt::Display for Token {
 }
 
 impl Token {
-    pub fn as_static_str(self) -> Option<&'static str> {
+    pub(crate) fn as_static_str(self) -> Option<&'static str> {
         match self {
             Token::OpeningTagStart            => Some("<"),
             Token::ProcessingInstructionStart => Some("<?"),
@@ -110,7 +116,7 @@ impl Token {
     }
 
     // using String.push_str(token.to_string()) is simply way too slow
-    pub fn push_to_string(self, target: &mut String) {
+    pub(crate) fn push_to_string(self, target: &mut String) {
         match self {
             Token::Character(c) => {
                 debug_assert!(is_xml10_char(c) || is_xml11_char(c));
@@ -159,6 +165,8 @@ enum State {
     InsideProcessingInstruction,
     /// `<!ENTITY "here">`
     InsideMarkupDeclarationQuotedString(QuoteStyle),
+    /// Triggered on ']' up to ']]' inside a conditional section of DTD
+    ConditionalSectionClosing(ClosingSubstate),
 }
 
 #[derive(Copy, Clone, Eq, PartialEq)]
@@ -216,17 +224,32 @@ macro_rules! dispatch_on_enum_state(
 /// When it is not set, errors will be reported as `Err` objects with a string message.
 /// By default this flag is not set. Use `enable_errors` and `disable_errors` methods
 /// to toggle the behavior.
+#[derive(Clone)]
 pub(crate) struct Lexer {
     st: State,
     reader: CharReader,
     pos: TextPosition,
     head_pos: TextPosition,
+    /// Last char read from the source, to count `\r\n` as one line break
+    prev_char: char,
     char_queue: VecDeque<char>,
     /// Default state to go back to after a tag end (may be `InsideDoctype`)
     normal_state: State,
     inside_token: bool,
     eof_handled: bool,
     reparse_depth: u8,
+    /// Number of open `<![INCLUDE[`/`<![IGNORE[` sections
+    conditional_depth: u32,
+    /// Conditional sections are allowed only in the external DTD subset
+    external_subset: bool,
+    capture_doctype: bool,
+    tab_width: u8,
+    raw_bytes_observer: Option<RawBytesObserver>,
+    /// Source text of the DOCTYPE read so far
+    doctype_text: Option<String>,
+    /// The last `error_context` chars of the source
+    recent_text: VecDeque<char>,
+    error_context: usize,
     #[cfg(test)]
     skip_errors: bool,
 
@@ -243,16 +266,35 @@ impl Position for Lexer {
 impl Lexer {
     /// Returns a new lexer with default state.
     pub(crate) fn new(config: &ParserConfig2) -> Lexer {
+        Self::new_with_queue(config, VecDeque::with_capacity(4)) // TODO: check size
+    }
+
+    fn new_with_queue(config: &ParserConfig2, mut char_queue: VecDeque<char>) -> Lexer {
+        char_queue.clear();
+        let mut reader = CharReader::new();
+        if config.raw_bytes_observer.is_some() {
+            reader.observe_bytes();
+        }
+        reader.skip_until_lt = config.skip_leading_garbage_until_lt;
         Lexer {
-            reader: CharReader::new(),
+            reader,
             pos: TextPosition::new(),
             head_pos: TextPosition::new(),
-            char_queue: VecDeque::with_capacity(4), // TODO: check size
+            prev_char: '\0',
+            char_queue,
             st: State::Normal,
             normal_state: State::Normal,
             inside_token: false,
             eof_handled: false,
             reparse_depth: 0,
+            conditional_depth: 0,
+            external_subset: false,
+            capture_doctype: config.capture_doctype_raw,
+            tab_width: config.tab_width,
+            raw_bytes_observer: config.raw_bytes_observer.clone(),
+            doctype_text: None,
+            recent_text: VecDeque::new(),
+            error_context: config.error_context,
             #[cfg(test)]
             skip_errors: false,
 
@@ -261,7 +303,13 @@ impl Lexer {
         }
     }
 
-    pub(crate) fn encoding(&mut self) -> Encoding {
+    /// Returns to the initial state, keeping the allocated queue
+    pub(crate) fn reset(&mut self, config: &ParserConfig2) {
+        let char_queue = std::mem::take(&mut self.char_queue);
+        *self = Lexer::new_with_queue(config, char_queue);
+    }
+
+    pub(crate) fn encoding(&self) -> Encoding {
         self.reader.encoding
     }
 
@@ -269,6 +317,26 @@ impl Lexer {
         self.reader.encoding = encoding;
     }
 
+    #[inline]
+    pub(crate) fn bytes_read(&self) -> u64 {
+        self.reader.bytes_read
+    }
+
+    /// Bytes skipped by `skip_leading_garbage_until_lt` since the last call
+    pub(crate) fn take_skipped_bytes(&mut self) -> u64 {
+        std::mem::take(&mut self.reader.skipped_bytes)
+    }
+
+    /// Source text of the DOCTYPE, if `capture_doctype_raw` is enabled, and it has ended
+    pub(crate) fn take_doctype_text(&mut self) -> Option<String> {
+        self.doctype_text.take()
+    }
+
+    /// Accept conditional sections, because the DTD is from the external subset
+    pub(crate) fn set_external_subset(&mut self) {
+        self.external_subset = true;
+    }
+
     /// Disables error handling so `next_token` will return `Some(Chunk(..))`
     /// upon invalid lexeme with this lexeme content.
     #[cfg(test)] fn disable_errors(&mut self) { self.skip_errors = true; }
@@ -287,17 +355,22 @@ impl Lexer {
     /// * `Ok(None)` - upon end of stream is reached;
     /// * `Ok(Some(token)) where token: Token` - in case a complete-token has been read from the stream.
     pub fn next_token<B: Read>(&mut self, b: &mut B) -> Result {
-        // Already reached end of buffer
-        if self.eof_handled {
-            return Ok(None);
+        let res = self.read_token(b);
+        if let Some(observer) = &self.raw_bytes_observer {
+            if let Some(bytes) = self.reader.take_observed_bytes() {
+                observer.observe(bytes);
+                bytes.clear();
+            }
         }
+        res
+    }
 
+    /// Tokens of reparsed entities, without reading from the source
+    pub(crate) fn next_queued_token(&mut self) -> Result {
         if !self.inside_token {
             self.pos = self.head_pos;
             self.inside_token = true;
         }
-
-        // Check if we have saved a char or two for ourselves
         while let Some(c) = self.char_queue.pop_front() {
             if let Some(t) = self.dispatch_char(c)? {
                 self.inside_token = false;
@@ -306,16 +379,72 @@ impl Lexer {
         }
         // if char_queue is empty, all circular reparsing is done
         self.reparse_depth = 0;
-        loop {
-            let c = match self.reader.next_char_from(b)? {
-                Some(c) => c,  // got next char
-                None => break, // nothing to read left
-            };
-
-            if c == '\n' {
-                self.head_pos.new_line();
-            } else {
-                self.head_pos.advance(1);
+        Ok(None)
+    }
+
+    /// For tokens that didn't come from this lexer
+    pub(crate) fn set_position(&mut self, pos: TextPosition) {
+        self.pos = pos;
+        self.head_pos = pos;
+    }
+
+    /// The recently read source, if `error_context` is enabled, with the index of the position in it
+    pub(crate) fn error_context(&self, pos: TextPosition) -> Option<ErrorContext> {
+        if self.recent_text.is_empty() {
+            return None;
+        }
+        let start = self.head_pos.char_offset - self.recent_text.len() as u64;
+        let error_char = pos.char_offset.checked_sub(start)
+            .and_then(|i| usize::try_from(i).ok())
+            .filter(|&i| i <= self.recent_text.len());
+        Some(ErrorContext {
+            text: self.recent_text.iter().collect(),
+            error_index: error_char.map(|n| self.recent_text.iter().take(n).map(|c| c.len_utf8()).sum()),
+        })
+    }
+
+    /// Between tokens outside of markup, with nothing read ahead
+    pub(crate) fn is_idle(&self) -> bool {
+        matches!((self.st, self.normal_state), (State::Normal, State::Normal))
+            && self.char_queue.is_empty() && !self.inside_token && !self.eof_handled
+            && self.reparse_depth == 0 && !self.reader.has_partial_char()
+    }
+
+    /// The position after the last char read from the source
+    pub(crate) fn head_position(&self) -> TextPosition {
+        self.head_pos
+    }
+
+    /// Continues from a saved position in the source, after `is_idle` has been true
+    pub(crate) fn resume_at(&mut self, pos: TextPosition, bytes_read: u64, encoding: Encoding) {
+        self.set_position(pos);
+        self.reader.bytes_read = bytes_read;
+        self.reader.encoding = encoding;
+        self.reader.skip_until_lt = false;
+    }
+
+    fn read_token<B: Read>(&mut self, b: &mut B) -> Result {
+        // Already reached end of buffer
+        if self.eof_handled {
+            return Ok(None);
+        }
+
+        // Check if we have saved a char or two for ourselves
+        if let Some(t) = self.next_queued_token()? {
+            return Ok(Some(t));
+        }
+        while let Some(c) = self.reader.next_char_from(b)? {
+            self.head_pos.advance_char(c, self.prev_char, self.reader.bytes_read, self.tab_width);
+            self.prev_char = c;
+            // only the source is captured, not expanded parameter entities
+            if let Some(text) = &mut self.doctype_text {
+                text.push(c);
+            }
+            if self.error_context > 0 {
+                if self.recent_text.len() >= self.error_context {
+                    self.recent_text.pop_front();
+                }
+                self.recent_text.push_back(c);
             }
 
             if let Some(t) = self.dispatch_char(c)? {
@@ -339,7 +468,8 @@ impl Lexer {
             State::CommentClosing(ClosingSubstate::Second) |
             State::InsideComment | State::InsideMarkupDeclaration |
             State::InsideProcessingInstruction | State::ProcessingInstructionClosing |
-            State::InsideDoctype | State::InsideMarkupDeclarationQuotedString(_) =>
+            State::InsideDoctype | State::InsideMarkupDeclarationQuotedString(_) |
+            State::ConditionalSectionClosing(_) =>
                 Err(self.error(SyntaxError::UnexpectedEof)),
             State::EmptyTagClosing =>
                 Ok(Some(Token::Character('/'))),
@@ -361,6 +491,7 @@ impl Lexer {
         Error {
             pos: self.position(),
             kind: ErrorKind::Syntax(e.to_cow()),
+            context: None,
         }
     }
 
@@ -384,6 +515,7 @@ impl Lexer {
             State::InvalidCDataClosing(s)         => Ok(self.invalid_cdata_closing(c, s)),
             State::InsideMarkupDeclaration        => self.markup_declaration(c),
             State::InsideMarkupDeclarationQuotedString(q) => Ok(Some(self.markup_declaration_string(c, q))),
+            State::ConditionalSectionClosing(s)   => Ok(self.conditional_section_closing(c, s)),
         }
     }
 
@@ -475,7 +607,6 @@ impl Lexer {
             '?'                        => self.move_to(State::ProcessingInstructionClosing),
             '<'                        => Some(Token::OpeningTagStart),
             '>'                        => Some(Token::TagEnd),
-            '/'                        => Some(Token::ClosingTagStart),
             '='                        => Some(Token::EqualsSign),
             '"'                        => Some(Token::DoubleQuote),
             '\''                       => Some(Token::SingleQuote),
@@ -508,6 +639,13 @@ impl Lexer {
     fn comment_or_cdata_or_doctype_started(&mut self, c: char) -> Result {
         match c {
             '-' => Ok(self.move_to(State::CommentStarted)),
+            '[' if matches!(self.normal_state, State::InsideDoctype) => {
+                if !self.external_subset {
+                    return Err(self.error(SyntaxError::ConditionalSectionInInternalSubset));
+                }
+                self.conditional_depth += 1;
+                Ok(Some(self.move_to_with(State::InsideDoctype, Token::ConditionalSectionStart)))
+            },
             '[' => Ok(self.move_to(State::CDataStarted(CDataStartedSubstate::E))),
             'D' => Ok(self.move_to(State::DoctypeStarted(DoctypeStartedSubstate::D))),
             'E' | 'A' | 'N' if matches!(self.normal_state, State::InsideDoctype) => {
@@ -568,14 +706,22 @@ impl Lexer {
             DOC    ; 'T' ; DOCT   ; "<!DOC",
             DOCT   ; 'Y' ; DOCTY  ; "<!DOCT",
             DOCTY  ; 'P' ; DOCTYP ; "<!DOCTY";
-            DOCTYP ; 'E' ; "<!DOCTYP" ; Ok(Some(self.move_to_and_reset_normal(State::InsideDoctype, Token::DoctypeStart)))
+            DOCTYP ; 'E' ; "<!DOCTYP" ; {
+                if self.capture_doctype {
+                    self.doctype_text = Some("<!DOCTYPE".into());
+                }
+                Ok(Some(self.move_to_and_reset_normal(State::InsideDoctype, Token::DoctypeStart)))
+            }
         )
     }
 
     /// State used while awaiting the closing bracket for the <!DOCTYPE tag
     fn inside_doctype(&mut self, c: char) -> Option<Token> {
         match c {
+            // the parser reports `>` inside conditional sections
+            '>' if self.conditional_depth > 0 => Some(Token::TagEnd),
             '>' => Some(self.move_to_and_reset_normal(State::Normal, Token::TagEnd)),
+            ']' if self.conditional_depth > 0 => self.move_to(State::ConditionalSectionClosing(ClosingSubstate::First)),
             '<'                        => self.move_to(State::TagStarted),
             '&'                        => Some(Token::ReferenceStart),
             ';'                        => Some(Token::ReferenceEnd),
@@ -585,6 +731,23 @@ impl Lexer {
         }
     }
 
+    /// Encountered ']' inside a conditional section
+    fn conditional_section_closing(&mut self, c: char, s: ClosingSubstate) -> Option<Token> {
+        match s {
+            ClosingSubstate::First => match c {
+                ']' => self.move_to(State::ConditionalSectionClosing(ClosingSubstate::Second)),
+                _ => Some(self.move_to_with_unread(State::InsideDoctype, &[c], Token::Character(']'))),
+            },
+            ClosingSubstate::Second => match c {
+                '>' => {
+                    self.conditional_depth -= 1;
+                    Some(self.move_to_with(State::InsideDoctype, Token::CDataEnd))
+                },
+                _ => Some(self.move_to_with_unread(State::InsideDoctype, &[']', c], Token::Character(']'))),
+            },
+        }
+    }
+
     /// Encountered '?'
     fn processing_instruction_closing(&mut self, c: char) -> Token {
         match c {
@@ -1,18 +1,25 @@
 //! Contains an implementation of pull-based XML parser.
 
-use crate::common::{is_xml10_char, is_xml11_char, is_xml11_char_not_restricted, is_name_char, is_name_start_char, is_whitespace_char};
+use crate::attribute::{AttributeDetails, Attributes, OwnedAttribute};
+use crate::common::{is_xml10_char, is_xml11_char, is_xml11_char_not_restricted, is_name_char, is_name_start_char, is_ncname, is_whitespace_char};
 use crate::common::{Position, TextPosition, XmlVersion};
-use crate::name::OwnedName;
-use crate::namespace::NamespaceStack;
-use crate::reader::config::ParserConfig2;
-use crate::reader::error::SyntaxError;
-use crate::reader::events::XmlEvent;
+use crate::name::{NameTable, OwnedName};
+use crate::namespace::{self, NamespaceStack};
+use crate::reader::config::{DocumentDelimiter, ParserConfig2, TextHandling};
+use crate::reader::entities::{Notation, UnparsedEntity};
+use crate::reader::error::{Limit, SyntaxError, Warning};
+use crate::reader::events::{EventMask, XmlEvent};
 use crate::reader::indexset::AttributesSet;
-use crate::reader::lexer::{Lexer, Token};
-use super::{Error, ErrorKind};
+use crate::reader::lexer::{Lexer, Result as LexResult, Token};
+use crate::util::Encoding;
+use super::{Error, ErrorKind, ReaderStats, TokenSource};
 
-use std::collections::HashMap;
-use std::io::Read;
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read};
+use std::ops::Range;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
 macro_rules! gen_takes(
     ($($field:ident -> $method:ident, $t:ty, $def:expr);+) => (
@@ -38,7 +45,12 @@ gen_takes!(
     element_name -> take_element_name, Option<OwnedName>, None;
 
     attr_name    -> take_attr_name, Option<OwnedName>, None;
-    attributes   -> take_attributes, AttributesSet, AttributesSet::new()
+    attributes   -> take_attributes, AttributesSet, AttributesSet::new();
+    raw_value    -> take_raw_value, String, String::new();
+    attr_details -> take_attr_details, Vec<AttributeDetails>, Vec::new();
+    attr_positions -> take_attr_positions, Vec<TextPosition>, Vec::new();
+
+    internal_subset -> take_internal_subset, Option<Range<u64>>, None
 );
 
 mod inside_cdata;
@@ -50,23 +62,38 @@ mod inside_opening_tag;
 mod inside_processing_instruction;
 mod inside_reference;
 mod outside_tag;
+mod saved_state;
+
+/// Text is split into parts of this size, if `lazy_text` is enabled
+const LAZY_TEXT_CHUNK: usize = 1 << 16;
+
+/// Positions of events that have been started, but not emitted yet, never exceed this
+const MAX_PENDING_POSITIONS: usize = 16;
 
 static DEFAULT_VERSION: XmlVersion = XmlVersion::Version10;
 static DEFAULT_STANDALONE: Option<bool> = None;
 
-type ElementStack = Vec<OwnedName>;
+/// Open elements, and positions of their opening tags
+type ElementStack = Vec<(OwnedName, TextPosition)>;
 pub type Result = super::Result<XmlEvent>;
 
 /// Pull-based XML parser.
+#[derive(Clone)]
 pub(crate) struct PullParser {
     config: ParserConfig2,
+    /// Strings of names, shared by the names of all events
+    names: NameTable,
+    /// The config from before the first change made with `ParserControls`, restored by `reset`
+    original_config: Option<Box<ParserConfig2>>,
     lexer: Lexer,
     st: State,
     state_after_reference: State,
     buf: String,
 
-    /// From DTD internal subset
-    entities: HashMap<String, String>,
+    /// From DTD internal subset, shared with the `EntityTable` until the document declares any
+    entities: Arc<HashMap<String, String>>,
+    /// Identifier of a known external DTD, used at the end of the DOCTYPE
+    external_dtd: Option<String>,
 
     nst: NamespaceStack,
 
@@ -75,13 +102,197 @@ pub(crate) struct PullParser {
     next_event: Option<Result>,
     est: ElementStack,
     pos: Vec<TextPosition>,
+    /// Start of the first comment or tag of a document without a declaration. Its position is reported
+    /// after its start like it always has been, so the start is kept only for the event byte range.
+    unpositioned_event_start: Option<u64>,
+    /// Byte offset of the last event, if it's not in `pos`
+    event_start_override: Option<u64>,
+
+    /// Declared encoding that the application has to decode itself
+    deferred_encoding: Option<String>,
+
+    stats: SharedStats,
+
+    /// `WouldBlock` and `TimedOut` errors in a row, counted inside markup for `max_stalled_reads`
+    stalled_reads: u32,
+
+    /// Source details of the attributes of the last `StartElement`
+    attribute_details: Vec<AttributeDetails>,
+
+    /// Attributes of the last `StartElement` with undecoded values, if `lazy_attributes` is enabled
+    lazy_attributes: Attributes,
+    /// Where the values of `lazy_attributes` start, for errors found when they're decoded
+    lazy_attribute_positions: Vec<TextPosition>,
+
+    /// Document-order indices of the open elements
+    element_indices: Vec<usize>,
+    /// Element of the last `StartElement` or `EndElement`, and its parent
+    element_index: Option<(usize, Option<usize>)>,
+    elements_started: usize,
+
+    /// Positions of elements with `xml:id`, if `track_xml_ids` is enabled
+    xml_ids: HashMap<String, TextPosition>,
+
+    /// ID-like types of attributes from `<!ATTLIST>`, by element and attribute name
+    attribute_types: HashMap<(String, String), IdType>,
+    /// Values of ID attributes, if `check_idrefs` is enabled
+    ids: HashSet<String>,
+    /// IDREFs to check at the end of the document
+    idrefs: Vec<(String, TextPosition)>,
+
+    /// From `<!NOTATION>` declarations
+    notations: Vec<Notation>,
+    /// From `<!ENTITY … NDATA …>` declarations
+    unparsed_entities: Vec<UnparsedEntity>,
+    /// Names of elements declared with element content, like `<!ELEMENT a (b, c)>`
+    element_content: HashSet<String>,
+    /// The last event is text that is only whitespace, as seen while the text was read
+    whitespace_text: bool,
+    /// The last event is whitespace in an element that has element content
+    element_content_whitespace: bool,
+    /// CDATA has been added to text that hasn't been emitted yet
+    cdata_in_text: bool,
+    /// The last event is text that includes CDATA
+    text_contains_cdata: bool,
+
+    /// Problems that have been worked around
+    warnings: Vec<Warning>,
+    /// Text outside the root element that is being skipped, if `text_outside_root` allows it
+    /// Its start, length, and length without trailing whitespace.
+    stray_text: Option<(TextPosition, usize, usize)>,
+
+    /// Markup of the next document that has ended the current one, if `multiple_documents` is enabled
+    next_document: Option<DocumentStart>,
+    /// Tokens of the next document that have been read already, in reverse order
+    replayed_tokens: Vec<Token>,
+
+    /// Part of the current text has been emitted already, if `lazy_text` is enabled
+    text_continued: bool,
+    /// Chosen by the `text_handling_hook` for the text after the last start or end tag
+    text_handling: TextHandling,
+    /// An event that has been read too far by the text reader
+    pushed_back: Option<XmlEvent>,
+    /// Offsets in `buf` of whitespace from character references, if `preserve_escaped_whitespace` is enabled
+    escaped_whitespace: Vec<usize>,
+    /// Depth of extension elements of the `xop:Include` being skipped, if `xop_includes` is enabled
+    xop_include: Option<u32>,
+    /// Content-id of the last `StartElement`, if it's `xop:Include`
+    xop_content_id: Option<String>,
 
     encountered: Encountered,
     inside_whitespace: bool,
+    /// The text read since the last text event is only whitespace, including escaped whitespace
+    text_whitespace: bool,
     read_prefix_separator: bool,
     pop_namespace: bool,
 }
 
+/// Where the parser takes the tokens from: a byte stream, or tokens from `CoreParser`
+pub(crate) trait TokenInput {
+    fn next_token(&mut self, lexer: &mut Lexer) -> LexResult;
+}
+
+impl<R: Read> TokenInput for R {
+    #[inline]
+    fn next_token(&mut self, lexer: &mut Lexer) -> LexResult {
+        lexer.next_token(self)
+    }
+}
+
+/// Tokens from a custom source, after the reparsed entities
+pub(crate) struct Tokens<'a, S: ?Sized>(pub &'a mut S);
+
+impl<S: TokenSource + ?Sized> TokenInput for Tokens<'_, S> {
+    fn next_token(&mut self, lexer: &mut Lexer) -> LexResult {
+        if let Some(t) = lexer.next_queued_token()? {
+            return Ok(Some(t));
+        }
+        let t = self.0.next_token()?;
+        lexer.set_position(self.0.position());
+        Ok(t)
+    }
+}
+
+/// A single token, after which the parser has to wait for more
+pub(crate) struct FedToken {
+    token: Option<Token>,
+}
+
+impl FedToken {
+    pub fn new(token: Token) -> Self {
+        Self { token: Some(token) }
+    }
+
+    /// The error is from running out of fed tokens, and parsing can continue
+    pub fn is_need_more_tokens(error: &Error) -> bool {
+        match error.kind() {
+            ErrorKind::Io(e) => e.get_ref().map_or(false, |e| e.is::<NeedMoreTokens>()),
+            _ => false,
+        }
+    }
+}
+
+/// Payload of the `WouldBlock` error of `FedToken`, which no other source can return
+#[derive(Debug)]
+struct NeedMoreTokens;
+
+impl std::fmt::Display for NeedMoreTokens {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("need more tokens")
+    }
+}
+
+impl std::error::Error for NeedMoreTokens {}
+
+impl TokenInput for FedToken {
+    fn next_token(&mut self, lexer: &mut Lexer) -> LexResult {
+        if let Some(t) = lexer.next_queued_token()? {
+            return Ok(Some(t));
+        }
+        match self.token.take() {
+            Some(t) => Ok(Some(t)),
+            None => Err(io::Error::new(io::ErrorKind::WouldBlock, NeedMoreTokens).into()),
+        }
+    }
+}
+
+/// How the next document of a stream has started
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) enum DocumentStart {
+    /// `<?xml` followed by this whitespace char
+    Declaration(char),
+    /// `<` of the root element
+    Element,
+}
+
+/// Statistics that are also updated through a shared reference, when values of lazy attributes are decoded
+#[derive(Default)]
+pub(crate) struct SharedStats(Mutex<ReaderStats>);
+
+impl SharedStats {
+    pub(crate) fn get_mut(&mut self) -> &mut ReaderStats {
+        self.0.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, ReaderStats> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Clone for SharedStats {
+    fn clone(&self) -> Self {
+        Self(Mutex::new(self.lock().clone()))
+    }
+}
+
+/// Attribute types declared in the DTD that are relevant to `check_idrefs`
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) enum IdType {
+    Id,
+    IdRef,
+    IdRefs,
+}
+
 // Keeps track when XML declaration can happen
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 enum Encountered {
@@ -103,21 +314,27 @@ impl PullParser {
 
     #[inline]
     fn new_with_config2(config: ParserConfig2) -> PullParser {
+        let config = Self::prepare_config(config);
         let mut lexer = Lexer::new(&config);
         if let Some(enc) = config.override_encoding {
             lexer.set_encoding(enc);
         }
 
-        let mut pos = Vec::with_capacity(16);
+        let mut pos = Vec::with_capacity(MAX_PENDING_POSITIONS);
         pos.push(TextPosition::new());
 
+        let entities = config.entity_table.entities().clone();
+
         PullParser {
             config,
+            names: NameTable::default(),
+            original_config: None,
             lexer,
             st: State::DocumentStart,
             state_after_reference: State::OutsideTag,
             buf: String::new(),
-            entities: HashMap::new(),
+            entities,
+            external_dtd: None,
             nst: NamespaceStack::default(),
 
             data: MarkupData {
@@ -130,22 +347,328 @@ impl PullParser {
                 quote: None,
                 attr_name: None,
                 attributes: AttributesSet::new(),
+                attr_index: 0,
+                raw_quote: '"',
+                raw_value: String::new(),
+                attr_details: Vec::new(),
+                value_pos: TextPosition::new(),
+                attr_positions: Vec::new(),
+                include_depth: 0,
+                ignore_depth: 0,
+                internal_subset: None,
             },
             final_result: None,
             next_event: None,
             est: Vec::new(),
             pos,
+            unpositioned_event_start: None,
+            event_start_override: None,
+
+            deferred_encoding: None,
+
+            stats: SharedStats::default(),
+
+            stalled_reads: 0,
+
+            attribute_details: Vec::new(),
+
+            lazy_attributes: Attributes::new(),
+            lazy_attribute_positions: Vec::new(),
+
+            element_indices: Vec::new(),
+            element_index: None,
+            elements_started: 0,
+            xml_ids: HashMap::new(),
+            attribute_types: HashMap::new(),
+            ids: HashSet::new(),
+            idrefs: Vec::new(),
+            notations: Vec::new(),
+            unparsed_entities: Vec::new(),
+            element_content: HashSet::new(),
+            whitespace_text: false,
+            element_content_whitespace: false,
+            cdata_in_text: false,
+            text_contains_cdata: false,
+
+            warnings: Vec::new(),
+            stray_text: None,
+
+            next_document: None,
+            replayed_tokens: Vec::new(),
+
+            text_continued: false,
+            text_handling: TextHandling::Collect,
+            pushed_back: None,
+            escaped_whitespace: Vec::new(),
+            xop_include: None,
+            xop_content_id: None,
 
             encountered: Encountered::None,
             inside_whitespace: true,
+            text_whitespace: true,
             read_prefix_separator: false,
             pop_namespace: false,
         }
     }
 
+    fn prepare_config(mut config: ParserConfig2) -> ParserConfig2 {
+        // unwanted comments and DOCTYPEs aren't even buffered
+        if !config.events_only.contains(EventMask::COMMENT) {
+            config.c.ignore_comments = true;
+        }
+        if !config.events_only.contains(EventMask::DOCTYPE) {
+            config.capture_doctype_raw = false;
+        }
+        config
+    }
+
+    /// Same as `reset`, but also replaces the config
+    pub fn reset_with_config(&mut self, config: ParserConfig2) {
+        self.config = Self::prepare_config(config);
+        self.original_config = None;
+        self.reset();
+    }
+
+    /// Prepares the parser for a new document, keeping the allocated buffers.
+    /// Changes of the config made with `ParserControls` are undone.
+    pub fn reset(&mut self) {
+        if let Some(config) = self.original_config.take() {
+            self.config = *config;
+        }
+        self.lexer.reset(&self.config);
+        if let Some(enc) = self.config.override_encoding {
+            self.lexer.set_encoding(enc);
+        }
+        self.st = State::DocumentStart;
+        self.state_after_reference = State::OutsideTag;
+        self.buf.clear();
+        self.entities = self.config.entity_table.entities().clone();
+        self.external_dtd = None;
+        // the bottom of the stack only holds the predefined prefixes
+        self.nst.0.truncate(1);
+
+        let data = &mut self.data;
+        data.name.clear();
+        data.ref_data.clear();
+        data.version = None;
+        data.encoding = None;
+        data.standalone = None;
+        data.element_name = None;
+        data.quote = None;
+        data.attr_name = None;
+        data.attributes = AttributesSet::new();
+        data.attr_index = 0;
+        data.raw_quote = '"';
+        data.raw_value.clear();
+        data.attr_details.clear();
+        data.attr_positions.clear();
+        data.include_depth = 0;
+        data.ignore_depth = 0;
+        data.internal_subset = None;
+
+        self.final_result = None;
+        self.next_event = None;
+        self.est.clear();
+        self.pos.clear();
+        self.pos.push(TextPosition::new());
+        self.unpositioned_event_start = None;
+        self.event_start_override = None;
+        self.deferred_encoding = None;
+        self.stats = SharedStats::default();
+        self.stalled_reads = 0;
+        self.attribute_details.clear();
+        self.lazy_attributes.clear();
+        self.lazy_attribute_positions.clear();
+        self.element_indices.clear();
+        self.element_index = None;
+        self.elements_started = 0;
+        self.xml_ids.clear();
+        self.attribute_types.clear();
+        self.ids.clear();
+        self.idrefs.clear();
+        self.notations.clear();
+        self.unparsed_entities.clear();
+        self.element_content.clear();
+        self.whitespace_text = false;
+        self.element_content_whitespace = false;
+        self.cdata_in_text = false;
+        self.text_contains_cdata = false;
+        self.warnings.clear();
+        self.stray_text = None;
+        self.next_document = None;
+        self.replayed_tokens.clear();
+        self.text_continued = false;
+        self.text_handling = TextHandling::Collect;
+        self.pushed_back = None;
+        self.escaped_whitespace.clear();
+        self.xop_include = None;
+        self.xop_content_id = None;
+        self.encountered = Encountered::None;
+        self.inside_whitespace = true;
+        self.text_whitespace = true;
+        self.read_prefix_separator = false;
+        self.pop_namespace = false;
+    }
+
     /// Checks if this parser ignores the end of stream errors.
     pub fn is_ignoring_end_of_stream(&self) -> bool { self.config.c.ignore_end_of_stream }
 
+    pub fn declared_entities(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entities.iter()
+            .filter(|(name, _)| !name.starts_with('%'))
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// The DTD of the document is an external subset, which can have conditional sections
+    pub fn set_external_subset(&mut self) {
+        self.lexer.set_external_subset();
+    }
+
+    pub fn take_entities(&mut self) -> HashMap<String, String> {
+        Arc::try_unwrap(std::mem::take(&mut self.entities)).unwrap_or_else(|shared| (*shared).clone())
+    }
+
+    pub fn attribute_details(&self) -> &[AttributeDetails] {
+        &self.attribute_details
+    }
+
+    pub(crate) fn config(&self) -> &ParserConfig2 {
+        &self.config
+    }
+
+    /// The config for changes that last until `reset`
+    pub(crate) fn config_mut(&mut self) -> &mut ParserConfig2 {
+        if self.original_config.is_none() {
+            self.original_config = Some(Box::new(self.config.clone()));
+        }
+        &mut self.config
+    }
+
+    pub fn lazy_attributes(&self) -> &[OwnedAttribute] {
+        &self.lazy_attributes
+    }
+
+    pub fn lazy_attribute_positions(&self) -> &[TextPosition] {
+        &self.lazy_attribute_positions
+    }
+
+    pub fn element_index(&self) -> Option<usize> {
+        self.element_index.map(|(index, _)| index)
+    }
+
+    pub fn parent_element_index(&self) -> Option<usize> {
+        self.element_index.and_then(|(_, parent)| parent)
+    }
+
+    pub fn xml_ids(&self) -> &HashMap<String, TextPosition> {
+        &self.xml_ids
+    }
+
+    pub fn unresolved_idrefs(&self) -> impl Iterator<Item = (&str, TextPosition)> {
+        self.idrefs.iter()
+            .filter(|(id, _)| !self.ids.contains(id))
+            .map(|(id, pos)| (id.as_str(), *pos))
+    }
+
+    pub fn notations(&self) -> &[Notation] {
+        &self.notations
+    }
+
+    pub fn unparsed_entities(&self) -> &[UnparsedEntity] {
+        &self.unparsed_entities
+    }
+
+    pub fn is_whitespace_text(&self) -> bool {
+        self.whitespace_text
+    }
+
+    pub fn is_element_content_whitespace(&self) -> bool {
+        self.element_content_whitespace
+    }
+
+    pub fn text_contains_cdata(&self) -> bool {
+        self.text_contains_cdata
+    }
+
+    /// The event will be returned again by the next call to `next`
+    pub fn push_back(&mut self, event: XmlEvent) {
+        debug_assert!(self.pushed_back.is_none());
+        self.pushed_back = Some(event);
+    }
+
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Records the warning, unless there are `max_warnings` already
+    fn warn(&mut self, warning: Warning) {
+        if self.warnings.len() < self.config.max_warnings {
+            self.warnings.push(warning);
+        }
+    }
+
+    /// The root element has ended, and the next document can start with an element or only a declaration
+    fn is_document_boundary(&self, element: bool) -> bool {
+        self.depth() == 0 && self.encountered == Encountered::Element && match self.config.multiple_documents {
+            Some(DocumentDelimiter::DeclarationOrElement) => true,
+            Some(DocumentDelimiter::Declaration) => !element,
+            None => false,
+        }
+    }
+
+    /// Resets the parser after `EndDocument`, if another document follows. The lexer, stats and warnings are kept.
+    pub fn start_next_document(&mut self) -> bool {
+        let start = match self.next_document.take() {
+            Some(start) => start,
+            None => return false,
+        };
+        // changes made with `ParserControls` were only for the previous document
+        let config = self.original_config.take().map_or_else(|| self.config.clone(), |config| *config);
+        let mut parser = Self::new_with_config2(config);
+        std::mem::swap(&mut parser.lexer, &mut self.lexer);
+        parser.stats = std::mem::take(&mut self.stats);
+        parser.warnings = std::mem::take(&mut self.warnings);
+        parser.pos[0] = parser.lexer.position();
+        parser.replayed_tokens = match start {
+            DocumentStart::Declaration(c) => vec![Token::Character(c), Token::Character('l'), Token::Character('m'), Token::Character('x'), Token::ProcessingInstructionStart],
+            DocumentStart::Element => vec![Token::OpeningTagStart],
+        };
+        *self = parser;
+        true
+    }
+
+    /// Prefixes declared outside of the parsed fragment, kept until [`reset`](Self::reset)
+    pub fn inherit_namespaces<'a>(&mut self, namespaces: impl IntoIterator<Item = (&'a String, &'a String)>) {
+        let level = self.nst.push_empty().peek_mut();
+        for (prefix, uri) in namespaces {
+            level.put(prefix.as_str(), uri.as_str());
+        }
+    }
+
+    pub fn event_byte_range(&self) -> std::ops::Range<u64> {
+        // the next event's start is pushed already if it's been seen
+        let end = self.pos.get(1).map(|next| next.byte_offset)
+            .or(self.unpositioned_event_start)
+            .unwrap_or_else(|| self.lexer.bytes_read());
+        self.event_start_override.unwrap_or(self.pos[0].byte_offset)..end
+    }
+
+    /// Encoding name from `<?xml encoding>` that the parser is waiting for the application to decode
+    pub fn deferred_encoding(&self) -> Option<&str> {
+        self.deferred_encoding.as_deref()
+    }
+
+    /// The source has been transcoded to UTF-8 by the application, so parsing can continue
+    pub fn resume_as_utf8(&mut self) {
+        if self.deferred_encoding.take().is_some() {
+            self.lexer.set_encoding(Encoding::Utf8);
+        }
+    }
+
     #[inline(never)]
     fn set_encountered(&mut self, new_encounter: Encountered) -> Option<Result> {
         if new_encounter <= self.encountered {
@@ -209,7 +732,25 @@ pub enum DoctypeSubstate {
     PEReferenceDefinitionStart,
     PEReferenceDefinition,
     SkipDeclaration,
+    /// `<!ATTLIST`, read if `check_idrefs` is enabled
+    AttlistDeclaration,
+    /// `<!NOTATION`, `<!ELEMENT`, or `<!ENTITY` with an external identifier
+    Declaration(DeclarationKind),
     Comment,
+    /// `INCLUDE` or `IGNORE` after `<![`
+    ConditionalSectionKeyword,
+    /// `%name;` in the keyword
+    ConditionalSectionPEReference,
+    /// Inside `<![IGNORE[`
+    IgnoredSection,
+}
+
+/// Declarations of the DTD that are read into `PullParser` tables
+#[derive(Copy, Clone, PartialEq)]
+pub enum DeclarationKind {
+    Notation,
+    Element,
+    ExternalEntity,
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -235,6 +776,9 @@ pub enum ClosingTagSubstate {
 pub enum ProcessingInstructionSubstate {
     PIInsideName,
     PIInsideData,
+    /// Target of an instruction that won't be emitted, kept in `data.name`, because the buffer may have text
+    IgnoredName,
+    IgnoredData,
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -295,6 +839,7 @@ impl QuoteToken {
     }
 }
 
+#[derive(Clone)]
 struct MarkupData {
     name: String,     // used for processing instruction name
     ref_data: String,  // used for reference content
@@ -308,6 +853,17 @@ struct MarkupData {
     quote: Option<QuoteToken>,  // used to hold opening quote for attribute value
     attr_name: Option<OwnedName>,  // used to hold attribute name
     attributes: AttributesSet,   // used to hold all accumulated attributes
+
+    attr_index: usize,  // used to count attributes and namespace declarations in a tag
+    raw_quote: char,  // used to hold the quote of the last attribute value
+    raw_value: String,  // used to hold attribute value as written
+    attr_details: Vec<AttributeDetails>,  // used to hold source details of accumulated attributes
+    value_pos: TextPosition,  // used to hold the position of the last attribute value
+    attr_positions: Vec<TextPosition>,  // used to hold value positions of accumulated lazy attributes
+
+    include_depth: u32,  // used to count open `<![INCLUDE[` sections
+    ignore_depth: u32,  // used to count nested sections inside `<![IGNORE[`
+    internal_subset: Option<Range<u64>>,  // used to hold byte offsets of the DTD's internal subset
 }
 
 impl PullParser {
@@ -315,15 +871,160 @@ impl PullParser {
     ///
     /// This method should be always called with the same buffer. If you call it
     /// providing different buffers each time, the result will be undefined.
-    pub fn next<R: Read>(&mut self, r: &mut R) -> Result {
+    pub fn next<T: TokenInput>(&mut self, r: &mut T) -> Result {
+        loop {
+            let res = self.next_unmasked(r);
+            match &res {
+                Ok(ev) if !self.config.events_only.matches(ev) => continue,
+                _ => return res,
+            }
+        }
+    }
+
+    /// Next event, including kinds that aren't in `events_only`
+    pub(crate) fn next_unmasked<T: TokenInput>(&mut self, r: &mut T) -> Result {
+        if let Some(ev) = self.pushed_back.take() {
+            return Ok(ev);
+        }
+        if self.xop_content_id.take().is_some() {
+            // the start tag of `xop:Include` has been returned
+            return self.finish_xop_include(r);
+        }
+        self.next_counted(r)
+    }
+
+    /// Skips the content of `xop:Include` up to its `EndElement`
+    fn finish_xop_include<T: TokenInput>(&mut self, r: &mut T) -> Result {
+        loop {
+            let ev = self.next_counted(r)?;
+            let depth = match self.xop_include.as_mut() {
+                Some(depth) => depth,
+                None => return Ok(ev), // an error has ended the document
+            };
+            match ev {
+                // extension elements are allowed, and ignored
+                XmlEvent::StartElement { .. } => *depth += 1,
+                XmlEvent::EndElement { .. } if *depth > 0 => *depth -= 1,
+                XmlEvent::EndElement { .. } => {
+                    self.xop_include = None;
+                    return Ok(ev);
+                },
+                XmlEvent::Characters(_) if *depth == 0 && !self.whitespace_text => {
+                    self.xop_include = None;
+                    let err = self.error(SyntaxError::UnexpectedXopIncludeContent);
+                    return self.set_final_result(err);
+                },
+                _ => {},
+            }
+        }
+    }
+
+    pub fn xop_content_id(&self) -> Option<&str> {
+        self.xop_content_id.as_deref()
+    }
+
+    /// Next event with its statistics
+    fn next_counted<T: TokenInput>(&mut self, r: &mut T) -> Result {
+        let res = loop {
+            let res = self.pull_event(r);
+            if self.config.skip_leading_garbage_until_lt {
+                let bytes = self.lexer.take_skipped_bytes();
+                if bytes > 0 {
+                    self.warn(Warning::LeadingGarbage { bytes });
+                }
+            }
+            if self.text_handling != TextHandling::Skip ||
+                !matches!(res, Ok(XmlEvent::Characters(_) | XmlEvent::CData(_) | XmlEvent::Whitespace(_))) {
+                break res;
+            }
+        };
+        #[cfg(feature = "unicode-normalization")]
+        let res = if self.config.normalize_text { res.map(normalize_text) } else { res };
+        let res = res.map_err(|mut e| {
+            if e.context.is_none() {
+                e.context = self.lexer.error_context(e.pos).map(Box::new);
+            }
+            e
+        });
+        if let Ok(ev) = &res {
+            // the states that emit text have set it
+            match ev {
+                XmlEvent::Whitespace(_) => self.whitespace_text = true,
+                XmlEvent::Characters(_) | XmlEvent::CData(_) => {},
+                _ => self.whitespace_text = false,
+            }
+            self.element_content_whitespace = self.whitespace_text &&
+                !matches!(ev, XmlEvent::CData(_)) && self.is_in_element_content();
+            // text that has been dropped, e.g. as whitespace, ends with markup
+            self.text_contains_cdata = matches!(ev, XmlEvent::Characters(_) | XmlEvent::Whitespace(_)) && self.cdata_in_text;
+            self.cdata_in_text = false;
+            let events = &mut self.stats.get_mut().events;
+            match ev {
+                XmlEvent::StartElement { .. } => {
+                    events.elements += 1;
+                    let index = self.elements_started;
+                    self.elements_started += 1;
+                    self.element_index = Some((index, self.element_indices.last().copied()));
+                    self.element_indices.push(index);
+                },
+                XmlEvent::EndElement { .. } => {
+                    self.element_index = self.element_indices.pop()
+                        .map(|index| (index, self.element_indices.last().copied()));
+                },
+                XmlEvent::Characters(_) | XmlEvent::Whitespace(_) => events.characters += 1,
+                XmlEvent::CData(_) => events.cdata += 1,
+                XmlEvent::Comment(_) => events.comments += 1,
+                XmlEvent::ProcessingInstruction { .. } => events.processing_instructions += 1,
+                _ => {},
+            }
+            if let Some(hook) = &self.config.text_handling_hook {
+                if matches!(ev, XmlEvent::StartElement { .. } | XmlEvent::EndElement { .. }) {
+                    self.text_handling = hook.next(ev);
+                }
+            }
+        }
+        res
+    }
+
+    /// The current element has been declared to contain only elements, so its whitespace isn't text
+    fn is_in_element_content(&self) -> bool {
+        if self.element_content.is_empty() {
+            return false;
+        }
+        // without building the qualified name, since this is checked for every whitespace
+        self.est.last().map_or(false, |(name, _)| match &name.prefix {
+            None => self.element_content.contains(name.local_name.as_str()),
+            Some(prefix) => self.element_content.iter().any(|declared| {
+                declared.split_once(':') == Some((prefix.as_str(), name.local_name.as_str()))
+            }),
+        })
+    }
+
+    /// Statistics of the document parsed so far
+    pub fn stats(&self) -> ReaderStats {
+        ReaderStats {
+            bytes_read: self.lexer.bytes_read(),
+            ..self.stats.lock().clone()
+        }
+    }
+
+    fn pull_event<T: TokenInput>(&mut self, r: &mut T) -> Result {
         if let Some(ref ev) = self.final_result {
             return ev.clone();
         }
 
+        if let Some(name) = self.deferred_encoding.as_deref() {
+            return self.error(SyntaxError::UnsupportedEncoding(name.into()));
+        }
+
         if let Some(ev) = self.next_event.take() {
             return ev;
         }
 
+        if self.next_document.is_some() {
+            return self.handle_eof();
+        }
+
         if self.pop_namespace {
             self.pop_namespace = false;
             self.nst.pop();
@@ -335,9 +1036,15 @@ impl PullParser {
 
             // While lexer gives us Ok(maybe_token) -- we loop.
             // Upon having a complete XML-event -- we return from the whole function.
-            match self.lexer.next_token(r) {
+            let token = match self.replayed_tokens.pop() {
+                Some(token) => Ok(Some(token)),
+                None => r.next_token(&mut self.lexer),
+            };
+            match token {
                 Ok(Some(token)) => {
+                    self.stalled_reads = 0;
                     match self.dispatch_token(token) {
+                        None if self.next_document.is_some() => break,
                         None => {}, // continue
                         Some(Ok(xml_event)) => {
                             self.next_pos();
@@ -345,12 +1052,18 @@ impl PullParser {
                         },
                         Some(Err(xml_error)) => {
                             self.next_pos();
+                            if self.deferred_encoding.is_some() {
+                                return Err(xml_error); // can be resumed
+                            }
                             return self.set_final_result(Err(xml_error));
                         },
                     }
                 },
                 Ok(None) => break,
                 Err(lexer_error) => {
+                    if FedToken::is_need_more_tokens(&lexer_error) || self.is_recoverable_stall(&lexer_error) {
+                        return Err(lexer_error);
+                    }
                     return self.set_final_result(Err(lexer_error))
                 },
             }
@@ -359,13 +1072,34 @@ impl PullParser {
         self.handle_eof()
     }
 
+    /// A `WouldBlock` or `TimedOut` error that is within the `max_stalled_reads` limit
+    fn is_recoverable_stall(&mut self, error: &Error) -> bool {
+        let limit = match self.config.max_stalled_reads {
+            Some(limit) => limit,
+            None => return false,
+        };
+        match error.kind() {
+            ErrorKind::Io(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {},
+            _ => return false,
+        }
+        if self.st == State::OutsideTag {
+            return true;
+        }
+        self.stalled_reads += 1;
+        self.stalled_reads <= limit
+    }
+
     /// Handle end of stream
     fn handle_eof(&mut self) -> std::result::Result<XmlEvent, super::Error> {
         // Forward pos to the lexer head
         self.next_pos();
+        self.end_stray_text();
         let ev = if self.depth() == 0 {
             if self.encountered == Encountered::Element && self.st == State::OutsideTag {  // all is ok
-                Ok(XmlEvent::EndDocument)
+                match self.unresolved_idrefs().next() {
+                    Some((id, pos)) => Err(Error { pos, kind: ErrorKind::Syntax(SyntaxError::UnresolvedIdref(id.into()).to_cow()), context: None }),
+                    None => Ok(XmlEvent::EndDocument),
+                }
             } else if self.encountered < Encountered::Element {
                 self.error(SyntaxError::NoRootElement)
             } else {  // self.st != State::OutsideTag
@@ -374,9 +1108,9 @@ impl PullParser {
         } else if self.config.c.ignore_end_of_stream {
             self.final_result = None;
             self.lexer.reset_eof_handled();
-            return self.error(SyntaxError::UnbalancedRootElement);
+            return self.unclosed_elements_error();
         } else {
-            self.error(SyntaxError::UnbalancedRootElement)
+            self.unclosed_elements_error()
         };
         self.set_final_result(ev)
     }
@@ -389,14 +1123,48 @@ impl PullParser {
         result
     }
 
+    #[cold]
+    fn unclosed_elements_error(&self) -> Result {
+        Err(Error {
+            pos: self.lexer.position(),
+            kind: ErrorKind::UnclosedElements(self.est.clone()),
+            context: None,
+        })
+    }
+
     #[cold]
     fn error(&self, e: SyntaxError) -> Result {
         Err(Error {
             pos: self.lexer.position(),
-            kind: ErrorKind::Syntax(e.to_cow()),
+            kind: e.into(),
+            context: None,
         })
     }
 
+    /// `observed` is the size that exceeds the limit, and `name` of what's being read or its element
+    #[cold]
+    fn limit_error(&self, limit: Limit, observed: usize, name: Option<String>) -> Result {
+        let configured = match limit {
+            Limit::NameLength => self.config.max_name_length,
+            Limit::Attributes => self.config.max_attributes,
+            Limit::AttributeLength => self.config.max_attribute_length,
+            Limit::DataLength => self.config.max_data_length,
+        };
+        Err(Error {
+            pos: self.lexer.position(),
+            kind: ErrorKind::LimitExceeded { limit, configured, observed, name: name.map(Into::into) },
+            context: None,
+        })
+    }
+
+    fn current_element_name(&self) -> Option<String> {
+        self.est.last().map(|(name, _)| name.borrow().to_repr())
+    }
+
+    fn current_attribute_name(&self) -> Option<String> {
+        self.data.attr_name.as_ref().map(|name| name.borrow().to_repr())
+    }
+
     #[inline]
     fn next_pos(&mut self) {
         // unfortunately calls to next_pos will never be perfectly balanced with push_pos,
@@ -404,8 +1172,10 @@ impl PullParser {
         if !self.pos.is_empty() {
             if self.pos.len() > 1 {
                 self.pos.remove(0);
+                self.event_start_override = None;
             } else {
                 self.pos[0] = self.lexer.position();
+                self.event_start_override = self.unpositioned_event_start.take();
             }
         }
     }
@@ -413,12 +1183,12 @@ impl PullParser {
     #[inline]
     #[track_caller]
     fn push_pos(&mut self) {
-        debug_assert!(self.pos.len() != self.pos.capacity(), "You've found a bug in xml-rs, caused by calls to push_pos() in states that don't end up emitting events.
+        debug_assert!(self.pos.len() < MAX_PENDING_POSITIONS, "You've found a bug in xml-rs, caused by calls to push_pos() in states that don't end up emitting events.
             This case is ignored in release mode, and merely causes document positions to be out of sync.
             Please file a bug and include the XML document that triggers this assert.");
 
-        // it has capacity preallocated for more than it ever needs, so this reduces code size
-        if self.pos.len() != self.pos.capacity() {
+        // it has capacity preallocated for more than it ever needs
+        if self.pos.len() < MAX_PENDING_POSITIONS {
             self.pos.push(self.lexer.position());
         } else if self.pos.len() > 1 {
             self.pos.remove(0); // this mitigates the excessive push_pos() call
@@ -447,6 +1217,76 @@ impl PullParser {
     }
 
     #[inline]
+    fn trims_text(&self) -> bool {
+        self.config.c.trim_whitespace || self.config.trim_text
+    }
+
+    #[inline]
+    fn drops_whitespace_only_text(&self) -> bool {
+        self.config.c.trim_whitespace || self.config.drop_whitespace_only_text
+    }
+
+    /// Applies `trim_text` and `collapse_whitespace` to a part of text that isn't only whitespace
+    fn finish_text(&self, text: String, is_last_part: bool) -> String {
+        let trim_start = self.trims_text() && !self.text_continued;
+        let trim_end = self.trims_text() && is_last_part;
+        let escaped = &self.escaped_whitespace;
+        if !self.config.collapse_whitespace && escaped.is_empty() {
+            return match (trim_start, trim_end) {
+                (true, true) => text.trim_matches(is_whitespace_char).into(),
+                (true, false) => text.trim_start_matches(is_whitespace_char).into(),
+                (false, true) => text.trim_end_matches(is_whitespace_char).into(),
+                (false, false) => text,
+            };
+        }
+        let mut out = String::with_capacity(text.len());
+        // whitespace is added only once it's known not to be trimmed
+        let mut run = String::new();
+        let mut in_run = false;
+        for (i, c) in text.char_indices() {
+            if is_whitespace_char(c) && escaped.binary_search(&i).is_err() {
+                in_run = true;
+                run.push(c);
+                continue;
+            }
+            if in_run && (!out.is_empty() || !trim_start) {
+                out.push_str(if self.config.collapse_whitespace { " " } else { &run });
+            }
+            run.clear();
+            in_run = false;
+            out.push(c);
+        }
+        if in_run && !trim_end {
+            out.push_str(if self.config.collapse_whitespace { " " } else { &run });
+        }
+        out
+    }
+
+    #[inline]
+    /// Text is returned in parts, because of `lazy_text` or the `text_handling_hook`
+    fn streams_text(&self) -> bool {
+        self.config.lazy_text || self.text_handling == TextHandling::Stream
+    }
+
+    /// Drops the text read so far, except the position where it started, if the `text_handling_hook` skips it,
+    /// or if it's going to be a kind of event excluded by `events_only`
+    fn skips_text_part(&mut self) -> bool {
+        let mask = self.config.events_only;
+        let unwanted = !mask.contains(EventMask::CHARACTERS) &&
+            (!self.inside_whitespace || self.config.c.whitespace_to_characters || !mask.contains(EventMask::WHITESPACE));
+        if (self.text_handling != TextHandling::Skip && !unwanted) || self.buf.len() < LAZY_TEXT_CHUNK {
+            return false;
+        }
+        self.buf.clear();
+        true
+    }
+
+    /// Attribute values aren't kept when `events_only` excludes attributes, and no other option needs them
+    fn skips_attribute_values(&self) -> bool {
+        !self.config.events_only.contains(EventMask::ATTRIBUTES) &&
+            !self.config.track_xml_ids && !self.config.check_idrefs && !self.config.xop_includes
+    }
+
     fn buf_has_data(&self) -> bool {
         !self.buf.is_empty()
     }
@@ -487,10 +1327,22 @@ impl PullParser {
         }
 
         let invoke_callback = move |this: &mut PullParser, t| {
-            let name = this.take_buf();
-            match name.parse() {
-                Ok(name) => on_name(this, t, name),
-                Err(()) => Some(this.error(SyntaxError::InvalidQualifiedName(name.into()))),
+            let buf = this.take_buf();
+            #[cfg(feature = "unicode-normalization")]
+            let buf = if this.config.normalize_names { to_nfc(buf) } else { buf };
+            match this.names.name(&buf) {
+                Some(mut name) => {
+                    // the buffer's allocation is kept for the next name
+                    this.buf = buf;
+                    this.buf.clear();
+                    // namespace declarations keep their case, since prefixes are not changed
+                    if this.config.lowercase_names && name.prefix_ref() != Some(namespace::NS_XMLNS_PREFIX) &&
+                        !(name.prefix.is_none() && name.local_name.eq_ignore_ascii_case(namespace::NS_XMLNS_PREFIX)) {
+                        name.make_local_name_lowercase();
+                    }
+                    on_name(this, t, name)
+                },
+                None => Some(this.error(SyntaxError::InvalidQualifiedName(buf.into()))),
             }
         };
 
@@ -505,7 +1357,7 @@ impl PullParser {
             Token::Character(c) if c != ':' && (self.buf.is_empty() && is_name_start_char(c) ||
                                           self.buf_has_data() && is_name_char(c)) => {
                 if self.buf.len() > self.config.max_name_length {
-                    return Some(self.error(SyntaxError::ExceededConfiguredLimit));
+                    return Some(self.limit_error(Limit::NameLength, self.buf.len(), Some(self.buf.clone())));
                 }
                 self.buf.push(c);
                 None
@@ -524,6 +1376,14 @@ impl PullParser {
         }
     }
 
+    /// The value being read is of a regular attribute that is decoded on demand
+    fn is_lazy_attribute(&self) -> bool {
+        self.config.lazy_attributes && self.data.attr_name.as_ref().map_or(false, |name| {
+            name.prefix_ref() != Some(namespace::NS_XMLNS_PREFIX) &&
+                !(name.prefix.is_none() && name.local_name == namespace::NS_XMLNS_PREFIX)
+        })
+    }
+
     /// Dispatches tokens in order to process attribute value.
     ///
     /// # Parameters
@@ -537,10 +1397,13 @@ impl PullParser {
             Token::DoubleQuote | Token::SingleQuote => match self.data.quote {
                 None => {  // Entered attribute value
                     self.data.quote = QuoteToken::from_token(t);
+                    self.data.value_pos = self.lexer.position();
+                    self.data.raw_value.clear();
                     None
                 },
                 Some(q) if q.as_token() == t => {
                     self.data.quote = None;
+                    self.data.raw_quote = if q == QuoteToken::SingleQuoteToken { '\'' } else { '"' };
                     let value = self.take_buf();
                     on_value(self, value)
                 },
@@ -551,18 +1414,34 @@ impl PullParser {
                         }
                     }
                     if self.buf.len() > self.config.max_attribute_length {
-                        return Some(self.error(SyntaxError::ExceededConfiguredLimit));
+                        return Some(self.limit_error(Limit::AttributeLength, self.buf.len(), self.current_attribute_name()));
                     }
                     t.push_to_string(&mut self.buf);
+                    if self.config.preserve_source_details {
+                        t.push_to_string(&mut self.data.raw_value);
+                    }
                     None
                 },
             },
 
             Token::ReferenceStart if self.data.quote.is_some() => {
+                if self.config.preserve_source_details {
+                    self.data.raw_value.push('&');
+                }
+                if self.is_lazy_attribute() {
+                    // the rest of the reference is kept as-is, and decoded by `unescape_lazy_value`
+                    self.buf.push('&');
+                    return None;
+                }
                 self.state_after_reference = self.st;
                 self.into_state_continue(State::InsideReference)
             },
 
+            Token::OpeningTagStart if self.data.quote.is_some() && self.config.lenient_attribute_values => {
+                self.warn(Warning::UnescapedAttributeCharacter { character: '<', position: self.lexer.position() });
+                self.push_attribute_value_token(t)
+            },
+
             Token::OpeningTagStart => Some(self.error(SyntaxError::UnexpectedOpeningTag)),
 
             Token::Character(c) if !self.is_valid_xml_char_not_restricted(c) => {
@@ -570,48 +1449,139 @@ impl PullParser {
             },
 
             // Every character except " and ' and < is okay
-            _ if self.data.quote.is_some() => {
-                if self.buf.len() > self.config.max_attribute_length {
-                    return Some(self.error(SyntaxError::ExceededConfiguredLimit));
-                }
-                t.push_to_string(&mut self.buf);
-                None
-            },
+            _ if self.data.quote.is_some() => self.push_attribute_value_token(t),
 
             _ => Some(self.error(SyntaxError::UnexpectedToken(t))),
         }
     }
 
+    fn push_attribute_value_token(&mut self, t: Token) -> Option<Result> {
+        if self.buf.len() > self.config.max_attribute_length {
+            return Some(self.limit_error(Limit::AttributeLength, self.buf.len(), self.current_attribute_name()));
+        }
+        t.push_to_string(&mut self.buf);
+        if self.config.preserve_source_details {
+            t.push_to_string(&mut self.data.raw_value);
+        }
+        None
+    }
+
     fn emit_start_element(&mut self, emit_end_element: bool) -> Option<Result> {
         let mut name = self.data.take_element_name()?;
-        let mut attributes = self.data.take_attributes().into_vec();
+        let mut attributes = self.data.take_attributes().into_attributes();
+        self.data.attr_index = 0;
+        self.attribute_details = self.data.take_attr_details();
+        let attr_positions = self.data.take_attr_positions();
 
         // check whether the name prefix is bound and fix its namespace
         match self.nst.get(name.borrow().prefix_repr()) {
-            Some("") => name.namespace = None, // default namespace
-            Some(ns) => name.namespace = Some(ns.into()),
-            None => return Some(self.error(SyntaxError::UnboundElementPrefix(name.to_string().into()))),
+            Some("") if name.prefix.is_none() => name.namespace = None, // default namespace
+            Some("") | None => return Some(self.error(SyntaxError::UnboundElementPrefix(name.to_string().into()))),
+            Some(ns) => name.namespace = Some(self.names.get(ns)),
         }
 
         // check and fix accumulated attributes prefixes
         for attr in &mut attributes {
             if let Some(ref pfx) = attr.name.prefix {
                 let new_ns = match self.nst.get(pfx) {
-                    Some("") => None, // default namespace
-                    Some(ns) => Some(ns.into()),
-                    None => return Some(self.error(SyntaxError::UnboundAttribute(attr.name.to_string().into()))),
+                    // the prefix has been undeclared
+                    Some("") | None => return Some(self.error(SyntaxError::UnboundAttribute(attr.name.to_string().into()))),
+                    Some(ns) => Some(self.names.get(ns)),
                 };
                 attr.name.namespace = new_ns;
             }
         }
 
+        if let Some(e) = self.check_namespace_policy(&name, &attributes) {
+            // reported at the start of the tag
+            let pos = self.pos.last().copied().unwrap_or_else(|| self.lexer.position());
+            return Some(Err(Error { pos, kind: ErrorKind::Syntax(e.to_cow()), context: None }));
+        }
+
+        if self.config.track_xml_ids {
+            let xml_id = attributes.iter()
+                .find(|attr| attr.name.local_name == "id" && attr.name.namespace.as_deref() == Some(namespace::NS_XML_URI));
+            if let Some(attr) = xml_id {
+                let value = match self.attribute_value(&attr.value) {
+                    Ok(value) => value,
+                    Err(e) => return Some(self.error(e)),
+                };
+                // the value is normalized like an ID-typed attribute
+                let id = value.trim_matches(' ');
+                if !is_ncname(id) {
+                    return Some(self.error(SyntaxError::InvalidXmlId(id.into())));
+                }
+                let pos = self.pos.last().copied().unwrap_or_default();
+                match self.xml_ids.entry(id.to_owned()) {
+                    Entry::Occupied(_) => return Some(self.error(SyntaxError::DuplicateXmlId(id.into()))),
+                    Entry::Vacant(e) => { e.insert(pos); },
+                }
+            }
+        }
+
+        if self.config.check_idrefs {
+            let pos = self.pos.last().copied().unwrap_or_default();
+            let element_name = name.borrow().to_repr();
+            for attr in &attributes {
+                let value = match self.attribute_value(&attr.value) {
+                    Ok(value) => value,
+                    Err(e) => return Some(self.error(e)),
+                };
+                let is_xml_id = attr.name.local_name == "id" && attr.name.namespace.as_deref() == Some(namespace::NS_XML_URI);
+                let id_type = if is_xml_id { Some(IdType::Id) } else {
+                    self.attribute_types.get(&(element_name.clone(), attr.name.borrow().to_repr())).copied()
+                };
+                match id_type {
+                    Some(IdType::Id) => {
+                        let id = value.trim_matches(' ');
+                        if !self.ids.insert(id.to_owned()) && !is_xml_id {
+                            return Some(self.error(SyntaxError::DuplicateId(id.into())));
+                        }
+                    },
+                    Some(IdType::IdRef) => self.idrefs.push((value.trim_matches(' ').to_owned(), pos)),
+                    Some(IdType::IdRefs) => {
+                        self.idrefs.extend(value.split(' ').filter(|id| !id.is_empty()).map(|id| (id.to_owned(), pos)));
+                    },
+                    None => {},
+                }
+            }
+        }
+
+        if self.config.xop_includes && name.local_name == "Include" && name.namespace.as_deref() == Some(namespace::NS_XOP_URI) {
+            if self.xop_include.is_some() {
+                return Some(self.error(SyntaxError::UnexpectedXopIncludeContent));
+            }
+            match self.parse_xop_href(&attributes) {
+                Ok(content_id) => {
+                    self.xop_content_id = Some(content_id);
+                    self.xop_include = Some(0);
+                },
+                Err(e) => return Some(self.error(e)),
+            }
+        }
+
+        let depth = self.depth() + 1;
+        let stats = self.stats.get_mut();
+        stats.max_depth = stats.max_depth.max(depth);
         if emit_end_element {
             self.pop_namespace = true;
             self.next_event = Some(Ok(XmlEvent::EndElement {
                 name: name.clone()
             }));
         } else {
-            self.est.push(name.clone());
+            // the start tag has the latest of the pending positions
+            let opened_at = self.pos.last().copied().unwrap_or_else(|| self.lexer.position());
+            self.est.push((name.clone(), opened_at));
+        }
+        // attributes of the previous tag may be lazy, even if this one isn't
+        self.lazy_attributes.clear();
+        self.lazy_attribute_positions.clear();
+        if !self.config.events_only.contains(EventMask::ATTRIBUTES) {
+            attributes = Attributes::new();
+            self.attribute_details = Vec::new();
+        } else if self.config.lazy_attributes {
+            self.lazy_attributes = std::mem::take(&mut attributes);
+            self.lazy_attribute_positions = attr_positions;
         }
         let namespace = self.nst.squash();
         self.into_state_emit(State::OutsideTag, Ok(XmlEvent::StartElement {
@@ -621,23 +1591,83 @@ impl PullParser {
         }))
     }
 
+    /// Checks `required_root_element`, `required_namespace` and `forbidden_namespaces`
+    fn check_namespace_policy(&self, name: &OwnedName, attributes: &[OwnedAttribute]) -> Option<SyntaxError> {
+        let config = &self.config;
+        if self.depth() == 0 {
+            let wrong_name = config.required_root_element.as_ref().map_or(false, |n| *n != name.local_name);
+            let wrong_namespace = config.required_namespace.as_ref().map_or(false, |ns| Some(ns.as_str()) != name.namespace.as_deref());
+            if wrong_name || wrong_namespace {
+                return Some(SyntaxError::UnexpectedRootElement(name.to_string().into()));
+            }
+        }
+        if !config.forbidden_namespaces.is_empty() {
+            let names = std::iter::once(name).chain(attributes.iter().map(|a| &a.name));
+            for name in names {
+                if let Some(ns) = name.namespace.as_ref().filter(|ns| config.forbidden_namespaces.iter().any(|f| f == *ns)) {
+                    return Some(SyntaxError::ForbiddenNamespace(Box::new((name.borrow().to_repr().into(), ns.as_str().into()))));
+                }
+            }
+        }
+        None
+    }
+
+    /// Value of an attribute of the tag that has just been read, which is still undecoded if `lazy_attributes` is enabled
+    fn attribute_value<'a>(&self, value: &'a str) -> std::result::Result<Cow<'a, str>, SyntaxError> {
+        if self.config.lazy_attributes {
+            self.unescape_lazy_value(value)
+        } else {
+            Ok(Cow::Borrowed(value))
+        }
+    }
+
+    /// Percent-decoded content-id from `href="cid:…"` of `xop:Include`
+    fn parse_xop_href(&self, attributes: &[OwnedAttribute]) -> std::result::Result<String, SyntaxError> {
+        let href = attributes.iter()
+            .find(|attr| attr.name.local_name == "href" && attr.name.namespace.is_none())
+            .map(|attr| self.attribute_value(&attr.value))
+            .transpose()?
+            .unwrap_or_default();
+        let invalid = || SyntaxError::InvalidXopHref(href.as_ref().into());
+        let cid = match href.get(..4) {
+            Some(scheme) if scheme.eq_ignore_ascii_case("cid:") => &href[4..],
+            _ => return Err(invalid()),
+        };
+        let mut bytes = Vec::with_capacity(cid.len());
+        let mut rest = cid.bytes();
+        while let Some(b) = rest.next() {
+            if b == b'%' {
+                let hex = [rest.next().ok_or_else(invalid)?, rest.next().ok_or_else(invalid)?];
+                let hex = std::str::from_utf8(&hex).map_err(|_| invalid())?;
+                bytes.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+            } else {
+                bytes.push(b);
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| invalid())
+    }
+
     fn emit_end_element(&mut self) -> Option<Result> {
         let mut name = self.data.take_element_name()?;
 
         // check whether the name prefix is bound and fix its namespace
         match self.nst.get(name.borrow().prefix_repr()) {
-            Some("") => name.namespace = None, // default namespace
-            Some(ns) => name.namespace = Some(ns.into()),
-            None => return Some(self.error(SyntaxError::UnboundElementPrefix(name.to_string().into()))),
+            Some("") if name.prefix.is_none() => name.namespace = None, // default namespace
+            Some("") | None => return Some(self.error(SyntaxError::UnboundElementPrefix(name.to_string().into()))),
+            Some(ns) => name.namespace = Some(self.names.get(ns)),
         }
 
-        let op_name = self.est.pop()?;
+        let (op_name, opened_at) = self.est.pop()?;
 
         if name == op_name {
             self.pop_namespace = true;
             self.into_state_emit(State::OutsideTag, Ok(XmlEvent::EndElement { name }))
         } else {
-            Some(self.error(SyntaxError::UnexpectedClosingTag(format!("{name} != {op_name}").into())))
+            Some(Err(Error {
+                pos: self.lexer.position(),
+                kind: ErrorKind::UnexpectedClosingTag { expected: Box::new(op_name), found: Box::new(name), opened_at },
+                context: None,
+            }))
         }
     }
 
@@ -660,6 +1690,28 @@ impl PullParser {
     }
 }
 
+/// Converts to NFC, without allocating if the string is normalized already
+#[cfg(feature = "unicode-normalization")]
+fn to_nfc(s: String) -> String {
+    use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+    if is_nfc_quick(s.chars()) == IsNormalized::Yes { s } else { s.nfc().collect() }
+}
+
+#[cfg(feature = "unicode-normalization")]
+fn normalize_text(event: XmlEvent) -> XmlEvent {
+    match event {
+        XmlEvent::Characters(text) => XmlEvent::Characters(to_nfc(text)),
+        XmlEvent::CData(text) => XmlEvent::CData(to_nfc(text)),
+        XmlEvent::StartElement { name, mut attributes, namespace } => {
+            for attr in &mut attributes {
+                attr.value = to_nfc(std::mem::take(&mut attr.value));
+            }
+            XmlEvent::StartElement { name, attributes, namespace }
+        },
+        event => event,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::attribute::OwnedAttribute;
@@ -780,7 +1832,8 @@ mod tests {
         expect_event!(r, p, Err(ref e) =>
             *e == Error {
                 kind: ErrorKind::Syntax(SyntaxError::UnexpectedOpeningTag.to_cow()),
-                pos: TextPosition { row: 1, column: 24 }
+                pos: TextPosition { row: 1, column: 24, byte_offset: 25, char_offset: 25 },
+                context: None,
             }
         );
     }
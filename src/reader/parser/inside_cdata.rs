@@ -3,19 +3,22 @@ use crate::reader::error::SyntaxError;
 use crate::reader::events::XmlEvent;
 use crate::reader::lexer::Token;
 
-use super::{PullParser, Result, State};
+use super::{PullParser, Result, State, LAZY_TEXT_CHUNK};
 
 impl PullParser {
     pub fn inside_cdata(&mut self, t: Token) -> Option<Result> {
         match t {
             Token::CDataEnd => {
                 let event = if self.config.c.cdata_to_characters {
+                    self.cdata_in_text = true;
                     // start called push_pos, but there will be no event to pop it
                     if self.buf.is_empty() {
                         self.next_pos();
                     }
                     None
                 } else {
+                    self.whitespace_text = self.text_whitespace;
+                    self.text_whitespace = true;
                     let data = self.take_buf();
                     Some(Ok(XmlEvent::CData(data)))
                 };
@@ -26,8 +29,23 @@ impl PullParser {
                 Some(self.error(SyntaxError::InvalidCharacterEntity(c as u32)))
             },
             Token::Character(c) => {
+                let text_was_whitespace = self.text_whitespace;
                 if !is_whitespace_char(c) {
                     self.inside_whitespace = false;
+                    self.text_whitespace = false;
+                }
+                if self.skips_text_part() {
+                    self.buf.push(c);
+                    return None;
+                }
+                if self.streams_text() && !self.config.c.cdata_to_characters && self.buf.len() >= LAZY_TEXT_CHUNK {
+                    let part = self.take_buf();
+                    self.whitespace_text = text_was_whitespace;
+                    self.text_whitespace = is_whitespace_char(c);
+                    // the next part starts here
+                    self.push_pos();
+                    self.buf.push(c);
+                    return Some(Ok(XmlEvent::CData(part)));
                 }
                 self.buf.push(c);
                 None
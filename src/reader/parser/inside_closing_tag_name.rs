@@ -9,8 +9,8 @@ impl PullParser {
         match s {
             ClosingTagSubstate::CTInsideName => self.read_qualified_name(t, QualifiedNameTarget::ClosingTagNameTarget, |this, token, name| {
                 match name.prefix_ref() {
-                    Some(prefix) if prefix == namespace::NS_XML_PREFIX ||
-                                    prefix == namespace::NS_XMLNS_PREFIX =>
+                    // the xml prefix is always bound, but xmlns is only for declarations
+                    Some(prefix) if prefix == namespace::NS_XMLNS_PREFIX && !this.config.allow_reserved_namespace_misuse =>
                         Some(this.error(SyntaxError::InvalidNamePrefix(prefix.into()))),
                     _ => {
                         this.data.element_name = Some(name.clone());
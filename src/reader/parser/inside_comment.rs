@@ -1,4 +1,4 @@
-use crate::reader::error::SyntaxError;
+use crate::reader::error::{Limit, SyntaxError};
 use crate::reader::events::XmlEvent;
 use crate::reader::lexer::Token;
 
@@ -24,7 +24,7 @@ impl PullParser {
 
             _ => {
                 if self.buf.len() > self.config.max_data_length {
-                    return Some(self.error(SyntaxError::ExceededConfiguredLimit));
+                    return Some(self.limit_error(Limit::DataLength, self.buf.len(), None));
                 }
                 t.push_to_string(&mut self.buf);
                 None
@@ -23,6 +23,17 @@ impl PullParser {
             let new_encoding = match new_encoding.parse() {
                 Ok(e) => e,
                 Err(_) if self.config.ignore_invalid_encoding_declarations => Encoding::Latin1,
+                Err(_) if self.config.defer_unsupported_encoding => {
+                    // the rest of the source will be replaced with UTF-8 before the StartDocument is emitted
+                    let err = self.error(SyntaxError::UnsupportedEncoding(new_encoding.into()));
+                    self.deferred_encoding = encoding.clone();
+                    self.next_event = Some(Ok(XmlEvent::StartDocument {
+                        version: version.unwrap_or(DEFAULT_VERSION),
+                        encoding: encoding.unwrap_or_default(),
+                        standalone,
+                    }));
+                    return self.into_state(State::OutsideTag, Some(err));
+                },
                 Err(_) => return Some(self.error(SyntaxError::UnsupportedEncoding(new_encoding.into()))),
             };
             let current_encoding = self.lexer.encoding();
@@ -1,14 +1,38 @@
-use crate::common::{is_name_char, is_name_start_char, is_whitespace_char};
-use crate::reader::error::SyntaxError;
+use crate::common::{is_name_char, is_name_start_char, is_whitespace_char, Position};
+use crate::reader::entities::{Notation, UnparsedEntity};
+use crate::reader::error::{Limit, SyntaxError};
+use crate::reader::events::XmlEvent;
 use crate::reader::lexer::Token;
 
-use super::{DoctypeSubstate, PullParser, QuoteToken, Result, State};
+use super::{DeclarationKind, DoctypeSubstate, IdType, PullParser, QuoteToken, Result, State};
+
+use std::sync::Arc;
 
 impl PullParser {
     pub fn inside_doctype(&mut self, t: Token, substate: DoctypeSubstate) -> Option<Result> {
         match substate {
             DoctypeSubstate::Outside => match t {
-                Token::TagEnd => self.into_state_continue(State::OutsideTag),
+                Token::TagEnd if self.data.include_depth > 0 => Some(self.error(SyntaxError::UnexpectedToken(t))),
+                Token::TagEnd => {
+                    // the internal subset has been read already, and it takes precedence
+                    if let Some(id) = self.external_dtd.take() {
+                        if let Some(entities) = self.config.entity_table.external_dtd(&id) {
+                            let declared = Arc::make_mut(&mut self.entities);
+                            for (name, value) in entities {
+                                declared.entry(name.clone()).or_insert_with(|| value.clone());
+                            }
+                        }
+                    }
+                    let internal_subset_byte_range = self.data.take_internal_subset();
+                    if let Some(syntax) = self.lexer.take_doctype_text() {
+                        return self.into_state_emit(State::OutsideTag, Ok(XmlEvent::Doctype {
+                            syntax,
+                            has_internal_subset: internal_subset_byte_range.is_some(),
+                            internal_subset_byte_range,
+                        }));
+                    }
+                    self.into_state_continue(State::OutsideTag)
+                },
                 Token::MarkupDeclarationStart => {
                     self.buf.clear();
                     self.into_state_continue(State::InsideDoctype(DoctypeSubstate::InsideName))
@@ -22,12 +46,32 @@ impl PullParser {
                     self.into_state_continue(State::InsideDoctype(DoctypeSubstate::Comment))
                 },
                 Token::SingleQuote | Token::DoubleQuote => {
-                    // just discard string literals
+                    // string literals are only checked for external DTDs in the entity table
                     self.data.quote = super::QuoteToken::from_token(t);
+                    self.buf.clear();
                     self.into_state_continue(State::InsideDoctype(DoctypeSubstate::String))
                 },
+                Token::ConditionalSectionStart => {
+                    self.buf.clear();
+                    self.into_state_continue(State::InsideDoctype(DoctypeSubstate::ConditionalSectionKeyword))
+                },
+                Token::CDataEnd if self.data.include_depth > 0 => {
+                    self.data.include_depth -= 1;
+                    None
+                },
                 Token::CDataEnd | Token::CDataStart => Some(self.error(SyntaxError::UnexpectedToken(t))),
-                // TODO: parse SYSTEM, and [
+                Token::Character('[') if self.data.internal_subset.is_none() => {
+                    let start = self.lexer.position().byte_offset;
+                    self.data.internal_subset = Some(start..start);
+                    None
+                },
+                Token::Character(']') if self.data.include_depth == 0 => {
+                    if let Some(subset) = &mut self.data.internal_subset {
+                        subset.end = self.lexer.head_position().byte_offset;
+                    }
+                    None
+                },
+                // TODO: parse SYSTEM
                 _ => None,
             },
             DoctypeSubstate::String => match t {
@@ -35,9 +79,19 @@ impl PullParser {
                 Token::DoubleQuote if self.data.quote != Some(QuoteToken::DoubleQuoteToken) => None,
                 Token::SingleQuote | Token::DoubleQuote => {
                     self.data.quote = None;
+                    let id = self.take_buf();
+                    if self.external_dtd.is_none() && self.config.entity_table.external_dtd(&id).is_some() {
+                        self.external_dtd = Some(id);
+                    }
                     self.into_state_continue(State::InsideDoctype(DoctypeSubstate::Outside))
                 },
-                _ => None,
+                _ => {
+                    if self.buf.len() > self.config.max_attribute_length {
+                        return Some(self.limit_error(Limit::AttributeLength, self.buf.len(), None));
+                    }
+                    t.push_to_string(&mut self.buf);
+                    None
+                },
             },
             DoctypeSubstate::Comment => match t {
                 Token::CommentEnd => {
@@ -54,7 +108,10 @@ impl PullParser {
                     let buf = self.take_buf();
                     match buf.as_str() {
                         "ENTITY" => self.into_state_continue(State::InsideDoctype(DoctypeSubstate::BeforeEntityName)),
-                        "NOTATION" | "ELEMENT" | "ATTLIST" => self.into_state_continue(State::InsideDoctype(DoctypeSubstate::SkipDeclaration)),
+                        "ATTLIST" if self.config.check_idrefs => self.into_state_continue(State::InsideDoctype(DoctypeSubstate::AttlistDeclaration)),
+                        "NOTATION" => self.into_state_continue(State::InsideDoctype(DoctypeSubstate::Declaration(DeclarationKind::Notation))),
+                        "ELEMENT" => self.into_state_continue(State::InsideDoctype(DoctypeSubstate::Declaration(DeclarationKind::Element))),
+                        "ATTLIST" => self.into_state_continue(State::InsideDoctype(DoctypeSubstate::SkipDeclaration)),
                         _ => Some(self.error(SyntaxError::UnknownMarkupDeclaration(buf.into()))),
                     }
                 },
@@ -70,7 +127,7 @@ impl PullParser {
                     },
                     Token::Character(c) if is_name_start_char(c) => {
                         if self.data.name.len() > self.config.max_name_length {
-                            return Some(self.error(SyntaxError::ExceededConfiguredLimit));
+                            return Some(self.limit_error(Limit::NameLength, self.data.name.len(), Some(self.data.name.clone())));
                         }
                         self.data.name.push(c);
                         self.into_state_continue(State::InsideDoctype(DoctypeSubstate::EntityName))
@@ -84,7 +141,7 @@ impl PullParser {
                 },
                 Token::Character(c) if is_name_char(c) => {
                     if self.data.name.len() > self.config.max_name_length {
-                        return Some(self.error(SyntaxError::ExceededConfiguredLimit));
+                        return Some(self.limit_error(Limit::NameLength, self.data.name.len(), Some(self.data.name.clone())));
                     }
                     self.data.name.push(c);
                     None
@@ -96,11 +153,17 @@ impl PullParser {
                 match t {
                     Token::Character(c) if is_whitespace_char(c) => None,
                     // SYSTEM/PUBLIC not supported
-                    Token::Character('S' | 'P') => {
+                    Token::Character(c @ ('S' | 'P')) => {
                         let name = self.data.take_name();
-                        self.entities.entry(name).or_default(); // Dummy value, but at least the name is recognized
+                        if !self.config.entity_table.is_frozen() || name.starts_with('%') {
+                            Arc::make_mut(&mut self.entities).entry(name.clone()).or_default(); // Dummy value, but at least the name is recognized
+                        }
 
-                        self.into_state_continue(State::InsideDoctype(DoctypeSubstate::SkipDeclaration))
+                        // the name is kept for NDATA
+                        self.buf.push_str(&name);
+                        self.buf.push(' ');
+                        self.buf.push(c);
+                        self.into_state_continue(State::InsideDoctype(DoctypeSubstate::Declaration(DeclarationKind::ExternalEntity)))
                     },
                     Token::SingleQuote | Token::DoubleQuote => {
                         self.data.quote = super::QuoteToken::from_token(t);
@@ -116,7 +179,9 @@ impl PullParser {
                     self.data.quote = None;
                     let name = self.data.take_name();
                     let val = self.take_buf();
-                    self.entities.entry(name).or_insert(val); // First wins
+                    if !self.config.entity_table.is_frozen() || name.starts_with('%') {
+                        Arc::make_mut(&mut self.entities).entry(name).or_insert(val); // First wins
+                    }
                     self.into_state_continue(State::InsideDoctype(DoctypeSubstate::SkipDeclaration)) // FIXME
                 },
                 Token::ReferenceStart | Token::Character('&') => {
@@ -149,7 +214,7 @@ impl PullParser {
             DoctypeSubstate::PEReferenceDefinition => match t {
                 Token::Character(c) if is_name_char(c) => {
                     if self.data.name.len() > self.config.max_name_length {
-                        return Some(self.error(SyntaxError::ExceededConfiguredLimit));
+                        return Some(self.limit_error(Limit::NameLength, self.data.name.len(), Some(self.data.name.clone())));
                     }
                     self.data.name.push(c);
                     None
@@ -231,6 +296,100 @@ impl PullParser {
                 },
                 _ => Some(self.error(SyntaxError::UnexpectedTokenInEntity(t))),
             },
+            DoctypeSubstate::ConditionalSectionKeyword => match t {
+                Token::Character(c) if is_whitespace_char(c) => None,
+                Token::Character('%') => {
+                    self.data.ref_data.clear();
+                    self.data.ref_data.push('%');
+                    self.into_state_continue(State::InsideDoctype(DoctypeSubstate::ConditionalSectionPEReference))
+                },
+                Token::Character('[') => {
+                    let keyword = self.take_buf();
+                    match keyword.trim() {
+                        "INCLUDE" => {
+                            self.data.include_depth += 1;
+                            self.into_state_continue(State::InsideDoctype(DoctypeSubstate::Outside))
+                        },
+                        "IGNORE" => {
+                            self.data.ignore_depth = 0;
+                            self.into_state_continue(State::InsideDoctype(DoctypeSubstate::IgnoredSection))
+                        },
+                        _ => Some(self.error(SyntaxError::InvalidConditionalSection(keyword.into()))),
+                    }
+                },
+                Token::Character(c @ 'A'..='Z') => {
+                    if self.buf.len() > self.config.max_name_length {
+                        return Some(self.limit_error(Limit::NameLength, self.buf.len(), Some(self.buf.clone())));
+                    }
+                    self.buf.push(c);
+                    None
+                },
+                _ => Some(self.error(SyntaxError::UnexpectedToken(t))),
+            },
+            DoctypeSubstate::ConditionalSectionPEReference => match t {
+                Token::Character(c) if is_name_char(c) => {
+                    self.data.ref_data.push(c);
+                    None
+                },
+                Token::ReferenceEnd | Token::Character(';') => {
+                    let name = self.data.take_ref_data();
+                    match self.entities.get(&name) {
+                        Some(ent) => {
+                            self.buf.push_str(ent.trim());
+                            self.into_state_continue(State::InsideDoctype(DoctypeSubstate::ConditionalSectionKeyword))
+                        },
+                        None => Some(self.error(SyntaxError::UndefinedEntity(name.into()))),
+                    }
+                },
+                _ => Some(self.error(SyntaxError::UnexpectedTokenInEntity(t))),
+            },
+            DoctypeSubstate::IgnoredSection => match t {
+                Token::ConditionalSectionStart => {
+                    self.data.ignore_depth += 1;
+                    None
+                },
+                Token::CDataEnd if self.data.ignore_depth > 0 => {
+                    self.data.ignore_depth -= 1;
+                    None
+                },
+                Token::CDataEnd => self.into_state_continue(State::InsideDoctype(DoctypeSubstate::Outside)),
+                _ => None,
+            },
+            DoctypeSubstate::AttlistDeclaration => match t {
+                Token::TagEnd => {
+                    let decl = self.take_buf();
+                    self.read_attlist_id_types(&decl);
+                    self.into_state_continue(State::InsideDoctype(DoctypeSubstate::Outside))
+                },
+                _ => {
+                    if self.buf.len() > self.config.max_data_length {
+                        return Some(self.limit_error(Limit::DataLength, self.buf.len(), None));
+                    }
+                    t.push_to_string(&mut self.buf);
+                    None
+                },
+            },
+            DoctypeSubstate::Declaration(kind) => match t {
+                Token::TagEnd => {
+                    let decl = self.take_buf();
+                    match kind {
+                        DeclarationKind::Notation => self.read_notation(&decl),
+                        DeclarationKind::Element => self.read_element_declaration(&decl),
+                        DeclarationKind::ExternalEntity => self.read_external_entity(&decl),
+                    }
+                    self.into_state_continue(State::InsideDoctype(DoctypeSubstate::Outside))
+                },
+                Token::Character(c) if !self.is_valid_xml_char(c) => {
+                    Some(self.error(SyntaxError::InvalidCharacterEntity(c as u32)))
+                },
+                _ => {
+                    if self.buf.len() > self.config.max_data_length {
+                        return Some(self.limit_error(Limit::DataLength, self.buf.len(), None));
+                    }
+                    t.push_to_string(&mut self.buf);
+                    None
+                },
+            },
             DoctypeSubstate::SkipDeclaration => match t {
                 Token::TagEnd => {
                     self.into_state_continue(State::InsideDoctype(DoctypeSubstate::Outside))
@@ -239,4 +398,109 @@ impl PullParser {
             },
         }
     }
+
+    /// Remembers ID, IDREF and IDREFS attributes from the body of `<!ATTLIST …>`
+    fn read_attlist_id_types(&mut self, decl: &str) {
+        let mut tokens = AttlistTokens(decl);
+        let element = match tokens.next() {
+            Some(element) => element,
+            None => return,
+        };
+        while let (Some(attribute), Some(attr_type)) = (tokens.next(), tokens.next()) {
+            if attr_type == "NOTATION" {
+                tokens.next(); // (enumeration)
+            }
+            if tokens.next() == Some("#FIXED") {
+                tokens.next(); // default value
+            }
+            let id_type = match attr_type {
+                "ID" => IdType::Id,
+                "IDREF" => IdType::IdRef,
+                "IDREFS" => IdType::IdRefs,
+                _ => continue,
+            };
+            // the first declaration of an attribute is binding
+            self.attribute_types.entry((element.to_owned(), attribute.to_owned())).or_insert(id_type);
+        }
+    }
+}
+
+impl PullParser {
+    /// Remembers `<!NOTATION name SYSTEM "…">` or `<!NOTATION name PUBLIC "…" "…">`
+    fn read_notation(&mut self, decl: &str) {
+        let mut tokens = AttlistTokens(decl);
+        if let Some(name) = tokens.next() {
+            let (public_id, system_id) = external_id(&mut tokens);
+            if public_id.is_some() || system_id.is_some() {
+                self.notations.push(Notation { name: name.into(), public_id, system_id });
+            }
+        }
+    }
+
+    /// Remembers elements that can't have text, from `<!ELEMENT name (children)>`
+    fn read_element_declaration(&mut self, decl: &str) {
+        let mut tokens = AttlistTokens(decl);
+        if let (Some(name), Some(content)) = (tokens.next(), tokens.next()) {
+            if content.starts_with('(') && !decl.contains("#PCDATA") {
+                self.element_content.insert(name.into());
+            }
+        }
+    }
+
+    /// Remembers unparsed entities, from the rest of `<!ENTITY name SYSTEM "…" NDATA notation>`
+    fn read_external_entity(&mut self, decl: &str) {
+        let mut tokens = AttlistTokens(decl);
+        let name = match tokens.next() {
+            Some(name) if !name.starts_with('%') => name,
+            _ => return,
+        };
+        let (public_id, system_id) = external_id(&mut tokens);
+        if let (Some(system_id), Some("NDATA"), Some(notation)) = (system_id, tokens.next(), tokens.next()) {
+            if !self.unparsed_entities.iter().any(|e| e.name == name) {
+                self.unparsed_entities.push(UnparsedEntity { name: name.into(), public_id, system_id, notation: notation.into() });
+            }
+        }
+    }
+}
+
+/// Reads `SYSTEM "…"` or `PUBLIC "…" "…"`, where the system identifier is optional for notations
+fn external_id(tokens: &mut AttlistTokens<'_>) -> (Option<String>, Option<String>) {
+    let unquote = |s: &str| s.get(1..s.len().saturating_sub(1)).unwrap_or_default().to_owned();
+    let is_quoted = |s: &&str| s.starts_with(['"', '\'']);
+    match tokens.next() {
+        Some("SYSTEM") => (None, tokens.next().filter(is_quoted).map(unquote)),
+        Some("PUBLIC") => {
+            let public_id = tokens.next().filter(is_quoted).map(unquote);
+            let mut rest = tokens.clone();
+            let system_id = match rest.next() {
+                Some(s) if is_quoted(&s) => {
+                    *tokens = rest;
+                    Some(unquote(s))
+                },
+                _ => None,
+            };
+            (public_id, system_id)
+        },
+        _ => (None, None),
+    }
+}
+
+/// Splits `<!ATTLIST>` into names, `(enumerations)` and quoted values
+#[derive(Clone)]
+struct AttlistTokens<'a>(&'a str);
+
+impl<'a> Iterator for AttlistTokens<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let s = self.0.trim_start_matches(is_whitespace_char);
+        let end = match s.chars().next()? {
+            '(' => s.find(')').map_or(s.len(), |i| i + 1),
+            q @ ('"' | '\'') => s[1..].find(q).map_or(s.len(), |i| i + 2),
+            _ => s.find(|c| is_whitespace_char(c) || c == '(').unwrap_or(s.len()),
+        };
+        let (token, rest) = s.split_at(end);
+        self.0 = rest;
+        Some(token)
+    }
 }
@@ -1,7 +1,9 @@
-use crate::attribute::OwnedAttribute;
-use crate::common::{is_name_start_char, is_whitespace_char};
+use crate::attribute::{AttributeDetails, OwnedAttribute};
+use crate::common::{is_name_start_char, is_whitespace_char, Position, XmlVersion};
 use crate::namespace;
-use crate::reader::error::SyntaxError;
+use crate::name::OwnedName;
+use crate::reader::error::{Limit, SyntaxError, Warning};
+use crate::reader::events::EventMask;
 
 use crate::reader::lexer::Token;
 
@@ -13,11 +15,11 @@ impl PullParser {
         match s {
             OpeningTagSubstate::InsideName => self.read_qualified_name(t, QualifiedNameTarget::OpeningTagNameTarget, |this, token, name| {
                 match name.prefix_ref() {
-                    Some(prefix) if prefix == namespace::NS_XML_PREFIX ||
-                                    prefix == namespace::NS_XMLNS_PREFIX =>
+                    // the xml prefix is always bound, but xmlns is only for declarations
+                    Some(prefix) if prefix == namespace::NS_XMLNS_PREFIX && !this.accept_reserved_namespace_misuse(&name) =>
                         Some(this.error(SyntaxError::InvalidNamePrefix(prefix.into()))),
                     _ => {
-                        this.data.element_name = Some(name.clone());
+                        this.data.element_name = Some(name);
                         match token {
                             Token::TagEnd => this.emit_start_element(false),
                             Token::EmptyTagEnd => this.emit_start_element(true),
@@ -34,7 +36,7 @@ impl PullParser {
                 Token::Character(c) if is_whitespace_char(c) => None, // skip whitespace
                 Token::Character(c) if is_name_start_char(c) => {
                     if self.buf.len() > self.config.max_name_length {
-                        return Some(self.error(SyntaxError::ExceededConfiguredLimit));
+                        return Some(self.limit_error(Limit::NameLength, self.buf.len(), Some(self.buf.clone())));
                     }
                     self.buf.push(c);
                     self.into_state_continue(State::InsideOpeningTag(OpeningTagSubstate::InsideAttributeName))
@@ -65,43 +67,72 @@ impl PullParser {
 
             OpeningTagSubstate::InsideAttributeValue => self.read_attribute_value(t, |this, value| {
                 let name = this.data.take_attr_name()?;  // will always succeed here
+                let index = this.data.attr_index;
+                this.data.attr_index += 1;
                 match name.prefix_ref() {
                     // declaring a new prefix; it is sufficient to check prefix only
                     // because "xmlns" prefix is reserved
                     Some(namespace::NS_XMLNS_PREFIX) => {
                         let ln = &*name.local_name;
-                        if ln == namespace::NS_XMLNS_PREFIX {
-                            Some(this.error(SyntaxError::CannotRedefineXmlnsPrefix))
+                        let reserved_misuse = if ln == namespace::NS_XMLNS_PREFIX {
+                            Some(SyntaxError::CannotRedefineXmlnsPrefix)
                         } else if ln == namespace::NS_XML_PREFIX && &*value != namespace::NS_XML_URI {
-                            Some(this.error(SyntaxError::CannotRedefineXmlPrefix))
-                        } else if value.is_empty() {
+                            Some(SyntaxError::CannotRedefineXmlPrefix)
+                        } else if ln != namespace::NS_XML_PREFIX && &*value == namespace::NS_XML_URI {
+                            Some(SyntaxError::CannotBindToXmlNamespace(ln.into()))
+                        } else if &*value == namespace::NS_XMLNS_URI {
+                            Some(SyntaxError::CannotBindToXmlnsNamespace(ln.into()))
+                        } else {
+                            None
+                        };
+                        if let Some(e) = reserved_misuse {
+                            if !this.accept_reserved_namespace_misuse(&name) {
+                                return Some(this.error(e));
+                            }
+                        }
+                        if value.is_empty() && this.data.version != Some(XmlVersion::Version11) {
+                            // undeclaring prefixes is allowed only by Namespaces in XML 1.1
                             Some(this.error(SyntaxError::CannotUndefinePrefix(ln.into())))
                         } else {
-                            this.nst.put(name.local_name.clone(), value);
-                            this.into_state_continue(State::InsideOpeningTag(OpeningTagSubstate::AfterAttributeValue))
+                            let prefix = ln.to_owned();
+                            this.declare_namespace(prefix, value, &name)
                         }
                     }
 
                     // declaring default namespace
                     None if &*name.local_name == namespace::NS_XMLNS_PREFIX =>
                         match &*value {
-                            namespace::NS_XMLNS_PREFIX | namespace::NS_XML_PREFIX | namespace::NS_XML_URI | namespace::NS_XMLNS_URI =>
+                            namespace::NS_XMLNS_PREFIX | namespace::NS_XML_PREFIX | namespace::NS_XML_URI | namespace::NS_XMLNS_URI
+                                if !this.accept_reserved_namespace_misuse(&name) =>
                                 Some(this.error(SyntaxError::InvalidDefaultNamespace(value.into()))),
-                            _ => {
-                                this.nst.put(namespace::NS_NO_PREFIX, value.clone());
-                                this.into_state_continue(State::InsideOpeningTag(OpeningTagSubstate::AfterAttributeValue))
-                            }
+                            _ => this.declare_namespace(namespace::NS_NO_PREFIX.into(), value, &name),
                         },
 
                     // regular attribute
                     _ => {
                         if this.data.attributes.len() >= max_attrs {
-                            return Some(this.error(SyntaxError::ExceededConfiguredLimit));
+                            let element = this.data.element_name.as_ref().map(|n| n.borrow().to_repr());
+                            return Some(this.limit_error(Limit::Attributes, this.data.attributes.len() + 1, element));
                         }
+                        let value = if this.skips_attribute_values() {
+                            // keep the buffer for the next value instead of allocating a new one
+                            this.buf = value;
+                            this.buf.clear();
+                            String::new()
+                        } else {
+                            value
+                        };
                         this.data.attributes.push(OwnedAttribute {
                             name,
                             value
                         });
+                        if this.config.lazy_attributes {
+                            this.data.attr_positions.push(this.data.value_pos);
+                        }
+                        if this.config.preserve_source_details && this.config.events_only.contains(EventMask::ATTRIBUTES) {
+                            let raw_value = this.data.take_raw_value();
+                            this.data.attr_details.push(AttributeDetails { index, quote: this.data.raw_quote, raw_value, max_length: this.config.max_attribute_length });
+                        }
                         this.into_state_continue(State::InsideOpeningTag(OpeningTagSubstate::AfterAttributeValue))
                     }
                 }
@@ -117,4 +148,23 @@ impl PullParser {
             },
         }
     }
+    /// Whether misuse of the reserved `xml` and `xmlns` namespaces by the element or attribute is allowed,
+    /// in which case it's reported as a warning
+    fn accept_reserved_namespace_misuse(&mut self, name: &OwnedName) -> bool {
+        if self.config.allow_reserved_namespace_misuse {
+            self.warn(Warning::ReservedNamespaceMisuse { name: name.to_string(), position: self.lexer.position() });
+        }
+        self.config.allow_reserved_namespace_misuse
+    }
+
+    /// Binds the prefix in the current tag, and handles a repeated declaration of the same prefix
+    fn declare_namespace(&mut self, prefix: String, uri: String, name: &OwnedName) -> Option<Result> {
+        if self.nst.peek_mut().force_put(prefix, uri).is_some() {
+            if !self.config.allow_duplicate_namespace_declarations {
+                return Some(self.error(SyntaxError::RedefinedNamespaceDeclaration(name.to_string().into())));
+            }
+            self.warn(Warning::DuplicateNamespaceDeclaration { name: name.to_string(), position: self.lexer.position() });
+        }
+        self.into_state_continue(State::InsideOpeningTag(OpeningTagSubstate::AfterAttributeValue))
+    }
 }
@@ -1,10 +1,10 @@
 use crate::common::{is_name_char, is_name_start_char, is_whitespace_char};
-use crate::reader::error::SyntaxError;
+use crate::reader::error::{Limit, SyntaxError};
 
-use crate::reader::events::XmlEvent;
+use crate::reader::events::{EventMask, XmlEvent};
 use crate::reader::lexer::Token;
 
-use super::{DeclarationSubstate, Encountered, ProcessingInstructionSubstate, PullParser, Result, State};
+use super::{DeclarationSubstate, DocumentStart, Encountered, ProcessingInstructionSubstate, PullParser, Result, State};
 
 impl PullParser {
     pub fn inside_processing_instruction(&mut self, t: Token, s: ProcessingInstructionSubstate) -> Option<Result> {
@@ -13,7 +13,7 @@ impl PullParser {
                 Token::Character(c) if self.buf.is_empty() && is_name_start_char(c) ||
                                  self.buf_has_data() && is_name_char(c) => {
                     if self.buf.len() > self.config.max_name_length {
-                        return Some(self.error(SyntaxError::ExceededConfiguredLimit));
+                        return Some(self.limit_error(Limit::NameLength, self.buf.len(), Some(self.buf.clone())));
                     }
                     self.buf.push(c);
                     None
@@ -64,6 +64,11 @@ impl PullParser {
                         "xml" if self.encountered == Encountered::None =>
                             self.into_state_continue(State::InsideDeclaration(DeclarationSubstate::BeforeVersion)),
 
+                        "xml" if self.is_document_boundary(false) => {
+                            self.next_document = Some(DocumentStart::Declaration(c));
+                            self.into_state_continue(State::OutsideTag)
+                        },
+
                         // Found <?xml-like PI after the beginning of a document,
                         // it is an error - see section 2.6 of XML 1.1 spec
                         n if "xml".eq_ignore_ascii_case(n) =>
@@ -99,15 +104,66 @@ impl PullParser {
                     Some(self.error(SyntaxError::InvalidCharacterEntity(c as u32)))
                 },
 
+                // the data of unwanted instructions isn't kept
+                _ if !self.config.events_only.contains(EventMask::PROCESSING_INSTRUCTION) => None,
+
                 // Any other token should be treated as plain characters
                 _ => {
                     if self.buf.len() > self.config.max_data_length {
-                        return Some(self.error(SyntaxError::ExceededConfiguredLimit));
+                        return Some(self.limit_error(Limit::DataLength, self.buf.len(), Some(self.data.name.clone())));
                     }
                     t.push_to_string(&mut self.buf);
                     None
                 },
             },
+
+            // the buffer may have text that continues after the instruction,
+            // and the target is checked like the target of any other instruction
+            ProcessingInstructionSubstate::IgnoredName => match t {
+                Token::Character(c) if self.data.name.is_empty() && is_name_start_char(c) ||
+                                 !self.data.name.is_empty() && is_name_char(c) => {
+                    if self.data.name.len() > self.config.max_name_length {
+                        return Some(self.limit_error(Limit::NameLength, self.data.name.len(), Some(self.data.name.clone())));
+                    }
+                    self.data.name.push(c);
+                    None
+                },
+
+                Token::ProcessingInstructionEnd => match self.take_ignored_target() {
+                    Err(e) => Some(self.error(e)),
+                    Ok(()) => self.into_state_continue(State::OutsideTag),
+                },
+
+                Token::Character(c) if is_whitespace_char(c) => match self.take_ignored_target() {
+                    Err(e) => Some(self.error(e)),
+                    Ok(()) => self.into_state_continue(State::InsideProcessingInstruction(ProcessingInstructionSubstate::IgnoredData)),
+                },
+
+                _ => {
+                    let name = self.data.take_name();
+                    Some(self.error(SyntaxError::UnexpectedProcessingInstruction(name.into(), t)))
+                },
+            },
+
+            ProcessingInstructionSubstate::IgnoredData => match t {
+                Token::ProcessingInstructionEnd => self.into_state_continue(State::OutsideTag),
+                Token::Character(c) if !self.is_valid_xml_char(c) => {
+                    Some(self.error(SyntaxError::InvalidCharacterEntity(c as u32)))
+                },
+                _ => None,
+            },
+        }
+    }
+
+    /// The target of an ignored instruction can't be empty nor `xml`, like in `PIInsideName`
+    fn take_ignored_target(&mut self) -> std::result::Result<(), SyntaxError> {
+        let name = self.data.take_name();
+        if name.is_empty() {
+            Err(SyntaxError::ProcessingInstructionWithoutName)
+        } else if "xml".eq_ignore_ascii_case(&name) {
+            Err(SyntaxError::InvalidXmlProcessingInstruction(name.into()))
+        } else {
+            Ok(())
         }
     }
 }
@@ -1,7 +1,10 @@
 use super::{PullParser, Result, State};
-use crate::common::{is_name_char, is_name_start_char, is_whitespace_char};
-use crate::reader::error::SyntaxError;
+use crate::common::{is_name_char, is_name_start_char, is_noncharacter, is_whitespace_char, Position};
+use crate::reader::config::{EntityExpansionCheck, InvalidCharReferences};
+use crate::reader::error::{CharReferenceKind, SyntaxError, Warning};
 use crate::reader::lexer::Token;
+use crate::reader::ReaderStats;
+use std::borrow::Cow;
 use std::char;
 
 impl PullParser {
@@ -18,6 +21,10 @@ impl PullParser {
                 if name.is_empty() {
                     return Some(self.error(SyntaxError::EmptyEntity));
                 }
+                if self.config.preserve_source_details && self.state_after_reference != State::OutsideTag {
+                    self.data.raw_value.push_str(&name);
+                    self.data.raw_value.push(';');
+                }
 
                 let c = match &*name {
                     "lt"   => Some('<'),
@@ -26,7 +33,17 @@ impl PullParser {
                     "apos" => Some('\''),
                     "quot" => Some('"'),
                     _ if name.starts_with('#') => match self.numeric_reference_from_str(&name[1..]) {
-                        Ok(c) => Some(c),
+                        Ok(c) => {
+                            if self.warns_about_reference(c, &name[1..]) {
+                                let code = reference_code(&name[1..]).unwrap_or(c as u32);
+                                self.warn(Warning::InvalidCharacterReference { code, position: self.lexer.position() });
+                            } else if is_whitespace_char(c) && self.config.preserve_escaped_whitespace && self.state_after_reference == State::OutsideTag {
+                                // it's not trimmed, so the text isn't whitespace-only
+                                self.escaped_whitespace.push(self.buf.len());
+                                self.inside_whitespace = false;
+                            }
+                            Some(c)
+                        },
                         Err(e) => return Some(self.error(e)),
                     },
                     _ => None,
@@ -34,8 +51,14 @@ impl PullParser {
                 if let Some(c) = c {
                     self.buf.push(c);
                 } else if let Some(v) = self.config.c.extra_entities.get(&name) {
+                    if !count_expansion(self.stats.get_mut(), self.config.entity_expansion_check.as_ref(), &name, v.len()) {
+                        return Some(self.error(SyntaxError::EntityExpansionRejected(name.into())));
+                    }
                     self.buf.push_str(v);
                 } else if let Some(v) = self.entities.get(&name) {
+                    if !count_expansion(self.stats.get_mut(), self.config.entity_expansion_check.as_ref(), &name, v.len()) {
+                        return Some(self.error(SyntaxError::EntityExpansionRejected(name.into())));
+                    }
                     if self.state_after_reference == State::OutsideTag {
                         // an entity can expand to *elements*, so outside of a tag it needs a full reparse
                         if let Err(e) = self.lexer.reparse(v) {
@@ -46,33 +69,162 @@ impl PullParser {
                         // so it can't be fed to the lexer
                         self.buf.push_str(v);
                     }
+                } else if let Some(v) = self.html_entity(&name) {
+                    if !count_expansion(self.stats.get_mut(), self.config.entity_expansion_check.as_ref(), &name, v.len()) {
+                        return Some(self.error(SyntaxError::EntityExpansionRejected(name.into())));
+                    }
+                    self.buf.push_str(v);
                 } else {
                     return Some(self.error(SyntaxError::UnexpectedEntity(name.into())));
                 }
                 let prev_st = self.state_after_reference;
                 if prev_st == State::OutsideTag && !is_whitespace_char(self.buf.chars().last().unwrap_or('\0')) {
                     self.inside_whitespace = false;
+                    self.text_whitespace = false;
                 }
                 self.into_state_continue(prev_st)
             },
 
+            _ if self.config.lenient_attribute_values && self.state_after_reference != State::OutsideTag => {
+                // it wasn't a reference, so the `&` and the name-like characters after it are text
+                let text = self.data.take_ref_data();
+                self.warn(Warning::UnescapedAttributeCharacter { character: '&', position: self.lexer.position() });
+                self.buf.push('&');
+                self.buf.push_str(&text);
+                if self.config.preserve_source_details {
+                    self.data.raw_value.push_str(&text);
+                }
+                self.st = self.state_after_reference;
+                self.dispatch_token(t)
+            },
+
             _ => Some(self.error(SyntaxError::UnexpectedTokenInEntity(t))),
         }
     }
 
-    pub(crate) fn numeric_reference_from_str(&self, num_str: &str) -> std::result::Result<char, SyntaxError> {
-        let val = if let Some(hex) = num_str.strip_prefix('x') {
-            u32::from_str_radix(hex, 16).map_err(move |_| SyntaxError::InvalidNumericEntity(num_str.into()))?
+    /// Expands references in a value of a lazy attribute, like `inside_reference` would have done
+    pub(crate) fn unescape_lazy_value<'a>(&self, raw: &'a str) -> std::result::Result<Cow<'a, str>, SyntaxError> {
+        if !raw.contains('&') {
+            return Ok(Cow::Borrowed(raw));
+        }
+        let mut value = String::with_capacity(raw.len());
+        let mut rest = raw;
+        while let Some(start) = rest.find('&') {
+            value.push_str(&rest[..start]);
+            rest = &rest[start + 1..];
+            let mut chars = rest.char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, ';')) => break Some(i),
+                    Some((0, c)) if is_name_start_char(c) || c == '#' => {},
+                    Some((i, c)) if i > 0 && is_name_char(c) => {},
+                    // a bare `&` is kept, like `inside_reference` does
+                    Some(_) | None if self.config.lenient_attribute_values => break None,
+                    Some((_, c)) => return Err(SyntaxError::UnexpectedTokenInEntity(Token::Character(c))),
+                    // the reference has run into the closing quote of the value
+                    None => return Err(SyntaxError::UnexpectedTokenInEntity(Token::DoubleQuote)),
+                }
+            };
+            let end = match end {
+                Some(end) => end,
+                None => {
+                    value.push('&');
+                    continue;
+                },
+            };
+            let name = &rest[..end];
+            if name.is_empty() {
+                return Err(SyntaxError::EmptyEntity);
+            }
+            rest = &rest[end + 1..];
+            match name {
+                "lt"   => value.push('<'),
+                "gt"   => value.push('>'),
+                "amp"  => value.push('&'),
+                "apos" => value.push('\''),
+                "quot" => value.push('"'),
+                _ if name.starts_with('#') => value.push(self.numeric_reference_from_str(&name[1..])?),
+                _ => match self.config.c.extra_entities.get(name).or_else(|| self.entities.get(name)).map(|v| &**v).or_else(|| self.html_entity(name)) {
+                    Some(v) => {
+                        let mut stats = self.stats.lock();
+                        if !count_expansion(&mut stats, self.config.entity_expansion_check.as_ref(), name, v.len()) {
+                            return Err(SyntaxError::EntityExpansionRejected(name.into()));
+                        }
+                        value.push_str(v);
+                    },
+                    None => return Err(SyntaxError::UnexpectedEntity(name.into())),
+                },
+            }
+        }
+        value.push_str(rest);
+        Ok(Cow::Owned(value))
+    }
+
+    #[cfg(feature = "html_entities")]
+    fn html_entity(&self, name: &str) -> Option<&'static str> {
+        if self.config.html_entities {
+            crate::reader::html_entities::html_entity(name)
         } else {
-            num_str.parse::<u32>().map_err(move |_| SyntaxError::InvalidNumericEntity(num_str.into()))?
-        };
+            None
+        }
+    }
+
+    #[cfg(not(feature = "html_entities"))]
+    #[inline]
+    fn html_entity(&self, _: &str) -> Option<&'static str> {
+        None
+    }
+
+    pub(crate) fn numeric_reference_from_str(&self, num_str: &str) -> std::result::Result<char, SyntaxError> {
+        let val = reference_code(num_str)?;
+        let surrogate = is_surrogate(val);
         match char::from_u32(val) {
-            Some(c) if self.is_valid_xml_char(c) => Ok(c),
-            Some(_) if self.config.c.replace_unknown_entity_references => Ok('\u{fffd}'),
-            None if self.config.c.replace_unknown_entity_references => {
-                Ok('\u{fffd}')
-            },
-            _ => Err(SyntaxError::InvalidCharacterEntity(val)),
+            Some(c) if !self.is_invalid_char_reference(c) => Ok(c),
+            _ if self.config.c.replace_unknown_entity_references => Ok('\u{fffd}'),
+            Some(_) if self.config.invalid_char_references == InvalidCharReferences::Replace => Ok('\u{fffd}'),
+            Some(c) if self.config.invalid_char_references == InvalidCharReferences::Warning => Ok(c),
+            // surrogates can't be kept in a `String`
+            None if surrogate && self.config.invalid_char_references != InvalidCharReferences::Error => Ok('\u{fffd}'),
+            None if surrogate => Err(SyntaxError::InvalidCharReference(val, CharReferenceKind::Surrogate)),
+            Some(c) if self.is_valid_xml_char(c) => Err(SyntaxError::InvalidCharReference(val, CharReferenceKind::Noncharacter)),
+            _ => Err(SyntaxError::InvalidCharReference(val, CharReferenceKind::Invalid)),
         }
     }
+
+    /// A referenced character that isn't allowed in XML, or a noncharacter if they're rejected
+    fn is_invalid_char_reference(&self, c: char) -> bool {
+        !self.is_valid_xml_char(c) || (self.config.reject_noncharacter_references && is_noncharacter(c))
+    }
+
+    /// `c` is from an invalid reference that has been kept, or a surrogate that has been replaced, because of `InvalidCharReferences::Warning`
+    fn warns_about_reference(&self, c: char, num_str: &str) -> bool {
+        self.config.invalid_char_references == InvalidCharReferences::Warning && !self.config.c.replace_unknown_entity_references &&
+            (self.is_invalid_char_reference(c) || reference_code(num_str).map_or(false, is_surrogate))
+    }
+}
+
+/// Code point of a numeric character reference without the `&#` and `;`, like `x20`
+fn reference_code(num_str: &str) -> std::result::Result<u32, SyntaxError> {
+    let val = if let Some(hex) = num_str.strip_prefix('x') {
+        u32::from_str_radix(hex, 16)
+    } else {
+        num_str.parse::<u32>()
+    };
+    val.map_err(move |_| SyntaxError::InvalidNumericEntity(num_str.into()))
+}
+
+fn is_surrogate(code: u32) -> bool {
+    (0xD800..=0xDFFF).contains(&code)
+}
+
+/// Updates the statistics with an expansion of the entity, and asks the configured callback whether to go ahead
+fn count_expansion(stats: &mut ReaderStats, check: Option<&EntityExpansionCheck>, name: &str, len: usize) -> bool {
+    stats.entity_expansions += 1;
+    let entity = match stats.entities.get_mut(name) {
+        Some(entity) => entity,
+        None => stats.entities.entry(name.to_owned()).or_default(),
+    };
+    entity.count += 1;
+    entity.bytes += len as u64;
+    check.map_or(true, |check| check.allows(name, entity))
 }
@@ -1,25 +1,30 @@
-use crate::common::is_whitespace_char;
-use crate::reader::error::SyntaxError;
-use crate::reader::events::XmlEvent;
+use crate::common::{is_whitespace_char, Position};
+use crate::reader::config::TextOutsideRoot;
+use crate::reader::error::{Limit, SyntaxError, Warning};
+use crate::reader::events::{EventMask, XmlEvent};
 use crate::reader::lexer::Token;
 
 use super::{
-    ClosingTagSubstate, DoctypeSubstate, Encountered, OpeningTagSubstate,
-    ProcessingInstructionSubstate, PullParser, Result, State,
+    ClosingTagSubstate, DoctypeSubstate, DocumentStart, Encountered, OpeningTagSubstate,
+    ProcessingInstructionSubstate, PullParser, Result, State, LAZY_TEXT_CHUNK,
 };
 
 impl PullParser {
     pub fn outside_tag(&mut self, t: Token) -> Option<Result> {
         match t {
+            _ if self.skips_stray_text(t) => self.push_stray_text(t),
+
             Token::Character(c) => {
+                let text_was_whitespace = self.text_whitespace;
                 if is_whitespace_char(c) {
                     // skip whitespace outside of the root element
-                    if (self.config.c.trim_whitespace && self.buf.is_empty()) ||
+                    if (self.trims_text() && self.drops_whitespace_only_text() && self.buf.is_empty()) ||
                         (self.depth() == 0 && self.config.c.ignore_root_level_whitespace) {
                             return None;
                     }
                 } else {
                     self.inside_whitespace = false;
+                    self.text_whitespace = false;
                     if self.depth() == 0 {
                         return Some(self.error(SyntaxError::UnexpectedTokenOutsideRoot(t)));
                     }
@@ -29,10 +34,28 @@ impl PullParser {
                     return Some(self.error(SyntaxError::InvalidCharacterEntity(c as u32)));
                 }
 
+                if self.skips_text_part() {
+                    self.buf.push(c);
+                    return None;
+                }
+
+                // split between whitespace and other text, so that runs of whitespace aren't split
+                if self.streams_text() && !is_whitespace_char(c) && self.buf.len() >= LAZY_TEXT_CHUNK {
+                    let part = self.take_buf();
+                    let part = self.finish_text(part, false);
+                    self.whitespace_text = text_was_whitespace;
+                    self.escaped_whitespace.clear();
+                    self.text_continued = true;
+                    // the next part starts here
+                    self.push_pos();
+                    self.buf.push(c);
+                    return Some(Ok(XmlEvent::Characters(part)));
+                }
+
                 if self.buf.is_empty() {
                     self.push_pos();
                 } else if self.buf.len() > self.config.max_data_length {
-                    return Some(self.error(SyntaxError::ExceededConfiguredLimit));
+                    return Some(self.limit_error(Limit::DataLength, self.buf.len(), self.current_element_name()));
                 }
                 self.buf.push(c);
                 None
@@ -45,12 +68,13 @@ impl PullParser {
                     return Some(self.error(SyntaxError::UnexpectedTokenOutsideRoot(t)));
                 }
                 self.inside_whitespace = false;
+                self.text_whitespace = false;
 
                 if let Some(s) = t.as_static_str() {
                     if self.buf.is_empty() {
                         self.push_pos();
                     } else if self.buf.len() > self.config.max_data_length {
-                        return Some(self.error(SyntaxError::ExceededConfiguredLimit));
+                        return Some(self.limit_error(Limit::DataLength, self.buf.len(), self.current_element_name()));
                     }
 
                     self.buf.push_str(s);
@@ -65,8 +89,9 @@ impl PullParser {
 
             Token::ReferenceEnd if self.depth() > 0 => { // Semi-colon in a text outside an entity
                 self.inside_whitespace = false;
+                self.text_whitespace = false;
                 if self.buf.len() > self.config.max_data_length {
-                    return Some(self.error(SyntaxError::ExceededConfiguredLimit));
+                    return Some(self.limit_error(Limit::DataLength, self.buf.len(), self.current_element_name()));
                 }
                 Token::ReferenceEnd.push_to_string(&mut self.buf);
                 None
@@ -78,6 +103,10 @@ impl PullParser {
                 self.into_state(State::InsideComment, next_event)
             }
 
+            Token::ProcessingInstructionStart if self.ignores_processing_instruction() && self.config.c.coalesce_characters => {
+                self.into_state_continue(State::InsideProcessingInstruction(ProcessingInstructionSubstate::IgnoredName))
+            },
+
             Token::CDataStart if self.depth() > 0 && self.config.c.coalesce_characters && self.config.c.cdata_to_characters => {
                 if self.buf.is_empty() {
                     self.push_pos();
@@ -86,29 +115,49 @@ impl PullParser {
             },
 
             _ => {
+                self.end_stray_text();
                 // Encountered some markup event, flush the buffer as characters
                 // or a whitespace
                 let mut next_event = if self.buf_has_data() {
+                    self.whitespace_text = self.text_whitespace;
                     let buf = self.take_buf();
-                    if self.inside_whitespace && self.config.c.trim_whitespace {
+                    if self.inside_whitespace && self.drops_whitespace_only_text() {
                         None
+                    } else if self.inside_whitespace && !self.config.c.whitespace_to_characters && !self.config.events_only.contains(EventMask::WHITESPACE) {
+                        // the event will be skipped, but it still has a position to balance
+                        self.buf = buf;
+                        self.buf.clear();
+                        Some(Ok(XmlEvent::Whitespace(String::new())))
                     } else if self.inside_whitespace && !self.config.c.whitespace_to_characters {
                         debug_assert!(buf.chars().all(|ch| ch.is_whitespace()), "ws={buf:?}");
                         Some(Ok(XmlEvent::Whitespace(buf)))
-                    } else if self.config.c.trim_whitespace {
-                        Some(Ok(XmlEvent::Characters(buf.trim_matches(is_whitespace_char).into())))
-                    } else {
+                    } else if self.inside_whitespace {
                         Some(Ok(XmlEvent::Characters(buf)))
+                    } else {
+                        Some(Ok(XmlEvent::Characters(self.finish_text(buf, true))))
                     }
                 } else { None };
                 self.inside_whitespace = true;  // Reset inside_whitespace flag
+                self.text_whitespace = true;
+                self.text_continued = false;
+                self.escaped_whitespace.clear();
 
                 // pos is popped whenever an event is emitted, so pushes must happen only if there will be an event to balance it
-                // and ignored comments don't pop
-                if t != Token::CommentStart || !self.config.c.ignore_comments {
+                // and ignored comments and processing instructions don't pop
+                let ignored = match t {
+                    Token::CommentStart => self.config.c.ignore_comments,
+                    Token::ProcessingInstructionStart => self.ignores_processing_instruction(),
+                    _ => false,
+                };
+                if !ignored {
                     self.push_pos();
                 }
                 match t {
+                    Token::OpeningTagStart if self.is_document_boundary(true) => {
+                        self.next_document = Some(DocumentStart::Element);
+                        self.into_state(State::OutsideTag, next_event)
+                    },
+
                     Token::OpeningTagStart if self.depth() > 0 || self.encountered < Encountered::Element || self.config.allow_multiple_root_elements => {
                         if let Some(e) = self.set_encountered(Encountered::Element) {
                             next_event = Some(e);
@@ -133,12 +182,16 @@ impl PullParser {
                             next_event = Some(e);
                         }
 
-                        // We don't have a doctype event so skip this position
-                        // FIXME: update when we have a doctype event
-                        self.next_pos();
+                        // The doctype event is optional, without it skip this position
+                        if !self.config.capture_doctype_raw {
+                            self.next_pos();
+                        }
                         self.into_state(State::InsideDoctype(DoctypeSubstate::Outside), next_event)
                     },
 
+                    Token::ProcessingInstructionStart if ignored =>
+                        self.into_state(State::InsideProcessingInstruction(ProcessingInstructionSubstate::IgnoredName), next_event),
+
                     Token::ProcessingInstructionStart =>
                         self.into_state(State::InsideProcessingInstruction(ProcessingInstructionSubstate::PIInsideName), next_event),
 
@@ -156,6 +209,14 @@ impl PullParser {
         debug_assert!(self.encountered < Encountered::Declaration);
 
         match t {
+            _ if self.skips_stray_text(t) => {
+                let next_event = self.set_encountered(Encountered::AnyChars);
+                if let Some(e) = self.push_stray_text(t) {
+                    return Some(e);
+                }
+                self.into_state(State::OutsideTag, next_event)
+            },
+
             Token::Character(c) => {
                 let next_event = self.set_encountered(Encountered::AnyChars);
 
@@ -163,9 +224,10 @@ impl PullParser {
                     return Some(self.error(SyntaxError::UnexpectedTokenOutsideRoot(t)));
                 }
                 self.inside_whitespace = true;
+                self.text_whitespace = true;
 
                 // skip whitespace outside of the root element
-                if (self.config.c.trim_whitespace && self.buf.is_empty()) ||
+                if (self.drops_whitespace_only_text() && self.buf.is_empty()) ||
                     (self.depth() == 0 && self.config.c.ignore_root_level_whitespace) {
                         return self.into_state(State::OutsideTag, next_event);
                 }
@@ -177,20 +239,27 @@ impl PullParser {
 
             Token::CommentStart => {
                 let next_event = self.set_encountered(Encountered::Comment);
+                if !self.config.c.ignore_comments {
+                    self.unpositioned_event_start = Some(self.lexer.position().byte_offset);
+                }
                 self.into_state(State::InsideComment, next_event)
             },
 
             Token::OpeningTagStart => {
                 let next_event = self.set_encountered(Encountered::Element);
+                self.unpositioned_event_start = Some(self.lexer.position().byte_offset);
                 self.nst.push_empty();
                 self.into_state(State::InsideOpeningTag(OpeningTagSubstate::InsideName), next_event)
             },
 
             Token::DoctypeStart => {
                 let next_event = self.set_encountered(Encountered::Doctype);
-                // We don't have a doctype event so skip this position
-                // FIXME: update when we have a doctype event
-                self.next_pos();
+                // The doctype event is optional, without it skip this position
+                if self.config.capture_doctype_raw {
+                    self.push_pos();
+                } else {
+                    self.next_pos();
+                }
                 self.into_state(State::InsideDoctype(DoctypeSubstate::Outside), next_event)
             },
 
@@ -202,4 +271,47 @@ impl PullParser {
             _ => Some(self.error(SyntaxError::UnexpectedToken(t))),
         }
     }
+
+    /// Instructions outside the root element may be declarations of the next document
+    fn ignores_processing_instruction(&self) -> bool {
+        self.depth() > 0 && self.config.ignore_processing_instructions
+    }
+
+    /// Text outside the root element, and whitespace following it, is skipped if `text_outside_root` allows it
+    fn skips_stray_text(&self, t: Token) -> bool {
+        if self.depth() > 0 || self.config.text_outside_root == TextOutsideRoot::Error {
+            return false;
+        }
+        match t {
+            Token::Character(c) => self.stray_text.is_some() || !is_whitespace_char(c),
+            Token::CommentEnd | Token::TagEnd | Token::EqualsSign |
+            Token::DoubleQuote | Token::SingleQuote |
+            Token::ProcessingInstructionEnd | Token::EmptyTagEnd |
+            Token::ReferenceStart | Token::ReferenceEnd | Token::CDataEnd => true,
+            _ => false,
+        }
+    }
+
+    fn push_stray_text(&mut self, t: Token) -> Option<Result> {
+        let position = self.lexer.position();
+        // the text itself isn't kept, only its length
+        let (_, length, trimmed_length) = self.stray_text.get_or_insert((position, 0, 0));
+        *length += match t {
+            Token::Character(c) => c.len_utf8(),
+            _ => t.as_static_str().map_or(0, str::len),
+        };
+        if !matches!(t, Token::Character(c) if is_whitespace_char(c)) {
+            *trimmed_length = *length;
+        }
+        None
+    }
+
+    /// Reports the skipped text when markup or the end of the document follows it
+    pub(super) fn end_stray_text(&mut self) {
+        if let Some((position, _, length)) = self.stray_text.take() {
+            if self.config.text_outside_root == TextOutsideRoot::Warning {
+                self.warn(Warning::TextOutsideRoot { length, position });
+            }
+        }
+    }
 }
@@ -0,0 +1,283 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::common::{TextPosition, XmlVersion};
+use crate::name::OwnedName;
+use crate::namespace::Namespace;
+use crate::util::Encoding;
+
+use super::{Encountered, IdType, PullParser, State};
+
+/// Start of the saved state, changed whenever the format changes
+const MAGIC: &[u8; 8] = b"xml-rs\x00\x02";
+
+impl PullParser {
+    /// Encodes the state needed to continue parsing from the source position,
+    /// if it's between events inside the root element, outside of any markup or text
+    pub fn save_state(&self, source_position: u64) -> Option<Vec<u8>> {
+        if self.st != State::OutsideTag || self.est.is_empty() || !self.buf.is_empty() || !self.lexer.is_idle()
+            || self.next_event.is_some() || self.final_result.is_some() || self.pushed_back.is_some()
+            || self.next_document.is_some() || !self.replayed_tokens.is_empty() || self.deferred_encoding.is_some()
+            || self.text_continued || self.xop_include.is_some() {
+            return None;
+        }
+
+        let mut w = StateWriter(MAGIC.to_vec());
+        w.u64(source_position);
+        w.position(self.lexer.head_position());
+        w.u64(self.lexer.bytes_read());
+        w.0.push(match self.lexer.encoding() {
+            Encoding::Utf8 => 0,
+            Encoding::Default => 1,
+            Encoding::Latin1 => 2,
+            Encoding::Ascii => 3,
+            Encoding::Utf16Be => 4,
+            Encoding::Utf16Le => 5,
+            Encoding::Utf16 => 6,
+            Encoding::Unknown => 7,
+        });
+        w.0.push(match self.data.version {
+            None => 0,
+            Some(XmlVersion::Version10) => 1,
+            Some(XmlVersion::Version11) => 2,
+        });
+
+        w.u64(self.est.len() as u64);
+        for (name, pos) in &self.est {
+            w.str(&name.local_name);
+            w.opt_str(name.prefix.as_deref());
+            w.opt_str(name.namespace.as_deref());
+            w.position(*pos);
+        }
+
+        // the bottom level has only the predefined prefixes,
+        // and the top one may be waiting to be popped after an `EndElement`
+        let levels = &self.nst.0[1..self.nst.0.len() - usize::from(self.pop_namespace)];
+        w.u64(levels.len() as u64);
+        for level in levels {
+            w.u64(level.0.len() as u64);
+            for (prefix, uri) in &level.0 {
+                w.str(prefix);
+                w.str(uri);
+            }
+        }
+
+        w.u64(self.entities.len() as u64);
+        for (name, value) in self.entities.iter() {
+            w.str(name);
+            w.str(value);
+        }
+
+        w.u64(self.elements_started as u64);
+        w.u64(self.element_indices.len() as u64);
+        for &index in &self.element_indices {
+            w.u64(index as u64);
+        }
+
+        w.u64(self.xml_ids.len() as u64);
+        for (id, pos) in &self.xml_ids {
+            w.str(id);
+            w.position(*pos);
+        }
+        w.u64(self.attribute_types.len() as u64);
+        for ((element, attribute), ty) in &self.attribute_types {
+            w.str(element);
+            w.str(attribute);
+            w.0.push(match ty {
+                IdType::Id => 0,
+                IdType::IdRef => 1,
+                IdType::IdRefs => 2,
+            });
+        }
+        w.u64(self.ids.len() as u64);
+        for id in &self.ids {
+            w.str(id);
+        }
+        w.u64(self.idrefs.len() as u64);
+        for (id, pos) in &self.idrefs {
+            w.str(id);
+            w.position(*pos);
+        }
+        w.u64(self.element_content.len() as u64);
+        for name in &self.element_content {
+            w.str(name);
+        }
+        Some(w.0)
+    }
+
+    /// Sets the state of a new parser from `save_state`, and returns the source position
+    pub fn restore_state(&mut self, bytes: &[u8]) -> Option<u64> {
+        let mut r = StateReader(bytes.strip_prefix(MAGIC)?);
+        let source_position = r.u64()?;
+        let position = r.position()?;
+        let bytes_read = r.u64()?;
+        let encoding = match r.u8()? {
+            0 => Encoding::Utf8,
+            1 => Encoding::Default,
+            2 => Encoding::Latin1,
+            3 => Encoding::Ascii,
+            4 => Encoding::Utf16Be,
+            5 => Encoding::Utf16Le,
+            6 => Encoding::Utf16,
+            7 => Encoding::Unknown,
+            _ => return None,
+        };
+        let version = match r.u8()? {
+            0 => None,
+            1 => Some(XmlVersion::Version10),
+            2 => Some(XmlVersion::Version11),
+            _ => return None,
+        };
+
+        let mut est = Vec::new();
+        for _ in 0..r.u64()? {
+            let local_name = r.string()?.into();
+            let prefix = r.opt_string()?.map(From::from);
+            let namespace = r.opt_string()?.map(From::from);
+            est.push((OwnedName { local_name, namespace, prefix }, r.position()?));
+        }
+        if est.is_empty() {
+            return None;
+        }
+
+        let mut levels = Vec::new();
+        for _ in 0..r.u64()? {
+            let mut level = Namespace::empty();
+            for _ in 0..r.u64()? {
+                level.put(r.string()?, r.string()?);
+            }
+            levels.push(level);
+        }
+
+        let mut entities = HashMap::new();
+        for _ in 0..r.u64()? {
+            entities.insert(r.string()?, r.string()?);
+        }
+
+        let elements_started = r.u64()? as usize;
+        let mut element_indices = Vec::new();
+        for _ in 0..r.u64()? {
+            element_indices.push(r.u64()? as usize);
+        }
+
+        let mut xml_ids = HashMap::new();
+        for _ in 0..r.u64()? {
+            xml_ids.insert(r.string()?, r.position()?);
+        }
+        let mut attribute_types = HashMap::new();
+        for _ in 0..r.u64()? {
+            let name = (r.string()?, r.string()?);
+            let ty = match r.u8()? {
+                0 => IdType::Id,
+                1 => IdType::IdRef,
+                2 => IdType::IdRefs,
+                _ => return None,
+            };
+            attribute_types.insert(name, ty);
+        }
+        let mut ids = HashSet::new();
+        for _ in 0..r.u64()? {
+            ids.insert(r.string()?);
+        }
+        let mut idrefs = Vec::new();
+        for _ in 0..r.u64()? {
+            idrefs.push((r.string()?, r.position()?));
+        }
+        let mut element_content = HashSet::new();
+        for _ in 0..r.u64()? {
+            element_content.insert(r.string()?);
+        }
+        if !r.0.is_empty() {
+            return None;
+        }
+
+        self.reset();
+        self.lexer.resume_at(position, bytes_read, encoding);
+        self.pos.clear();
+        self.pos.push(position);
+        self.data.version = version;
+        self.st = State::OutsideTag;
+        self.encountered = Encountered::Element;
+        self.est = est;
+        self.nst.0.extend(levels);
+        if entities != *self.entities {
+            self.entities = Arc::new(entities);
+        }
+        self.elements_started = elements_started;
+        self.element_indices = element_indices;
+        self.xml_ids = xml_ids;
+        self.attribute_types = attribute_types;
+        self.ids = ids;
+        self.idrefs = idrefs;
+        self.element_content = element_content;
+        Some(source_position)
+    }
+}
+
+struct StateWriter(Vec<u8>);
+
+impl StateWriter {
+    fn u64(&mut self, n: u64) {
+        self.0.extend_from_slice(&n.to_le_bytes());
+    }
+
+    fn str(&mut self, s: &str) {
+        self.u64(s.len() as u64);
+        self.0.extend_from_slice(s.as_bytes());
+    }
+
+    fn opt_str(&mut self, s: Option<&str>) {
+        match s {
+            Some(s) => {
+                self.0.push(1);
+                self.str(s);
+            },
+            None => self.0.push(0),
+        }
+    }
+
+    fn position(&mut self, pos: TextPosition) {
+        self.u64(pos.row);
+        self.u64(pos.column);
+        self.u64(pos.byte_offset);
+        self.u64(pos.char_offset);
+    }
+}
+
+struct StateReader<'a>(&'a [u8]);
+
+impl StateReader<'_> {
+    fn bytes(&mut self, len: usize) -> Option<&[u8]> {
+        if len > self.0.len() {
+            return None;
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = usize::try_from(self.u64()?).ok()?;
+        String::from_utf8(self.bytes(len)?.to_vec()).ok()
+    }
+
+    fn opt_string(&mut self) -> Option<Option<String>> {
+        match self.u8()? {
+            0 => Some(None),
+            1 => self.string().map(Some),
+            _ => None,
+        }
+    }
+
+    fn position(&mut self) -> Option<TextPosition> {
+        Some(TextPosition { row: self.u64()?, column: self.u64()?, byte_offset: self.u64()?, char_offset: self.u64()? })
+    }
+}
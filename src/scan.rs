@@ -0,0 +1,99 @@
+//! Quick searches in large documents, for when only the location of an element is needed,
+//! e.g. to decide where to route a message before parsing it.
+//!
+//! The document is only split into tokens. Namespaces, attributes, entities and text aren't
+//! processed, and most well-formedness errors aren't detected.
+
+use std::io::Read;
+
+use crate::common::{is_whitespace_char, Position, TextPosition};
+use crate::filter::Pattern;
+use crate::reader::{Lexer, ParserConfig2, Token};
+
+/// Position of the start tag of the first element that matches the path, in document order.
+///
+/// The path is made of local names separated by `/`, like in [`TransformRules`](crate::filter::TransformRules).
+/// `item` matches an element with this name anywhere, and `/feed/entry` only children of the root.
+/// Prefixes are ignored, and the document is assumed to be in UTF-8 or UTF-16 with a BOM.
+///
+/// Returns `None` if there's no such element, or the source can't be read or tokenized before finding it.
+///
+/// ```rust
+/// let doc = r#"<envelope><!-- <body> --><header a=">"/><body><order/></body></envelope>"#;
+/// let pos = xml::scan::find_first(doc.as_bytes(), "/envelope/body").unwrap();
+/// assert_eq!(pos.byte_offset, 40);
+/// assert!(xml::scan::find_first(doc.as_bytes(), "header/order").is_none());
+/// ```
+pub fn find_first<R: Read>(mut source: R, path: &str) -> Option<TextPosition> {
+    let pattern = Pattern::new(path);
+    let mut lexer = Lexer::new(&ParserConfig2::new());
+    let mut path = Vec::new();
+    let mut name = String::new();
+    let mut start = TextPosition::new();
+    let mut st = State::Outside;
+    loop {
+        let token = lexer.next_token(&mut source).ok()??;
+        st = match (st, token) {
+            (State::Outside, Token::OpeningTagStart) => {
+                start = lexer.position();
+                name.clear();
+                State::StartTagName
+            },
+            (State::Outside, Token::ClosingTagStart) => State::EndTag,
+            (State::Outside, Token::ProcessingInstructionStart) => State::ProcessingInstruction,
+            (State::Outside, _) => State::Outside,
+
+            (State::ProcessingInstruction, Token::ProcessingInstructionEnd) => State::Outside,
+            (State::ProcessingInstruction, _) => State::ProcessingInstruction,
+
+            (State::StartTagName, Token::Character(c)) if !is_whitespace_char(c) => {
+                name.push(c);
+                State::StartTagName
+            },
+            (State::StartTagName, _) => {
+                let local_name = name.split_once(':').map_or(&*name, |(_, local_name)| local_name);
+                path.push(local_name.into());
+                if pattern.matches(&path) {
+                    return Some(start);
+                }
+                match token {
+                    Token::TagEnd => State::Outside,
+                    Token::EmptyTagEnd => {
+                        path.pop();
+                        State::Outside
+                    },
+                    _ => State::StartTag(None),
+                }
+            },
+
+            (State::StartTag(None), Token::TagEnd) => State::Outside,
+            (State::StartTag(None), Token::EmptyTagEnd) => {
+                path.pop();
+                State::Outside
+            },
+            (State::StartTag(None), Token::DoubleQuote | Token::SingleQuote) => State::StartTag(Some(token)),
+            (State::StartTag(Some(quote)), _) if quote == token => State::StartTag(None),
+            (State::StartTag(quote), _) => State::StartTag(quote),
+
+            (State::EndTag, Token::TagEnd) => {
+                path.pop();
+                State::Outside
+            },
+            (State::EndTag, _) => State::EndTag,
+        };
+    }
+}
+
+#[derive(Copy, Clone)]
+enum State {
+    /// Text, or markup other than tags
+    Outside,
+    /// After `<`
+    StartTagName,
+    /// After the name, with the quote of an attribute value
+    StartTag(Option<Token>),
+    /// After `</`
+    EndTag,
+    /// After `<?`, which may contain anything
+    ProcessingInstruction,
+}
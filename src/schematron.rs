@@ -0,0 +1,872 @@
+//! Streaming validation with [Schematron](https://schematron.com) rules.
+//!
+//! Only a subset of Schematron is supported, which can be checked without building a tree of the document:
+//!
+//! * Rule contexts are patterns of element names separated by `/` or `//`, like `Invoice/InvoiceLine`
+//!   or `/Invoice//Price`, optionally with alternatives separated by `|`. `*` matches any element.
+//! * Tests can use the context element (`.`), its attributes (`@currency`) and its child elements (`Quantity`, `*`),
+//!   literals, comparisons, `and`, `or`, and the functions `not`, `true`, `false`, `count`, `sum`, `number`,
+//!   `string`, `string-length`, `normalize-space`, `concat`, `contains` and `starts-with`.
+//! * Messages can contain `<name/>` and `<value-of select="…"/>` with the same expressions.
+//!
+//! Names are matched by their local names, so namespace prefixes in the rules don't matter.
+//! Schemas using other features of XPath fail to parse, instead of being checked incorrectly.
+//!
+//! ```rust
+//! use xml::schematron::{DiagnosticKind, Schema};
+//! use xml::EventReader;
+//!
+//! let schema: Schema = r#"<schema xmlns="http://purl.oclc.org/dsdl/schematron">
+//!     <pattern>
+//!         <rule context="Invoice/Line">
+//!             <assert test="@quantity > 0">Line <value-of select="@id"/> has no quantity</assert>
+//!             <report test="count(Note) > 1">Too many notes</report>
+//!         </rule>
+//!     </pattern>
+//! </schema>"#.parse().unwrap();
+//!
+//! let doc = r#"<Invoice><Line id="1" quantity="2"/><Line id="2" quantity="0"/></Invoice>"#;
+//! let diagnostics = schema.validate(EventReader::from_str(doc)).unwrap();
+//! assert_eq!(diagnostics.len(), 1);
+//! assert_eq!(diagnostics[0].kind, DiagnosticKind::Assert);
+//! assert_eq!(diagnostics[0].message, "Line 2 has no quantity");
+//! assert_eq!(diagnostics[0].position.column, 36);
+//! ```
+
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
+
+use crate::attribute::Attributes;
+use crate::common::{is_name_char, is_name_start_char, is_whitespace_char, Position, TextPosition};
+use crate::name::{OwnedName, SharedStr};
+use crate::reader::{self, EventReader, XmlEvent};
+
+/// Namespace of ISO Schematron
+pub const NS_SCHEMATRON_URI: &str = "http://purl.oclc.org/dsdl/schematron";
+/// Namespace of Schematron 1.5
+const NS_SCHEMATRON_15_URI: &str = "http://www.ascc.net/xml/schematron";
+
+/// Rules parsed from a Schematron schema
+#[derive(Clone, Debug)]
+pub struct Schema {
+    patterns: Vec<Vec<Rule>>,
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    context: Vec<ContextPattern>,
+    checks: Vec<Check>,
+}
+
+#[derive(Clone, Debug)]
+struct Check {
+    kind: DiagnosticKind,
+    test_source: String,
+    test: Expr,
+    id: Option<String>,
+    role: Option<String>,
+    message: Vec<MessagePart>,
+}
+
+#[derive(Clone, Debug)]
+enum MessagePart {
+    Text(String),
+    Name,
+    ValueOf(Expr),
+}
+
+/// Whether a [`Diagnostic`] comes from a failed `assert` or from a successful `report`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum DiagnosticKind {
+    /// `<assert>` whose test is false
+    Assert,
+    /// `<report>` whose test is true
+    Report,
+}
+
+/// A failed assertion or a report about an element of the validated document
+#[derive(Clone, PartialEq, Debug)]
+pub struct Diagnostic {
+    /// Which kind of check has fired
+    pub kind: DiagnosticKind,
+    /// The `id` attribute of the check
+    pub id: Option<String>,
+    /// The `role` attribute of the check, e.g. `error` or `warning`
+    pub role: Option<String>,
+    /// The `test` expression of the check
+    pub test: String,
+    /// Text of the check with `<name/>` and `<value-of/>` filled in, and whitespace collapsed
+    pub message: String,
+    /// Name of the context element
+    pub element: OwnedName,
+    /// Position of the start tag of the context element
+    pub position: TextPosition,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} <{}>: {}", self.position, self.element.borrow().to_repr(), self.message)
+    }
+}
+
+/// Failure to load a [`Schema`]
+#[derive(Debug)]
+pub enum SchemaError {
+    /// The schema isn't a well-formed XML document
+    Reader(reader::Error),
+    /// The schema is incomplete, or uses unsupported features of Schematron or XPath
+    Invalid {
+        /// What is wrong
+        message: String,
+        /// Position of the element with the problem
+        position: TextPosition,
+    },
+}
+
+impl fmt::Display for SchemaError {
+    #[cold]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::Reader(e) => e.fmt(f),
+            SchemaError::Invalid { message, position } => write!(f, "{position} {message}"),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SchemaError::Reader(e) => Some(e),
+            SchemaError::Invalid { .. } => None,
+        }
+    }
+}
+
+impl From<reader::Error> for SchemaError {
+    #[cold]
+    fn from(e: reader::Error) -> Self {
+        SchemaError::Reader(e)
+    }
+}
+
+impl Schema {
+    /// Reads a schema from an XML document with a Schematron `<schema>` element.
+    ///
+    /// Rules marked as `abstract` are skipped, and so are elements that don't affect validation, like `<title>` or `<p>`.
+    pub fn from_reader<R: Read>(source: R) -> Result<Self, SchemaError> {
+        let mut reader = EventReader::new(source);
+        let mut patterns = Vec::new();
+        let mut rule: Option<Rule> = None;
+        let mut check: Option<Check> = None;
+        // depth of elements that are skipped, or are inside a check's message
+        let mut skip_depth = 0;
+        let mut depth = 0;
+        loop {
+            let event = reader.next()?;
+            let position = reader.position();
+            let invalid = |message: String| SchemaError::Invalid { message, position };
+            match event {
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    depth += 1;
+                    let attr = |local_name: &str| attributes.iter()
+                        .find(|a| a.name.local_name == local_name && a.name.namespace.is_none())
+                        .map(|a| a.value.clone());
+                    let required = |local_name: &str| attr(local_name)
+                        .ok_or_else(|| invalid(format!("<{}> must have the {local_name} attribute", name.local_name)));
+                    let is_schematron = matches!(name.namespace.as_deref(), Some(NS_SCHEMATRON_URI | NS_SCHEMATRON_15_URI));
+                    if depth == 1 && !(is_schematron && name.local_name == "schema") {
+                        return Err(invalid("the root element must be a Schematron <schema>".into()));
+                    }
+                    if let Some(check) = check.as_mut() {
+                        match &*name.local_name {
+                            "name" if is_schematron => check.message.push(MessagePart::Name),
+                            "value-of" if is_schematron => {
+                                let select = required("select")?;
+                                check.message.push(MessagePart::ValueOf(Expr::parse(&select).map_err(invalid)?));
+                            },
+                            _ => {},
+                        }
+                        skip_depth += 1;
+                    } else if skip_depth > 0 || !is_schematron {
+                        skip_depth += 1;
+                    } else {
+                        match &*name.local_name {
+                            "schema" => {},
+                            "pattern" => patterns.push(Vec::new()),
+                            "rule" if attr("abstract").as_deref() == Some("true") => skip_depth += 1,
+                            "rule" => {
+                                let context = required("context")?;
+                                let context = context.split('|').map(ContextPattern::parse).collect::<Result<_, _>>().map_err(invalid)?;
+                                rule = Some(Rule { context, checks: Vec::new() });
+                            },
+                            "assert" | "report" if rule.is_some() => {
+                                let test_source = required("test")?;
+                                check = Some(Check {
+                                    kind: if name.local_name == "assert" { DiagnosticKind::Assert } else { DiagnosticKind::Report },
+                                    test: Expr::parse(&test_source).map_err(invalid)?,
+                                    test_source,
+                                    id: attr("id"),
+                                    role: attr("role"),
+                                    message: Vec::new(),
+                                });
+                            },
+                            "extends" | "let" | "include" => return Err(invalid(format!("<{}> is not supported", name.local_name))),
+                            _ => skip_depth += 1,
+                        }
+                    }
+                },
+                XmlEvent::EndElement { name } => {
+                    depth -= 1;
+                    if skip_depth > 0 {
+                        skip_depth -= 1;
+                    } else if name.local_name == "rule" {
+                        let rule = rule.take().ok_or_else(|| invalid("<rule> must be inside a <pattern>".into()))?;
+                        patterns.last_mut().ok_or_else(|| invalid("<rule> must be inside a <pattern>".into()))?.push(rule);
+                    } else if let Some(check) = check.take() {
+                        if let Some(rule) = rule.as_mut() {
+                            rule.checks.push(check);
+                        }
+                    }
+                },
+                XmlEvent::Characters(text) | XmlEvent::CData(text) | XmlEvent::Whitespace(text) => {
+                    if let Some(check) = check.as_mut() {
+                        check.message.push(MessagePart::Text(text));
+                    }
+                },
+                XmlEvent::EndDocument => break,
+                _ => {},
+            }
+        }
+        Ok(Self { patterns })
+    }
+
+    /// Checks all elements of the document, and returns the failed assertions and reports in document order
+    /// of the ends of the elements. Errors are only returned for malformed documents.
+    pub fn validate<R: Read>(&self, mut reader: EventReader<R>) -> reader::Result<Vec<Diagnostic>> {
+        let mut validator = self.validator();
+        let mut diagnostics = Vec::new();
+        loop {
+            let event = reader.next()?;
+            let position = reader.position();
+            diagnostics.extend(validator.event(&event, position));
+            if event == XmlEvent::EndDocument {
+                return Ok(diagnostics);
+            }
+        }
+    }
+
+    /// Creates a validator for events of a document read elsewhere, e.g. while the document is processed
+    #[must_use]
+    pub fn validator(&self) -> Validator<'_> {
+        Validator { schema: self, path: Vec::new(), frames: Vec::new() }
+    }
+}
+
+impl FromStr for Schema {
+    type Err = SchemaError;
+
+    fn from_str(s: &str) -> Result<Self, SchemaError> {
+        Self::from_reader(s.as_bytes())
+    }
+}
+
+/// Checks a document event by event, see [`Schema::validator`]
+#[derive(Debug)]
+pub struct Validator<'schema> {
+    schema: &'schema Schema,
+    /// Local names of the open elements
+    path: Vec<SharedStr>,
+    frames: Vec<Frame>,
+}
+
+/// An open element of the validated document
+#[derive(Debug)]
+struct Frame {
+    name: OwnedName,
+    attributes: Attributes,
+    position: TextPosition,
+    /// Indices of rules, by pattern, whose context is this element
+    rules: Vec<(usize, usize)>,
+    /// Text and string values of children are needed for this element or an ancestor
+    collect: bool,
+    /// String value of this element, so far
+    text: String,
+    /// Local names and string values of child elements, if this element is a context
+    children: Vec<(SharedStr, String)>,
+}
+
+impl Validator<'_> {
+    /// Checks the next event of the document. The position should be the start of the event,
+    /// i.e. [`EventReader::position`] after reading it.
+    ///
+    /// Elements are checked when they end, because tests can use their content.
+    pub fn event(&mut self, event: &XmlEvent, position: TextPosition) -> Vec<Diagnostic> {
+        match event {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                self.path.push(name.local_name.clone());
+                let rules: Vec<_> = self.schema.patterns.iter().enumerate()
+                    .filter_map(|(p, rules)| {
+                        // in each pattern, only the first matching rule is used
+                        let r = rules.iter().position(|rule| rule.context.iter().any(|c| c.matches(&self.path)))?;
+                        Some((p, r))
+                    })
+                    .collect();
+                let collect = !rules.is_empty() || self.frames.last().map_or(false, |f| f.collect);
+                self.frames.push(Frame {
+                    name: name.clone(),
+                    attributes: if collect { attributes.clone() } else { Attributes::new() },
+                    position,
+                    rules,
+                    collect,
+                    text: String::new(),
+                    children: Vec::new(),
+                });
+                Vec::new()
+            },
+            XmlEvent::Characters(text) | XmlEvent::CData(text) | XmlEvent::Whitespace(text) => {
+                if let Some(frame) = self.frames.last_mut().filter(|f| f.collect) {
+                    frame.text.push_str(text);
+                }
+                Vec::new()
+            },
+            XmlEvent::EndElement { .. } => {
+                self.path.pop();
+                let frame = match self.frames.pop() {
+                    Some(frame) => frame,
+                    None => return Vec::new(),
+                };
+                let diagnostics = self.check(&frame);
+                if let Some(parent) = self.frames.last_mut().filter(|f| f.collect) {
+                    parent.text.push_str(&frame.text);
+                    if !parent.rules.is_empty() {
+                        parent.children.push((frame.name.local_name, frame.text));
+                    }
+                }
+                diagnostics
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    fn check(&self, frame: &Frame) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for &(p, r) in &frame.rules {
+            for check in &self.schema.patterns[p][r].checks {
+                let fired = check.test.eval(frame).to_bool() == (check.kind == DiagnosticKind::Report);
+                if !fired {
+                    continue;
+                }
+                let mut message = String::new();
+                for part in &check.message {
+                    match part {
+                        MessagePart::Text(text) => message.push_str(text),
+                        MessagePart::Name => message.push_str(&frame.name.borrow().to_repr()),
+                        MessagePart::ValueOf(expr) => message.push_str(&expr.eval(frame).to_string()),
+                    }
+                }
+                diagnostics.push(Diagnostic {
+                    kind: check.kind,
+                    id: check.id.clone(),
+                    role: check.role.clone(),
+                    test: check.test_source.clone(),
+                    message: normalize_space(&message),
+                    element: frame.name.clone(),
+                    position: frame.position,
+                });
+            }
+        }
+        diagnostics
+    }
+}
+
+/// An alternative of a rule context, like `a/b//c`
+#[derive(Clone, Debug)]
+struct ContextPattern {
+    steps: Vec<Step>,
+}
+
+#[derive(Clone, Debug)]
+struct Step {
+    /// `None` for `*`
+    name: Option<String>,
+    /// Separated from the previous step, or from the root, by `//`
+    descendant: bool,
+}
+
+impl ContextPattern {
+    fn parse(source: &str) -> Result<Self, String> {
+        let source = source.trim_matches(is_whitespace_char);
+        let unsupported = || format!("Unsupported rule context: '{source}'");
+        let (mut rest, mut descendant) = match source.strip_prefix("//") {
+            Some(rest) => (rest, true),
+            None => match source.strip_prefix('/') {
+                Some(rest) => (rest, false),
+                None => (source, true),
+            },
+        };
+        let mut steps = Vec::new();
+        loop {
+            let end = rest.find('/').unwrap_or(rest.len());
+            let name = rest[..end].trim_matches(is_whitespace_char);
+            let name = match name {
+                "*" => None,
+                _ if is_qname(name) => Some(local_name(name).to_owned()),
+                _ => return Err(unsupported()),
+            };
+            steps.push(Step { name, descendant });
+            if end == rest.len() {
+                return Ok(Self { steps });
+            }
+            rest = &rest[end + 1..];
+            descendant = rest.starts_with('/');
+            if descendant {
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    fn matches(&self, path: &[SharedStr]) -> bool {
+        fn matches_steps(steps: &[Step], path: &[SharedStr]) -> bool {
+            let ((step, steps), (name, path)) = match (steps.split_last(), path.split_last()) {
+                (Some(s), Some(p)) => (s, p),
+                _ => return false,
+            };
+            if step.name.as_ref().map_or(false, |n| n != name) {
+                return false;
+            }
+            match (steps.is_empty(), step.descendant) {
+                (true, true) => true,
+                (true, false) => path.is_empty(),
+                (false, false) => matches_steps(steps, path),
+                (false, true) => (1..=path.len()).any(|len| matches_steps(steps, &path[..len])),
+            }
+        }
+        matches_steps(&self.steps, path)
+    }
+}
+
+fn is_qname(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().map_or(false, is_name_start_char) && chars.all(is_name_char) && name.split(':').count() <= 2
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn normalize_space(s: &str) -> String {
+    s.split(is_whitespace_char).filter(|w| !w.is_empty()).collect::<Vec<_>>().join(" ")
+}
+
+/// An expression of the supported XPath subset
+#[derive(Clone, Debug)]
+enum Expr {
+    /// `.`
+    Context,
+    /// `@name`, or `@*` for `None`
+    Attribute(Option<String>),
+    /// Child elements `name`, or `*` for `None`
+    Children(Option<String>),
+    String(String),
+    Number(f64),
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Compare(Box<Expr>, CompareOp, Box<Expr>),
+    Function(Function, Vec<Expr>),
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Function {
+    Not,
+    True,
+    False,
+    Count,
+    Sum,
+    Number,
+    String,
+    StringLength,
+    NormalizeSpace,
+    Concat,
+    Contains,
+    StartsWith,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "not" => Self::Not,
+            "true" => Self::True,
+            "false" => Self::False,
+            "count" => Self::Count,
+            "sum" => Self::Sum,
+            "number" => Self::Number,
+            "string" => Self::String,
+            "string-length" => Self::StringLength,
+            "normalize-space" => Self::NormalizeSpace,
+            "concat" => Self::Concat,
+            "contains" => Self::Contains,
+            "starts-with" => Self::StartsWith,
+            _ => return None,
+        })
+    }
+
+    /// Allowed numbers of arguments
+    fn arity(self) -> (usize, usize) {
+        match self {
+            Self::True | Self::False => (0, 0),
+            Self::Not | Self::Count | Self::Sum => (1, 1),
+            Self::Number | Self::String | Self::StringLength | Self::NormalizeSpace => (0, 1),
+            Self::Contains | Self::StartsWith => (2, 2),
+            Self::Concat => (2, usize::MAX),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token<'a> {
+    Name(&'a str),
+    String(&'a str),
+    Number(f64),
+    Op(CompareOp),
+    At,
+    Dot,
+    Star,
+    Comma,
+    Open,
+    Close,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token<'_>>, ()> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    loop {
+        rest = rest.trim_start_matches(is_whitespace_char);
+        let c = match rest.chars().next() {
+            Some(c) => c,
+            None => return Ok(tokens),
+        };
+        let (token, len) = match c {
+            '\'' | '"' => {
+                let end = rest[1..].find(c).ok_or(())?;
+                (Token::String(&rest[1..=end]), end + 2)
+            },
+            '0'..='9' | '.' if c != '.' || rest[1..].starts_with(|c: char| c.is_ascii_digit()) => {
+                let len = rest.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len());
+                (Token::Number(rest[..len].parse().map_err(|_| ())?), len)
+            },
+            _ if is_name_start_char(c) => {
+                let len = rest.find(|c: char| !is_name_char(c) && c != ':').unwrap_or(rest.len());
+                (Token::Name(&rest[..len]), len)
+            },
+            '!' if rest.starts_with("!=") => (Token::Op(CompareOp::Ne), 2),
+            '<' if rest.starts_with("<=") => (Token::Op(CompareOp::Le), 2),
+            '>' if rest.starts_with(">=") => (Token::Op(CompareOp::Ge), 2),
+            '<' => (Token::Op(CompareOp::Lt), 1),
+            '>' => (Token::Op(CompareOp::Gt), 1),
+            '=' => (Token::Op(CompareOp::Eq), 1),
+            '@' => (Token::At, 1),
+            '.' => (Token::Dot, 1),
+            '*' => (Token::Star, 1),
+            ',' => (Token::Comma, 1),
+            '(' => (Token::Open, 1),
+            ')' => (Token::Close, 1),
+            _ => return Err(()),
+        };
+        tokens.push(token);
+        rest = &rest[len..];
+    }
+}
+
+struct ExprParser<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+}
+
+impl<'a> ExprParser<'a> {
+    fn peek(&self) -> Option<&Token<'a>> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Expr, ()> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Name("or")) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ()> {
+        let mut expr = self.compare()?;
+        while self.peek() == Some(&Token::Name("and")) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.compare()?));
+        }
+        Ok(expr)
+    }
+
+    fn compare(&mut self) -> Result<Expr, ()> {
+        let mut expr = self.value()?;
+        while let Some(&Token::Op(op)) = self.peek() {
+            self.pos += 1;
+            expr = Expr::Compare(Box::new(expr), op, Box::new(self.value()?));
+        }
+        Ok(expr)
+    }
+
+    fn value(&mut self) -> Result<Expr, ()> {
+        Ok(match self.next().ok_or(())? {
+            Token::Dot => Expr::Context,
+            Token::Star => Expr::Children(None),
+            Token::String(s) => Expr::String(s.into()),
+            Token::Number(n) => Expr::Number(n),
+            Token::At => match self.next().ok_or(())? {
+                Token::Star => Expr::Attribute(None),
+                Token::Name(name) => Expr::Attribute(Some(local_name(name).into())),
+                _ => return Err(()),
+            },
+            Token::Open => {
+                let expr = self.or()?;
+                if self.next() != Some(Token::Close) {
+                    return Err(());
+                }
+                expr
+            },
+            Token::Name(name) if self.peek() == Some(&Token::Open) => {
+                self.pos += 1;
+                let function = Function::from_name(name).ok_or(())?;
+                let mut args = Vec::new();
+                if self.peek() == Some(&Token::Close) {
+                    self.pos += 1;
+                } else {
+                    loop {
+                        args.push(self.or()?);
+                        match self.next() {
+                            Some(Token::Comma) => {},
+                            Some(Token::Close) => break,
+                            _ => return Err(()),
+                        }
+                    }
+                }
+                let (min, max) = function.arity();
+                if args.len() < min || args.len() > max {
+                    return Err(());
+                }
+                if matches!(function, Function::Count | Function::Sum) && !matches!(args[0], Expr::Attribute(_) | Expr::Children(_) | Expr::Context) {
+                    return Err(());
+                }
+                Expr::Function(function, args)
+            },
+            Token::Name(name) => Expr::Children(Some(local_name(name).into())),
+            _ => return Err(()),
+        })
+    }
+}
+
+/// Result of an expression
+#[derive(Clone, Debug)]
+enum Value {
+    /// String values of selected nodes
+    Nodes(Vec<String>),
+    String(String),
+    Number(f64),
+    Boolean(bool),
+}
+
+impl Expr {
+    fn parse(source: &str) -> Result<Self, String> {
+        let unsupported = || format!("Unsupported XPath expression: '{source}'");
+        let tokens = tokenize(source).map_err(|_| unsupported())?;
+        let mut parser = ExprParser { tokens, pos: 0 };
+        let expr = parser.or().map_err(|_| unsupported())?;
+        if parser.pos != parser.tokens.len() {
+            return Err(unsupported());
+        }
+        Ok(expr)
+    }
+
+    fn eval(&self, frame: &Frame) -> Value {
+        match self {
+            Expr::Context => Value::Nodes(vec![frame.text.clone()]),
+            Expr::Attribute(name) => Value::Nodes(frame.attributes.iter()
+                .filter(|a| name.as_ref().map_or(true, |n| *n == a.name.local_name))
+                .map(|a| a.value.clone())
+                .collect()),
+            Expr::Children(name) => Value::Nodes(frame.children.iter()
+                .filter(|(local_name, _)| name.as_ref().map_or(true, |n| n == local_name))
+                .map(|(_, text)| text.clone())
+                .collect()),
+            Expr::String(s) => Value::String(s.clone()),
+            Expr::Number(n) => Value::Number(*n),
+            Expr::Or(a, b) => Value::Boolean(a.eval(frame).to_bool() || b.eval(frame).to_bool()),
+            Expr::And(a, b) => Value::Boolean(a.eval(frame).to_bool() && b.eval(frame).to_bool()),
+            Expr::Compare(a, op, b) => Value::Boolean(compare(&a.eval(frame), *op, &b.eval(frame))),
+            Expr::Function(function, args) => {
+                let args: Vec<_> = args.iter().map(|arg| arg.eval(frame)).collect();
+                // functions taking one string use the context if the argument is omitted
+                let string_arg = |i: usize| args.get(i).map_or_else(|| frame.text.clone(), Value::to_string);
+                match function {
+                    Function::Not => Value::Boolean(!args[0].to_bool()),
+                    Function::True => Value::Boolean(true),
+                    Function::False => Value::Boolean(false),
+                    Function::Count => Value::Number(match &args[0] {
+                        Value::Nodes(nodes) => nodes.len() as f64,
+                        _ => f64::NAN,
+                    }),
+                    Function::Sum => Value::Number(match &args[0] {
+                        Value::Nodes(nodes) => nodes.iter().map(|s| to_number(s)).sum(),
+                        _ => f64::NAN,
+                    }),
+                    Function::Number => match args.first() {
+                        Some(arg) => Value::Number(arg.to_number()),
+                        None => Value::Number(to_number(&frame.text)),
+                    },
+                    Function::String => Value::String(string_arg(0)),
+                    Function::StringLength => Value::Number(string_arg(0).chars().count() as f64),
+                    Function::NormalizeSpace => Value::String(normalize_space(&string_arg(0))),
+                    Function::Concat => Value::String(args.iter().map(Value::to_string).collect()),
+                    Function::Contains => Value::Boolean(string_arg(0).contains(&string_arg(1))),
+                    Function::StartsWith => Value::Boolean(string_arg(0).starts_with(&string_arg(1))),
+                }
+            },
+        }
+    }
+}
+
+fn to_number(s: &str) -> f64 {
+    s.trim_matches(is_whitespace_char).parse().unwrap_or(f64::NAN)
+}
+
+impl Value {
+    fn to_bool(&self) -> bool {
+        match self {
+            Value::Nodes(nodes) => !nodes.is_empty(),
+            Value::String(s) => !s.is_empty(),
+            Value::Number(n) => *n != 0. && !n.is_nan(),
+            Value::Boolean(b) => *b,
+        }
+    }
+
+    fn to_number(&self) -> f64 {
+        match self {
+            Value::Number(n) => *n,
+            Value::Boolean(b) => if *b { 1. } else { 0. },
+            _ => to_number(&self.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Nodes(nodes) => f.write_str(nodes.first().map_or("", |s| s.as_str())),
+            Value::String(s) => f.write_str(s),
+            Value::Number(n) if n.is_nan() => f.write_str("NaN"),
+            Value::Number(n) => write!(f, "{n}"),
+            Value::Boolean(b) => write!(f, "{b}"),
+        }
+    }
+}
+
+/// Comparison with the rules of XPath 1.0, where node-sets compare true if any of their nodes does
+fn compare(a: &Value, op: CompareOp, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Nodes(nodes), Value::Boolean(_)) => compare(&Value::Boolean(!nodes.is_empty()), op, b),
+        (Value::Boolean(_), Value::Nodes(nodes)) => compare(a, op, &Value::Boolean(!nodes.is_empty())),
+        (Value::Nodes(nodes), _) => nodes.iter().any(|n| compare(&Value::String(n.clone()), op, b)),
+        (_, Value::Nodes(nodes)) => nodes.iter().any(|n| compare(a, op, &Value::String(n.clone()))),
+        _ => match op {
+            CompareOp::Eq | CompareOp::Ne => {
+                let equal = match (a, b) {
+                    (Value::Boolean(_), _) | (_, Value::Boolean(_)) => a.to_bool() == b.to_bool(),
+                    (Value::Number(_), _) | (_, Value::Number(_)) => a.to_number() == b.to_number(),
+                    _ => a.to_string() == b.to_string(),
+                };
+                equal == (op == CompareOp::Eq)
+            },
+            CompareOp::Lt => a.to_number() < b.to_number(),
+            CompareOp::Le => a.to_number() <= b.to_number(),
+            CompareOp::Gt => a.to_number() > b.to_number(),
+            CompareOp::Ge => a.to_number() >= b.to_number(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Schema;
+    use crate::reader::EventReader;
+
+    fn messages(rules: &str, doc: &str) -> Vec<String> {
+        let schema: Schema = format!(r#"<sch:schema xmlns:sch="http://purl.oclc.org/dsdl/schematron"><sch:pattern>{rules}</sch:pattern></sch:schema>"#)
+            .parse().unwrap();
+        schema.validate(EventReader::from_str(doc)).unwrap().into_iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn contexts() {
+        let rules = r#"<sch:rule context="/a/b"><sch:report test="true()">abs</sch:report></sch:rule>
+            <sch:rule context="a//c | d"><sch:report test="true()">desc</sch:report></sch:rule>
+            <sch:rule context="*"><sch:report test="@x">any</sch:report></sch:rule>"#;
+        let doc = "<a><b/><x:b xmlns:x='urn:x' x='1'><c/></x:b><d/></a>";
+        assert_eq!(messages(rules, doc), [
+            "1:4 <b>: abs",
+            "1:35 <c>: desc",
+            "1:8 <x:b>: abs",
+            "1:45 <d>: desc",
+        ]);
+    }
+
+    #[test]
+    fn expressions() {
+        let doc = r#"<order currency="EUR"><item price="2.5">pen</item><item price="10">book</item><note>  a   b </note></order>"#;
+        let report = |test: &str| !messages(&format!(r#"<sch:rule context="order"><sch:report test="{test}">x</sch:report></sch:rule>"#), doc).is_empty();
+        assert!(report("@currency = 'EUR' and not(@vat)"));
+        assert!(report("count(item) = 2 and count(*) = 3 and count(@*) = 1"));
+        assert!(!report("sum(item) = 0"));
+        assert!(report("item = 'book' and item != 'book'"));
+        assert!(report("normalize-space(note) = 'a b'"));
+        assert!(report("string-length(@currency) = 3 or false()"));
+        assert!(report("contains(., 'penbook') and starts-with(concat(@currency, '-', 1), 'EUR-1')"));
+        assert!(report("(@missing or true()) and number('x') != number('x')"));
+        assert!(!report("@currency > 1"));
+        assert!(!report("item = 'pencil'"));
+
+        let prices = r#"<sch:rule context="item"><sch:assert test="number(@price) &lt;= 5" role="warning"><sch:name/> <sch:value-of select="."/> costs <sch:value-of select="@price"/></sch:assert></sch:rule>"#;
+        assert_eq!(messages(prices, doc), ["1:51 <item>: item book costs 10"]);
+    }
+
+    #[test]
+    fn invalid() {
+        for rules in [
+            r#"<sch:rule context="a[1]"/>"#,
+            r#"<sch:rule context="a"><sch:assert test="../b"/></sch:rule>"#,
+            r#"<sch:rule context="a"><sch:assert test="$x"/></sch:rule>"#,
+            r#"<sch:rule context="a"><sch:assert test="count('x')"/></sch:rule>"#,
+            r#"<sch:rule context="a"><sch:assert/></sch:rule>"#,
+            r#"<sch:rule><sch:assert test="1"/></sch:rule>"#,
+        ] {
+            let schema = format!(r#"<sch:schema xmlns:sch="http://purl.oclc.org/dsdl/schematron"><sch:pattern>{rules}</sch:pattern></sch:schema>"#);
+            assert!(schema.parse::<Schema>().is_err(), "{rules}");
+        }
+        assert!("<schema/>".parse::<Schema>().is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! Helpers for checking that events survive writing and parsing back.
+//!
+//! The same rules are used by the crate's own tests, so crates that transform event streams
+//! can check that their output is stable with [`roundtrip`].
+
+use std::error::Error;
+use std::fmt;
+
+use crate::namespace::Namespace;
+use crate::reader::{self, ParserConfig, XmlEvent};
+use crate::writer::{self, EmitterConfig};
+
+/// Reason why [`roundtrip`] failed
+#[derive(Debug)]
+pub enum RoundtripError {
+    /// The writer rejected the events
+    Write(writer::Error),
+    /// The written document could not be parsed
+    Read(reader::Error),
+    /// Normalized events differ
+    Mismatch {
+        /// Index in the normalized event list
+        index: usize,
+        /// Event from the input, `None` if the parsed document has more events
+        expected: Option<Box<XmlEvent>>,
+        /// Parsed event, `None` if the parsed document has fewer events
+        found: Option<Box<XmlEvent>>,
+    },
+}
+
+impl fmt::Display for RoundtripError {
+    #[cold]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoundtripError::Write(e) => write!(f, "can't write events: {e}"),
+            RoundtripError::Read(e) => write!(f, "can't parse written document: {e}"),
+            RoundtripError::Mismatch { index, expected, found } => {
+                write!(f, "event {index} differs: expected {expected:?}, found {found:?}")
+            },
+        }
+    }
+}
+
+impl Error for RoundtripError {
+}
+
+/// Writes the events, parses the result, and checks that the parsed events are the same.
+///
+/// Both event streams are compared after [`normalize`], so differences in formatting that
+/// XML doesn't preserve are not reported. The output of the writer is returned for inspection.
+///
+/// ```rust
+/// use xml::reader::XmlEvent;
+/// use xml::name::OwnedName;
+///
+/// let events = [
+///     XmlEvent::StartElement { name: OwnedName::local("a"), attributes: Default::default(), namespace: xml::namespace::Namespace::empty() },
+///     XmlEvent::Characters("<&>".into()),
+///     XmlEvent::EndElement { name: OwnedName::local("a") },
+/// ];
+/// xml::testing::roundtrip(&events).unwrap();
+/// ```
+pub fn roundtrip(events: &[XmlEvent]) -> Result<Vec<u8>, RoundtripError> {
+    let mut out = Vec::new();
+    let mut w = EmitterConfig::new()
+        .autopad_comments(false)
+        .create_writer(&mut out);
+    for e in events {
+        let e = match *e {
+            // the writer always outputs UTF-8
+            XmlEvent::StartDocument { version, standalone, .. } => writer::XmlEvent::StartDocument { version, encoding: None, standalone },
+            ref e => match e.as_writer_event() {
+                Some(e) => e,
+                None => continue,
+            },
+        };
+        w.write(e).map_err(RoundtripError::Write)?;
+    }
+
+    let parsed = ParserConfig::new()
+        .ignore_comments(false)
+        .coalesce_characters(true)
+        .create_reader(&out[..])
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(RoundtripError::Read)?;
+
+    let expected = normalize(events.iter().cloned());
+    let found = normalize(parsed);
+    if expected != found {
+        let index = expected.iter().zip(&found).take_while(|(e, f)| e == f).count();
+        return Err(RoundtripError::Mismatch {
+            index,
+            expected: expected.get(index).cloned().map(Box::new),
+            found: found.get(index).cloned().map(Box::new),
+        });
+    }
+    Ok(out)
+}
+
+/// Makes event streams comparable, using the rules of [`roundtrip`]:
+///
+/// * `StartDocument` and `EndDocument` are removed, because the writer may add the declaration,
+/// * `Whitespace` becomes `Characters`, adjacent `Characters` are merged, and empty ones removed,
+/// * namespace mappings of `StartElement` are cleared, since names already have their namespace URIs,
+/// * empty processing instruction data becomes `None`.
+pub fn normalize(events: impl IntoIterator<Item = XmlEvent>) -> Vec<XmlEvent> {
+    let mut out: Vec<XmlEvent> = Vec::new();
+    for e in events {
+        let e = match e {
+            XmlEvent::StartDocument { .. } | XmlEvent::EndDocument => continue,
+            XmlEvent::Characters(s) | XmlEvent::Whitespace(s) => {
+                if let Some(XmlEvent::Characters(prev)) = out.last_mut() {
+                    prev.push_str(&s);
+                    continue;
+                }
+                if s.is_empty() {
+                    continue;
+                }
+                XmlEvent::Characters(s)
+            },
+            XmlEvent::StartElement { name, attributes, .. } => {
+                XmlEvent::StartElement { name, attributes, namespace: Namespace::empty() }
+            },
+            XmlEvent::ProcessingInstruction { name, data } => XmlEvent::ProcessingInstruction {
+                name,
+                data: data.filter(|d| !d.is_empty()),
+            },
+            e => e,
+        };
+        out.push(e);
+    }
+    out
+}
@@ -97,31 +97,80 @@ impl fmt::Display for Encoding {
     }
 }
 
+#[derive(Clone)]
 pub(crate) struct CharReader {
     pub encoding: Encoding,
+    /// Total number of bytes taken from the source
+    pub bytes_read: u64,
+    /// Bytes taken since the last `take_observed_bytes`, if they're observed
+    observed: Option<Vec<u8>>,
+    /// Bytes of a char that was interrupted by an I/O error, to continue with on the next call
+    partial: ([u8; 4], usize),
+    /// Bytes before the first `<` are dropped
+    pub skip_until_lt: bool,
+    /// Number of bytes dropped by `skip_until_lt`
+    pub skipped_bytes: u64,
 }
 
 impl CharReader {
     pub fn new() -> Self {
         Self {
             encoding: Encoding::Unknown,
+            bytes_read: 0,
+            observed: None,
+            partial: ([0; 4], 0),
+            skip_until_lt: false,
+            skipped_bytes: 0,
         }
     }
 
+    /// Some bytes of a char have been taken, but not the whole char
+    pub fn has_partial_char(&self) -> bool {
+        self.partial.1 != 0
+    }
+
+    /// Keep the bytes taken from the source for `take_observed_bytes`
+    pub fn observe_bytes(&mut self) {
+        self.observed = Some(Vec::new());
+    }
+
+    /// Bytes taken since the last call, if `observe_bytes` is enabled
+    pub fn take_observed_bytes(&mut self) -> Option<&mut Vec<u8>> {
+        self.observed.as_mut().filter(|bytes| !bytes.is_empty())
+    }
+
     pub fn next_char_from<R: Read>(&mut self, source: &mut R) -> Result<Option<char>, CharReadError> {
         let mut bytes = source.bytes();
         const MAX_CODEPOINT_LEN: usize = 4;
 
-        let mut buf = [0u8; MAX_CODEPOINT_LEN];
-        let mut pos = 0;
+        let (mut buf, mut pos) = std::mem::replace(&mut self.partial, ([0u8; MAX_CODEPOINT_LEN], 0));
         loop {
             let next = match bytes.next() {
-                Some(Ok(b)) => b,
-                Some(Err(e)) => return Err(e.into()),
+                Some(Ok(b)) => {
+                    self.bytes_read += 1;
+                    if let Some(observed) = &mut self.observed {
+                        observed.push(b);
+                    }
+                    b
+                },
+                Some(Err(e)) => {
+                    self.partial = (buf, pos);
+                    return Err(e.into());
+                },
                 None if pos == 0 => return Ok(None),
                 None => return Err(CharReadError::UnexpectedEof),
             };
 
+            if self.skip_until_lt && pos == 0 {
+                if next == b'<' || matches!(self.encoding, Encoding::Utf16 | Encoding::Utf16Be | Encoding::Utf16Le) {
+                    self.skip_until_lt = false;
+                } else if self.encoding != Encoding::Unknown || ![0xEF, 0xFE, 0xFF].contains(&next) {
+                    // BOM is still sniffed until the first `<`
+                    self.skipped_bytes += 1;
+                    continue;
+                }
+            }
+
             match self.encoding {
                 Encoding::Utf8 | Encoding::Default => {
                     // fast path for ASCII subset
@@ -171,6 +220,15 @@ impl CharReader {
                     } else if pos == 1 && self.encoding == Encoding::Utf16 {
                         // sniff ASCII char in UTF-16
                         self.encoding = if next == 0 { Encoding::Utf16Be } else { Encoding::Utf16Le };
+                    } else if self.skip_until_lt && next != b'<' {
+                        // not a BOM after all
+                        self.skipped_bytes += pos as u64;
+                        pos = 0;
+                    } else if self.skip_until_lt {
+                        self.skipped_bytes += pos as u64 - 1;
+                        self.skip_until_lt = false;
+                        self.encoding = Encoding::Default;
+                        return Ok(Some('<'));
                     } else {
                         // UTF-8 is the default, but XML decl can change it to other 8-bit encoding
                         self.encoding = Encoding::Default;
@@ -252,22 +310,22 @@ mod tests {
         assert_eq!(CharReader::new().next_char_from(&mut bytes).unwrap(), Some('п'));
 
         let mut bytes: &[u8] = "правильно".as_bytes();
-        assert_eq!(CharReader { encoding: Encoding::Utf16Be }.next_char_from(&mut bytes).unwrap(), Some('킿'));
+        assert_eq!(CharReader { encoding: Encoding::Utf16Be, ..CharReader::new() }.next_char_from(&mut bytes).unwrap(), Some('킿'));
 
         let mut bytes: &[u8] = "правильно".as_bytes();
-        assert_eq!(CharReader { encoding: Encoding::Utf16Le }.next_char_from(&mut bytes).unwrap(), Some('뿐'));
+        assert_eq!(CharReader { encoding: Encoding::Utf16Le, ..CharReader::new() }.next_char_from(&mut bytes).unwrap(), Some('뿐'));
 
         let mut bytes: &[u8] = b"\xD8\xD8\x80";
-        assert!(CharReader { encoding: Encoding::Utf16 }.next_char_from(&mut bytes).is_err());
+        assert!(CharReader { encoding: Encoding::Utf16, ..CharReader::new() }.next_char_from(&mut bytes).is_err());
 
         let mut bytes: &[u8] = b"\x00\x42";
-        assert_eq!(CharReader { encoding: Encoding::Utf16 }.next_char_from(&mut bytes).unwrap(), Some('B'));
+        assert_eq!(CharReader { encoding: Encoding::Utf16, ..CharReader::new() }.next_char_from(&mut bytes).unwrap(), Some('B'));
 
         let mut bytes: &[u8] = b"\x42\x00";
-        assert_eq!(CharReader { encoding: Encoding::Utf16 }.next_char_from(&mut bytes).unwrap(), Some('B'));
+        assert_eq!(CharReader { encoding: Encoding::Utf16, ..CharReader::new() }.next_char_from(&mut bytes).unwrap(), Some('B'));
 
         let mut bytes: &[u8] = b"\x00";
-        assert!(CharReader { encoding: Encoding::Utf16Be }.next_char_from(&mut bytes).is_err());
+        assert!(CharReader { encoding: Encoding::Utf16Be, ..CharReader::new() }.next_char_from(&mut bytes).is_err());
 
         let mut bytes: &[u8] = "😊".as_bytes();          // correct non-BMP
         assert_eq!(CharReader::new().next_char_from(&mut bytes).unwrap(), Some('😊'));
@@ -0,0 +1,151 @@
+//! A generic tree of a document's elements and text, for when defining types or keeping
+//! a full DOM would be too much.
+//!
+//! With the `serde` feature the tree is `Serialize`/`Deserialize`. Text is a plain string,
+//! and elements are maps, so it converts naturally to JSON and similar formats.
+//!
+//! ```rust
+//! use xml::value::Value;
+//!
+//! let value = xml::to_value(xml::EventReader::from_str(r#"<a k="v"><b>x</b>y</a>"#)).unwrap();
+//! let a = value.as_element().unwrap();
+//! assert_eq!(a.attributes["k"], "v");
+//! assert_eq!(a.children[0].as_element().unwrap().text(), "x");
+//! assert_eq!(a.children[1], Value::Text("y".into()));
+//! ```
+
+use std::collections::BTreeMap;
+use std::io::Read;
+
+use crate::reader::{self, EventReader, XmlEvent};
+
+/// Element or text in the tree
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(untagged))]
+pub enum Value {
+    /// Text content, including CDATA. Adjacent text is merged.
+    Text(String),
+    /// A child element
+    Element(Element),
+}
+
+impl Value {
+    /// The element, if this isn't text
+    #[must_use]
+    pub fn as_element(&self) -> Option<&Element> {
+        match self {
+            Self::Element(e) => Some(e),
+            Self::Text(_) => None,
+        }
+    }
+
+    /// The text, if this isn't an element
+    #[must_use]
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Self::Text(t) => Some(t),
+            Self::Element(_) => None,
+        }
+    }
+}
+
+/// An element with its attributes and content
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Element {
+    /// Local name, without the prefix
+    pub name: String,
+    /// Prefix as written in the document
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub prefix: Option<String>,
+    /// Namespace URI that the name has been resolved to
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub namespace: Option<String>,
+    /// Attribute values by their name as written, e.g. `"xml:lang"`
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "BTreeMap::is_empty"))]
+    pub attributes: BTreeMap<String, String>,
+    /// Namespace URIs by the prefix (empty for the default namespace) declared on this element
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "BTreeMap::is_empty"))]
+    pub namespaces: BTreeMap<String, String>,
+    /// Content in order
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Vec::is_empty"))]
+    pub children: Vec<Value>,
+}
+
+impl Element {
+    /// Child elements with the given local name
+    pub fn elements<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter_map(Value::as_element).filter(move |e| e.name == name)
+    }
+
+    /// All text of this element and its descendants
+    #[must_use]
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        self.push_text(&mut out);
+        out
+    }
+
+    fn push_text(&self, out: &mut String) {
+        for child in &self.children {
+            match child {
+                Value::Text(t) => out.push_str(t),
+                Value::Element(e) => e.push_text(out),
+            }
+        }
+    }
+}
+
+/// Reads the whole document, and returns its root element as a [`Value::Element`].
+///
+/// Comments, processing instructions and the doctype are not kept.
+/// Whitespace is kept or dropped depending on the reader's configuration.
+pub fn to_value<R: Read>(mut reader: EventReader<R>) -> reader::Result<Value> {
+    let mut stack: Vec<Element> = Vec::new();
+    let mut in_scope = vec![BTreeMap::new()];
+    let mut root = None;
+    loop {
+        match reader.next()? {
+            XmlEvent::StartElement { name, attributes, namespace } => {
+                let namespace: BTreeMap<_, _> = namespace.0.into_iter().collect();
+                let parent = in_scope.last().unwrap();
+                let namespaces = namespace.iter()
+                    .filter(|&(prefix, uri)| parent.get(prefix) != Some(uri))
+                    .filter(|&(prefix, _)| prefix != "xml" && prefix != "xmlns")
+                    .map(|(prefix, uri)| (prefix.clone(), uri.clone()))
+                    .collect();
+                in_scope.push(namespace);
+                stack.push(Element {
+                    name: name.local_name.into(),
+                    prefix: name.prefix.map(String::from),
+                    namespace: name.namespace.map(String::from),
+                    attributes: attributes.into_iter().map(|a| (a.name.borrow().to_repr(), a.value)).collect(),
+                    namespaces,
+                    children: Vec::new(),
+                });
+            },
+            XmlEvent::EndElement { .. } => {
+                in_scope.pop();
+                if let Some(element) = stack.pop() {
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(Value::Element(element)),
+                        None => root = Some(element),
+                    }
+                }
+            },
+            XmlEvent::Characters(text) | XmlEvent::CData(text) | XmlEvent::Whitespace(text) => {
+                if let Some(parent) = stack.last_mut() {
+                    if let Some(Value::Text(last)) = parent.children.last_mut() {
+                        last.push_str(&text);
+                    } else {
+                        parent.children.push(Value::Text(text));
+                    }
+                }
+            },
+            XmlEvent::EndDocument => break,
+            _ => {},
+        }
+    }
+    // the parser doesn't end a document without a root element
+    Ok(Value::Element(root.unwrap_or_default()))
+}
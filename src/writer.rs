@@ -3,24 +3,45 @@
 //! The most important type in this module is `EventWriter` which allows writing an XML document
 //! to some output stream.
 
-pub use self::config::EmitterConfig;
+pub use self::config::{EmitterConfig, NewlineStyle};
 pub use self::emitter::EmitterError as Error;
+pub use self::emitter::OutputPosition;
 pub use self::emitter::Result;
 pub use self::events::XmlEvent;
 
 use self::emitter::Emitter;
+use crate::binary::BinaryEncoding;
+use crate::common::EventCounts;
+use crate::name::{Name, OwnedName};
 
+use std::fmt;
+use std::io;
 use std::io::prelude::*;
 
 mod config;
 mod emitter;
 pub mod events;
 
+/// Statistics about the output written so far, returned by [`EventWriter::stats`].
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct WriterStats {
+    /// Bytes written to the sink
+    pub bytes_written: u64,
+    /// Events written by the writer
+    pub events: EventCounts,
+    /// Deepest nesting of elements
+    pub max_depth: usize,
+}
+
 /// A wrapper around an `std::io::Write` instance which emits XML document according to provided
 /// events.
 pub struct EventWriter<W> {
     sink: W,
     emitter: Emitter,
+    /// Bytes written since the last automatic flush
+    unflushed_bytes: usize,
+    stats: WriterStats,
 }
 
 impl<W: Write> EventWriter<W> {
@@ -38,6 +59,8 @@ impl<W: Write> EventWriter<W> {
         EventWriter {
             sink,
             emitter: Emitter::new(config),
+            unflushed_bytes: 0,
+            stats: WriterStats::default(),
         }
     }
 
@@ -49,24 +72,310 @@ impl<W: Write> EventWriter<W> {
     /// Another example is that `XmlEvent::CData` may be represented as characters in
     /// the output stream.
     pub fn write<'a, E>(&mut self, event: E) -> Result<()> where E: Into<XmlEvent<'a>> {
-        match event.into() {
-            XmlEvent::StartDocument { version, encoding, standalone } =>
-                self.emitter.emit_start_document(&mut self.sink, version, encoding.unwrap_or("UTF-8"), standalone),
-            XmlEvent::ProcessingInstruction { name, data } =>
-                self.emitter.emit_processing_instruction(&mut self.sink, name, data),
-            XmlEvent::StartElement { name, attributes, namespace } => {
-                self.emitter.namespace_stack_mut().push_empty().checked_target().extend(namespace.as_ref());
-                self.emitter.emit_start_element(&mut self.sink, name, &attributes)
-            },
-            XmlEvent::EndElement { name } => {
-                let r = self.emitter.emit_end_element(&mut self.sink, name);
-                self.emitter.namespace_stack_mut().try_pop();
-                r
-            },
-            XmlEvent::Comment(content) => self.emitter.emit_comment(&mut self.sink, content),
-            XmlEvent::CData(content) => self.emitter.emit_cdata(&mut self.sink, content),
-            XmlEvent::Characters(content) => self.emitter.emit_characters(&mut self.sink, content),
+        let event = event.into();
+        let events = &mut self.stats.events;
+        match event {
+            XmlEvent::ProcessingInstruction { .. } => events.processing_instructions += 1,
+            XmlEvent::StartElement { .. } => events.elements += 1,
+            XmlEvent::Comment(_) => events.comments += 1,
+            XmlEvent::CData(_) => events.cdata += 1,
+            XmlEvent::Characters(_) => events.characters += 1,
+            _ => {},
+        }
+        let closed_element = matches!(event, XmlEvent::EndElement { .. });
+        let mut sink = CountingWriter { inner: &mut self.sink, written: 0 };
+        let res = self.emitter.emit_event(&mut sink, event);
+        self.stats.max_depth = self.stats.max_depth.max(self.emitter.depth());
+        let written = sink.written;
+        self.stats.bytes_written += written as u64;
+        self.unflushed_bytes += written;
+        res.and_then(|()| self.auto_flush(closed_element))
+            .map_err(|e| self.with_position(e))
+    }
+
+    /// Adds the output position to the error, if `error_positions` is enabled
+    #[cold]
+    fn with_position(&self, error: Error) -> Error {
+        if !self.emitter.config().error_positions || matches!(error, Error::Positioned { .. }) {
+            return error;
+        }
+        Error::Positioned {
+            error: Box::new(error),
+            position: Box::new(OutputPosition {
+                bytes_written: self.stats.bytes_written,
+                element_path: self.emitter.element_names().to_vec(),
+            }),
+        }
+    }
+
+    fn auto_flush(&mut self, closed_element: bool) -> Result<()> {
+        let config = self.emitter.config();
+        // depth of the closed element is one more than the depth of its parent
+        let flush_depth = closed_element && config.auto_flush_depth.map_or(false, |d| self.emitter.depth() < d);
+        let flush_bytes = config.auto_flush_bytes.map_or(false, |n| self.unflushed_bytes >= n);
+        if flush_depth || flush_bytes {
+            self.unflushed_bytes = 0;
+            self.sink.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Returns an adapter that writes character content, escaped like the `Characters` event,
+    /// but without having all of it in memory, e.g. for a large base64-encoded blob.
+    ///
+    /// The bytes must be UTF-8, but can be split at any point. The text ends when the adapter
+    /// is dropped, and [`TextWriter::finish`] also reports errors, and a UTF-8 sequence that was left incomplete.
+    ///
+    /// ```rust
+    /// use std::io::Write;
+    /// use xml::writer::{EmitterConfig, XmlEvent};
+    ///
+    /// let mut out = Vec::new();
+    /// let mut writer = EmitterConfig::new().write_document_declaration(false).create_writer(&mut out);
+    /// writer.write(XmlEvent::start_element("data")).unwrap();
+    /// let mut text = writer.text_writer();
+    /// for _ in 0..3 {
+    ///     text.write_all(b"<>").unwrap();
+    /// }
+    /// text.finish().unwrap();
+    /// writer.write(XmlEvent::end_element()).unwrap();
+    /// assert_eq!(String::from_utf8(out).unwrap(), "<data>&lt;&gt;&lt;&gt;&lt;&gt;</data>");
+    /// ```
+    pub fn text_writer(&mut self) -> TextWriter<'_, W> {
+        TextWriter { writer: self, partial: ([0; 4], 0), started: false, cdata: None }
+    }
+
+    /// Returns an adapter that writes a CDATA section, like [`text_writer`](Self::text_writer) does for text.
+    ///
+    /// `]]>` in the content is split into two adjacent CDATA sections, even if it's split between writes.
+    /// The section is closed when the adapter is dropped or [finished](TextWriter::finish).
+    /// If `cdata_to_characters` is enabled, it writes escaped text instead.
+    ///
+    /// ```rust
+    /// use std::io::Write;
+    /// use xml::writer::{EmitterConfig, XmlEvent};
+    ///
+    /// let mut out = Vec::new();
+    /// let mut writer = EmitterConfig::new().write_document_declaration(false).create_writer(&mut out);
+    /// writer.write(XmlEvent::start_element("script")).unwrap();
+    /// let mut cdata = writer.cdata_writer();
+    /// cdata.write_all(b"a[0]]").unwrap();
+    /// cdata.write_all(b"> b").unwrap();
+    /// cdata.finish().unwrap();
+    /// writer.write(XmlEvent::end_element()).unwrap();
+    /// assert_eq!(String::from_utf8(out).unwrap(), "<script><![CDATA[a[0]]]]><![CDATA[> b]]></script>");
+    /// ```
+    pub fn cdata_writer(&mut self) -> TextWriter<'_, W> {
+        let cdata = if self.emitter.config().cdata_to_characters { None } else { Some(0) };
+        TextWriter { writer: self, partial: ([0; 4], 0), started: false, cdata }
+    }
+
+    /// Writes all bytes from the reader as base64-encoded character content, without line breaks.
+    ///
+    /// The data is encoded and written in chunks. Returns the number of bytes read.
+    ///
+    /// ```rust
+    /// use xml::writer::{EmitterConfig, XmlEvent};
+    ///
+    /// let mut out = Vec::new();
+    /// let mut writer = EmitterConfig::new().write_document_declaration(false).create_writer(&mut out);
+    /// writer.write(XmlEvent::start_element("blob")).unwrap();
+    /// writer.write_base64(&b"hello"[..]).unwrap();
+    /// writer.write(XmlEvent::end_element()).unwrap();
+    /// assert_eq!(String::from_utf8(out).unwrap(), "<blob>aGVsbG8=</blob>");
+    /// ```
+    pub fn write_base64(&mut self, reader: impl Read) -> Result<u64> {
+        self.write_binary(reader, BinaryEncoding::Base64)
+    }
+
+    /// Writes all bytes from the reader as hex-encoded character content (like `xs:hexBinary`).
+    ///
+    /// Returns the number of bytes read.
+    pub fn write_hex(&mut self, reader: impl Read) -> Result<u64> {
+        self.write_binary(reader, BinaryEncoding::Hex)
+    }
+
+    fn write_binary(&mut self, mut reader: impl Read, encoding: BinaryEncoding) -> Result<u64> {
+        let mut buf = vec![0; 3 << 12];
+        let mut filled = 0;
+        let mut total = 0;
+        let mut text = String::new();
+        loop {
+            let n = match reader.read(&mut buf[filled..]) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            filled += n;
+            total += n as u64;
+            // at the end the rest is padded
+            let whole = if n == 0 { filled } else { encoding.whole_len(filled) };
+            text.clear();
+            encoding.encode(&buf[..whole], &mut text);
+            if !text.is_empty() {
+                self.emit(|e, sink| e.emit_characters(sink, &text))?;
+            }
+            buf.copy_within(whole..filled, 0);
+            filled -= whole;
+            if n == 0 {
+                break;
+            }
         }
+        if total > 0 {
+            self.stats.events.characters += 1;
+        }
+        Ok(total)
+    }
+
+    /// Counts the output of the emitter, and flushes if it's over the limit
+    fn emit(&mut self, emit: impl FnOnce(&mut Emitter, &mut CountingWriter<'_, W>) -> Result<()>) -> Result<()> {
+        let mut sink = CountingWriter { inner: &mut self.sink, written: 0 };
+        let res = emit(&mut self.emitter, &mut sink);
+        self.stats.bytes_written += sink.written as u64;
+        self.unflushed_bytes += sink.written;
+        res.and_then(|()| self.auto_flush(false))
+            .map_err(|e| self.with_position(e))
+    }
+
+    /// Ends the current document, and allows writing another one to the same sink,
+    /// e.g. for a stream of concatenated documents.
+    ///
+    /// The next document can have its own declaration, DOCTYPE and root element. All elements
+    /// must be closed first. Documents are separated by the configured line separator.
+    ///
+    /// ```rust
+    /// use xml::writer::{EmitterConfig, XmlEvent};
+    ///
+    /// let mut out = Vec::new();
+    /// let mut writer = EmitterConfig::new().create_writer(&mut out);
+    /// for name in ["a", "b"] {
+    ///     writer.write(XmlEvent::start_element(name)).unwrap();
+    ///     writer.write(XmlEvent::end_element()).unwrap();
+    ///     writer.start_new_document().unwrap();
+    /// }
+    /// assert_eq!(String::from_utf8(out).unwrap(), "<?xml version=\"1.0\" encoding=\"utf-8\"?><a />\n<?xml version=\"1.0\" encoding=\"utf-8\"?><b />\n");
+    /// ```
+    pub fn start_new_document(&mut self) -> Result<()> {
+        self.emit(|e, sink| e.start_new_document(sink))
+    }
+
+    /// Writes an `<xop:Include href="cid:…"/>` element, which refers to a binary attachment of
+    /// a [XOP](https://www.w3.org/TR/xop10/) package, like a SOAP message with MTOM.
+    ///
+    /// The content-id is percent-encoded in the `href` URL as needed, and the `xop` prefix
+    /// is declared if it isn't bound to the XOP namespace already.
+    ///
+    /// ```rust
+    /// use xml::writer::{EmitterConfig, XmlEvent};
+    ///
+    /// let mut out = Vec::new();
+    /// let mut writer = EmitterConfig::new().write_document_declaration(false).create_writer(&mut out);
+    /// writer.write(XmlEvent::start_element("photo")).unwrap();
+    /// writer.write_xop_include("part1@example.org").unwrap();
+    /// writer.write(XmlEvent::end_element()).unwrap();
+    /// assert_eq!(String::from_utf8(out).unwrap(),
+    ///     r#"<photo><xop:Include xmlns:xop="http://www.w3.org/2004/08/xop/include" href="cid:part1@example.org" /></photo>"#);
+    /// ```
+    pub fn write_xop_include(&mut self, content_id: &str) -> Result<()> {
+        self.emit(|e, sink| e.emit_xop_include(sink, content_id))
+    }
+
+    /// Adds an attribute to the element started last, with a value made of many pieces.
+    ///
+    /// The pieces are escaped and written one by one, without concatenating them first.
+    /// It must be called right after the `StartElement` event, before its content.
+    ///
+    /// ```rust
+    /// use xml::writer::{EmitterConfig, XmlEvent};
+    ///
+    /// let mut out = Vec::new();
+    /// let mut writer = EmitterConfig::new().write_document_declaration(false).create_writer(&mut out);
+    /// writer.write(XmlEvent::start_element("div").attr("id", "x")).unwrap();
+    /// let classes = ["a", "b&c"];
+    /// writer.write_attribute_streamed("class", classes.iter().flat_map(|c| [" ", *c]).skip(1)).unwrap();
+    /// writer.write(XmlEvent::end_element()).unwrap();
+    /// assert_eq!(String::from_utf8(out).unwrap(), r#"<div id="x" class="a b&amp;c" />"#);
+    /// ```
+    pub fn write_attribute_streamed<'a, 'p>(&mut self, name: impl Into<Name<'a>>, pieces: impl IntoIterator<Item = &'p str>) -> Result<()> {
+        let name = name.into();
+        self.emit(|e, sink| e.emit_attribute_streamed(sink, name, pieces))
+    }
+
+    /// Sets quote characters for the attributes of the next `StartElement` event, in order,
+    /// e.g. from [`EventReader::attribute_details`](crate::EventReader::attribute_details).
+    ///
+    /// `'` selects single quotes. Other characters, and attributes beyond the end of the list, use double quotes.
+    /// Escaping makes the value safe with either quote.
+    pub fn set_next_attribute_quotes(&mut self, quotes: impl IntoIterator<Item = char>) {
+        self.emitter.set_attribute_quotes(quotes.into_iter().collect());
+    }
+
+    /// Writes a part of the document with a modified configuration, and then restores the original one.
+    ///
+    /// This allows e.g. indenting a document, but keeping signed or preformatted content in it exact.
+    /// `keep_element_names_stack` can't be changed this way.
+    ///
+    /// ```rust
+    /// use xml::writer::{EmitterConfig, XmlEvent};
+    ///
+    /// let mut out = Vec::new();
+    /// let mut writer = EmitterConfig::new().perform_indent(true).write_document_declaration(false).create_writer(&mut out);
+    /// writer.write(XmlEvent::start_element("doc")).unwrap();
+    /// writer.with_config(|c| c.perform_indent(false), |w| {
+    ///     w.write(XmlEvent::start_element("pre"))?;
+    ///     w.write(XmlEvent::start_element("b"))?;
+    ///     w.write(XmlEvent::end_element())?;
+    ///     w.write(XmlEvent::end_element())
+    /// }).unwrap();
+    /// writer.write(XmlEvent::end_element()).unwrap();
+    /// assert_eq!(String::from_utf8(out).unwrap(), "<doc><pre><b /></pre>\n</doc>");
+    /// ```
+    pub fn with_config<T>(&mut self, change: impl FnOnce(EmitterConfig) -> EmitterConfig, write: impl FnOnce(&mut Self) -> T) -> T {
+        let original = self.emitter.config().clone();
+        let mut config = change(original.clone());
+        config.keep_element_names_stack = original.keep_element_names_stack;
+        self.emitter.replace_config(config);
+        let result = write(self);
+        self.emitter.replace_config(original);
+        result
+    }
+
+    /// Counts of bytes and events written so far, e.g. for monitoring or capacity planning
+    #[must_use]
+    pub fn stats(&self) -> WriterStats {
+        self.stats.clone()
+    }
+
+    /// Number of currently open elements
+    #[must_use]
+    pub fn depth(&self) -> usize {
+        self.emitter.depth()
+    }
+
+    /// Names of the currently open elements, from the root.
+    ///
+    /// It's empty if [`EmitterConfig::keep_element_names_stack`] is disabled.
+    ///
+    /// ```rust
+    /// use xml::writer::{EventWriter, XmlEvent};
+    ///
+    /// let mut writer = EventWriter::new(Vec::new());
+    /// writer.write(XmlEvent::start_element("feed")).unwrap();
+    /// writer.write(XmlEvent::start_element("entry")).unwrap();
+    /// let path: Vec<_> = writer.current_element_path().iter().map(|name| name.local_name.as_str()).collect();
+    /// assert_eq!(path, ["feed", "entry"]);
+    /// assert_eq!(writer.depth(), 2);
+    /// ```
+    #[must_use]
+    pub fn current_element_path(&self) -> &[OwnedName] {
+        self.emitter.element_names()
+    }
+
+    /// Anything has been written to the current document, i.e. since the writer was created
+    /// or since the last [`start_new_document`](Self::start_new_document)
+    #[must_use]
+    pub fn has_open_document(&self) -> bool {
+        self.emitter.has_open_document()
     }
 
     /// Returns a mutable reference to the underlying `Writer`.
@@ -75,10 +384,37 @@ impl<W: Write> EventWriter<W> {
     /// documents. Use this method with care. Valid use cases for this method include accessing
     /// methods like `Write::flush`, which do not emit new data but rather change the state
     /// of the stream itself.
+    ///
+    /// If [`normalize_empty_elements`](EmitterConfig::normalize_empty_elements) is disabled,
+    /// the last start tag is closed first.
     pub fn inner_mut(&mut self) -> &mut W {
+        // an I/O error of the sink will be reported again by the caller's own writes
+        let _ = self.emit(|e, sink| e.fix_start_tag_before_raw_output(sink));
         &mut self.sink
     }
 
+    /// Switches to a new sink, e.g. to rotate log files, and returns the previous one.
+    ///
+    /// The state of the document, such as open elements, namespaces and indentation, is kept,
+    /// so the output continues in the new sink. A start tag that was left open is closed
+    /// in the previous sink first. The previous sink isn't flushed.
+    ///
+    /// ```rust
+    /// use xml::writer::{EmitterConfig, XmlEvent};
+    ///
+    /// let mut writer = EmitterConfig::new().write_document_declaration(false).create_writer(Vec::new());
+    /// writer.write(XmlEvent::start_element("log")).unwrap();
+    /// let first = writer.replace_sink(Vec::new()).unwrap();
+    /// writer.write(XmlEvent::end_element()).unwrap();
+    /// assert_eq!(first, b"<log>");
+    /// assert_eq!(writer.into_inner(), b"</log>");
+    /// ```
+    pub fn replace_sink(&mut self, sink: W) -> Result<W> {
+        self.emit(|e, sink| e.fix_non_empty_element(sink))?;
+        self.unflushed_bytes = 0;
+        Ok(std::mem::replace(&mut self.sink, sink))
+    }
+
     /// Returns an immutable reference to the underlying `Writer`.
     pub fn inner_ref(&self) -> &W {
         &self.sink
@@ -89,7 +425,243 @@ impl<W: Write> EventWriter<W> {
     /// Note that this is a destructive operation: unwrapping a writer and then wrapping
     /// it again with `EventWriter::new()` will create a fresh writer whose state will be
     /// blank; for example, accumulated namespaces will be reset.
-    pub fn into_inner(self) -> W {
+    pub fn into_inner(mut self) -> W {
+        let _ = self.emit(|e, sink| e.fix_start_tag_before_raw_output(sink));
         self.sink
     }
 }
+
+impl<W> fmt::Debug for EventWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventWriter")
+            .field("path", &ElementPath(self.emitter.element_names()))
+            .field("depth", &self.emitter.depth())
+            .field("has_open_document", &self.emitter.has_open_document())
+            .field("stats", &self.stats)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Prints names like `/feed/x:entry`
+struct ElementPath<'a>(&'a [OwnedName]);
+
+impl fmt::Debug for ElementPath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("/");
+        }
+        for name in self.0 {
+            write!(f, "/{}", name.borrow().to_repr())?;
+        }
+        Ok(())
+    }
+}
+
+/// Low-level writer that writes events to a target given for each call, instead of owning the sink like [`EventWriter`].
+///
+/// It keeps the state of the document, such as open elements, namespaces and indentation, so the output
+/// can be switched between targets at any point, e.g. to split a document into chunks or rotating files.
+/// It doesn't buffer anything, and doesn't flush the targets.
+///
+/// ```rust
+/// use xml::writer::{EmitterConfig, EventEmitter, XmlEvent};
+///
+/// let mut emitter = EventEmitter::new(EmitterConfig::new().write_document_declaration(false));
+/// let (mut head, mut tail) = (Vec::new(), Vec::new());
+/// emitter.write(&mut head, XmlEvent::start_element("log")).unwrap();
+/// emitter.write(&mut head, "first").unwrap();
+/// emitter.write(&mut tail, "second").unwrap();
+/// emitter.write(&mut tail, XmlEvent::end_element()).unwrap();
+/// assert_eq!(head, b"<log>first");
+/// assert_eq!(tail, b"second</log>");
+/// ```
+pub struct EventEmitter {
+    emitter: Emitter,
+}
+
+impl EventEmitter {
+    /// Creates an emitter that will write a new document
+    #[must_use]
+    pub fn new(config: EmitterConfig) -> Self {
+        Self { emitter: Emitter::new(config) }
+    }
+
+    /// Writes the next piece of the document to `target`, like [`EventWriter::write`]
+    pub fn write<'a, W: Write>(&mut self, target: &mut W, event: impl Into<XmlEvent<'a>>) -> Result<()> {
+        self.emitter.emit_event(target, event.into())
+    }
+
+    /// Ends the current document, like [`EventWriter::start_new_document`]
+    pub fn start_new_document<W: Write>(&mut self, target: &mut W) -> Result<()> {
+        self.emitter.start_new_document(target)
+    }
+
+    /// Number of currently open elements
+    #[must_use]
+    pub fn depth(&self) -> usize {
+        self.emitter.depth()
+    }
+
+    /// Names of the currently open elements, like [`EventWriter::current_element_path`]
+    #[must_use]
+    pub fn current_element_path(&self) -> &[OwnedName] {
+        self.emitter.element_names()
+    }
+
+    /// Anything has been written to the current document, like [`EventWriter::has_open_document`]
+    #[must_use]
+    pub fn has_open_document(&self) -> bool {
+        self.emitter.has_open_document()
+    }
+
+    /// The configuration given to [`new`](Self::new)
+    #[must_use]
+    pub fn config(&self) -> &EmitterConfig {
+        self.emitter.config()
+    }
+}
+
+impl fmt::Debug for EventEmitter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventEmitter")
+            .field("path", &ElementPath(self.emitter.element_names()))
+            .field("depth", &self.depth())
+            .field("has_open_document", &self.has_open_document())
+            .finish()
+    }
+}
+
+/// Writes text or CDATA to an [`EventWriter`], see [`EventWriter::text_writer`] and [`EventWriter::cdata_writer`]
+pub struct TextWriter<'w, W: Write> {
+    writer: &'w mut EventWriter<W>,
+    /// Start of a UTF-8 sequence split between writes
+    partial: ([u8; 4], u8),
+    /// Anything has been written, so there's an event to end
+    started: bool,
+    /// Writing CDATA, and the number of `]` that ended the last piece
+    cdata: Option<usize>,
+}
+
+impl<W: Write> TextWriter<'_, W> {
+    /// Ends the text or CDATA, and checks that it didn't end in the middle of a UTF-8 sequence
+    pub fn finish(mut self) -> Result<()> {
+        self.end()
+    }
+
+    fn end(&mut self) -> Result<()> {
+        let incomplete = self.partial.1 > 0;
+        self.partial.1 = 0;
+        if self.cdata.is_some() && self.started {
+            self.started = false;
+            self.writer.emit(|e, sink| e.emit_cdata_end(sink))?;
+        }
+        if incomplete {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "incomplete UTF-8 sequence").into());
+        }
+        Ok(())
+    }
+
+    fn write_str(&mut self, text: &str) -> io::Result<()> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        let res = match &mut self.cdata {
+            Some(brackets) => {
+                let start = !self.started;
+                self.writer.emit(|e, sink| {
+                    e.check_text_placement(text)?;
+                    if start {
+                        e.emit_cdata_start(sink)?;
+                    }
+                    e.emit_cdata_piece(sink, text, brackets)
+                })
+            },
+            None => self.writer.emit(|e, sink| e.emit_characters(sink, text)),
+        };
+        if !self.started && res.is_ok() {
+            self.started = true;
+            let events = &mut self.writer.stats.events;
+            if self.cdata.is_some() { events.cdata += 1; } else { events.characters += 1; }
+        }
+        res.map_err(|e| match e {
+            Error::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::Other, e),
+        })
+    }
+}
+
+impl<W: Write> Drop for TextWriter<'_, W> {
+    fn drop(&mut self) {
+        let _ = self.end();
+    }
+}
+
+impl<W: Write> Write for TextWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        // completes the sequence from the previous write first
+        let (mut bytes, len) = self.partial;
+        let mut len = usize::from(len);
+        while len > 0 && !rest.is_empty() {
+            bytes[len] = rest[0];
+            len += 1;
+            rest = &rest[1..];
+            match std::str::from_utf8(&bytes[..len]) {
+                Ok(c) => {
+                    self.partial.1 = 0;
+                    self.write_str(c)?;
+                    break;
+                },
+                Err(e) if e.error_len().is_some() || len == 4 => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+                Err(_) => self.partial = (bytes, len as u8),
+            }
+        }
+        if self.partial.1 > 0 {
+            return Ok(buf.len());
+        }
+
+        let (text, incomplete) = match std::str::from_utf8(rest) {
+            Ok(text) => (text, &[][..]),
+            Err(e) if e.error_len().is_none() => {
+                let (valid, incomplete) = rest.split_at(e.valid_up_to());
+                (std::str::from_utf8(valid).unwrap_or_default(), incomplete)
+            },
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        self.write_str(text)?;
+        self.partial.0[..incomplete.len()].copy_from_slice(incomplete);
+        self.partial.1 = incomplete.len() as u8;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.sink.flush()
+    }
+}
+
+/// Keeps track of the amount of output for statistics and the auto-flush policy
+struct CountingWriter<'a, W> {
+    inner: &'a mut W,
+    written: usize,
+}
+
+impl<W: Write> Write for CountingWriter<'_, W> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n;
+        Ok(n)
+    }
+
+    #[inline]
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.inner.write_all(buf)?;
+        self.written += buf.len();
+        Ok(())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
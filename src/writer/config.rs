@@ -1,5 +1,6 @@
 //! Contains emitter configuration structure.
 
+use crate::writer::emitter::is_reference;
 use crate::writer::EventWriter;
 use std::borrow::Cow;
 use std::io::Write;
@@ -7,7 +8,11 @@ use std::io::Write;
 /// Emitter configuration structure.
 ///
 /// This structure contains various options which control XML document emitter behavior.
+///
+/// It can't be created with a struct literal, because new options may be added.
+/// Use [`EmitterConfig::new()`] or a preset and the setters.
 #[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
 pub struct EmitterConfig {
     /// Line separator used to separate lines in formatted output. Default is `"\n"`.
     pub line_separator: Cow<'static, str>,
@@ -45,6 +50,17 @@ pub struct EmitterConfig {
     /// before a root element is written if it was not emitted explicitly by the user.
     pub write_document_declaration: bool,
 
+    /// Whether or not to write the `encoding` attribute in the XML document declaration.
+    /// Default is true.
+    ///
+    /// The attribute is optional for UTF-8 documents, so disabling it produces a shorter
+    /// declaration like `<?xml version="1.0" standalone="yes"?>`.
+    pub write_declaration_encoding: bool,
+
+    /// Whether or not to use single quotes instead of double quotes for attribute values
+    /// in the XML document declaration. Default is false.
+    pub declaration_single_quotes: bool,
+
     /// Whether or not to convert elements with empty content to empty elements. Default is true.
     ///
     /// This option allows turning elements like `<a></a>` (an element with empty content)
@@ -81,6 +97,115 @@ pub struct EmitterConfig {
     /// this option is also true, the same element would appear `<a />`. If this option is false,
     /// then the same element would appear `<a/>`.
     pub pad_self_closing: bool,
+
+    /// Whether or not to end the document with `line_separator`. Default is false.
+    ///
+    /// It's written after the root element is closed, and after each comment or processing
+    /// instruction that follows the root element, so that the last one ends the line.
+    ///
+    /// Text files are expected to end with a newline by many tools, e.g. POSIX utilities and
+    /// `git diff`.
+    pub write_trailing_newline: bool,
+
+    /// Flush the underlying writer whenever an element at this depth or above is closed.
+    /// Default is `None`.
+    ///
+    /// The root element has depth 1, so `Some(1)` flushes once the document is complete,
+    /// and `Some(2)` also flushes after every child of the root element. This gives long-running
+    /// exporters durable partial output without manual calls to `flush()`.
+    pub auto_flush_depth: Option<usize>,
+
+    /// Flush the underlying writer once at least this many bytes have been written since
+    /// the last flush. Default is `None`.
+    ///
+    /// The check is made after every event, so a large event can exceed the limit.
+    pub auto_flush_bytes: Option<usize>,
+
+    /// Whether or not to change local names of elements and attributes to lowercase.
+    /// Default is false.
+    ///
+    /// Prefixes and namespace URIs are written unchanged. End element names are compared
+    /// with start element names after the change.
+    pub lowercase_names: bool,
+
+    /// Whether or not to self-close only HTML void elements like `<br />`. Default is false.
+    ///
+    /// This option is only meaningful if `normalize_empty_elements` is true. Other empty elements
+    /// are written with an end tag, like `<p></p>`, because HTML parsers don't understand `<p />`.
+    pub self_close_only_void_elements: bool,
+
+    /// Whether or not to write elements in the way HTML parsers expect. Default is false.
+    ///
+    /// HTML void elements like `<br />` are always self-closed, and other elements never are,
+    /// so that empty elements are written like `<div></div>`. `>` isn't escaped in text, and
+    /// HTML boolean attributes like `disabled` are written without a value if their value is empty
+    /// or the same as their name. The output isn't always well-formed XML.
+    pub html_serialization: bool,
+
+    /// Whether or not to return an error when a second root element or text outside of the root
+    /// element is written. Default is false.
+    ///
+    /// Such documents are not well-formed. Whitespace, comments and processing instructions
+    /// are allowed outside of the root element.
+    pub single_root: bool,
+
+    /// Whether or not errors of [`EventWriter`](crate::EventWriter) are wrapped in
+    /// [`EmitterError::Positioned`](crate::writer::Error::Positioned) with the position in the output
+    /// where they happened. Default is false.
+    pub error_positions: bool,
+
+    /// Characters that are written as the given references, set with [`entity_map`](Self::entity_map),
+    /// which checks that they are references
+    pub(crate) entity_map: Vec<(char, String)>,
+
+    /// Line breaks to write in text content. Default is [`NewlineStyle::Keep`].
+    ///
+    /// `\n`, `\r\n` and lone `\r` in `Characters` events (and CDATA written as characters) are all
+    /// written as the chosen line break, for consumers that expect e.g. Windows line endings.
+    /// `\r\n` split between adjacent text events is one line break too.
+    /// Indentation uses [`line_separator`](Self::line_separator) instead.
+    pub normalize_text_newlines: NewlineStyle,
+}
+
+/// Line breaks written in text, see [`EmitterConfig::normalize_text_newlines`]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum NewlineStyle {
+    /// Write text unchanged
+    Keep,
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    CrLf,
+}
+
+impl NewlineStyle {
+    /// `after_cr` is whether the previous text ended with `\r`, which has been converted to a whole line break already
+    pub(crate) fn convert(self, text: &str, after_cr: bool) -> Cow<'_, str> {
+        let text = match text.strip_prefix('\n') {
+            Some(rest) if after_cr && self != NewlineStyle::Keep => rest,
+            _ => text,
+        };
+        let newline = match self {
+            NewlineStyle::Keep => return Cow::Borrowed(text),
+            NewlineStyle::Lf if !text.contains('\r') => return Cow::Borrowed(text),
+            NewlineStyle::Lf => "\n",
+            NewlineStyle::CrLf => "\r\n",
+        };
+        let mut rest = match text.find(['\r', '\n']) {
+            Some(_) => text,
+            None => return Cow::Borrowed(text),
+        };
+        let mut out = String::with_capacity(text.len() + text.len() / 16);
+        while let Some(i) = rest.find(['\r', '\n']) {
+            out.push_str(&rest[..i]);
+            out.push_str(newline);
+            let len = if rest[i..].starts_with("\r\n") { 2 } else { 1 };
+            rest = &rest[i + len..];
+        }
+        out.push_str(rest);
+        Cow::Owned(out)
+    }
 }
 
 impl EmitterConfig {
@@ -105,12 +230,100 @@ impl EmitterConfig {
             perform_indent: false,
             perform_escaping: true,
             write_document_declaration: true,
+            write_declaration_encoding: true,
+            declaration_single_quotes: false,
             normalize_empty_elements: true,
             cdata_to_characters: false,
             keep_element_names_stack: true,
             autopad_comments: true,
             pad_self_closing: true,
+            write_trailing_newline: false,
+            auto_flush_depth: None,
+            auto_flush_bytes: None,
+            lowercase_names: false,
+            self_close_only_void_elements: false,
+            html_serialization: false,
+            single_root: false,
+            error_positions: false,
+            entity_map: Vec::new(),
+            normalize_text_newlines: NewlineStyle::Keep,
+        }
+    }
+
+    /// Indented output that ends with a newline, for documents read by people
+    #[inline]
+    #[must_use]
+    pub fn pretty() -> EmitterConfig {
+        EmitterConfig::new()
+            .perform_indent(true)
+            .write_trailing_newline(true)
+    }
+
+    /// Output without any optional whitespace, for documents read by programs
+    #[inline]
+    #[must_use]
+    pub fn compact() -> EmitterConfig {
+        EmitterConfig::new()
+            .pad_self_closing(false)
+            .autopad_comments(false)
+    }
+
+    /// Output close to [Canonical XML](https://www.w3.org/TR/xml-c14n11/): no XML declaration,
+    /// end tags for all elements, and CDATA written as text.
+    ///
+    /// Attributes and namespace declarations are written in the order they're given,
+    /// so sort them before writing to get the canonical form.
+    #[inline]
+    #[must_use]
+    pub fn canonical() -> EmitterConfig {
+        EmitterConfig::new()
+            .write_document_declaration(false)
+            .normalize_empty_elements(false)
+            .cdata_to_characters(true)
+            .autopad_comments(false)
+    }
+
+    /// Output that can be parsed both as XML and as HTML: no XML declaration, CDATA written as text,
+    /// and only void elements like `<br />` are self-closed.
+    #[inline]
+    #[must_use]
+    pub fn html_polyglot() -> EmitterConfig {
+        EmitterConfig::new()
+            .write_document_declaration(false)
+            .cdata_to_characters(true)
+            .self_close_only_void_elements(true)
+    }
+
+    /// Writes these characters as the given references in text and attribute values,
+    /// e.g. `('\u{a0}', "&#160;")`. Default is none.
+    ///
+    /// The references are written as they are. Named entities like `&nbsp;` must be declared
+    /// in the document's DTD.
+    ///
+    /// ```rust
+    /// use xml::writer::{EmitterConfig, XmlEvent};
+    ///
+    /// let mut out = Vec::new();
+    /// let mut writer = EmitterConfig::new()
+    ///     .write_document_declaration(false)
+    ///     .entity_map(&[('\u{a0}', "&#160;"), ('—', "&mdash;")])
+    ///     .create_writer(&mut out);
+    /// writer.write(XmlEvent::start_element("p")).unwrap();
+    /// writer.write("a\u{a0}b — c").unwrap();
+    /// writer.write(XmlEvent::end_element()).unwrap();
+    /// assert_eq!(String::from_utf8(out).unwrap(), "<p>a&#160;b &mdash; c</p>");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// If a reference isn't `&name;`, `&#decimal;` or `&#xhex;`, or refers to a character that can't be in XML.
+    #[must_use]
+    pub fn entity_map(mut self, map: &[(char, &str)]) -> EmitterConfig {
+        if let Some((_, reference)) = map.iter().find(|(_, reference)| !is_reference(reference)) {
+            panic!("{reference:?} is not an entity or character reference");
         }
+        self.entity_map = map.iter().map(|&(c, reference)| (c, reference.to_owned())).collect();
+        self
     }
 
     /// Creates an XML writer with this configuration.
@@ -149,9 +362,20 @@ gen_setters!(EmitterConfig,
     indent_string: into Cow<'static, str>,
     perform_indent: val bool,
     write_document_declaration: val bool,
+    write_declaration_encoding: val bool,
+    declaration_single_quotes: val bool,
     normalize_empty_elements: val bool,
     cdata_to_characters: val bool,
     keep_element_names_stack: val bool,
     autopad_comments: val bool,
-    pad_self_closing: val bool
+    pad_self_closing: val bool,
+    write_trailing_newline: val bool,
+    auto_flush_depth: val Option<usize>,
+    auto_flush_bytes: val Option<usize>,
+    lowercase_names: val bool,
+    self_close_only_void_elements: val bool,
+    html_serialization: val bool,
+    single_root: val bool,
+    error_positions: val bool,
+    normalize_text_newlines: val NewlineStyle
 );
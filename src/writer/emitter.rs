@@ -5,14 +5,16 @@ use std::{fmt, io, result};
 use crate::attribute::Attribute;
 use crate::common;
 use crate::common::XmlVersion;
-use crate::escape::{AttributeEscapes, Escaped, PcDataEscapes};
-use crate::name::{Name, OwnedName};
-use crate::namespace::{NamespaceStack, NS_EMPTY_URI, NS_NO_PREFIX, NS_XMLNS_PREFIX, NS_XML_PREFIX};
+use crate::escape::{AttributeEscapes, Escaped, Escapes, HtmlTextEscapes, PcDataEscapes};
+use crate::name::{Name, NameError, OwnedName};
+use crate::namespace::{NamespaceStack, NS_EMPTY_URI, NS_NO_PREFIX, NS_XMLNS_PREFIX, NS_XML_PREFIX, NS_XOP_URI};
 
 use crate::writer::config::EmitterConfig;
+use crate::writer::events::XmlEvent;
 
 /// An error which may be returned by `XmlWriter` when writing XML events.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum EmitterError {
     /// An I/O error occured in the underlying `Write` instance.
     Io(io::Error),
@@ -20,6 +22,25 @@ pub enum EmitterError {
     /// Document declaration has already been written to the output stream.
     DocumentStartAlreadyEmitted,
 
+    /// Document declaration can't be written after comments, processing instructions, the DOCTYPE
+    /// or other content. It must be the first thing in the document.
+    DocumentStartAfterContent,
+
+    /// The DOCTYPE must be written before the root element.
+    DoctypeAfterRootElement,
+
+    /// The document can have only one DOCTYPE.
+    DoctypeAlreadyEmitted,
+
+    /// The root element has already been written, and `single_root` is enabled.
+    MultipleRootElements,
+
+    /// Text can't be written outside of the root element when `single_root` is enabled.
+    TextOutsideRootElement,
+
+    /// A new document can't be started before all elements of the current one are closed.
+    UnclosedElements,
+
     /// The name of the last opening element is not available.
     LastElementNameNotAvailable,
 
@@ -30,6 +51,78 @@ pub enum EmitterError {
     /// End element name is not specified when it is needed, for example, when automatic
     /// closing is not enabled in configuration.
     EndElementNameIsNotSpecified,
+
+    /// An attribute can be added only right after its start element.
+    AttributeOutsideStartElement,
+
+    /// Raw markup is not well-formed. The error is at the given byte offset of the markup.
+    MalformedMarkup(usize),
+
+    /// The `Doctype` event is not a single `<!DOCTYPE …>` declaration. The error is at the given byte offset.
+    MalformedDoctype(usize),
+
+    /// A prefix can be undeclared with `xmlns:prefix=""` only in XML 1.1 documents.
+    PrefixUndeclarationRequiresXml11(String),
+
+    /// A name of an element or attribute, or a namespace prefix, isn't a valid XML name.
+    /// Nothing of the element has been written.
+    InvalidName(String, NameError),
+
+    /// Another error, and where it happened, if [`EmitterConfig::error_positions`] is enabled.
+    Positioned {
+        /// The error without the position
+        error: Box<EmitterError>,
+        /// State of the output when the error happened
+        position: Box<OutputPosition>,
+    },
+}
+
+impl EmitterError {
+    /// The error, without the position if it's [`Positioned`](Self::Positioned)
+    #[must_use]
+    pub fn error(&self) -> &EmitterError {
+        match self {
+            EmitterError::Positioned { error, .. } => error,
+            other => other,
+        }
+    }
+
+    /// Where the error happened, if [`EmitterConfig::error_positions`] is enabled
+    #[must_use]
+    pub fn position(&self) -> Option<&OutputPosition> {
+        match self {
+            EmitterError::Positioned { position, .. } => Some(position),
+            _ => None,
+        }
+    }
+}
+
+/// Position in the generated output, see [`EmitterError::position`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub struct OutputPosition {
+    /// Bytes written to the sink, including any part of the failed event
+    pub bytes_written: u64,
+    /// Names of the open elements, from the root. It's empty if `keep_element_names_stack` is disabled.
+    pub element_path: Vec<OwnedName>,
+}
+
+impl fmt::Display for OutputPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "output byte {}", self.bytes_written)?;
+        if !self.element_path.is_empty() {
+            f.write_str(" in ")?;
+            for name in &self.element_path {
+                write!(f, "/{}", name.borrow().to_repr())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The output would be malformed with this name
+fn check_name(name: Name<'_>) -> Result<()> {
+    name.validate().map_err(|e| EmitterError::InvalidName(name.to_repr(), e))
 }
 
 impl From<io::Error> for EmitterError {
@@ -42,18 +135,35 @@ impl From<io::Error> for EmitterError {
 impl fmt::Display for EmitterError {
     #[cold]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str("emitter error: ")?;
         match self {
-            EmitterError::Io(e) => write!(f, "I/O error: {e}"),
-            EmitterError::DocumentStartAlreadyEmitted => f.write_str("document start event has already been emitted"),
-            EmitterError::LastElementNameNotAvailable => f.write_str("last element name is not available"),
-            EmitterError::EndElementNameIsNotEqualToLastStartElementName => f.write_str("end element name is not equal to last start element name"),
-            EmitterError::EndElementNameIsNotSpecified => f.write_str("end element name is not specified and can't be inferred"),
+            EmitterError::Positioned { error, position } => write!(f, "{error}, at {position}"),
+            EmitterError::Io(e) => write!(f, "emitter error: I/O error: {e}"),
+            EmitterError::DocumentStartAlreadyEmitted => f.write_str("emitter error: document start event has already been emitted"),
+            EmitterError::DocumentStartAfterContent => f.write_str("emitter error: document start event must be written before any other content"),
+            EmitterError::DoctypeAfterRootElement => f.write_str("emitter error: DOCTYPE must be written before the root element"),
+            EmitterError::DoctypeAlreadyEmitted => f.write_str("emitter error: DOCTYPE has already been emitted"),
+            EmitterError::MultipleRootElements => f.write_str("emitter error: root element has already been emitted"),
+            EmitterError::TextOutsideRootElement => f.write_str("emitter error: text can't be emitted outside of the root element"),
+            EmitterError::UnclosedElements => f.write_str("emitter error: the document has unclosed elements"),
+            EmitterError::LastElementNameNotAvailable => f.write_str("emitter error: last element name is not available"),
+            EmitterError::EndElementNameIsNotEqualToLastStartElementName => f.write_str("emitter error: end element name is not equal to last start element name"),
+            EmitterError::EndElementNameIsNotSpecified => f.write_str("emitter error: end element name is not specified and can't be inferred"),
+            EmitterError::AttributeOutsideStartElement => f.write_str("emitter error: attribute must be written right after its start element"),
+            EmitterError::MalformedMarkup(offset) => write!(f, "emitter error: raw markup is not well-formed at byte {offset}"),
+            EmitterError::MalformedDoctype(offset) => write!(f, "emitter error: DOCTYPE is not a single declaration, error at byte {offset}"),
+            EmitterError::PrefixUndeclarationRequiresXml11(prefix) => write!(f, "emitter error: prefix '{prefix}' can be undeclared only in XML 1.1"),
+            EmitterError::InvalidName(name, e) => write!(f, "emitter error: invalid name '{name}': {e}"),
         }
     }
 }
 
 impl Error for EmitterError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EmitterError::Positioned { error, .. } => Some(error),
+            _ => None,
+        }
+    }
 }
 
 /// A result type yielded by `XmlWriter`.
@@ -72,7 +182,21 @@ pub struct Emitter {
     element_names: Vec<OwnedName>,
 
     start_document_emitted: bool,
+    /// Anything other than the document declaration has been written
+    wrote_content: bool,
+    doctype_emitted: bool,
+    root_element_emitted: bool,
     just_wrote_start_element: bool,
+    /// The last byte written is a newline, so the next document doesn't need a separator
+    wrote_newline: bool,
+    /// The last thing written is text that ends with `\r`, which may be followed by `\n` in the next text
+    text_ended_with_cr: bool,
+
+    /// Version from the document declaration, affects escaping
+    xml_version: XmlVersion,
+
+    /// Quotes for attributes of the next start element
+    attribute_quotes: Vec<char>,
 }
 
 impl Emitter {
@@ -91,7 +215,16 @@ impl Emitter {
             element_names: Vec::new(),
 
             start_document_emitted: false,
+            wrote_content: false,
+            doctype_emitted: false,
+            root_element_emitted: false,
             just_wrote_start_element: false,
+            wrote_newline: false,
+            text_ended_with_cr: false,
+
+            xml_version: XmlVersion::Version10,
+
+            attribute_quotes: Vec::new(),
         }
     }
 }
@@ -104,10 +237,35 @@ enum IndentFlags {
 }
 
 impl Emitter {
-    /// Returns the current state of namespaces.
     #[inline]
-    pub fn namespace_stack_mut(&mut self) -> &mut NamespaceStack {
-        &mut self.nst
+    pub fn config(&self) -> &EmitterConfig {
+        &self.config
+    }
+
+    /// Uses a different configuration for the following events, and returns the previous one
+    pub fn replace_config(&mut self, config: EmitterConfig) -> EmitterConfig {
+        std::mem::replace(&mut self.config, config)
+    }
+
+    /// Quote characters to use for attributes of the next start element
+    pub fn set_attribute_quotes(&mut self, quotes: Vec<char>) {
+        self.attribute_quotes = quotes;
+    }
+
+    /// Number of currently open elements
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.indent_level
+    }
+
+    /// Names of the open elements, if `keep_element_names_stack` is enabled
+    pub fn element_names(&self) -> &[OwnedName] {
+        &self.element_names
+    }
+
+    /// Anything has been written since the start or the last `start_new_document`
+    pub fn has_open_document(&self) -> bool {
+        self.start_document_emitted || self.wrote_content
     }
 
     #[inline]
@@ -134,6 +292,27 @@ impl Emitter {
         }
     }
 
+    /// Ends the document with a newline if `write_trailing_newline` is enabled and the root element has ended,
+    /// so that it ends with one after comments and processing instructions that follow the root too
+    fn end_document_line<W: Write>(&mut self, target: &mut W) -> Result<()> {
+        if self.indent_level == 0 && self.root_element_emitted && self.config.write_trailing_newline {
+            target.write_all(self.config.line_separator.as_bytes())?;
+            // further markup must not add another newline
+            self.set_wrote_text();
+            self.wrote_newline = true;
+        }
+        Ok(())
+    }
+
+    /// XML 1.1 documents need restricted characters to be written as references
+    #[inline]
+    fn escaped<'a, E: Escapes>(&'a self, s: &'a str) -> Escaped<'a, E> {
+        match self.xml_version {
+            XmlVersion::Version10 => Escaped::new(s),
+            XmlVersion::Version11 => Escaped::new_xml11(s),
+        }.with_entities(&self.config.entity_map)
+    }
+
     fn write_newline<W: Write>(&mut self, target: &mut W, level: usize) -> Result<()> {
         target.write_all(self.config.line_separator.as_bytes())?;
         for _ in 0..level {
@@ -147,7 +326,7 @@ impl Emitter {
            (self.indent_level > 0 || self.wrote_markup()) {
             let indent_level = self.indent_level;
             self.write_newline(target, indent_level)?;
-            if self.indent_level > 0 && self.config.indent_string.len() > 0 {
+            if self.indent_level > 0 && !self.config.indent_string.is_empty() {
                 self.after_markup();
             }
         }
@@ -180,6 +359,7 @@ impl Emitter {
     }
 
     fn after_end_element(&mut self) {
+        self.text_ended_with_cr = false;
         if self.indent_level > 0 {
             self.indent_level -= 1;
             self.indent_stack.pop();
@@ -198,15 +378,26 @@ impl Emitter {
         if self.start_document_emitted {
             return Err(EmitterError::DocumentStartAlreadyEmitted);
         }
+        if self.wrote_content {
+            return Err(EmitterError::DocumentStartAfterContent);
+        }
         self.start_document_emitted = true;
+        self.xml_version = version;
+
+        let q = if self.config.declaration_single_quotes { '\'' } else { '"' };
+        let write_encoding = self.config.write_declaration_encoding;
 
         self.before_markup(target)?;
         let result = {
             let mut write = move || {
-                write!(target, "<?xml version=\"{version}\" encoding=\"{encoding}\"")?;
+                write!(target, "<?xml version={q}{version}{q}")?;
+
+                if write_encoding {
+                    write!(target, " encoding={q}{encoding}{q}")?;
+                }
 
                 if let Some(standalone) = standalone {
-                    write!(target, " standalone=\"{}\"", if standalone { "yes" } else { "no" })?;
+                    write!(target, " standalone={q}{}{q}", if standalone { "yes" } else { "no" })?;
                 }
 
                 write!(target, "?>")?;
@@ -220,16 +411,81 @@ impl Emitter {
         result
     }
 
+    /// Writes the event with the matching `emit_` method
+    pub fn emit_event<W: Write>(&mut self, target: &mut W, event: XmlEvent<'_>) -> Result<()> {
+        match event {
+            XmlEvent::StartDocument { version, encoding, standalone } =>
+                self.emit_start_document(target, version, encoding.unwrap_or("UTF-8"), standalone),
+            XmlEvent::ProcessingInstruction { name, data } => self.emit_processing_instruction(target, name, data.as_deref()),
+            XmlEvent::Doctype(syntax) => self.emit_doctype(target, syntax),
+            XmlEvent::StartElement { name, attributes, namespace } => {
+                self.nst.push_empty().checked_target().extend(namespace.as_ref());
+                self.emit_start_element(target, name, &attributes)
+            },
+            XmlEvent::EndElement { name } => {
+                let r = self.emit_end_element(target, name);
+                self.nst.try_pop();
+                r
+            },
+            XmlEvent::Comment(content) => self.emit_comment(target, content),
+            XmlEvent::CData(content) => self.emit_cdata(target, content),
+            XmlEvent::Characters(content) => self.emit_characters(target, content),
+            XmlEvent::RawMarkup(markup) => self.emit_raw_markup(target, markup),
+        }
+    }
+
+    /// Writes an `xop:Include` element, and declares the `xop` prefix if it isn't bound to the XOP namespace already
+    pub fn emit_xop_include<W: Write>(&mut self, target: &mut W, content_id: &str) -> Result<()> {
+        let nst = self.nst.push_empty();
+        if nst.get("xop") != Some(NS_XOP_URI) {
+            nst.put("xop", NS_XOP_URI);
+        }
+        let name = Name::prefixed("Include", "xop");
+        let href = cid_url(content_id);
+        let r = self.emit_start_element(target, name, &[Attribute::new(Name::local("href"), &href)])
+            .and_then(|()| self.emit_end_element(target, Some(name)));
+        self.nst.try_pop();
+        r
+    }
+
+    /// Ends the current document with a line separator unless it ends with a newline,
+    /// and forgets its declaration, DOCTYPE and root element
+    pub fn start_new_document<W: Write>(&mut self, target: &mut W) -> Result<()> {
+        if self.indent_level > 0 {
+            return Err(EmitterError::UnclosedElements);
+        }
+        if (self.start_document_emitted || self.wrote_content) && !self.wrote_newline {
+            target.write_all(self.config.line_separator.as_bytes())?;
+        }
+        self.wrote_newline = false;
+        self.indent_stack.clear();
+        self.indent_stack.push(IndentFlags::WroteNothing);
+        self.start_document_emitted = false;
+        self.wrote_content = false;
+        self.doctype_emitted = false;
+        self.root_element_emitted = false;
+        self.just_wrote_start_element = false;
+        self.xml_version = XmlVersion::Version10;
+        self.attribute_quotes.clear();
+        Ok(())
+    }
+
+    /// Writes the automatic declaration, if needed, before any other content
     fn check_document_started<W: Write>(&mut self, target: &mut W) -> Result<()> {
-        if !self.start_document_emitted && self.config.write_document_declaration {
+        let result = if !self.start_document_emitted && !self.wrote_content && self.config.write_document_declaration {
             self.emit_start_document(target, common::XmlVersion::Version10, "utf-8", None)
         } else {
             Ok(())
-        }
+        };
+        self.wrote_content = true;
+        self.wrote_newline = false;
+        self.text_ended_with_cr = false;
+        result
     }
 
-    fn fix_non_empty_element<W: Write>(&mut self, target: &mut W) -> Result<()> {
-        if self.config.normalize_empty_elements && self.just_wrote_start_element {
+    /// Writes the `>` of the last start tag, if it's still open for attributes or `/>`
+    pub fn fix_non_empty_element<W: Write>(&mut self, target: &mut W) -> Result<()> {
+        if self.just_wrote_start_element {
             self.just_wrote_start_element = false;
             target.write_all(b">").map_err(From::from)
         } else {
@@ -237,6 +493,16 @@ impl Emitter {
         }
     }
 
+    /// Writes the `>` of the last start tag if it's held back only for streamed attributes,
+    /// so that it can't end up after bytes written directly to the sink
+    pub fn fix_start_tag_before_raw_output<W: Write>(&mut self, target: &mut W) -> Result<()> {
+        if self.config.normalize_empty_elements {
+            Ok(())
+        } else {
+            self.fix_non_empty_element(target)
+        }
+    }
+
     pub fn emit_processing_instruction<W: Write>(&mut self,
                                                  target: &mut W,
                                                  name: &str,
@@ -246,24 +512,23 @@ impl Emitter {
 
         self.before_markup(target)?;
 
-        let result = {
-            let mut write = move || {
-                write!(target, "<?{name}")?;
+        let write = move |target: &mut W| -> Result<()> {
+            write!(target, "<?{name}")?;
 
-                if let Some(data) = data {
-                    write!(target, " {data}")?;
-                }
+            if let Some(data) = data {
+                write!(target, " {data}")?;
+            }
 
-                write!(target, "?>")?;
+            write!(target, "?>")?;
 
-                Ok(())
-            };
-            write()
+            Ok(())
         };
+        let result = write(target);
 
         self.after_markup();
 
-        result
+        result?;
+        self.end_document_line(target)
     }
 
     #[track_caller]
@@ -272,8 +537,12 @@ impl Emitter {
                                      attributes: &[Attribute<'_>]) -> Result<()>
         where W: Write
     {
+        if self.config.single_root && self.root_element_emitted && self.indent_level == 0 {
+            return Err(EmitterError::MultipleRootElements);
+        }
         self.check_document_started(target)?;
         self.fix_non_empty_element(target)?;
+        self.root_element_emitted = true;
         self.before_start_element(target)?;
         write!(target, "<{}", name.repr_display())?;
         self.emit_current_namespace_attributes(target)?;
@@ -288,17 +557,64 @@ impl Emitter {
                                  attributes: &[Attribute<'_>]) -> Result<()>
         where W: Write
     {
+        if self.config.lowercase_names {
+            let name = name.to_lowercase();
+            let attr_names: Vec<_> = attributes.iter().map(|a| a.name.to_lowercase()).collect();
+            let attributes: Vec<_> = attributes.iter().zip(&attr_names)
+                .map(|(a, n)| Attribute::new(n.borrow(), a.value))
+                .collect();
+            return self.emit_start_element_cased(target, name.borrow(), &attributes);
+        }
+        self.emit_start_element_cased(target, name, attributes)
+    }
+
+    #[track_caller]
+    fn emit_start_element_cased<W>(&mut self, target: &mut W,
+                                   name: Name<'_>,
+                                   attributes: &[Attribute<'_>]) -> Result<()>
+        where W: Write
+    {
+        check_name(name)?;
+        for attr in attributes {
+            check_name(attr.name)?;
+        }
+        for (prefix, _) in self.nst.peek() {
+            if prefix != NS_NO_PREFIX {
+                check_name(Name::local(prefix))?;
+            }
+        }
+
         if self.config.keep_element_names_stack {
             self.element_names.push(name.to_owned());
         }
 
         self.emit_start_element_initial(target, name, attributes)?;
+
+        // the `>` is written later, after streamed attributes, or not at all if the element turns out to be empty
         self.just_wrote_start_element = true;
 
-        if !self.config.normalize_empty_elements {
-            write!(target, ">")?;
-        }
+        Ok(())
+    }
 
+    /// Adds an attribute to the start element written last, escaping the value piece by piece
+    pub fn emit_attribute_streamed<'a, W: Write>(&mut self, target: &mut W,
+                                                 name: Name<'_>,
+                                                 pieces: impl IntoIterator<Item = &'a str>) -> Result<()> {
+        if !self.just_wrote_start_element {
+            return Err(EmitterError::AttributeOutsideStartElement);
+        }
+        check_name(name)?;
+        let lowercase_name = Some(name).filter(|_| self.config.lowercase_names).map(|n| n.to_lowercase());
+        let name = lowercase_name.as_ref().map_or(name, OwnedName::borrow);
+        write!(target, " {}=\"", name.repr_display())?;
+        for piece in pieces {
+            if self.config.perform_escaping {
+                write!(target, "{}", self.escaped::<AttributeEscapes>(piece))?;
+            } else {
+                target.write_all(piece.as_bytes())?;
+            }
+        }
+        target.write_all(b"\"")?;
         Ok(())
     }
 
@@ -312,10 +628,14 @@ impl Emitter {
                 NS_XMLNS_PREFIX | NS_XML_PREFIX => Ok(()),
                 //// there is already a namespace binding with this prefix in scope
                 //prefix if self.nst.get(prefix) == Some(uri) => Ok(()),
-                // emit xmlns only if it is overridden
-                NS_NO_PREFIX => if uri != NS_EMPTY_URI {
-                    write!(target, " xmlns=\"{uri}\"")
-                } else { Ok(()) },
+                // the stack has the empty default namespace only where it's overridden, including undeclaration
+                NS_NO_PREFIX => write!(target, " xmlns=\"{uri}\""),
+                // undeclaration of a prefix
+                prefix if uri == NS_EMPTY_URI => if self.xml_version == XmlVersion::Version11 {
+                    write!(target, " xmlns:{prefix}=\"\"")
+                } else {
+                    return Err(EmitterError::PrefixUndeclarationRequiresXml11(prefix.into()));
+                },
                 // everything else
                 prefix => write!(target, " xmlns:{prefix}=\"{uri}\"")
             }?;
@@ -325,20 +645,32 @@ impl Emitter {
 
     pub fn emit_attributes<W: Write>(&mut self, target: &mut W,
                                       attributes: &[Attribute<'_>]) -> Result<()> {
-        for attr in attributes {            
-            write!(target, " {}=\"", attr.name.repr_display())?;
+        let quotes = std::mem::take(&mut self.attribute_quotes);
+        for (i, attr) in attributes.iter().enumerate() {
+            if self.config.html_serialization && attr.name.prefix.is_none() && is_html_boolean_attribute(attr.name.local_name)
+                && (attr.value.is_empty() || attr.value.eq_ignore_ascii_case(attr.name.local_name)) {
+                write!(target, " {}", attr.name.local_name)?;
+                continue;
+            }
+            let q = if quotes.get(i) == Some(&'\'') { '\'' } else { '"' };
+            write!(target, " {}={q}", attr.name.repr_display())?;
             if self.config.perform_escaping {
-                write!(target, "{}", Escaped::<AttributeEscapes>::new(attr.value))?;
+                write!(target, "{}", self.escaped::<AttributeEscapes>(attr.value))?;
             } else {
                 write!(target, "{}", attr.value)?;
             }
-            write!(target, "\"")?;
+            write!(target, "{q}")?;
         }
         Ok(())
     }
 
     pub fn emit_end_element<W: Write>(&mut self, target: &mut W,
                                       name: Option<Name<'_>>) -> Result<()> {
+        if let Some(name) = name {
+            check_name(name)?;
+        }
+        let lowercase_name = name.filter(|_| self.config.lowercase_names).map(|n| n.to_lowercase());
+        let name = lowercase_name.as_ref().map(OwnedName::borrow).or(name);
         let owned_name = if self.config.keep_element_names_stack {
             Some(self.element_names.pop().ok_or(EmitterError::LastElementNameNotAvailable)?)
         } else {
@@ -355,57 +687,176 @@ impl Emitter {
         }
 
         if let Some(name) = owned_name.as_ref().map(|n| n.borrow()).or(name) {
-            if self.config.normalize_empty_elements && self.just_wrote_start_element {
+            let self_close = self.just_wrote_start_element && if self.config.html_serialization {
+                is_html_void_element(name.local_name)
+            } else {
+                self.config.normalize_empty_elements &&
+                    (!self.config.self_close_only_void_elements || is_html_void_element(name.local_name))
+            };
+            if self_close {
                 self.just_wrote_start_element = false;
                 let termination = if self.config.pad_self_closing { " />" } else { "/>" };
-                let result = target.write_all(termination.as_bytes()).map_err(From::from);
+                let result = target.write_all(termination.as_bytes());
                 self.after_end_element();
-                result
+                result?;
             } else {
+                self.fix_non_empty_element(target)?;
                 self.just_wrote_start_element = false;
 
                 self.before_end_element(target)?;
-                let result = write!(target, "</{}>", name.repr_display()).map_err(From::from);
+                let result = write!(target, "</{}>", name.repr_display());
                 self.after_end_element();
-
-                result
+                result?;
             }
+
+            self.end_document_line(target)
         } else {
             Err(EmitterError::EndElementNameIsNotSpecified)
         }
     }
 
     pub fn emit_cdata<W: Write>(&mut self, target: &mut W, content: &str) -> Result<()> {
-        self.fix_non_empty_element(target)?;
+        self.check_text_placement(content)?;
         if self.config.cdata_to_characters {
             self.emit_characters(target, content)
         } else {
-            // TODO: escape ']]>' characters in CDATA as two adjacent CDATA blocks
-            target.write_all(b"<![CDATA[")?;
-            target.write_all(content.as_bytes())?;
-            target.write_all(b"]]>")?;
+            self.emit_cdata_start(target)?;
+            self.emit_cdata_piece(target, content, &mut 0)?;
+            self.emit_cdata_end(target)
+        }
+    }
 
-            self.after_text();
+    /// Starts CDATA, which is then written with `emit_cdata_piece` and ended with `emit_cdata_end`
+    pub fn emit_cdata_start<W: Write>(&mut self, target: &mut W) -> Result<()> {
+        self.check_document_started(target)?;
+        self.fix_non_empty_element(target)?;
+        target.write_all(b"<![CDATA[")?;
+        Ok(())
+    }
 
-            Ok(())
+    /// `]]>` is split into two adjacent CDATA sections, also when it spans pieces.
+    /// `brackets` counts `]` at the end of the previous piece.
+    pub fn emit_cdata_piece<W: Write>(&mut self, target: &mut W, content: &str, brackets: &mut usize) -> Result<()> {
+        let bytes = content.as_bytes();
+        let mut start = 0;
+        for (i, &b) in bytes.iter().enumerate() {
+            match b {
+                b']' => *brackets += 1,
+                b'>' if *brackets >= 2 => {
+                    target.write_all(&bytes[start..i])?;
+                    target.write_all(b"]]><![CDATA[")?;
+                    start = i;
+                    *brackets = 0;
+                },
+                _ => *brackets = 0,
+            }
         }
+        target.write_all(&bytes[start..])?;
+        Ok(())
+    }
+
+    pub fn emit_cdata_end<W: Write>(&mut self, target: &mut W) -> Result<()> {
+        target.write_all(b"]]>")?;
+        self.after_text();
+        Ok(())
+    }
+
+    pub fn check_text_placement(&self, content: &str) -> Result<()> {
+        if self.config.single_root && self.indent_level == 0 && !content.chars().all(common::is_whitespace_char) {
+            return Err(EmitterError::TextOutsideRootElement);
+        }
+        Ok(())
     }
 
     pub fn emit_characters<W: Write>(&mut self, target: &mut W, content: &str) -> Result<()> {
+        self.check_text_placement(content)?;
+        let after_cr = self.text_ended_with_cr;
         self.check_document_started(target)?;
         self.fix_non_empty_element(target)?;
+        self.text_ended_with_cr = content.ends_with('\r') || (after_cr && content.is_empty());
 
-        if self.config.perform_escaping {
-            write!(target, "{}", Escaped::<PcDataEscapes>::new(content))?;
+        let content = &*self.config.normalize_text_newlines.convert(content, after_cr);
+        if self.config.perform_escaping && self.config.html_serialization {
+            write!(target, "{}", self.escaped::<HtmlTextEscapes>(content))?;
+        } else if self.config.perform_escaping {
+            write!(target, "{}", self.escaped::<PcDataEscapes>(content))?;
         } else {
             target.write_all(content.as_bytes())?;
         }
+        self.wrote_newline = content.ends_with('\n');
 
         self.after_text();
         Ok(())
     }
 
+    pub fn emit_doctype<W: Write>(&mut self, target: &mut W, syntax: &str) -> Result<()> {
+        if self.root_element_emitted {
+            return Err(EmitterError::DoctypeAfterRootElement);
+        }
+        if self.doctype_emitted {
+            return Err(EmitterError::DoctypeAlreadyEmitted);
+        }
+        check_doctype(syntax).map_err(EmitterError::MalformedDoctype)?;
+        self.doctype_emitted = true;
+        self.check_document_started(target)?;
+        self.fix_non_empty_element(target)?;
+
+        self.before_markup(target)?;
+        let result = target.write_all(syntax.as_bytes());
+        self.after_markup();
+
+        Ok(result?)
+    }
+
+    pub fn emit_raw_markup<W: Write>(&mut self, target: &mut W, markup: &str) -> Result<()> {
+        let scan = MarkupScan::new(markup).map_err(EmitterError::MalformedMarkup)?;
+        if scan.closed.len() > self.indent_level {
+            return Err(EmitterError::LastElementNameNotAvailable);
+        }
+        if self.config.keep_element_names_stack {
+            let outer = self.element_names.iter().rev().map(|n| n.borrow().to_repr());
+            if outer.zip(&scan.closed).any(|(name, &closed)| name != closed) {
+                return Err(EmitterError::EndElementNameIsNotEqualToLastStartElementName);
+            }
+        }
+        let root_level = scan.lowest_start.map_or(false, |depth| self.indent_level as isize + depth == 0);
+        if root_level && self.config.single_root && self.root_element_emitted {
+            return Err(EmitterError::MultipleRootElements);
+        }
+        let text_at_root_level = scan.lowest_text.map_or(false, |depth| self.indent_level as isize + depth == 0);
+        if text_at_root_level && self.config.single_root {
+            return Err(EmitterError::TextOutsideRootElement);
+        }
+
+        self.check_document_started(target)?;
+        self.fix_non_empty_element(target)?;
+        target.write_all(markup.as_bytes())?;
+
+        for _ in &scan.closed {
+            if self.config.keep_element_names_stack {
+                self.element_names.pop();
+            }
+            self.after_end_element();
+            self.nst.try_pop();
+        }
+        for name in scan.opened {
+            if self.config.keep_element_names_stack {
+                self.element_names.push(Name::from(name).to_owned());
+            }
+            self.indent_stack.push(IndentFlags::WroteNothing);
+            self.indent_level += 1;
+            self.nst.push_empty();
+        }
+        if scan.lowest_start.is_some() {
+            self.root_element_emitted = true;
+        }
+        // whitespace around the markup is up to the markup
+        self.after_text();
+        Ok(())
+    }
+
     pub fn emit_comment<W: Write>(&mut self, target: &mut W, content: &str) -> Result<()> {
+        self.check_document_started(target)?;
         self.fix_non_empty_element(target)?;
 
         // TODO: add escaping dashes at the end of the comment
@@ -433,6 +884,245 @@ impl Emitter {
         let result = write(target);
         self.after_markup();
 
-        result
+        result?;
+        self.end_document_line(target)
+    }
+}
+
+/// Elements that can't have content in HTML, and are written as `<br>`
+fn is_html_void_element(local_name: &str) -> bool {
+    const VOID_ELEMENTS: [&str; 14] = ["area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source", "track", "wbr"];
+    VOID_ELEMENTS.iter().any(|v| v.eq_ignore_ascii_case(local_name))
+}
+
+/// Attributes that are true when present in HTML, and are written as `<input disabled>`
+fn is_html_boolean_attribute(local_name: &str) -> bool {
+    const BOOLEAN_ATTRIBUTES: [&str; 24] = ["allowfullscreen", "async", "autofocus", "autoplay", "checked", "controls",
+        "default", "defer", "disabled", "formnovalidate", "hidden", "inert", "ismap", "itemscope", "loop", "multiple",
+        "muted", "nomodule", "novalidate", "open", "readonly", "required", "reversed", "selected"];
+    BOOLEAN_ATTRIBUTES.iter().any(|a| a.eq_ignore_ascii_case(local_name))
+}
+
+/// `cid:` URL of a MIME part, with characters that aren't allowed in URLs percent-encoded
+fn cid_url(content_id: &str) -> String {
+    let mut url = String::with_capacity(4 + content_id.len());
+    url.push_str("cid:");
+    for b in content_id.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@".contains(&b) {
+            url.push(char::from(b));
+        } else {
+            url.push_str(&format!("%{b:02X}"));
+        }
+    }
+    url
+}
+
+/// Net effect of a markup fragment on the stack of open elements
+struct MarkupScan<'a> {
+    /// Elements opened before the fragment, and closed in it
+    closed: Vec<&'a str>,
+    /// Elements left open by the fragment
+    opened: Vec<&'a str>,
+    /// Depth relative to the start of the fragment of the least nested element start
+    lowest_start: Option<isize>,
+    /// Depth relative to the start of the fragment of the least nested text that isn't whitespace
+    lowest_text: Option<isize>,
+}
+
+impl<'a> MarkupScan<'a> {
+    /// Checks well-formedness of tags, names and references, and returns the offset of an error
+    fn new(markup: &'a str) -> result::Result<Self, usize> {
+        let mut scan = MarkupScan { closed: Vec::new(), opened: Vec::new(), lowest_start: None, lowest_text: None };
+        let bytes = markup.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            let rest = &markup[i..];
+            i = if let Some(comment) = rest.strip_prefix("<!--") {
+                let end = comment.find("-->").ok_or(i)?;
+                if comment[..end].contains("--") {
+                    return Err(i);
+                }
+                i + 4 + end + 3
+            } else if rest.starts_with("<![CDATA[") {
+                let end = rest.find("]]>").ok_or(i)?;
+                if !rest[9..end].chars().all(common::is_whitespace_char) {
+                    scan.text_at_depth();
+                }
+                i + end + 3
+            } else if let Some(pi) = rest.strip_prefix("<?") {
+                let end = pi.find("?>").ok_or(i)?;
+                let target = pi[..end].split(common::is_whitespace_char).next().unwrap_or("");
+                if !is_name(target) || target.eq_ignore_ascii_case("xml") {
+                    return Err(i);
+                }
+                i + 2 + end + 2
+            } else if let Some(tag) = rest.strip_prefix("</") {
+                let name = name_prefix(tag);
+                let after = tag[name.len()..].trim_start_matches(common::is_whitespace_char);
+                if name.is_empty() || !after.starts_with('>') {
+                    return Err(i);
+                }
+                match scan.opened.pop() {
+                    Some(open) if open != name => return Err(i),
+                    Some(_) => {},
+                    None => scan.closed.push(name),
+                }
+                markup.len() - after.len() + 1
+            } else if let Some(tag) = rest.strip_prefix('<') {
+                let name = name_prefix(tag);
+                if name.is_empty() {
+                    return Err(i);
+                }
+                let depth = scan.depth();
+                scan.lowest_start = Some(scan.lowest_start.map_or(depth, |d| d.min(depth)));
+                let mut attrs = &tag[name.len()..];
+                let mut attr_names = Vec::new();
+                loop {
+                    let trimmed = attrs.trim_start_matches(common::is_whitespace_char);
+                    if let Some(after) = trimmed.strip_prefix("/>") {
+                        break markup.len() - after.len();
+                    }
+                    if let Some(after) = trimmed.strip_prefix('>') {
+                        scan.opened.push(name);
+                        break markup.len() - after.len();
+                    }
+                    let attr_name = name_prefix(trimmed);
+                    if attr_name.is_empty() || trimmed.len() == attrs.len() || attr_names.contains(&attr_name) {
+                        return Err(markup.len() - trimmed.len());
+                    }
+                    attr_names.push(attr_name);
+                    let value = trimmed[attr_name.len()..].trim_start_matches(common::is_whitespace_char)
+                        .strip_prefix('=').ok_or(markup.len() - trimmed.len())?
+                        .trim_start_matches(common::is_whitespace_char);
+                    let quote = value.chars().next().filter(|&q| q == '"' || q == '\'').ok_or(markup.len() - value.len())?;
+                    let end = value[1..].find(quote).ok_or(markup.len() - value.len())? + 1;
+                    let value_start = markup.len() - value.len() + 1;
+                    check_text(&value[1..end], value_start, true)?;
+                    attrs = &value[end + 1..];
+                }
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                check_text(&rest[..end], i, false)?;
+                if !rest[..end].chars().all(common::is_whitespace_char) {
+                    scan.text_at_depth();
+                }
+                i + end
+            };
+        }
+        Ok(scan)
+    }
+
+    fn depth(&self) -> isize {
+        self.opened.len() as isize - self.closed.len() as isize
+    }
+
+    /// Records text that isn't whitespace at the current depth
+    fn text_at_depth(&mut self) {
+        let depth = self.depth();
+        self.lowest_text = Some(self.lowest_text.map_or(depth, |d| d.min(depth)));
+    }
+}
+
+/// Checks references, and `<` or `]]>` that can't be in text
+fn check_text(text: &str, offset: usize, attribute: bool) -> result::Result<(), usize> {
+    if attribute && text.contains('<') {
+        return Err(offset + text.find('<').unwrap_or(0));
+    }
+    if let Some(pos) = text.find("]]>") {
+        return Err(offset + pos);
+    }
+    let mut rest = text;
+    while let Some(pos) = rest.find('&') {
+        let reference = &rest[pos + 1..];
+        let end = reference.find(';').ok_or(offset + text.len() - rest.len() + pos)?;
+        let name = &reference[..end];
+        let valid = match name.strip_prefix('#') {
+            Some(hex) if hex.starts_with('x') => hex.len() > 1 && hex[1..].bytes().all(|b| b.is_ascii_hexdigit()),
+            Some(dec) => !dec.is_empty() && dec.bytes().all(|b| b.is_ascii_digit()),
+            None => is_name(name),
+        };
+        if !valid {
+            return Err(offset + text.len() - rest.len() + pos);
+        }
+        rest = &reference[end + 1..];
     }
+    Ok(())
+}
+
+/// Checks that the string is one `<!DOCTYPE name …>` declaration, and returns the offset of an error.
+/// Declarations in the internal subset are checked only for balanced `<`, `>` and quotes.
+fn check_doctype(syntax: &str) -> result::Result<(), usize> {
+    const START: &str = "<!DOCTYPE";
+    let bytes = syntax.as_bytes();
+    if !syntax.starts_with(START) || !bytes.get(START.len()).map_or(false, u8::is_ascii_whitespace) {
+        return Err(0);
+    }
+    let mut i = START.len() + syntax[START.len()..].len() - syntax[START.len()..].trim_start().len();
+    let name = name_prefix(&syntax[i..]);
+    if name.is_empty() {
+        return Err(i);
+    }
+    i += name.len();
+
+    // inside the internal subset, and inside a declaration there
+    let mut subset = None;
+    let mut had_subset = false;
+    while i < bytes.len() {
+        match (bytes[i], subset) {
+            (quote @ (b'"' | b'\''), _) if !had_subset || subset.is_some() => {
+                i += 1 + syntax[i + 1..].find(quote as char).ok_or(i)?;
+            },
+            (b'[', None) if !had_subset => {
+                subset = Some(false);
+                had_subset = true;
+            },
+            (b']', Some(false)) => subset = None,
+            (b'<', Some(false)) if syntax[i..].starts_with("<!--") => {
+                i += 4 + syntax[i + 4..].find("-->").ok_or(i)? + 2;
+            },
+            (b'<', Some(false)) if syntax[i..].starts_with("<?") => {
+                i += 2 + syntax[i + 2..].find("?>").ok_or(i)? + 1;
+            },
+            (b'<', Some(false)) => subset = Some(true),
+            (b'>', Some(true)) => subset = Some(false),
+            (b'>', None) => return if i + 1 == bytes.len() { Ok(()) } else { Err(i + 1) },
+            (b'<' | b'>' | b'[' | b']', _) => return Err(i),
+            // only whitespace after the internal subset
+            (b, None) if had_subset && !b.is_ascii_whitespace() => return Err(i),
+            _ => {},
+        }
+        i += 1;
+    }
+    Err(bytes.len())
+}
+
+/// The longest name at the start of the string
+fn name_prefix(s: &str) -> &str {
+    let mut chars = s.char_indices();
+    match chars.next() {
+        Some((_, c)) if common::is_name_start_char(c) => {},
+        _ => return "",
+    }
+    let end = chars.find(|&(_, c)| !common::is_name_char(c)).map_or(s.len(), |(i, _)| i);
+    &s[..end]
+}
+
+fn is_name(s: &str) -> bool {
+    !s.is_empty() && name_prefix(s).len() == s.len()
+}
+
+/// Checks that the string is one entity reference like `&nbsp;`, or a character reference like `&#160;` or `&#xA0;`
+pub(crate) fn is_reference(s: &str) -> bool {
+    let name = match s.strip_prefix('&').and_then(|s| s.strip_suffix(';')) {
+        Some(name) => name,
+        None => return false,
+    };
+    let code = if let Some(hex) = name.strip_prefix("#x") {
+        hex.bytes().all(|b| b.is_ascii_hexdigit()).then(|| u32::from_str_radix(hex, 16).ok()).flatten()
+    } else if let Some(dec) = name.strip_prefix('#') {
+        dec.bytes().all(|b| b.is_ascii_digit()).then(|| dec.parse().ok()).flatten()
+    } else {
+        return is_name(name);
+    };
+    code.and_then(char::from_u32).map_or(false, common::is_xml11_char)
 }
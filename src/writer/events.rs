@@ -4,7 +4,8 @@ use std::borrow::Cow;
 
 use crate::attribute::Attribute;
 use crate::common::XmlVersion;
-use crate::name::Name;
+use crate::escape::{AttributeEscapes, Escaped};
+use crate::name::{is_valid_ncname, Name, NameError};
 use crate::namespace::{Namespace, NS_NO_PREFIX};
 
 /// A part of an XML output stream.
@@ -12,6 +13,7 @@ use crate::namespace::{Namespace, NS_NO_PREFIX};
 /// Objects of this enum are consumed by `EventWriter`. They correspond to different parts of
 /// an XML document.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum XmlEvent<'a> {
     /// Corresponds to XML document declaration.
     ///
@@ -21,7 +23,8 @@ pub enum XmlEvent<'a> {
     StartDocument {
         /// XML version.
         ///
-        /// Defaults to `XmlVersion::Version10`.
+        /// Defaults to `XmlVersion::Version10`. In XML 1.1 documents control characters
+        /// in text and attribute values are written as character references.
         version: XmlVersion,
 
         /// XML document encoding.
@@ -41,9 +44,16 @@ pub enum XmlEvent<'a> {
         name: &'a str,
 
         /// Processing instruction content.
-        data: Option<&'a str>,
+        data: Option<Cow<'a, str>>,
     },
 
+    /// Denotes a document type declaration.
+    ///
+    /// The string is the complete `<!DOCTYPE …>` declaration, and it is written verbatim,
+    /// without checking its syntax, e.g. from the reader's `Doctype` event.
+    /// It must be written once, before the root element.
+    Doctype(&'a str),
+
     /// Denotes a beginning of an XML element.
     StartElement {
         /// Qualified name of the element.
@@ -91,6 +101,14 @@ pub enum XmlEvent<'a> {
     /// Contents of this event will be escaped if `perform_escaping` option is enabled,
     /// that is, every character invalid for PCDATA will appear as a character entity.
     Characters(&'a str),
+
+    /// Denotes a fragment of markup that is written without escaping, e.g. a pre-rendered template.
+    ///
+    /// The fragment is checked to be well-formed: tags must be balanced, except that it can close
+    /// elements opened earlier by the writer, and leave elements open to be closed later.
+    /// Names, attributes, references, comments, CDATA and processing instructions are checked
+    /// for syntax only. Namespaces declared in the fragment are not tracked.
+    RawMarkup(&'a str),
 }
 
 impl<'a> XmlEvent<'a> {
@@ -98,7 +116,7 @@ impl<'a> XmlEvent<'a> {
     #[inline]
     #[must_use]
     pub fn processing_instruction(name: &'a str, data: Option<&'a str>) -> XmlEvent<'a> {
-        XmlEvent::ProcessingInstruction { name, data }
+        XmlEvent::ProcessingInstruction { name, data: data.map(Cow::Borrowed) }
     }
 
     /// Returns a builder for a starting element.
@@ -150,6 +168,37 @@ impl<'a> XmlEvent<'a> {
     pub fn comment(data: &'a str) -> XmlEvent<'a> {
         XmlEvent::Comment(data)
     }
+
+    /// Returns an event for a fragment of markup, which is written unescaped.
+    #[inline]
+    #[must_use]
+    pub fn raw_markup(markup: &'a str) -> XmlEvent<'a> {
+        XmlEvent::RawMarkup(markup)
+    }
+
+    /// Returns an `<?xml-stylesheet href="…" type="…"?>` processing instruction, which
+    /// [associates a style sheet](https://www.w3.org/TR/xml-stylesheet/) with the document.
+    ///
+    /// Values of the pseudo-attributes are quoted and escaped.
+    #[must_use]
+    pub fn xml_stylesheet(href: &str, type_: &str) -> XmlEvent<'a> {
+        XmlEvent::ProcessingInstruction {
+            name: "xml-stylesheet",
+            data: Some(pseudo_attributes(&[("href", href), ("type", type_)]).into()),
+        }
+    }
+
+    /// Returns an `<?xml-model href="…" schematypens="…"?>` processing instruction, which
+    /// [associates a schema](https://www.w3.org/TR/xml-model/) with the document.
+    ///
+    /// Values of the pseudo-attributes are quoted and escaped.
+    #[must_use]
+    pub fn xml_model(href: &str, schematypens: &str) -> XmlEvent<'a> {
+        XmlEvent::ProcessingInstruction {
+            name: "xml-model",
+            data: Some(pseudo_attributes(&[("href", href), ("schematypens", schematypens)]).into()),
+        }
+    }
 }
 
 impl<'a> From<&'a str> for XmlEvent<'a> {
@@ -204,15 +253,33 @@ impl<'a> StartElementBuilder<'a> {
     /// are a violation of XML document well-formedness.
     ///
     /// The writer checks that you don't specify reserved prefix names, for example `xmlns`.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if the local name or the prefix isn't a valid name. Use [`try_attr()`](Self::try_attr)
+    /// for names that aren't known to be valid.
     #[inline]
     #[must_use]
     pub fn attr<N>(mut self, name: N, value: &'a str) -> StartElementBuilder<'a>
         where N: Into<Name<'a>>
     {
-        self.attributes.push(Attribute::new(name.into(), value));
+        let name = name.into();
+        debug_assert!(name.validate().is_ok(), "invalid attribute name {:?}", name.to_repr());
+        self.attributes.push(Attribute::new(name, value));
         self
     }
 
+    /// Same as [`attr()`](Self::attr), but fails if the local name or the prefix isn't a valid name,
+    /// instead of the writer failing with [`InvalidName`](crate::writer::Error::InvalidName).
+    pub fn try_attr<N>(mut self, name: N, value: &'a str) -> Result<StartElementBuilder<'a>, NameError>
+        where N: Into<Name<'a>>
+    {
+        let name = name.into();
+        name.validate()?;
+        self.attributes.push(Attribute::new(name, value));
+        Ok(self)
+    }
+
     /// Adds a namespace to the current namespace context.
     ///
     /// If no namespace URI was bound to the provided prefix at this point of the document,
@@ -225,15 +292,37 @@ impl<'a> StartElementBuilder<'a> {
     /// If some other namespace URI was bound to the provided prefix at this point of the document,
     /// then another binding will be added as a part of this element attribute set, shadowing
     /// the outer binding.
+    ///
+    /// An empty URI undeclares the prefix, which is allowed only in XML 1.1 documents.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if the prefix isn't empty and isn't a valid name without colons.
+    /// Use [`try_ns()`](Self::try_ns) for prefixes that aren't known to be valid.
     #[inline]
     #[must_use]
     pub fn ns<S1, S2>(mut self, prefix: S1, uri: S2) -> StartElementBuilder<'a>
         where S1: Into<String>, S2: Into<String>
     {
+        let prefix = prefix.into();
+        debug_assert!(prefix.is_empty() || is_valid_ncname(&prefix), "invalid namespace prefix {prefix:?}");
         self.namespace.put(prefix, uri);
         self
     }
 
+    /// Same as [`ns()`](Self::ns), but fails if the prefix isn't empty and isn't a valid name
+    /// without colons, instead of the writer failing with [`InvalidName`](crate::writer::Error::InvalidName).
+    pub fn try_ns<S1, S2>(mut self, prefix: S1, uri: S2) -> Result<StartElementBuilder<'a>, NameError>
+        where S1: Into<String>, S2: Into<String>
+    {
+        let prefix = prefix.into();
+        if !prefix.is_empty() {
+            Name::try_local(&prefix)?;
+        }
+        self.namespace.put(prefix, uri);
+        Ok(self)
+    }
+
     /// Adds a default namespace mapping to the current namespace context.
     ///
     /// Same rules as for `ns()` are also valid for the default namespace mapping.
@@ -257,3 +346,15 @@ impl<'a> From<StartElementBuilder<'a>> for XmlEvent<'a> {
         }
     }
 }
+
+/// Data of a processing instruction like `<?xml-stylesheet?>`. Only predefined entities can be used in the values.
+fn pseudo_attributes(attributes: &[(&str, &str)]) -> String {
+    let mut data = String::new();
+    for (name, value) in attributes {
+        if !data.is_empty() {
+            data.push(' ');
+        }
+        data.push_str(&format!("{name}=\"{}\"", Escaped::<AttributeEscapes>::new(value)));
+    }
+    data
+}
@@ -0,0 +1,92 @@
+//! Counts heap allocations of the reader, to catch regressions of the per-attribute allocations
+//! on documents with many small elements with a few attributes.
+//!
+//! The counts are compared with the same document without attributes, so that they don't
+//! depend on the allocations of the rest of the parser.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use xml::reader::XmlEvent;
+use xml::EventReader;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = Cell::new(0);
+}
+
+fn count() {
+    // the counter may be gone while the thread exits
+    let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns the number of allocations, and the number of attributes and of start tags with attributes
+fn allocations_while_reading(xml: &str) -> (usize, usize, usize) {
+    let (mut attributes, mut tags) = (0, 0);
+    let before = ALLOCATIONS.with(Cell::get);
+    for e in EventReader::new(xml.as_bytes()) {
+        if let XmlEvent::StartElement { attributes: a, .. } = e.unwrap() {
+            attributes += a.len();
+            tags += usize::from(!a.is_empty());
+        }
+    }
+    (ALLOCATIONS.with(Cell::get) - before, attributes, tags)
+}
+
+fn svg(attributes: bool) -> String {
+    let mut xml = String::from(r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 100 100">"#);
+    for i in 0..1000 {
+        if attributes {
+            xml += &format!(r#"<path d="M{i} 0L0 {i}Z" fill="red" opacity="0.{i}"/><circle cx="{i}" cy="5" r="2" stroke="red"/>"#);
+        } else {
+            xml += "<path/><circle/>";
+        }
+    }
+    xml += "</svg>";
+    xml
+}
+
+fn ooxml(attributes: bool) -> String {
+    let mut xml = String::from(r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheetData>"#);
+    for row in 1..200 {
+        xml += &if attributes { format!(r#"<row r="{row}" spans="1:5">"#) } else { "<row>".into() };
+        for col in ['A', 'B', 'C', 'D', 'E'] {
+            xml += &if attributes { format!(r#"<c r="{col}{row}" s="1" t="n"><v>{row}</v></c>"#) } else { format!("<c><v>{row}</v></c>") };
+        }
+        xml += "</row>";
+    }
+    xml += "</sheetData></worksheet>";
+    xml
+}
+
+// Same documents as the `read_svg_paths` and `read_ooxml_cells` benches.
+// Each attribute needs its value, and names of attributes are shared, so a tag with
+// a few attributes may only add one more allocation (for a value that outgrows its buffer).
+#[test]
+fn attribute_heavy_documents() {
+    for (doc, xml) in [("svg", svg as fn(bool) -> String), ("ooxml", ooxml)] {
+        let (with, attributes, tags) = allocations_while_reading(&xml(true));
+        let (without, ..) = allocations_while_reading(&xml(false));
+        assert!(with - without <= attributes + tags, "{doc}: {with} - {without} for {attributes} attributes in {tags} tags");
+    }
+}
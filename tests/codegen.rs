@@ -0,0 +1,75 @@
+#![cfg(feature = "codegen")]
+
+use xml::codegen::{BindError, Codegen};
+use xml::infer::InferredSchema;
+use xml::EventReader;
+
+mod order {
+    include!("codegen/order.rs");
+}
+
+mod order_serde {
+    include!("codegen/order_serde.rs");
+}
+
+const ORDER_XSD: &str = r#"<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema">
+    <xs:element name="order">
+        <xs:complexType>
+            <xs:sequence>
+                <xs:element name="customer" type="xs:string"/>
+                <xs:element name="paid" type="xs:boolean" minOccurs="0"/>
+                <xs:element name="item" maxOccurs="unbounded">
+                    <xs:complexType>
+                        <xs:simpleContent>
+                            <xs:extension base="xs:decimal">
+                                <xs:attribute name="sku" type="xs:string" use="required"/>
+                                <xs:attribute name="qty" type="xs:positiveInteger"/>
+                            </xs:extension>
+                        </xs:simpleContent>
+                    </xs:complexType>
+                </xs:element>
+            </xs:sequence>
+            <xs:attribute name="id" type="xs:int" use="required"/>
+        </xs:complexType>
+    </xs:element>
+</xs:schema>"#;
+
+#[test]
+fn generated_code_is_up_to_date() {
+    let schema = InferredSchema::from_xsd(EventReader::from_str(ORDER_XSD)).unwrap();
+    let code = Codegen::new().generate(&schema);
+    assert_eq!(code, include_str!("codegen/order.rs"));
+    let code = Codegen::new().serde(true).generate(&schema);
+    assert_eq!(code, include_str!("codegen/order_serde.rs"));
+}
+
+#[test]
+fn read_into_generated_types() {
+    let doc = r#"<order id="7"><customer>Ann</customer><item sku="a" qty="2"> 1.5 </item><item sku="b">3</item></order>"#;
+    let order: order::Order = xml::codegen::from_reader(EventReader::from_str(doc)).unwrap();
+    assert_eq!(order, order::Order {
+        id: 7,
+        customer: "Ann".into(),
+        paid: None,
+        item: vec![
+            order::Item { sku: "a".into(), qty: Some(2), text: 1.5 },
+            order::Item { sku: "b".into(), qty: None, text: 3. },
+        ],
+    });
+
+    let err = xml::codegen::from_reader::<order::Order, _>(EventReader::from_str(r#"<order id="x"/>"#)).unwrap_err();
+    assert!(matches!(&err, BindError::InvalidValue { element, name, value } if element == "order" && name == "id" && value == "x"));
+    let err = xml::codegen::from_reader::<order::Order, _>(EventReader::from_str(r#"<order id="1"/>"#)).unwrap_err();
+    assert_eq!(err.to_string(), "<order> is missing customer");
+    assert!(matches!(xml::codegen::from_reader::<order::Order, _>(EventReader::from_str("<order>")), Err(BindError::Reader(_))));
+}
+
+#[test]
+fn generated_serde_types() {
+    fn serde<T: serde::Serialize + serde::de::DeserializeOwned>(_: &T) {}
+
+    let doc = r#"<order id="7"><customer>Ann</customer><item sku="a">1.5</item></order>"#;
+    let order: order_serde::Order = xml::codegen::from_reader(EventReader::from_str(doc)).unwrap();
+    assert_eq!(order.item, [order_serde::Item { sku: "a".into(), qty: None, text: 1.5 }]);
+    serde(&order);
+}
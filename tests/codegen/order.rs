@@ -0,0 +1,39 @@
+// Generated by xml::codegen. Do not edit.
+
+/// The `order` element
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Order {
+    pub id: i64,
+    pub customer: String,
+    pub paid: Option<bool>,
+    pub item: Vec<Item>,
+}
+
+impl xml::codegen::FromElement for Order {
+    fn from_element(element: &xml::value::Element) -> Result<Self, xml::codegen::BindError> {
+        Ok(Self {
+            id: xml::codegen::attribute(element, "id")?,
+            customer: xml::codegen::child(element, "customer")?,
+            paid: xml::codegen::optional_child(element, "paid")?,
+            item: xml::codegen::children(element, "item")?,
+        })
+    }
+}
+
+/// The `item` element
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Item {
+    pub sku: String,
+    pub qty: Option<i64>,
+    pub text: f64,
+}
+
+impl xml::codegen::FromElement for Item {
+    fn from_element(element: &xml::value::Element) -> Result<Self, xml::codegen::BindError> {
+        Ok(Self {
+            sku: xml::codegen::attribute(element, "sku")?,
+            qty: xml::codegen::optional_attribute(element, "qty")?,
+            text: xml::codegen::text(element)?,
+        })
+    }
+}
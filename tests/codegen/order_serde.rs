@@ -0,0 +1,47 @@
+// Generated by xml::codegen. Do not edit.
+
+/// The `order` element
+#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Order {
+    #[serde(rename = "@id")]
+    pub id: i64,
+    pub customer: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paid: Option<bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub item: Vec<Item>,
+}
+
+impl xml::codegen::FromElement for Order {
+    fn from_element(element: &xml::value::Element) -> Result<Self, xml::codegen::BindError> {
+        Ok(Self {
+            id: xml::codegen::attribute(element, "id")?,
+            customer: xml::codegen::child(element, "customer")?,
+            paid: xml::codegen::optional_child(element, "paid")?,
+            item: xml::codegen::children(element, "item")?,
+        })
+    }
+}
+
+/// The `item` element
+#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Item {
+    #[serde(rename = "@sku")]
+    pub sku: String,
+    #[serde(rename = "@qty", default, skip_serializing_if = "Option::is_none")]
+    pub qty: Option<i64>,
+    #[serde(rename = "$text")]
+    pub text: f64,
+}
+
+impl xml::codegen::FromElement for Item {
+    fn from_element(element: &xml::value::Element) -> Result<Self, xml::codegen::BindError> {
+        Ok(Self {
+            sku: xml::codegen::attribute(element, "sku")?,
+            qty: xml::codegen::optional_attribute(element, "qty")?,
+            text: xml::codegen::text(element)?,
+        })
+    }
+}
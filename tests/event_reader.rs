@@ -2,9 +2,9 @@
 
 use std::fmt;
 use std::fs::File;
-use std::io::{stderr, BufRead, BufReader, Write};
+use std::io::{stderr, BufRead, BufReader, Read, Write};
 use std::path::Path;
-use xml::reader::ParserConfig2;
+use xml::reader::{CharReferenceKind, EntityTable, ErrorKind, InvalidCharReferences, ParserConfig2, TextOutsideRoot, Warning};
 
 use xml::common::Position;
 use xml::name::OwnedName;
@@ -275,7 +275,7 @@ fn limits1() {
         br#"<a attr='veeeeeeeeeeeeeeeeeeeerylooooooooooooooong'> "#,
         br#"
             |StartDocument(1.0, UTF-8)
-            |1:14 This document is larger/more complex than allowed by the parser's configuration
+            |1:14 The document exceeds max_attribute_length = 3 of the parser's configuration with 4 in 'attr'
         "#,
         ParserConfig::new().max_attribute_length(3),
         false,
@@ -288,7 +288,7 @@ fn limits2() {
         br#"<a a1='1' a2='2' a3='3' a4='4' a5='5'> "#,
         br#"
             |StartDocument(1.0, UTF-8)
-            |1:30 This document is larger/more complex than allowed by the parser's configuration
+            |1:30 The document exceeds max_attributes = 3 of the parser's configuration with 4 in 'a'
         "#,
         ParserConfig::new().max_attributes(3),
         false,
@@ -298,7 +298,7 @@ fn limits2() {
         br#"
             |StartDocument(1.0, UTF-8)
             |StartElement(a)
-            |1:15 This document is larger/more complex than allowed by the parser's configuration
+            |1:15 The document exceeds max_data_length = 10 of the parser's configuration with 11 in 'a'
         "#,
         ParserConfig::new().max_data_length(10),
         false,
@@ -312,7 +312,7 @@ fn limits3() {
         br#"
             |StartDocument(1.0, UTF-8)
             |StartElement(a)
-            |1:19 This document is larger/more complex than allowed by the parser's configuration
+            |1:19 The document exceeds max_data_length = 10 of the parser's configuration with 11
         "#,
         ParserConfig::new().max_data_length(10).ignore_comments(false),
         false,
@@ -322,13 +322,34 @@ fn limits3() {
         br#"
             |StartDocument(1.0, UTF-8)
             |StartElement(a)
-            |1:20 This document is larger/more complex than allowed by the parser's configuration
+            |1:20 The document exceeds max_data_length = 10 of the parser's configuration with 11 in 'ok'
         "#,
         ParserConfig::new().max_data_length(10),
         false,
     );
 }
 
+#[test]
+fn limit_error_details() {
+    use xml::reader::Limit;
+
+    let mut reader = ParserConfig2::new().max_attribute_length(5).create_reader(&br#"<a><b href="http://example.com"/></a>"#[..]);
+    let err = loop {
+        if let Err(e) = reader.next() {
+            break e;
+        }
+    };
+    match err.kind() {
+        ErrorKind::LimitExceeded { limit, configured, observed, name } => {
+            assert_eq!(*limit, Limit::AttributeLength);
+            assert_eq!(*configured, 5);
+            assert_eq!(*observed, 6);
+            assert_eq!(name.as_deref(), Some("href"));
+        },
+        other => panic!("{other:?}"),
+    }
+}
+
 #[test]
 fn limits4() {
     test(
@@ -336,7 +357,7 @@ fn limits4() {
         br#"
             |StartDocument(1.0, UTF-8)
             |StartElement(ok)
-            |1:10 This document is larger/more complex than allowed by the parser's configuration
+            |1:10 The document exceeds max_name_length = 3 of the parser's configuration with 4 in 'aver'
         "#,
         ParserConfig::new().max_name_length(3),
         false,
@@ -345,7 +366,7 @@ fn limits4() {
         br#"<a veeeeeeeeeeeeeeeeeeeerylooooooooooooooong='1'>"#,
         br#"
             |StartDocument(1.0, UTF-8)
-            |1:15 This document is larger/more complex than allowed by the parser's configuration
+            |1:15 The document exceeds max_name_length = 10 of the parser's configuration with 11 in 'veeeeeeeeee'
         "#,
         ParserConfig::new().max_name_length(10),
         false,
@@ -562,6 +583,127 @@ fn issue_attribues_have_no_default_namespace() {
     );
 }
 
+#[test]
+fn duplicate_namespace_declarations() {
+    test(
+        br#"<a xmlns:p="urn:1" xmlns:p="urn:2"/>"#,
+        br#"
+            |StartDocument(1.0, UTF-8)
+            |1:34 Namespace declaration 'xmlns:p' is repeated in the same tag
+        "#,
+        ParserConfig::new(),
+        false,
+    );
+
+    test(
+        br#"<a xmlns="urn:1" xmlns="urn:2"/>"#,
+        br#"
+            |StartDocument(1.0, UTF-8)
+            |1:30 Namespace declaration 'xmlns' is repeated in the same tag
+        "#,
+        ParserConfig::new(),
+        false,
+    );
+
+    test(
+        br#"<p:a xmlns:p="urn:1" xmlns:p="urn:2" xmlns="urn:3" xmlns="urn:4"><b xmlns:p="urn:5"/></p:a>"#,
+        br#"
+            |StartDocument(1.0, UTF-8)
+            |StartElement({urn:2}p:a)
+            |StartElement({urn:4}b)
+            |EndElement({urn:4}b)
+            |EndElement({urn:2}p:a)
+            |EndDocument
+        "#,
+        ParserConfig::new().allow_duplicate_namespace_declarations(true),
+        false,
+    );
+
+    let mut reader = ParserConfig::new().allow_duplicate_namespace_declarations(true)
+        .create_reader(&br#"<a xmlns:p="urn:1" xmlns:p="urn:2" xmlns="urn:3" xmlns="urn:4"/>"#[..]);
+    while !matches!(reader.next().unwrap(), XmlEvent::EndDocument) {}
+    let warnings: Vec<_> = reader.warnings().iter().map(|w| w.to_string()).collect();
+    assert_eq!(warnings, [
+        "1:34: used the last of repeated 'xmlns:p' declarations",
+        "1:62: used the last of repeated 'xmlns' declarations",
+    ]);
+}
+
+#[test]
+fn reserved_namespace_misuse() {
+    test(
+        br#"<a xmlns:p="http://www.w3.org/XML/1998/namespace"/>"#,
+        br#"
+            |StartDocument(1.0, UTF-8)
+            |1:49 Prefix 'p' cannot be bound to the reserved xml namespace
+        "#,
+        ParserConfig::new(),
+        false,
+    );
+
+    test(
+        br#"<a xmlns:p="http://www.w3.org/2000/xmlns/"/>"#,
+        br#"
+            |StartDocument(1.0, UTF-8)
+            |1:43 Prefix 'p' cannot be bound to the reserved xmlns namespace
+        "#,
+        ParserConfig::new(),
+        false,
+    );
+
+    test(
+        br#"<xmlns:a/>"#,
+        br#"
+            |StartDocument(1.0, UTF-8)
+            |1:9 'xmlns' cannot be an element name prefix
+        "#,
+        ParserConfig::new(),
+        false,
+    );
+
+    test(
+        br#"<xml:a xmlns:p="http://www.w3.org/2000/xmlns/"><xmlns:b/></xml:a>"#,
+        br#"
+            |StartDocument(1.0, UTF-8)
+            |StartElement({http://www.w3.org/XML/1998/namespace}xml:a)
+            |StartElement({http://www.w3.org/2000/xmlns/}xmlns:b)
+            |EndElement({http://www.w3.org/2000/xmlns/}xmlns:b)
+            |EndElement({http://www.w3.org/XML/1998/namespace}xml:a)
+            |EndDocument
+        "#,
+        ParserConfig::new().allow_reserved_namespace_misuse(true),
+        false,
+    );
+
+    let mut reader = ParserConfig::new().allow_reserved_namespace_misuse(true)
+        .create_reader(&br#"<xml:a xmlns:p="http://www.w3.org/2000/xmlns/" xmlns="http://www.w3.org/2000/xmlns/"><xmlns:b/></xml:a>"#[..]);
+    while !matches!(reader.next().unwrap(), XmlEvent::EndDocument) {}
+    let warnings: Vec<_> = reader.warnings().iter().map(|w| w.to_string()).collect();
+    assert_eq!(warnings, [
+        "1:47: accepted 'xmlns:p' that misuses a reserved namespace",
+        "1:85: accepted 'xmlns' that misuses a reserved namespace",
+        "1:94: accepted 'xmlns:b' that misuses a reserved namespace",
+    ]);
+}
+
+#[test]
+fn xml_stylesheet_processing_instruction() {
+    let mut r = EventReader::from_str(r#"<?xml-stylesheet href="a&amp;b.css" type="text/css" media='print' alternate="yes"?><?xml-stylesheet type="text/css"?><?xml-stylesheet href="x" bad?><a/>"#);
+    assert!(matches!(r.next().unwrap(), XmlEvent::StartDocument { .. }));
+
+    let s = r.next().unwrap().xml_stylesheet().unwrap();
+    assert_eq!(s.href, "a&b.css");
+    assert_eq!(s.mime_type.as_deref(), Some("text/css"));
+    assert_eq!(s.media.as_deref(), Some("print"));
+    assert_eq!(s.title, None);
+    assert!(s.alternate);
+
+    // href is required
+    assert_eq!(r.next().unwrap().xml_stylesheet(), None);
+    assert_eq!(r.next().unwrap().xml_stylesheet(), None);
+    assert_eq!(r.next().unwrap().xml_stylesheet(), None);
+}
+
 #[test]
 fn error_after_end() {
     test(
@@ -588,7 +730,7 @@ fn issue_replacement_character_entity_reference() {
         br#"
             |StartDocument(1.0, UTF-8)
             |StartElement(doc)
-            |1:13 Invalid character U+D83D
+            |1:13 Reference to surrogate U+D83D, which isn't a character
         "#,
         ParserConfig::new(),
         false,
@@ -599,7 +741,7 @@ fn issue_replacement_character_entity_reference() {
         br#"
             |StartDocument(1.0, UTF-8)
             |StartElement(doc)
-            |1:13 Invalid character U+D83D
+            |1:13 Reference to surrogate U+D83D, which isn't a character
         "#,
         ParserConfig::new(),
         false,
@@ -714,6 +856,1733 @@ fn push_pos_issue() {
     parser.into_iter().for_each(|e| { e.unwrap(); });
 }
 
+#[test]
+fn deferred_unsupported_encoding() {
+    let source = "<?xml version=\"1.0\" encoding=\"x-rot13\"?><ebbg>uryyb</ebbg>";
+
+    let mut reader = ParserConfig2::new().create_reader(source.as_bytes());
+    assert!(reader.next().is_err());
+    assert_eq!(None, reader.unsupported_encoding());
+
+    let mut reader = ParserConfig2::new().defer_unsupported_encoding(true).create_reader(source.as_bytes());
+    assert!(reader.next().is_err());
+    assert!(reader.next().is_err());
+    assert_eq!(Some("x-rot13"), reader.unsupported_encoding());
+
+    let reader = reader.resume_with_utf8_source(|mut source| {
+        let mut rot13 = String::new();
+        source.read_to_string(&mut rot13).unwrap();
+        let decoded: String = rot13.chars().map(|c| match c {
+            'a'..='m' => (c as u8 + 13) as char,
+            'n'..='z' => (c as u8 - 13) as char,
+            c => c,
+        }).collect();
+        std::io::Cursor::new(decoded)
+    });
+    let events: Vec<_> = reader.into_iter().map(|e| e.unwrap()).collect();
+    assert_eq!(events[0], XmlEvent::StartDocument {
+        version: xml::common::XmlVersion::Version10,
+        encoding: "x-rot13".into(),
+        standalone: None,
+    });
+    assert_eq!(events[2], XmlEvent::Characters("hello".into()));
+    assert_eq!(events[4], XmlEvent::EndDocument);
+}
+
+#[test]
+fn reader_stats() {
+    let source = "<!DOCTYPE r [<!ENTITY e 'x'>]><r><a><b/>&e;&e;</a><!--c--><?pi?><![CDATA[d]]>&extra;</r>";
+    let mut reader = ParserConfig::new()
+        .add_entity("extra", "y")
+        .ignore_comments(false)
+        .create_reader(source.as_bytes());
+    while reader.next().unwrap() != XmlEvent::EndDocument {}
+
+    let stats = reader.stats();
+    assert_eq!(stats.bytes_read, source.len() as u64);
+    assert_eq!(stats.events.elements, 3);
+    assert_eq!(stats.events.characters, 2);
+    assert_eq!(stats.events.cdata, 1);
+    assert_eq!(stats.events.comments, 1);
+    assert_eq!(stats.events.processing_instructions, 1);
+    assert_eq!(stats.max_depth, 3);
+    assert_eq!(stats.entity_expansions, 3);
+}
+
+#[test]
+fn attribute_source_details() {
+    let source = r#"<!DOCTYPE r [<!ENTITY e 'x'>]><r xmlns:p="urn:p" p:a='1&amp;2' b="&e;&#x33;"><c/></r>"#;
+    let mut reader = ParserConfig::new()
+        .preserve_source_details(true)
+        .create_reader(source.as_bytes());
+    loop {
+        if let XmlEvent::StartElement { name, attributes, .. } = reader.next().unwrap() {
+            let details = reader.attribute_details();
+            if name.local_name == "r" {
+                assert_eq!(attributes.len(), details.len());
+                assert_eq!(attributes[0].value, "1&2");
+                assert_eq!((details[0].index, details[0].quote, &*details[0].raw_value), (1, '\'', "1&amp;2"));
+                assert_eq!(attributes[1].value, "x3");
+                assert_eq!((details[1].index, details[1].quote, &*details[1].raw_value), (2, '"', "&e;&#x33;"));
+
+                let mut entities = EntityTable::new();
+                assert!(details[1].decode(&entities).is_err());
+                entities.insert("e", "x");
+                let decoded: Vec<_> = details.iter().map(|d| d.decode(&entities).unwrap()).collect();
+                assert_eq!(decoded, ["1&2", "x3"]);
+            } else {
+                assert!(details.is_empty());
+                break;
+            }
+        }
+    }
+
+    let mut reader = EventReader::from_str("<r a='1'/>");
+    while !matches!(reader.next().unwrap(), XmlEvent::StartElement { .. }) {}
+    assert!(reader.attribute_details().is_empty());
+
+    // decoded like the parser does, which doesn't expand references in values of entities in attributes
+    let source = "<!DOCTYPE r [<!ENTITY l0 'ha'><!ENTITY l1 '&l0;&l0;'>]><r a='&l1;' b='&l0;&l0;&l0;'/>";
+    let mut reader = ParserConfig2::new().preserve_source_details(true).max_attribute_length(5).create_reader(source.as_bytes());
+    while !matches!(reader.next().unwrap(), XmlEvent::StartElement { .. }) {}
+    let mut entities = EntityTable::new();
+    for (name, value) in reader.declared_entities() {
+        entities.insert(name, value);
+    }
+    let details = reader.attribute_details();
+    assert_eq!(details[0].decode(&entities).unwrap(), "&l0;&l0;");
+    assert_eq!(details[1].decode(&entities).unwrap(), "hahaha");
+    entities.insert("l0", "hahaha");
+    let err = details[1].decode(&entities).unwrap_err();
+    assert_eq!((err.offset, err.kind), (4, xml::escape::UnescapeErrorKind::TooBig));
+}
+
+#[test]
+fn entity_table() {
+    let mut table = EntityTable::new();
+    table.insert("b", "<b>&a;</b>");
+    table.load_dtd("<!ENTITY a 'A'><!ENTITY b 'ignored'><!ELEMENT r ANY>").unwrap();
+    table.add_external_dtd("urn:ext", "<!ENTITY ext 'E'><!ENTITY a 'ignored'>").unwrap();
+    assert_eq!(table.get("a"), Some("A"));
+    assert_eq!(table.get("b"), Some("<b>&a;</b>"));
+    assert!(table.load_dtd("<!ENTITY broken").is_err());
+
+    test(
+        br#"<!DOCTYPE r SYSTEM "urn:ext" [<!ENTITY ext "internal">]><r>&b;&ext;</r>"#,
+        br#"
+            |StartDocument(1.0, UTF-8)
+            |StartElement(r)
+            |StartElement(b)
+            |Characters("A")
+            |EndElement(b)
+            |Characters("internal")
+            |EndElement(r)
+            |EndDocument
+        "#,
+        ParserConfig2::new().entity_table(table.clone()),
+        false,
+    );
+    test(
+        br#"<!DOCTYPE r SYSTEM "urn:ext"><r>&ext;</r>"#,
+        br#"
+            |StartDocument(1.0, UTF-8)
+            |StartElement(r)
+            |Characters("E")
+            |EndElement(r)
+            |EndDocument
+        "#,
+        ParserConfig2::new().entity_table(table.clone()),
+        false,
+    );
+
+    let mut reader = ParserConfig2::new()
+        .entity_table(table.clone())
+        .create_reader(&b"<!DOCTYPE r [<!ENTITY c 'C'>]><r/>"[..]);
+    while reader.next().unwrap() != XmlEvent::EndDocument {}
+    let mut names: Vec<_> = reader.declared_entities().map(|(name, _)| name).collect();
+    names.sort_unstable();
+    assert_eq!(names, ["a", "b", "c"]);
+
+    table.freeze();
+    test(
+        br#"<!DOCTYPE r [<!ENTITY c "C">]><r>&a;&c;</r>"#,
+        br#"
+            |StartDocument(1.0, UTF-8)
+            |StartElement(r)
+            |1:39 Unexpected entity: c
+        "#,
+        ParserConfig2::new().entity_table(table),
+        false,
+    );
+}
+
+#[test]
+fn dtd_conditional_sections() {
+    let mut table = EntityTable::new();
+    table.load_dtd(r#"
+        <!ENTITY % draft "IGNORE">
+        <!ENTITY % final "INCLUDE">
+        <![%draft;[
+            <!ENTITY status "draft">
+            <![INCLUDE[ <!ENTITY nested "ignored too"> ]]>
+            <!ELEMENT doc (#PCDATA)>
+        ]]>
+        <![ %final; [
+            <!ENTITY status "final">
+            <![IGNORE[ <!ENTITY nested "ignored"> ]]>
+            <![INCLUDE[<!ENTITY nested "included">]]>
+        ]]>
+    "#).unwrap();
+    assert_eq!(table.get("status"), Some("final"));
+    assert_eq!(table.get("nested"), Some("included"));
+
+    assert!(EntityTable::new().load_dtd("<![INCLUDE[ <!ENTITY a 'b'>").is_err());
+    assert!(EntityTable::new().load_dtd("<![MAYBE[ <!ENTITY a 'b'> ]]>").is_err());
+    // only the external subset can have conditional sections
+    test(
+        br#"<!DOCTYPE r [<![INCLUDE[ <!ENTITY a 'b'> ]]>]><r/>"#,
+        br#"
+            |StartDocument(1.0, UTF-8)
+            |1:14 Conditional sections are only allowed in the external DTD subset
+        "#,
+        ParserConfig::new(),
+        false,
+    );
+}
+
+#[test]
+fn doctype_raw() {
+    test(
+        b"<?xml version='1.0'?>\n<!DOCTYPE r SYSTEM 'r.dtd' [\n  <!ENTITY % p '<!ENTITY e \"x\">'>\n  %p;\n]>\n<r>&e;</r>",
+        br#"
+            |1:1 StartDocument(1.0, UTF-8)
+            |2:1 Doctype("<!DOCTYPE r SYSTEM \'r.dtd\' [\n  <!ENTITY % p \'<!ENTITY e \"x\">\'>\n  %p;\n]>")
+            |6:1 StartElement(r)
+            |6:7 Characters("x")
+            |6:7 EndElement(r)
+            |6:11 EndDocument
+        "#,
+        ParserConfig2::new().capture_doctype_raw(true),
+        true,
+    );
+}
+
+#[test]
+fn idref_checking() {
+    let dtd = "<!DOCTYPE doc [\n<!ATTLIST item key ID #REQUIRED\n  type (a|b) 'a' ref IDREF #IMPLIED>\n<!ATTLIST doc refs IDREFS #IMPLIED>\n]>\n";
+
+    let source = format!("{dtd}<doc refs='i1  x2'><item key='i1' ref='x2'/><item key='i2' ref='i3'/><x xml:id='x2'/></doc>");
+    let mut reader = ParserConfig2::new().check_idrefs(true).create_reader(source.as_bytes());
+    loop {
+        match reader.next() {
+            Ok(XmlEvent::EndDocument) => break,
+            Ok(_) => {},
+            Err(e) => {
+                assert_eq!(e.to_string(), "6:45 IDREF refers to a missing ID: 'i3'");
+                break;
+            },
+        }
+    }
+    let unresolved: Vec<_> = reader.unresolved_idrefs().map(|(id, pos)| format!("{pos} {id}")).collect();
+    assert_eq!(unresolved, ["6:45 i3"]);
+
+    test(
+        format!("{dtd}<doc><item key='i1'/><item key='i1'/></doc>").as_bytes(),
+        br#"
+            |StartDocument(1.0, UTF-8)
+            |StartElement(doc)
+            |StartElement(item [key="i1"])
+            |EndElement(item)
+            |6:36 Duplicate ID: 'i1'
+        "#,
+        ParserConfig2::new().check_idrefs(true),
+        false,
+    );
+}
+
+#[test]
+fn raw_bytes_observer() {
+    use std::sync::{Arc, Mutex};
+
+    let mut input = vec![0xFF, 0xFE];
+    input.extend("<a>ąę</a>".encode_utf16().flat_map(u16::to_le_bytes));
+    let chunks = Arc::new(Mutex::new(Vec::new()));
+    let observed = chunks.clone();
+    let mut reader = ParserConfig2::new()
+        .raw_bytes_observer(move |bytes| observed.lock().unwrap().push(bytes.to_vec()))
+        .create_reader(&input[..]);
+    while reader.next().unwrap() != XmlEvent::EndDocument {}
+
+    let chunks = chunks.lock().unwrap();
+    assert!(chunks.len() > 1);
+    assert!(chunks.iter().all(|c| c.len() % 2 == 0));
+    assert_eq!(chunks.concat(), input);
+}
+
+#[test]
+fn tee_and_event_byte_ranges() {
+    let input = "<!--c--><a x='é'>text<b/>tail<?pi?></a>";
+    let mut reader = ParserConfig::new().ignore_comments(false).create_reader(input.as_bytes()).tee(Vec::new());
+    let mut slices = Vec::new();
+    loop {
+        let event = reader.next().unwrap();
+        if event == XmlEvent::EndDocument {
+            break;
+        }
+        let range = reader.event_byte_range();
+        slices.push(&input[range.start as usize..range.end as usize]);
+    }
+    assert_eq!(slices, ["", "<!--c-->", "<a x='é'>", "text", "<b/>", "<b/>", "tail", "<?pi?>", "</a>"]);
+    let (_, copy) = reader.into_inner().into_inner();
+    assert_eq!(copy, input.as_bytes());
+}
+
+#[test]
+fn events_only_mask() {
+    use xml::reader::EventMask;
+
+    let input = br#"<a x="1"><?pi?><!--c--><b>text</b> <c/><![CDATA[d]]></a>"#;
+    test(
+        input,
+        br#"
+            |1:9 StartElement(a)
+            |1:24 StartElement(b)
+            |1:36 StartElement(c)
+            |1:57 EndDocument
+        "#,
+        ParserConfig2::new().events_only(EventMask::START_ELEMENT),
+        true,
+    );
+    test(
+        input,
+        br#"
+            |ProcessingInstruction(pi="")
+            |Comment("c")
+            |Characters("text")
+            |EndElement(b)
+            |Whitespace(" ")
+            |EndElement(c)
+            |CData("d")
+            |EndElement(a)
+            |EndDocument
+        "#,
+        ParserConfig::new().ignore_comments(false).events_only(!(EventMask::START_DOCUMENT | EventMask::START_ELEMENT) | EventMask::ATTRIBUTES),
+        false,
+    );
+
+    let mut reader = ParserConfig2::new()
+        .events_only(EventMask::START_ELEMENT | EventMask::CHARACTERS)
+        .create_reader(&input[..]);
+    reader.next().unwrap();
+    reader.skip().unwrap();
+    let counts = reader.drain_count().unwrap();
+    assert_eq!((counts.elements, counts.characters, counts.comments), (0, 0, 0));
+
+    let counts = EventReader::new(&input[..]).drain_count().unwrap();
+    assert_eq!((counts.elements, counts.characters, counts.cdata, counts.processing_instructions), (3, 2, 1, 1));
+
+    // unwanted text isn't buffered, so it's not limited either
+    let long_text = format!("<a b='{}'>{}</a>", "v".repeat(1000), "t".repeat(1 << 20));
+    let config = ParserConfig2::new().max_data_length(100_000);
+    assert!(config.clone().create_reader(long_text.as_bytes()).drain_count().is_err());
+    let counts = config.events_only(EventMask::START_ELEMENT).create_reader(long_text.as_bytes()).drain_count().unwrap();
+    assert_eq!(counts.elements, 1);
+}
+
+#[test]
+fn event_filter_suppression() {
+    use xml::reader::EventMask;
+
+    test(
+        b"<!DOCTYPE a>\n<?pi data?>\n<a>\n  <!--c--><?pi more data?> <b>x</b>\n</a>",
+        br#"
+            |1:1 StartDocument(1.0, UTF-8)
+            |3:1 StartElement(a)
+            |4:28 StartElement(b)
+            |4:31 Characters("x")
+            |4:32 EndElement(b)
+            |5:1 EndElement(a)
+            |5:5 EndDocument
+        "#,
+        ParserConfig2::new()
+            .capture_doctype_raw(true)
+            .ignore_comments(false)
+            .event_filter(EventMask::DOCTYPE | EventMask::PROCESSING_INSTRUCTION | EventMask::COMMENT | EventMask::WHITESPACE),
+        true,
+    );
+}
+
+#[test]
+fn lazy_attributes() {
+    let input = br#"<!DOCTYPE a [<!ENTITY e "ent">]><a xmlns:p="urn:p" p:x="1 &lt; 2" y="&e;&#x41;" z="&bad;" w="&#0;"><b/></a>"#;
+    let mut reader = ParserConfig2::new().lazy_attributes(true).create_reader(&input[..]);
+    loop {
+        match reader.next().unwrap() {
+            XmlEvent::StartElement { name, attributes, namespace } if name.local_name == "a" => {
+                assert!(attributes.is_empty());
+                assert_eq!(namespace.get("p"), Some("urn:p"));
+                break;
+            },
+            _ => {},
+        }
+    }
+
+    let attrs: Vec<_> = reader.lazy_attributes().collect();
+    assert_eq!(attrs.len(), 4);
+    assert_eq!(attrs[0].name(), &OwnedName::qualified("x", "urn:p", Some("p")));
+    assert_eq!(attrs[0].raw_value(), "1 &lt; 2");
+    assert_eq!(attrs[0].value().unwrap(), "1 < 2");
+    assert_eq!(attrs[1].value().unwrap(), "entA");
+    let err = attrs[2].value().unwrap_err();
+    assert!(err.to_string().contains("bad"));
+    assert_eq!(err.position().to_string(), "1:83");
+    assert_eq!(attrs[3].value().unwrap_err().position().to_string(), "1:93");
+    assert_eq!(attrs[1].to_owned_attribute().unwrap(), xml::attribute::OwnedAttribute::new(OwnedName::local("y"), "entA"));
+
+    // raw values are still checked for the characters that can't appear in attributes
+    assert!(ParserConfig2::new().lazy_attributes(true).create_reader(&b"<a x='<'/>"[..]).into_iter().any(|e| e.is_err()));
+
+    match reader.next().unwrap() {
+        XmlEvent::StartElement { .. } => assert_eq!(reader.lazy_attributes().len(), 0),
+        e => panic!("{e:?}"),
+    }
+}
+
+#[test]
+#[cfg(feature = "unicode-normalization")]
+fn unicode_normalization() {
+    // the end tag uses a decomposed "é"
+    let input = "<caf\u{e9} a=\"e\u{301}\">cafe\u{301}<![CDATA[e\u{301}]]></cafe\u{301}>";
+    let events: Vec<_> = ParserConfig2::new().normalize_names(true).normalize_text(true)
+        .create_reader(input.as_bytes()).into_iter().map(Result::unwrap).collect();
+    match &events[1] {
+        XmlEvent::StartElement { name, attributes, .. } => {
+            assert_eq!(name.local_name, "caf\u{e9}");
+            assert_eq!(attributes[0].value, "\u{e9}");
+        },
+        e => panic!("{e:?}"),
+    }
+    assert_eq!(events[2], XmlEvent::Characters("caf\u{e9}".into()));
+    assert_eq!(events[3], XmlEvent::CData("\u{e9}".into()));
+    assert_eq!(events[4], XmlEvent::EndElement { name: OwnedName::local("caf\u{e9}") });
+
+    let mut reader = ParserConfig2::new().normalize_names(true).create_reader(input.as_bytes());
+    reader.next().unwrap();
+    match reader.next().unwrap() {
+        XmlEvent::StartElement { attributes, .. } => assert_eq!(attributes[0].value, "e\u{301}"),
+        e => panic!("{e:?}"),
+    }
+    assert!(EventReader::from_str(input).into_iter().any(|e| e.is_err()));
+}
+
+#[test]
+fn max_stalled_reads() {
+    /// Returns bytes of the chunks, with a `WouldBlock` error before each chunk
+    struct StallingSource<'a> {
+        chunks: std::slice::Iter<'a, &'a [u8]>,
+        current: &'a [u8],
+    }
+
+    impl Read for StallingSource<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.current.is_empty() {
+                match self.chunks.next() {
+                    Some(chunk) => self.current = chunk,
+                    None => return Ok(0),
+                }
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+            let n = self.current.len().min(buf.len());
+            buf[..n].copy_from_slice(&self.current[..n]);
+            self.current = &self.current[n..];
+            Ok(n)
+        }
+    }
+
+    // the split is in the middle of a tag, and of the UTF-8 bytes of "é"
+    let chunks: &[&[u8]] = &[b"<a>", b"t\xC3", b"\xA9<b x='", b"1'/></a>"];
+    let source = StallingSource { chunks: chunks.iter(), current: &[] };
+    let mut reader = ParserConfig2::new().max_stalled_reads(Some(1)).create_reader(source);
+    let mut events = Vec::new();
+    let mut stalls = 0;
+    loop {
+        match reader.next() {
+            Ok(XmlEvent::EndDocument) => break,
+            Ok(e) => events.push(Event(&Ok(e)).to_string()),
+            Err(e) => {
+                assert!(matches!(e.kind(), xml::reader::ErrorKind::Io(e) if e.kind() == std::io::ErrorKind::WouldBlock));
+                stalls += 1;
+            },
+        }
+    }
+    assert_eq!(events, [
+        "StartDocument(1.0, UTF-8)", "StartElement(a)", "Characters(\"t\u{e9}\")",
+        r#"StartElement(b [x="1"])"#, "EndElement(b)", "EndElement(a)",
+    ]);
+    assert!(stalls >= 4);
+
+    // without the option any I/O error is final
+    let source = StallingSource { chunks: chunks.iter(), current: &[] };
+    let mut reader = EventReader::new(source);
+    let err = reader.next().unwrap_err();
+    assert_eq!(reader.next().unwrap_err(), err);
+
+    // inside a tag only one stall in a row is allowed
+    struct Stalled<'a>(&'a [u8]);
+    impl Read for Stalled<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(std::io::ErrorKind::TimedOut.into());
+            }
+            let n = self.0.len().min(buf.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+    let mut reader = ParserConfig2::new().max_stalled_reads(Some(1)).create_reader(Stalled(b"<a><b x="));
+    assert!(reader.next().is_ok());
+    assert!(reader.next().is_ok());
+    let first = reader.next().unwrap_err();
+    let second = reader.next().unwrap_err();
+    assert_eq!(first, second);
+    assert_eq!(reader.next().unwrap_err(), second);
+}
+
+#[test]
+fn infoset_declarations() {
+    let mut reader = ParserConfig2::new().create_reader(&br#"<!DOCTYPE list [
+        <!NOTATION gif PUBLIC "-//GIF//EN">
+        <!NOTATION png SYSTEM "image/png">
+        <!ENTITY logo SYSTEM "logo.png" NDATA png>
+        <!ENTITY ext PUBLIC "-//EXT//EN" "ext.xml">
+        <!ELEMENT list (item*)>
+        <!ELEMENT item (#PCDATA)>
+    ]>
+    <list>
+        <item> </item>
+    </list>"#[..]);
+
+    let mut element_content_whitespace = Vec::new();
+    loop {
+        match reader.next().unwrap() {
+            XmlEvent::Whitespace(_) => element_content_whitespace.push(reader.is_element_content_whitespace()),
+            XmlEvent::EndDocument => break,
+            _ => assert!(!reader.is_element_content_whitespace()),
+        }
+    }
+    assert_eq!(element_content_whitespace, [true, false, true]);
+
+    let notations = reader.notations();
+    assert_eq!(notations.len(), 2);
+    assert_eq!((notations[0].name.as_str(), notations[0].public_id.as_deref(), notations[0].system_id.as_deref()), ("gif", Some("-//GIF//EN"), None));
+    assert_eq!((notations[1].name.as_str(), notations[1].public_id.as_deref(), notations[1].system_id.as_deref()), ("png", None, Some("image/png")));
+
+    let entities = reader.unparsed_entities();
+    assert_eq!(entities.len(), 1);
+    assert_eq!((entities[0].name.as_str(), entities[0].system_id.as_str(), entities[0].notation.as_str()), ("logo", "logo.png", "png"));
+
+    // declarations of prefixed names match the prefix as written
+    let doc = r#"<!DOCTYPE x:list [<!ELEMENT x:list (x:item*)><!ELEMENT list (#PCDATA)>]><x:list xmlns:x="urn:x"> <x:item/> <list> </list></x:list>"#;
+    let mut reader = ParserConfig2::new().create_reader(doc.as_bytes());
+    let mut element_content_whitespace = Vec::new();
+    loop {
+        match reader.next().unwrap() {
+            XmlEvent::Whitespace(_) => element_content_whitespace.push(reader.is_element_content_whitespace()),
+            XmlEvent::EndDocument => break,
+            _ => {},
+        }
+    }
+    assert_eq!(element_content_whitespace, [true, true, false]);
+}
+
+#[test]
+fn skip_leading_garbage_until_lt() {
+    let source = &b"HTTP/1.1 200 OK\r\n\r\n\xEF\xBB\xBF\xEF\xBB\xBF<?xml version='1.0'?><a>\xC3\xA9</a>"[..];
+    assert!(EventReader::new(source).into_iter().any(|e| e.is_err()));
+
+    let mut reader = ParserConfig2::new().skip_leading_garbage_until_lt(true).create_reader(source);
+    assert!(matches!(reader.next().unwrap(), XmlEvent::StartDocument { .. }));
+    assert_eq!(reader.warnings(), [xml::reader::Warning::LeadingGarbage { bytes: 22 }]);
+    reader.next().unwrap();
+    assert_eq!(reader.next().unwrap(), XmlEvent::Characters("\u{e9}".into()));
+
+    let mut reader = ParserConfig2::new().skip_leading_garbage_until_lt(true).create_reader(&b"<a/>"[..]);
+    while reader.next().unwrap() != XmlEvent::EndDocument {}
+    assert!(reader.warnings().is_empty());
+}
+
+#[test]
+fn multiple_documents() {
+    use xml::reader::DocumentDelimiter;
+
+    let source = "<?xml version='1.0'?><a>1</a>\n<?xml version=\"1.1\" encoding='utf-8'?>\n<!-- x --><b/> <c/>";
+    let events: Vec<_> = ParserConfig2::new()
+        .multiple_documents(Some(DocumentDelimiter::DeclarationOrElement))
+        .ignore_comments(true)
+        .create_reader(source.as_bytes())
+        .documents()
+        .map(|e| Event(&e).to_string())
+        .collect();
+    assert_eq!(events, [
+        "StartDocument(1.0, UTF-8)", "StartElement(a)", "Characters(\"1\")", "EndElement(a)", "EndDocument",
+        "StartDocument(1.1, utf-8)", "StartElement(b)", "EndElement(b)", "EndDocument",
+        "StartDocument(1.0, UTF-8)", "StartElement(c)", "EndElement(c)", "EndDocument",
+    ]);
+
+    let mut reader = ParserConfig2::new()
+        .multiple_documents(Some(DocumentDelimiter::Declaration))
+        .create_reader(source.as_bytes());
+    assert!(reader.next_document().unwrap());
+    assert_eq!(reader.position().row, 1);
+    assert!(matches!(reader.next().unwrap(), XmlEvent::StartDocument { version: xml::common::XmlVersion::Version11, .. }));
+    // multiple root elements are allowed by default
+    assert!(!reader.next_document().unwrap());
+
+    let mut reader = ParserConfig2::new().create_reader(source.as_bytes());
+    assert!(reader.next_document().is_err());
+    let mut reader = ParserConfig2::new().create_reader(&b"<a/>"[..]);
+    assert!(!reader.next_document().unwrap());
+}
+
+#[test]
+fn checkpoint_rewind() {
+    let source = std::io::Cursor::new("<a xmlns:p='urn:p'>\n  <p:b>text&amp;more</p:b>\n</a>");
+    let mut reader = ParserConfig2::new().trim_whitespace(true).create_reader(source);
+    reader.next().unwrap();
+    reader.next().unwrap();
+    let checkpoint = reader.checkpoint().unwrap();
+
+    let mut first_pass = Vec::new();
+    loop {
+        let event = reader.next().unwrap();
+        first_pass.push((Event(&Ok(event.clone())).to_string(), reader.position()));
+        if event == XmlEvent::EndDocument {
+            break;
+        }
+    }
+    assert_eq!(first_pass.len(), 5);
+
+    reader.rewind_to(&checkpoint).unwrap();
+    for (expected, pos) in &first_pass {
+        let event = reader.next();
+        assert_eq!(&Event(&event).to_string(), expected);
+        assert_eq!(reader.position(), *pos);
+    }
+    reader.rewind_to(&checkpoint).unwrap();
+    match reader.next().unwrap() {
+        XmlEvent::StartElement { name, .. } => assert_eq!(name.namespace.as_deref(), Some("urn:p")),
+        e => panic!("{e:?}"),
+    }
+}
+
+#[test]
+fn speculative_fork() {
+    let source = br#"<!DOCTYPE r [<!ENTITY e "ent">]><r xmlns="urn:r"><v kind="int">1</v><v kind="str">&e;</v></r>"#;
+    let mut reader = EventReader::new(&source[..]);
+    let mut events = Vec::new();
+    loop {
+        // each event is read by a fork first, which is then discarded
+        let mut fork = reader.clone();
+        let speculated = fork.next();
+        let event = reader.next();
+        assert_eq!(Event(&speculated).to_string(), Event(&event).to_string());
+        assert_eq!(fork.position(), reader.position());
+        if let Ok(XmlEvent::EndDocument) = event {
+            break;
+        }
+        events.push(event.unwrap());
+    }
+    assert!(events.contains(&XmlEvent::Characters("ent".into())));
+}
+
+#[test]
+fn thread_safety() {
+    fn send_sync<T: Send + Sync>() {}
+    send_sync::<EventReader<std::fs::File>>();
+    send_sync::<xml::reader::Events<std::fs::File>>();
+    send_sync::<ParserConfig>();
+    send_sync::<ParserConfig2>();
+    send_sync::<EntityTable>();
+    send_sync::<XmlEvent>();
+    send_sync::<xml::reader::Error>();
+    send_sync::<xml::reader::Checkpoint>();
+    send_sync::<xml::EventWriter<std::fs::File>>();
+    send_sync::<xml::writer::Error>();
+
+    let mut entities = EntityTable::new();
+    entities.insert("who", "world");
+    let config = std::sync::Arc::new(ParserConfig2::new().entity_table(entities));
+    let threads: Vec<_> = (0..4).map(|i| {
+        let config = config.clone();
+        std::thread::spawn(move || {
+            let doc = format!("<!DOCTYPE a [<!ENTITY n '{i}'>]><a>&who;&n;</a>");
+            let mut reader = EventReader::new_with_config(doc.as_bytes(), (*config).clone());
+            reader.next().unwrap();
+            reader.next().unwrap();
+            match reader.next().unwrap() {
+                XmlEvent::Characters(text) => text,
+                e => panic!("{e:?}"),
+            }
+        })
+    }).collect();
+    let texts: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+    assert_eq!(texts, ["world0", "world1", "world2", "world3"]);
+    assert_eq!(config.entity_table.get("n"), None);
+}
+
+#[test]
+fn reuse_reader() {
+    use xml::reader::ReaderPool;
+
+    let first = "<!DOCTYPE a [<!ENTITY e 'x'>]>\n<a>&e;<b/></a>";
+    let second = "<a>&e;</a>";
+    let mut reader = ParserConfig2::new().create_reader(first.as_bytes());
+    while reader.next().unwrap() != XmlEvent::EndDocument {}
+    assert_eq!(reader.reuse(second.as_bytes()), b"");
+    assert!(matches!(reader.next().unwrap(), XmlEvent::StartDocument { .. }));
+    assert!(matches!(reader.next().unwrap(), XmlEvent::StartElement { .. }));
+    assert_eq!(reader.position().row, 0);
+    // the entity was declared only in the first document
+    assert!(reader.next().is_err());
+
+    let pool = ReaderPool::new(ParserConfig2::new().check_idrefs(true));
+    for doc in [first, second, first] {
+        let mut reader = pool.reader(doc.as_bytes());
+        let mut events = Vec::new();
+        loop {
+            let event = reader.next();
+            events.push(Event(&event).to_string());
+            if !matches!(event, Ok(e) if e != XmlEvent::EndDocument) {
+                break;
+            }
+        }
+        if doc == first {
+            assert_eq!(events.len(), 7, "{events:?}");
+            assert!(reader.stats().bytes_read > 0);
+        } else {
+            assert_eq!(events.len(), 3, "{events:?}");
+        }
+        pool.recycle(reader);
+    }
+
+    // the pool's config replaces the config of a reader from elsewhere
+    let pool = ReaderPool::new(ParserConfig2::new());
+    let reader = ParserConfig2::new().add_entity("e", "y").create_reader(&b""[..]);
+    pool.recycle(reader);
+    assert!(pool.reader(second.as_bytes()).into_iter().any(|e| e.is_err()));
+}
+
+#[test]
+fn core_parser_tokens() {
+    use xml::common::TextPosition;
+    use xml::reader::{CoreParser, Token, TokenSource};
+
+    fn lex(text: &str) -> Vec<Token> {
+        let markup = [("<!DOCTYPE", Token::DoctypeStart), ("<!", Token::MarkupDeclarationStart), ("</", Token::ClosingTagStart),
+            ("/>", Token::EmptyTagEnd), ("<", Token::OpeningTagStart), (">", Token::TagEnd), ("=", Token::EqualsSign),
+            ("\"", Token::DoubleQuote), ("&", Token::ReferenceStart), (";", Token::ReferenceEnd)];
+        let mut rest = text;
+        let mut tokens = Vec::new();
+        while let Some(c) = rest.chars().next() {
+            match markup.iter().find(|(m, _)| rest.starts_with(m)) {
+                Some(&(m, t)) => { tokens.push(t); rest = &rest[m.len()..]; },
+                None => { tokens.push(Token::Character(c)); rest = &rest[c.len_utf8()..]; },
+            }
+        }
+        tokens
+    }
+
+    struct Columns(std::vec::IntoIter<Token>, u64);
+    impl TokenSource for Columns {
+        fn next_token(&mut self) -> Result<Option<Token>> {
+            self.1 += 1;
+            Ok(self.0.next())
+        }
+        fn position(&self) -> TextPosition {
+            TextPosition { row: 0, column: self.1 - 1, ..TextPosition::new() }
+        }
+    }
+
+    let doc = r#"<!DOCTYPE a [<!ENTITY e "hi">]><a x="1">&e;<b/>&amp;</a>"#;
+    let mut parser = CoreParser::new(ParserConfig2::new());
+    let mut tokens = lex(doc).into_iter();
+    let mut events = Vec::new();
+    loop {
+        let event = parser.advance(&mut tokens);
+        events.push(Event(&event).to_string());
+        if !matches!(event, Ok(e) if e != XmlEvent::EndDocument) {
+            break;
+        }
+    }
+    assert_eq!(events, [
+        "StartDocument(1.0, (unknown))", "StartElement(a [x=\"1\"])", "Characters(\"hi\")",
+        "StartElement(b)", "EndElement(b)", "Characters(\"&\")", "EndElement(a)", "EndDocument",
+    ]);
+
+    // the same document fed token by token
+    let mut parser = CoreParser::new(ParserConfig2::new());
+    let mut fed = Vec::new();
+    for token in lex(doc) {
+        fed.extend(parser.feed_token(token).unwrap().iter().map(|e| Event(&Ok(e.clone())).to_string()));
+    }
+    fed.push(Event(&parser.advance(&mut std::iter::empty())).to_string());
+    assert_eq!(fed, events);
+
+    let mut parser = CoreParser::new(ParserConfig2::new());
+    let mut source = Columns(lex("<a></b>").into_iter(), 0);
+    assert!(matches!(parser.advance(&mut source), Ok(XmlEvent::StartDocument { .. })));
+    assert!(matches!(parser.advance(&mut source), Ok(XmlEvent::StartElement { .. })));
+    let err = parser.advance(&mut source).unwrap_err();
+    // position of the `>` token from the source
+    assert_eq!(err.position().column, 5);
+
+    // errors of the last fed token aren't mistaken for waiting for more tokens
+    let mut parser = CoreParser::new(ParserConfig2::new());
+    let results: Vec<_> = lex("<a></b>").into_iter().map(|t| parser.feed_token(t)).collect();
+    let (last, rest) = results.split_last().unwrap();
+    assert!(rest.iter().all(|r| r.is_ok()));
+    assert!(matches!(last.as_ref().unwrap_err().kind(), ErrorKind::UnexpectedClosingTag { .. }), "{last:?}");
+}
+
+#[test]
+fn lazy_text() {
+    let long = "lorem ipsum ".repeat(20000);
+    let doc = format!("<doc>  {long}<![CDATA[{long}]]>&amp;</doc>");
+    let config = ParserConfig2::new().lazy_text(true).trim_whitespace(true);
+
+    let mut reader = config.clone().create_reader(doc.as_bytes());
+    let mut text = String::new();
+    loop {
+        match reader.next().unwrap() {
+            XmlEvent::Characters(part) | XmlEvent::CData(part) => {
+                assert!(part.len() <= 1 << 16);
+                text.push_str(&part);
+            },
+            XmlEvent::EndDocument => break,
+            _ => {},
+        }
+    }
+    assert_eq!(text, format!("{}{long}&", long.trim()));
+
+    let mut reader = config.create_reader(doc.as_bytes());
+    reader.next().unwrap();
+    reader.next().unwrap();
+    let mut text = match reader.next().unwrap() {
+        XmlEvent::Characters(part) => part,
+        e => panic!("{}", Event(&Ok(e))),
+    };
+    reader.text_reader().read_to_string(&mut text).unwrap();
+    assert_eq!(text, format!("{}{long}&", long.trim()));
+    match reader.next().unwrap() {
+        XmlEvent::EndElement { name } => assert_eq!(name.local_name, "doc"),
+        e => panic!("{}", Event(&Ok(e))),
+    }
+    assert_eq!(reader.next().unwrap(), XmlEvent::EndDocument);
+
+    // a part of the text that is only whitespace is a `Whitespace` event
+    let spaces = " ".repeat(70000);
+    let doc = format!("<doc>a<![CDATA[ ]]>{spaces}<![CDATA[b]]></doc>");
+    let mut reader = ParserConfig2::new().lazy_text(true).create_reader(doc.as_bytes());
+    reader.next().unwrap();
+    reader.next().unwrap();
+    let mut text = match reader.next().unwrap() {
+        XmlEvent::Characters(part) => part,
+        e => panic!("{}", Event(&Ok(e))),
+    };
+    reader.text_reader().read_to_string(&mut text).unwrap();
+    assert_eq!(text, format!("a {spaces}b"));
+    assert!(matches!(reader.next().unwrap(), XmlEvent::EndElement { .. }));
+}
+
+#[test]
+fn whitespace_options() {
+    fn text_events(config: ParserConfig2) -> Vec<String> {
+        config.create_reader(&b"<a>  x  \n y <b> </b>  </a>"[..]).into_iter().filter_map(|e| match e.unwrap() {
+            XmlEvent::Characters(s) => Some(format!("{s:?}")),
+            XmlEvent::Whitespace(s) => Some(format!("ws{s:?}")),
+            _ => None,
+        }).collect()
+    }
+
+    assert_eq!(text_events(ParserConfig2::new().trim_text(true)), [r#""x  \n y""#, r#"ws" ""#, r#"ws"  ""#]);
+    assert_eq!(text_events(ParserConfig2::new().collapse_whitespace(true)), [r#"" x y ""#, r#"ws" ""#, r#"ws"  ""#]);
+    assert_eq!(text_events(ParserConfig2::new().drop_whitespace_only_text(true)), [r#""  x  \n y ""#]);
+    assert_eq!(text_events(ParserConfig2::new().trim_text(true).collapse_whitespace(true).drop_whitespace_only_text(true)), [r#""x y""#]);
+    assert_eq!(
+        text_events(ParserConfig2::new().trim_text(true).drop_whitespace_only_text(true)),
+        text_events(ParserConfig2::new().trim_whitespace(true)),
+    );
+}
+
+#[test]
+fn char_reference_options() {
+    fn text(config: ParserConfig2, doc: &str) -> Result<String> {
+        let mut text = String::new();
+        for e in config.create_reader(doc.as_bytes()) {
+            if let XmlEvent::Characters(s) = e? {
+                text.push_str(&s);
+            }
+        }
+        Ok(text)
+    }
+
+    let doc = "<a> &#xA;x &#32;<b>&#9;</b></a>";
+    assert_eq!(text(ParserConfig2::new().trim_whitespace(true), doc).unwrap(), "x");
+    assert_eq!(text(ParserConfig2::new().trim_whitespace(true).preserve_escaped_whitespace(true), doc).unwrap(), "\nx  \t");
+    assert_eq!(text(ParserConfig2::new().collapse_whitespace(true).preserve_escaped_whitespace(true), doc).unwrap(), " \nx  \t");
+
+    let doc = "<a>&#1;</a>";
+    assert!(text(ParserConfig2::new(), doc).is_err());
+    let config = ParserConfig2::new().invalid_char_references(InvalidCharReferences::Replace);
+    assert_eq!(text(config, doc).unwrap(), "\u{fffd}");
+
+    let mut reader = ParserConfig2::new().invalid_char_references(InvalidCharReferences::Warning).create_reader(doc.as_bytes());
+    reader.next().unwrap();
+    reader.next().unwrap();
+    assert_eq!(reader.next().unwrap(), XmlEvent::Characters("\u{1}".into()));
+    match reader.warnings() {
+        [Warning::InvalidCharacterReference { code: 1, position }] => assert_eq!(position.column, 6),
+        w => panic!("{w:?}"),
+    }
+
+    let doc = "<a>&#xD800;<b c='&#57343;'/></a>";
+    let err = text(ParserConfig2::new(), doc).unwrap_err();
+    assert_eq!(err.to_string(), "1:11 Reference to surrogate U+D800, which isn't a character");
+    assert_eq!(err.invalid_char_reference(), Some((0xD800, CharReferenceKind::Surrogate)));
+    let config = ParserConfig2::new().invalid_char_references(InvalidCharReferences::Replace);
+    assert_eq!(text(config, doc).unwrap(), "\u{fffd}");
+    let mut reader = ParserConfig2::new().invalid_char_references(InvalidCharReferences::Warning).create_reader(doc.as_bytes());
+    while !matches!(reader.next().unwrap(), XmlEvent::EndDocument) {}
+    assert!(matches!(reader.warnings(), [
+        Warning::InvalidCharacterReference { code: 0xD800, .. },
+        Warning::InvalidCharacterReference { code: 0xDFFF, .. },
+    ]), "{:?}", reader.warnings());
+
+    let doc = "<a>&#xFDD0;&#x10FFFF;</a>";
+    assert_eq!(text(ParserConfig2::new(), doc).unwrap(), "\u{fdd0}\u{10ffff}");
+    let config = ParserConfig2::new().reject_noncharacter_references(true);
+    let err = text(config.clone(), doc).unwrap_err();
+    assert_eq!(err.to_string(), "1:11 Reference to noncharacter U+FDD0");
+    assert_eq!(err.invalid_char_reference(), Some((0xFDD0, CharReferenceKind::Noncharacter)));
+    assert_eq!(text(config.invalid_char_references(InvalidCharReferences::Replace), doc).unwrap(), "\u{fffd}\u{fffd}");
+    let err = text(ParserConfig2::new(), "<a>&#xFFFE;</a>").unwrap_err();
+    assert!(err.to_string().contains("U+FFFE"));
+    assert_eq!(err.invalid_char_reference(), Some((0xFFFE, CharReferenceKind::Invalid)));
+    assert_eq!(text(ParserConfig2::new(), "<a>\u{1}</a>").unwrap_err().invalid_char_reference(), None);
+}
+
+#[test]
+fn unexpected_closing_tag() {
+    let mut reader = EventReader::from_str("<a>\n  <x:b xmlns:x='urn:x'></x:c>\n</a>");
+    let err = loop {
+        if let Err(e) = reader.next() {
+            break e;
+        }
+    };
+    match err.kind() {
+        ErrorKind::UnexpectedClosingTag { expected, found, opened_at } => {
+            assert_eq!(expected.local_name, "b");
+            assert_eq!(expected.namespace.as_deref(), Some("urn:x"));
+            assert_eq!(found.local_name, "c");
+            assert_eq!((opened_at.row, opened_at.column), (1, 2));
+        },
+        e => panic!("{e:?}"),
+    }
+    assert_eq!(err.to_string(), "2:29 Unexpected closing tag </x:c>, expected </x:b> of the element opened at 2:3");
+}
+
+#[test]
+fn unclosed_elements() {
+    let mut reader = EventReader::from_str("<a>\n  <b><c/>\n    <d>text");
+    let err = loop {
+        if let Err(e) = reader.next() {
+            break e;
+        }
+    };
+    match err.kind() {
+        ErrorKind::UnclosedElements(open) => {
+            let open: Vec<_> = open.iter().map(|(name, pos)| (&*name.local_name, pos.row, pos.column)).collect();
+            assert_eq!(open, [("a", 0, 0), ("b", 1, 2), ("d", 2, 4)]);
+        },
+        e => panic!("{e:?}"),
+    }
+    assert_eq!(err.to_string(), "3:12 Unexpected end of stream: still inside the root element; unclosed <a> at 1:1 <b> at 2:3 <d> at 3:5");
+}
+
+#[test]
+#[cfg(feature = "html_entities")]
+fn html_entities() {
+    let doc = "<!DOCTYPE p [<!ENTITY hellip 'etc'>]><p title='&rsquo;'>a&nbsp;b&mdash;&hellip;</p>";
+    assert!(EventReader::from_str(doc).into_iter().any(|e| e.is_err()));
+
+    let events: Vec<_> = ParserConfig2::new().html_entities(true).create_reader(doc.as_bytes()).into_iter().map(|e| e.unwrap()).collect();
+    match &events[1] {
+        XmlEvent::StartElement { attributes, .. } => assert_eq!(attributes[0].value, "\u{2019}"),
+        e => panic!("{}", Event(&Ok(e.clone()))),
+    }
+    assert_eq!(events[2], XmlEvent::Characters("a\u{a0}b\u{2014}etc".into()));
+}
+
+#[test]
+#[cfg(any(feature = "flate2", feature = "zstd"))]
+fn decompressing_reader() {
+    use xml::compression::{Compression, DecompressingReader};
+
+    fn root_text(source: &[u8], compression: Compression) -> String {
+        let source = DecompressingReader::new(source).unwrap();
+        assert_eq!(source.compression(), compression);
+        EventReader::new(source).into_iter().find_map(|e| match e.unwrap() {
+            XmlEvent::Characters(s) => Some(s),
+            _ => None,
+        }).unwrap()
+    }
+
+    let doc = b"<urlset><url>https://example.com/</url></urlset>";
+    assert_eq!(root_text(doc, Compression::None), "https://example.com/");
+    assert_eq!(root_text(b"<a>x</a>", Compression::None), "x");
+
+    #[cfg(feature = "flate2")]
+    {
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(doc).unwrap();
+        assert_eq!(root_text(&gz.finish().unwrap(), Compression::Gzip), "https://example.com/");
+    }
+    #[cfg(feature = "zstd")]
+    {
+        let zst = zstd::encode_all(&doc[..], 3).unwrap();
+        assert_eq!(root_text(&zst, Compression::Zstd), "https://example.com/");
+    }
+}
+
+#[test]
+fn xop_includes() {
+    let doc = r#"<m xmlns:xop="http://www.w3.org/2004/08/xop/include">
+        <photo><xop:Include href="cid:part1%40example.org"/></photo>
+        <sig><xop:Include href="CID:sig"> <ext xmlns="urn:x">?</ext> </xop:Include></sig>
+    </m>"#;
+    let mut reader = ParserConfig2::new().trim_whitespace(true).xop_includes(true).create_reader(doc.as_bytes());
+    let mut events = Vec::new();
+    loop {
+        match reader.next().unwrap() {
+            XmlEvent::StartElement { name, .. } => events.push(format!("<{}>{}", name.local_name, reader.xop_content_id().unwrap_or(""))),
+            XmlEvent::EndElement { name } => events.push(format!("</{}>{}", name.local_name, reader.xop_content_id().unwrap_or(""))),
+            XmlEvent::EndDocument => break,
+            _ => {},
+        }
+    }
+    assert_eq!(events, ["<m>", "<photo>", "<Include>part1@example.org", "</Include>", "</photo>",
+        "<sig>", "<Include>sig", "</Include>", "</sig>", "</m>"]);
+
+    // disabled by default
+    assert!(EventReader::from_str(doc).into_iter().any(|e| matches!(e, Ok(XmlEvent::StartElement { name, .. }) if name.local_name == "Include")));
+
+    for doc in [
+        r#"<xop:Include xmlns:xop="http://www.w3.org/2004/08/xop/include" href="part1"/>"#,
+        r#"<xop:Include xmlns:xop="http://www.w3.org/2004/08/xop/include" href="cid:%4"/>"#,
+        r#"<xop:Include xmlns:xop="http://www.w3.org/2004/08/xop/include" href="cid:a">text</xop:Include>"#,
+    ] {
+        let config = ParserConfig2::new().xop_includes(true);
+        assert!(config.create_reader(doc.as_bytes()).into_iter().any(|e| e.is_err()), "{doc}");
+    }
+}
+
+#[test]
+fn xsi_attributes() {
+    let doc = r#"<r xmlns:i="http://www.w3.org/2001/XMLSchema-instance" xmlns:t="urn:types" xmlns="urn:default">
+        <a i:nil=" true "/><b i:type="t:Point"/><c i:type="Local" i:nil="false"/>
+        <d xsi:nil="true" xsi:type="t:Point" xmlns:xsi="urn:not-xsi"/><e i:type="u:Bad"/>
+    </r>"#;
+    let starts: Vec<_> = EventReader::from_str(doc).into_iter().map(|e| e.unwrap())
+        .filter(|e| matches!(e, XmlEvent::StartElement { .. })).collect();
+    let nil: Vec<_> = starts.iter().map(|e| e.is_xsi_nil()).collect();
+    assert_eq!(nil, [false, true, false, false, false, false]);
+    let types: Vec<_> = starts.iter().map(|e| e.xsi_type().map(|n| (n.local_name, n.namespace))).collect();
+    assert_eq!(types, [
+        None,
+        None,
+        Some(("Point".into(), Some("urn:types".into()))),
+        Some(("Local".into(), Some("urn:default".into()))),
+        None,
+        None,
+    ]);
+    assert!(!XmlEvent::EndDocument.is_xsi_nil());
+}
+
+#[test]
+fn prefix_undeclaration() {
+    let doc = r#"<?xml version="1.1"?><p:a xmlns:p="urn:p"><b xmlns:p=""><p:c xmlns:p="urn:q"/></b></p:a>"#;
+    let events: Vec<_> = EventReader::from_str(doc).into_iter().map(|e| e.unwrap()).collect();
+    match &events[2] {
+        XmlEvent::StartElement { name, namespace, .. } => {
+            assert_eq!(name.namespace, None);
+            assert_eq!(namespace.get("p"), Some(""));
+        },
+        e => panic!("{}", Event(&Ok(e.clone()))),
+    }
+    match &events[3] {
+        XmlEvent::StartElement { name, .. } => assert_eq!(name.namespace.as_deref(), Some("urn:q")),
+        e => panic!("{}", Event(&Ok(e.clone()))),
+    }
+
+    for doc in [
+        r#"<?xml version="1.0"?><p:a xmlns:p="urn:p"><b xmlns:p=""/></p:a>"#,
+        r#"<?xml version="1.1"?><p:a xmlns:p="urn:p"><p:b xmlns:p=""/></p:a>"#,
+        r#"<?xml version="1.1"?><p:a xmlns:p="urn:p"><b xmlns:p="" p:x="1"/></p:a>"#,
+    ] {
+        assert!(EventReader::from_str(doc).into_iter().any(|e| e.is_err()), "{doc}");
+    }
+}
+
+#[test]
+fn namespace_policy() {
+    fn error(config: ParserConfig2, doc: &str) -> Option<String> {
+        config.create_reader(doc.as_bytes()).into_iter().find_map(|e| e.err()).map(|e| e.to_string())
+    }
+
+    let soap = ParserConfig2::new()
+        .require_root_element("Envelope")
+        .require_namespace("http://www.w3.org/2003/05/soap-envelope")
+        .forbid_namespaces(["http://www.w3.org/2001/XInclude"]);
+    let doc = r#"<s:Envelope xmlns:s="http://www.w3.org/2003/05/soap-envelope" xmlns:xi="http://www.w3.org/2001/XInclude"><s:Body/></s:Envelope>"#;
+    assert_eq!(error(soap.clone(), doc), None);
+
+    let doc = r#"<Envelope><Body/></Envelope>"#;
+    assert_eq!(error(soap.clone(), doc).unwrap(), "1:1 The document type is not allowed: unexpected root element Envelope");
+    let doc = r#"<s:Envelope xmlns:s="http://www.w3.org/2003/05/soap-envelope"><s:Body><x xmlns:xi="http://www.w3.org/2001/XInclude" xi:href="a"/></s:Body></s:Envelope>"#;
+    assert_eq!(error(soap, doc).unwrap(), "1:71 xi:href is in a forbidden namespace 'http://www.w3.org/2001/XInclude'");
+}
+
+#[test]
+fn to_value() {
+    use xml::value::{Element, Value};
+
+    let doc = r#"<?xml version="1.0"?><!-- c --><feed xmlns="urn:atom" xmlns:x="urn:x" xml:lang="en"><title>a <![CDATA[&]]> b</title><x:e x:k="v"/><?pi?></feed>"#;
+    let value = xml::to_value(EventReader::from_str(doc)).unwrap();
+    let mut attributes = std::collections::BTreeMap::new();
+    attributes.insert("x:k".to_string(), "v".to_string());
+    assert_eq!(value, Value::Element(Element {
+        name: "feed".into(),
+        prefix: None,
+        namespace: Some("urn:atom".into()),
+        attributes: [("xml:lang".to_string(), "en".to_string())].into_iter().collect(),
+        namespaces: [(String::new(), "urn:atom".to_string()), ("x".to_string(), "urn:x".to_string())].into_iter().collect(),
+        children: vec![
+            Value::Element(Element {
+                name: "title".into(),
+                namespace: Some("urn:atom".into()),
+                children: vec![Value::Text("a & b".into())],
+                ..Element::default()
+            }),
+            Value::Element(Element {
+                name: "e".into(),
+                prefix: Some("x".into()),
+                namespace: Some("urn:x".into()),
+                attributes,
+                ..Element::default()
+            }),
+        ],
+    }));
+    assert_eq!(value.as_element().unwrap().elements("title").next().unwrap().text(), "a & b");
+
+    assert!(xml::to_value(EventReader::from_str("<a>")).is_err());
+}
+
+#[test]
+fn xml_index() {
+    use std::io::Cursor;
+    use xml::reader::XmlIndex;
+
+    let dump = "<mediawiki xmlns=\"urn:mw\">\n  <page><title>A</title></page>\n  <page><title>B &amp; C</title><section><section/></section></page>\n</mediawiki>";
+    let index = XmlIndex::build(EventReader::from_str(dump), &["/mediawiki/page", "section"]).unwrap();
+    let ranges: Vec<_> = index.entries().iter().map(|e| (e.path, &dump[e.byte_range.start as usize..e.byte_range.end as usize])).collect();
+    assert_eq!(ranges, [
+        (0, "<page><title>A</title></page>"),
+        (0, "<page><title>B &amp; C</title><section><section/></section></page>"),
+        (1, "<section><section/></section>"),
+        (1, "<section/>"),
+    ]);
+
+    let page = &index.entries()[1];
+    let value = xml::to_value(page.reader(Cursor::new(dump), ParserConfig2::new()).unwrap()).unwrap();
+    let page = value.as_element().unwrap();
+    assert_eq!(page.namespace.as_deref(), Some("urn:mw"));
+    assert_eq!(page.text(), "B & C");
+}
+
+#[test]
+fn saved_checkpoints() {
+    use std::io::Cursor;
+    use xml::reader::Checkpoint;
+
+    let doc = "<?xml version=\"1.1\"?>\n<!DOCTYPE r [<!ENTITY e \"ent\">]>\n<r xmlns=\"urn:r\" xmlns:p=\"urn:p\">\n <p:a x=\"&e;\"><b/>t&e;<!--c--></p:a>\n <c xmlns:p=\"urn:q\"><p:d/></c><?pi?></r>";
+    let config = ParserConfig2::new();
+    let mut reader = EventReader::new_with_config(Cursor::new(doc), config.clone());
+    let mut events = Vec::new();
+    let mut saved = Vec::new();
+    loop {
+        let event = reader.next().unwrap();
+        let end = event == XmlEvent::EndDocument;
+        events.push((event, reader.position()));
+        if let Some(bytes) = reader.checkpoint().unwrap().to_bytes() {
+            saved.push((events.len(), bytes));
+        }
+        if end {
+            break;
+        }
+    }
+    // after the start and end tags, and the processing instruction
+    assert_eq!(saved.len(), 8);
+
+    for (done, bytes) in saved {
+        let mut reader = EventReader::new_with_config(Cursor::new(doc), config.clone());
+        reader.rewind_to(&Checkpoint::from_bytes(&bytes, config.clone()).unwrap()).unwrap();
+        for expected in &events[done..] {
+            assert_eq!(&(reader.next().unwrap(), reader.position()), expected);
+        }
+    }
+
+    assert!(Checkpoint::from_bytes(b"xml-rs", config).is_err());
+
+    // IDs and DTD declarations seen before the checkpoint are still checked after it
+    let doc = "<!DOCTYPE d [<!ATTLIST i k ID #REQUIRED r IDREF #IMPLIED>]><d><i k='a' r='z'/><i k='a'/></d>";
+    let config = ParserConfig2::new().check_idrefs(true);
+    let mut reader = EventReader::new_with_config(Cursor::new(doc), config.clone());
+    while !matches!(reader.next().unwrap(), XmlEvent::EndElement { .. }) {}
+    let bytes = reader.checkpoint().unwrap().to_bytes().unwrap();
+    let mut reader = EventReader::new_with_config(Cursor::new(doc), config.clone());
+    reader.rewind_to(&Checkpoint::from_bytes(&bytes, config).unwrap()).unwrap();
+    assert_eq!(reader.unresolved_idrefs().map(|(id, _)| id).collect::<Vec<_>>(), ["z"]);
+    assert_eq!(reader.next().unwrap_err().to_string(), "1:87 Duplicate ID: 'a'");
+}
+
+#[test]
+fn entity_expansion_stats() {
+    let doc = br#"<!DOCTYPE d [<!ENTITY a "aaaa"><!ENTITY b "&a;&a;">]><d x="&a;">&b;&b;&c;</d>"#;
+    let mut reader = ParserConfig::new().add_entity("c", "cc").create_reader(&doc[..]);
+    loop {
+        if reader.next().unwrap() == XmlEvent::EndDocument {
+            break;
+        }
+    }
+    let stats = reader.stats();
+    assert_eq!(stats.entity_expansions, 8);
+    assert_eq!((stats.entities["a"].count, stats.entities["a"].bytes), (5, 20));
+    assert_eq!((stats.entities["b"].count, stats.entities["b"].bytes), (2, 12));
+    assert_eq!((stats.entities["c"].count, stats.entities["c"].bytes), (1, 2));
+
+    let mut reader = ParserConfig2::new()
+        .check_entity_expansion(|name, stats| name != "a" || stats.bytes <= 12)
+        .create_reader(&doc[..]);
+    let err = loop {
+        match reader.next() {
+            Ok(XmlEvent::EndDocument) => panic!("expansion wasn't stopped"),
+            Ok(_) => {},
+            Err(e) => break e,
+        }
+    };
+    assert!(err.to_string().contains("entity 'a' was rejected"), "{err}");
+    assert_eq!(reader.stats().entities["a"].count, 4);
+
+    // values of lazy attributes are counted and checked when they're decoded
+    let mut reader = ParserConfig2::new()
+        .lazy_attributes(true)
+        .check_entity_expansion(|_, stats| stats.count <= 1)
+        .create_reader(&doc[..]);
+    while !matches!(reader.next().unwrap(), XmlEvent::StartElement { .. }) {}
+    let attr = reader.lazy_attributes().next().unwrap();
+    assert_eq!(attr.value().unwrap(), "aaaa");
+    assert_eq!(reader.stats().entities["a"].count, 1);
+    assert!(attr.value().unwrap_err().to_string().contains("entity 'a' was rejected"));
+}
+
+#[test]
+fn text_handling_hook() {
+    use xml::reader::TextHandling;
+
+    let long = "lorem ipsum ".repeat(20000);
+    let doc = format!("<doc><skip>{long}<![CDATA[{long}]]></skip><keep>a&amp;b</keep><stream>{long}</stream><skip/>end</doc>");
+    let mut reader = ParserConfig2::new()
+        .text_handling(|event| match event {
+            XmlEvent::StartElement { name, .. } => match &*name.local_name {
+                "keep" => TextHandling::Collect,
+                "stream" => TextHandling::Stream,
+                _ => TextHandling::Skip,
+            },
+            _ => TextHandling::Collect,
+        })
+        .create_reader(doc.as_bytes());
+    let mut texts = Vec::new();
+    let mut streamed = String::new();
+    let mut elements = 0;
+    loop {
+        match reader.next().unwrap() {
+            XmlEvent::StartElement { .. } => elements += 1,
+            XmlEvent::Characters(text) | XmlEvent::CData(text) if text.len() > 3 => {
+                assert!(text.len() <= 1 << 16);
+                streamed.push_str(&text);
+            },
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => texts.push(text),
+            XmlEvent::EndDocument => break,
+            _ => {},
+        }
+    }
+    assert_eq!(elements, 5);
+    assert_eq!(texts, ["a&b", "end"]);
+    assert_eq!(streamed, long);
+    assert_eq!(reader.stats().events.characters, 6);
+}
+
+#[test]
+fn scan_find_first() {
+    let doc = "<?xml version='1.0'?>\n<!DOCTYPE a [<!ENTITY e '<b/>'>]>\n<a xmlns:x='urn:x'>\n  <![CDATA[<b>]]><?pi <b>?>\n  <c t='/>'><x:b/></c>\n  <b/>\n</a>";
+    let mut reader = EventReader::from_str(doc);
+    let mut starts = Vec::new();
+    loop {
+        match reader.next().unwrap() {
+            XmlEvent::StartElement { name, .. } => starts.push((name.local_name, reader.position())),
+            XmlEvent::EndDocument => break,
+            _ => {},
+        }
+    }
+    let position_of = |name: &str, nth: usize| starts.iter().filter(|(n, _)| n == name).nth(nth).unwrap().1;
+
+    assert_eq!(xml::scan::find_first(doc.as_bytes(), "b"), Some(position_of("b", 0)));
+    assert_eq!(xml::scan::find_first(doc.as_bytes(), "/a/b"), Some(position_of("b", 1)));
+    assert_eq!(xml::scan::find_first(doc.as_bytes(), "a/*"), Some(position_of("c", 0)));
+    assert_eq!(xml::scan::find_first(doc.as_bytes(), "/b"), None);
+    assert_eq!(xml::scan::find_first(&b"<a><b"[..], "c"), None);
+}
+
+#[test]
+fn events_with_positions() {
+    let doc = "<a>\n  <b x='1'>text</b>\n</a>";
+    let mut expected = Vec::new();
+    let mut reader = EventReader::from_str(doc);
+    loop {
+        let event = reader.next().unwrap();
+        let done = event == XmlEvent::EndDocument;
+        expected.push((reader.position(), event));
+        if done {
+            break;
+        }
+    }
+    let events: Vec<_> = EventReader::from_str(doc).into_events_with_positions().map(Result::unwrap).collect();
+    assert_eq!(events, expected);
+    assert_eq!(events[4].0.to_string(), "2:12");
+
+    let mut events = EventReader::from_str("<a></b>").into_events_with_positions();
+    assert!(events.by_ref().any(|e| e.is_err()));
+    assert!(events.next().is_none());
+}
+
+#[test]
+fn parser_controls() {
+    let doc = "<a><b>&x;</b><c>0123456789</c></a>";
+    let mut events = EventReader::from_str(doc).into_iter();
+    assert!(events.by_ref().take(3).all(|e| e.is_ok()));
+    events.controls().add_entity("x", "y").set_max_data_length(5);
+    assert_eq!(events.controls().config().max_data_length, 5);
+    assert_eq!(events.next().unwrap().unwrap(), XmlEvent::Characters("y".into()));
+    let err = events.find_map(|e| e.err()).unwrap();
+    assert!(matches!(err.kind(), ErrorKind::LimitExceeded { configured: 5, .. }), "{err}");
+
+    let mut reader = ParserConfig2::new().max_data_length(5).create_reader(doc.as_bytes());
+    reader.controls().set_max_data_length(100).add_entity("x", "");
+    let mut documents = reader.documents();
+    assert!(documents.by_ref().all(|e| e.is_ok()));
+    assert_eq!(documents.controls().config().max_data_length, 100);
+
+    // attributes that have been read lazily stay lazy
+    let mut reader = ParserConfig2::new().lazy_attributes(true).create_reader(&b"<a x='&lt;'><b y='&amp;'/></a>"[..]);
+    reader.next().unwrap();
+    reader.next().unwrap();
+    reader.controls().set_lazy_attributes(false);
+    assert_eq!(reader.lazy_attributes().next().unwrap().value().unwrap(), "<");
+    match reader.next().unwrap() {
+        XmlEvent::StartElement { attributes, .. } => assert_eq!(attributes[0].value, "&"),
+        e => panic!("{e:?}"),
+    }
+    assert_eq!(reader.lazy_attributes().len(), 0);
+
+    // changes are only for the current document
+    let mut reader = ParserConfig2::new().max_data_length(5).create_reader(doc.as_bytes());
+    reader.controls().set_max_data_length(100).add_entity("x", "");
+    while reader.next().unwrap() != XmlEvent::EndDocument {}
+    reader.reuse(doc.as_bytes());
+    assert_eq!(reader.controls().config().max_data_length, 5);
+    assert!(reader.into_iter().any(|e| e.is_err()));
+}
+
+#[test]
+fn lenient_attribute_values() {
+    let doc = r#"<ad href="?a=1&b=2&amp;c" alt="x < y & z" title='&nbsp'/>"#;
+    let mut reader = EventReader::from_str(doc);
+    reader.next().unwrap();
+    assert!(reader.next().is_err());
+
+    for lazy in [false, true] {
+        let mut reader = ParserConfig2::new().lenient_attribute_values(true).lazy_attributes(lazy).create_reader(doc.as_bytes());
+        reader.next().unwrap();
+        let attributes = match reader.next().unwrap() {
+            XmlEvent::StartElement { attributes, .. } if !lazy => attributes,
+            XmlEvent::StartElement { .. } => reader.lazy_attributes().map(|a| a.to_owned_attribute().unwrap()).collect(),
+            e => panic!("{e:?}"),
+        };
+        let values: Vec<_> = attributes.iter().map(|a| &*a.value).collect();
+        assert_eq!(values, ["?a=1&b=2&c", "x < y & z", "&nbsp"]);
+        if !lazy {
+            let warnings: Vec<_> = reader.warnings().iter().map(|w| w.to_string()).collect();
+            assert_eq!(warnings, [
+                "1:17: kept unescaped '&' in an attribute value",
+                "1:34: kept unescaped '<' in an attribute value",
+                "1:39: kept unescaped '&' in an attribute value",
+                "1:55: kept unescaped '&' in an attribute value",
+            ]);
+        }
+    }
+}
+
+#[test]
+fn error_context() {
+    let doc = "<feed>\n\t<entry id=\"1\"/>\n\t<entry id=2/>\n</feed>";
+    let err = EventReader::from_str(doc).into_iter().find_map(Result::err).unwrap();
+    assert!(err.context().is_none());
+
+    let mut reader = ParserConfig2::new().error_context(29).create_reader(doc.as_bytes());
+    let err = loop {
+        if let Err(e) = reader.next() {
+            break e;
+        }
+    };
+    let context = err.context().unwrap();
+    assert_eq!(context.text, "\t<entry id=\"1\"/>\n\t<entry id=2");
+    assert_eq!(&context.text[context.error_index.unwrap()..], "2");
+    assert_eq!(err.to_string(), "3:12 Unexpected token: 2\n    \t<entry id=\"1\"/>\n    \t<entry id=2\n    \t          ^");
+}
+
+#[test]
+fn text_outside_root() {
+    let doc = "<?xml version=\"1.0\"?>\nwarning: x > 1\n<rss><item>a &amp; b</item></rss>\n<!-- end --> junk & more; \n";
+    let mut reader = EventReader::from_str(doc);
+    reader.next().unwrap();
+    assert!(reader.next().is_err());
+
+    for mode in [TextOutsideRoot::Warning, TextOutsideRoot::Ignore] {
+        let mut reader = ParserConfig2::new()
+            .ignore_root_level_whitespace(true)
+            .ignore_comments(false)
+            .text_outside_root(mode)
+            .create_reader(doc.as_bytes());
+        let mut events = Vec::new();
+        loop {
+            match reader.next().unwrap() {
+                XmlEvent::EndDocument => break,
+                XmlEvent::StartElement { name, .. } => events.push(name.local_name.into()),
+                XmlEvent::Characters(text) => events.push(text),
+                XmlEvent::Comment(text) => events.push(text),
+                _ => {},
+            }
+        }
+        assert_eq!(events, ["rss", "item", "a & b", " end "]);
+        let warnings: Vec<_> = reader.warnings().iter().map(|w| w.to_string()).collect();
+        if mode == TextOutsideRoot::Warning {
+            assert_eq!(warnings, [
+                "2:1: skipped 14 bytes of text outside the root element",
+                "4:14: skipped 12 bytes of text outside the root element",
+            ]);
+            assert_eq!(reader.take_warnings().len(), 2);
+            assert!(reader.warnings().is_empty());
+        } else {
+            assert!(warnings.is_empty());
+        }
+    }
+
+    let mut reader = ParserConfig2::new().text_outside_root(TextOutsideRoot::Warning).max_warnings(1)
+        .create_reader(doc.as_bytes());
+    while !matches!(reader.next().unwrap(), XmlEvent::EndDocument) {}
+    assert_eq!(reader.warnings().len(), 1);
+
+    // text before the declaration is still an error
+    let reader = ParserConfig2::new().text_outside_root(TextOutsideRoot::Ignore)
+        .create_reader(&b"junk<?xml version=\"1.0\"?><a/>"[..]);
+    assert!(reader.into_iter().any(|e| e.is_err()));
+
+    let mut reader = ParserConfig2::new().text_outside_root(TextOutsideRoot::Ignore)
+        .create_reader(&b"junk <a/>"[..]);
+    assert!(matches!(reader.next().unwrap(), XmlEvent::StartDocument { .. }));
+    assert!(matches!(reader.next().unwrap(), XmlEvent::StartElement { .. }));
+}
+
+#[test]
+fn doctype_internal_subset() {
+    let subset = |doc: &[u8]| {
+        let mut reader = ParserConfig2::new().capture_doctype_raw(true).create_reader(doc);
+        loop {
+            match reader.next().unwrap() {
+                XmlEvent::Doctype { has_internal_subset, internal_subset_byte_range, .. } => {
+                    assert_eq!(has_internal_subset, internal_subset_byte_range.is_some());
+                    return internal_subset_byte_range;
+                },
+                XmlEvent::EndDocument => panic!("no doctype"),
+                _ => {},
+            }
+        }
+    };
+
+    assert_eq!(subset(br#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Strict//EN" "x[1].dtd"><html/>"#), None);
+    assert_eq!(subset(b"<!DOCTYPE svg><svg/>"), None);
+
+    let doc = "<?xml version=\"1.0\"?>\n<!DOCTYPE r SYSTEM \"r.dtd\" [\n  <!ENTITY e \"é]\">\n  <!-- ] -->\n]><r>&e;</r>";
+    let range = subset(doc.as_bytes()).unwrap();
+    assert_eq!(&doc[range.start as usize..range.end as usize], "[\n  <!ENTITY e \"é]\">\n  <!-- ] -->\n]");
+
+    let utf16: Vec<u8> = "\u{feff}<!DOCTYPE r [<!ENTITY e 'x'>]><r/>".encode_utf16().flat_map(u16::to_le_bytes).collect();
+    assert_eq!(subset(&utf16), Some(26..60));
+}
+
+#[test]
+fn build_element_tree() {
+    let doc = r#"<list xmlns="urn:l" xmlns:x="urn:x"><item x:n="1">a<b/><!--c--></item> <skip/><item x:n="2"><item/></item></list>"#;
+    let mut reader = EventReader::from_str(doc);
+    let mut items = Vec::new();
+    let mut rest = Vec::new();
+    loop {
+        let event = reader.next().unwrap();
+        match &event {
+            XmlEvent::StartElement { name, .. } if name.local_name == "item" => items.push(reader.build_element_tree(event).unwrap()),
+            XmlEvent::EndDocument => break,
+            _ => rest.push(event),
+        }
+    }
+    let written: Vec<String> = items.iter().map(|item| {
+        let mut out = Vec::new();
+        item.write_to(&mut xml::EmitterConfig::new().write_document_declaration(false).create_writer(&mut out)).unwrap();
+        String::from_utf8(out).unwrap()
+    }).collect();
+    assert_eq!(written, [
+        r#"<item xmlns="urn:l" xmlns:x="urn:x" x:n="1">a<b /></item>"#,
+        r#"<item xmlns="urn:l" xmlns:x="urn:x" x:n="2"><item /></item>"#,
+    ]);
+    // the events between the trees are still returned
+    assert_eq!(rest.len(), 6);
+    assert!(matches!(&rest[4], XmlEvent::EndElement { name } if name.local_name == "skip"));
+
+    let mut reader = EventReader::from_str("<a/>");
+    let event = reader.next().unwrap();
+    assert!(reader.build_element_tree(event).is_err());
+}
+
+#[test]
+fn coalesce_across_ignored_markup() {
+    let doc = "<?pi before?><a>x<!--c-->y<?pi d?><![CDATA[z]]> <b/><?pi?>w<c>v</c></a>";
+    let mut reader = ParserConfig2::new().cdata_to_characters(true).ignore_processing_instructions(true).create_reader(doc.as_bytes());
+    let mut events = Vec::new();
+    loop {
+        let event = reader.next().unwrap();
+        match event {
+            XmlEvent::Characters(text) => events.push(format!("{text}{}", if reader.text_contains_cdata() { " (cdata)" } else { "" })),
+            XmlEvent::ProcessingInstruction { name, .. } => events.push(format!("<?{name}?>")),
+            XmlEvent::StartElement { name, .. } => events.push(format!("<{name}>")),
+            XmlEvent::EndDocument => break,
+            _ => assert!(!reader.text_contains_cdata()),
+        }
+    }
+    assert_eq!(events, ["<?pi?>", "<a>", "xyz  (cdata)", "<b>", "w", "<c>", "v"]);
+
+    let events: Vec<_> = ParserConfig2::new().coalesce_characters(false).ignore_processing_instructions(true)
+        .create_reader(&b"<a>x<?pi?>y<![CDATA[z]]></a>"[..])
+        .into_iter().map(Result::unwrap).collect();
+    assert_eq!(events[2..5], [XmlEvent::Characters("x".into()), XmlEvent::Characters("y".into()), XmlEvent::CData("z".into())]);
+
+    // targets of ignored instructions are still checked
+    for doc in ["<a>x<?xml version='1.0'?>y</a>", "<a><?1bad?></a>", "<a><? pi?></a>", "<a><?p<?></a>"] {
+        for coalesce in [true, false] {
+            let reader = ParserConfig2::new().coalesce_characters(coalesce).ignore_processing_instructions(true).create_reader(doc.as_bytes());
+            assert!(reader.into_iter().any(|e| e.is_err()), "{doc}");
+        }
+    }
+}
+
+#[test]
+fn whitespace_text_flag() {
+    let doc = "<a> <![CDATA[ ]]>&#x20;<b>\t</b><c><![CDATA[\n]]></c>x <!-- --> </a>";
+    let mut reader = ParserConfig2::new().cdata_to_characters(true).ignore_comments(true).create_reader(doc.as_bytes());
+    let mut texts = Vec::new();
+    loop {
+        match reader.next().unwrap() {
+            XmlEvent::Characters(text) | XmlEvent::Whitespace(text) => texts.push((text, reader.is_whitespace_text())),
+            XmlEvent::EndDocument => break,
+            _ => assert!(!reader.is_whitespace_text()),
+        }
+    }
+    assert_eq!(texts, [("   ".into(), true), ("\t".into(), true), ("\n".into(), true), ("x  ".into(), false)]);
+
+    // text after a CDATA section, and escaped whitespace that isn't trimmed
+    let doc = "<a><![CDATA[x]]> <b>&#x20;</b></a>";
+    let mut reader = ParserConfig2::new().preserve_escaped_whitespace(true).create_reader(doc.as_bytes());
+    let mut texts = Vec::new();
+    loop {
+        match reader.next().unwrap() {
+            XmlEvent::Characters(text) | XmlEvent::Whitespace(text) | XmlEvent::CData(text) => texts.push((text, reader.is_whitespace_text())),
+            XmlEvent::EndDocument => break,
+            _ => {},
+        }
+    }
+    assert_eq!(texts, [("x".into(), false), (" ".into(), true), (" ".into(), true)]);
+}
+
+#[test]
+fn xml_ids() {
+    let mut reader = ParserConfig2::new()
+        .track_xml_ids(true)
+        .create_reader(&b"<a xml:id='top'>\n <b xml:id=' b1 '/><c id='x'/></a>"[..]);
+    while !matches!(reader.next().unwrap(), XmlEvent::EndDocument) {}
+    let mut ids: Vec<_> = reader.xml_ids().iter().map(|(id, pos)| (id.as_str(), pos.to_string())).collect();
+    ids.sort();
+    assert_eq!(ids, [("b1", "2:2".to_string()), ("top", "1:1".to_string())]);
+
+    test(
+        br#"<a xml:id="x"><b xml:id="x"/></a>"#,
+        br#"
+            |StartDocument(1.0, UTF-8)
+            |StartElement(a [{http://www.w3.org/XML/1998/namespace}xml:id="x"])
+            |1:28 Duplicate xml:id: 'x'
+        "#,
+        ParserConfig2::new().track_xml_ids(true),
+        false,
+    );
+    test(
+        br#"<a xml:id="1x"/>"#,
+        br#"
+            |StartDocument(1.0, UTF-8)
+            |1:15 Invalid xml:id value: '1x'
+        "#,
+        ParserConfig2::new().track_xml_ids(true),
+        false,
+    );
+}
+
+#[test]
+fn element_indices() {
+    let mut reader = EventReader::from_str("<a><b><c/></b>text<d/></a>");
+    let mut indices = Vec::new();
+    loop {
+        match reader.next().unwrap() {
+            XmlEvent::StartElement { name, .. } => indices.push((format!("<{name}>"), reader.element_index(), reader.parent_element_index())),
+            XmlEvent::EndElement { name } => indices.push((format!("</{name}>"), reader.element_index(), reader.parent_element_index())),
+            XmlEvent::EndDocument => break,
+            _ => {},
+        }
+    }
+    assert_eq!(indices, [
+        ("<a>".to_string(), Some(0), None),
+        ("<b>".to_string(), Some(1), Some(0)),
+        ("<c>".to_string(), Some(2), Some(1)),
+        ("</c>".to_string(), Some(2), Some(1)),
+        ("</b>".to_string(), Some(1), Some(0)),
+        ("<d>".to_string(), Some(3), Some(0)),
+        ("</d>".to_string(), Some(3), Some(0)),
+        ("</a>".to_string(), Some(0), None),
+    ]);
+}
+
+#[test]
+fn position_line_endings() {
+    test(
+        b"<a>\r\n<b/>\r<c/>\n\r\n</a>\r&",
+        br#"
+            |1:1 StartDocument(1.0, UTF-8)
+            |1:3 StartElement(a)
+            |1:4 Whitespace("\r\n")
+            |2:1 StartElement(b)
+            |2:1 EndElement(b)
+            |2:5 Whitespace("\r")
+            |3:1 StartElement(c)
+            |3:1 EndElement(c)
+            |3:5 Whitespace("\n\r\n")
+            |5:1 EndElement(a)
+            |6:1 6:1 Unexpected token: &
+        "#,
+        ParserConfig2::new(),
+        true,
+    );
+}
+
+#[test]
+fn position_offsets_and_tabs() {
+    let mut reader = ParserConfig2::new()
+        .tab_width(4)
+        .create_reader("<r>\n\t<é/>\t<x/></r>".as_bytes());
+    let mut positions = Vec::new();
+    loop {
+        match reader.next().unwrap() {
+            XmlEvent::StartElement { name, .. } if name.local_name != "r" => {
+                let pos = reader.position();
+                positions.push((pos.line(), pos.column_number(), pos.byte_offset, pos.char_offset));
+            },
+            XmlEvent::EndDocument => break,
+            _ => {},
+        }
+    }
+    assert_eq!(positions, [(2, 5, 5, 5), (2, 13, 11, 10)]);
+
+    // the same line and column in a different source
+    let x_position = |doc: &str| {
+        let mut reader = ParserConfig2::new().create_reader(doc.as_bytes());
+        while !matches!(reader.next().unwrap(), XmlEvent::StartElement { name, .. } if name.local_name == "x") {}
+        reader.position()
+    };
+    let (utf8, ascii) = (x_position("<r>é<x/></r>"), x_position("<r>e<x/></r>"));
+    assert_ne!(utf8.byte_offset, ascii.byte_offset);
+    assert_eq!(utf8, ascii);
+}
+
+#[test]
+fn xhtml_entities() {
+    test(
+        br#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Strict//EN" "http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd"><p>&nbsp;&euro;&amp;</p>"#,
+        r#"
+            |StartDocument(1.0, UTF-8)
+            |StartElement(p)
+            |Characters("\u{a0}€&")
+            |EndElement(p)
+            |EndDocument
+        "#.as_bytes(),
+        ParserConfig2::new().entity_table(EntityTable::xhtml()),
+        false,
+    );
+}
+
+#[test]
+fn lowercase_names() {
+    test(
+        br#"<Root xmlns:P="urn:p" Attr="1" P:Other="2"><P:Child/><ITEM>x</item></ROOT>"#,
+        br#"
+            |StartDocument(1.0, UTF-8)
+            |StartElement(root [attr="1", {urn:p}P:other="2"])
+            |StartElement({urn:p}P:child)
+            |EndElement({urn:p}P:child)
+            |StartElement(item)
+            |Characters("x")
+            |EndElement(item)
+            |EndElement(root)
+            |EndDocument
+        "#,
+        ParserConfig2::new().lowercase_names(true),
+        false,
+    );
+    test(
+        br#"<a A="1" a="2"/>"#,
+        br#"
+            |StartDocument(1.0, UTF-8)
+            |1:11 Attribute 'a' is redefined
+        "#,
+        ParserConfig2::new().lowercase_names(true),
+        false,
+    );
+}
+
 // clones a lot but that's fine
 fn trim_until_bar(s: String) -> String {
     match s.trim() {
@@ -874,6 +2743,9 @@ impl<'a> fmt::Display for Event<'a> {
                     write!(f, r#"Characters("{}")"#, data.escape_debug()),
                 XmlEvent::Whitespace(ref data) =>
                     write!(f, r#"Whitespace("{}")"#, data.escape_debug()),
+                XmlEvent::Doctype { ref syntax, .. } =>
+                    write!(f, r#"Doctype("{}")"#, syntax.escape_debug()),
+                ref e => write!(f, "{e:?}"),
             },
             Err(ref e) => e.fmt(f),
         }
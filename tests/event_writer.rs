@@ -293,3 +293,1125 @@ fn accidental_cdata_suffix_in_characters_is_escaped() {
         assert!(matches!(r.next().unwrap(), XmlEvent::EndDocument));
     }
 }
+
+#[test]
+fn writing_document_declaration_options() {
+    use xml::common::XmlVersion;
+    use xml::writer::XmlEvent;
+
+    let mut b = Vec::new();
+    {
+        let mut w = EmitterConfig::new()
+            .write_declaration_encoding(false)
+            .declaration_single_quotes(true)
+            .create_writer(&mut b);
+
+        unwrap_all! {
+            w.write(XmlEvent::StartDocument { version: XmlVersion::Version10, encoding: None, standalone: Some(true) });
+            w.write(XmlEvent::start_element("root"));
+            w.write(XmlEvent::end_element())
+        }
+    }
+
+    assert_eq!(str::from_utf8(&b).unwrap(), "<?xml version='1.0' standalone='yes'?><root />");
+}
+
+#[test]
+fn writing_xml11_restricted_chars() {
+    use xml::common::XmlVersion;
+    use xml::writer::XmlEvent;
+
+    let mut b = Vec::new();
+    {
+        let mut w = EmitterConfig::new().create_writer(&mut b);
+
+        unwrap_all! {
+            w.write(XmlEvent::StartDocument { version: XmlVersion::Version11, encoding: None, standalone: None });
+            w.write(XmlEvent::start_element("root").attr("a", "\u{1}<\u{85}"));
+            w.write(XmlEvent::characters("\u{7f}&\u{e9}"));
+            w.write(XmlEvent::end_element())
+        }
+    }
+
+    let s = str::from_utf8(&b).unwrap();
+    assert_eq!(s, "<?xml version=\"1.1\" encoding=\"UTF-8\"?><root a=\"&#x1;&lt;\u{85}\">&#x7F;&amp;\u{e9}</root>");
+
+    {
+        use xml::reader::{EventReader, XmlEvent};
+        let mut r = EventReader::new(s.as_bytes());
+        assert!(matches!(r.next().unwrap(), XmlEvent::StartDocument { version: XmlVersion::Version11, .. }));
+        match r.next().unwrap() {
+            XmlEvent::StartElement { attributes, .. } => assert_eq!(attributes[0].value, "\u{1}<\u{85}"),
+            e => panic!("unexpected {e:?}"),
+        }
+        assert_eq!(r.next().unwrap(), XmlEvent::Characters("\u{7f}&\u{e9}".into()));
+    }
+}
+
+#[test]
+fn writing_trailing_newline() {
+    use xml::writer::XmlEvent;
+
+    let mut b = Vec::new();
+    {
+        let mut w = EmitterConfig::new()
+            .perform_indent(true)
+            .line_separator("\r\n")
+            .write_trailing_newline(true)
+            .create_writer(&mut b);
+
+        unwrap_all! {
+            w.write(XmlEvent::start_element("root"));
+            w.write(XmlEvent::start_element("child"));
+            w.write(XmlEvent::end_element());
+            w.write(XmlEvent::end_element());
+            w.write(XmlEvent::comment("after"));
+            w.write(XmlEvent::processing_instruction("pi", None))
+        }
+    }
+
+    assert_eq!(
+        str::from_utf8(&b).unwrap(),
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\r\n<root>\r\n  <child />\r\n</root>\r\n<!-- after -->\r\n<?pi?>\r\n"
+    );
+}
+
+#[test]
+fn writing_lowercase_names() {
+    use xml::writer::XmlEvent;
+
+    let mut b = Vec::new();
+    {
+        let mut w = EmitterConfig::new()
+            .write_document_declaration(false)
+            .lowercase_names(true)
+            .create_writer(&mut b);
+
+        unwrap_all! {
+            w.write(XmlEvent::start_element("P:Root").ns("P", "urn:P").attr("Attr", "V"));
+            w.write(XmlEvent::start_element("Child"));
+            w.write(XmlEvent::characters("Text"));
+            w.write(XmlEvent::end_element().name("CHILD"));
+            w.write(XmlEvent::end_element())
+        }
+    }
+
+    assert_eq!(
+        str::from_utf8(&b).unwrap(),
+        r#"<P:root xmlns:P="urn:P" attr="V"><child>Text</child></P:root>"#
+    );
+}
+
+#[test]
+fn writing_raw_doctype() {
+    let source = "<!DOCTYPE  r [\n<!ENTITY e 'x'>\n]><r>&e;</r>";
+    let reader = xml::ParserConfig::new()
+        .capture_doctype_raw(true)
+        .create_reader(source.as_bytes());
+    let mut b = Vec::new();
+    let mut w = EmitterConfig::new().create_writer(&mut b);
+    for e in reader {
+        if let Some(e) = e.unwrap().as_writer_event() {
+            w.write(e).unwrap();
+        }
+    }
+    assert_eq!(str::from_utf8(&b).unwrap(), "<?xml version=\"1.0\" encoding=\"UTF-8\"?><!DOCTYPE  r [\n<!ENTITY e 'x'>\n]><r>x</r>");
+}
+
+#[test]
+fn filter_chain() {
+    use xml::filter::{self, EventFilter, FilterOutput};
+    use xml::reader::XmlEvent;
+
+    /// Removes `<script>` elements with their content
+    struct StripScripts { depth: usize }
+    impl EventFilter for StripScripts {
+        fn transform(&mut self, event: XmlEvent) -> FilterOutput {
+            match event {
+                XmlEvent::StartElement { ref name, .. } if self.depth > 0 || name.local_name == "script" => {
+                    self.depth += 1;
+                    FilterOutput::Drop
+                },
+                XmlEvent::EndElement { .. } if self.depth > 0 => {
+                    self.depth -= 1;
+                    FilterOutput::Drop
+                },
+                _ if self.depth > 0 => FilterOutput::Drop,
+                e => FilterOutput::Emit(e),
+            }
+        }
+    }
+
+    let wrap_text = |e: XmlEvent| match e {
+        XmlEvent::Characters(text) => FilterOutput::Replace(vec![
+            XmlEvent::StartElement { name: xml::name::OwnedName::local("span"), attributes: xml::attribute::Attributes::new(), namespace: xml::namespace::Namespace::empty() },
+            XmlEvent::Characters(text),
+            XmlEvent::EndElement { name: xml::name::OwnedName::local("span") },
+        ]),
+        e => FilterOutput::Emit(e),
+    };
+
+    let reader = EventReader::from_str("<p>a<script>x<b>y</b></script>b</p>");
+    let mut b = Vec::new();
+    let mut w = EmitterConfig::new().create_writer(&mut b);
+    filter::copy(reader, &mut w, StripScripts { depth: 0 }.chain(wrap_text)).unwrap();
+    assert_eq!(str::from_utf8(&b).unwrap(), r#"<?xml version="1.0" encoding="UTF-8"?><p><span>a</span><span>b</span></p>"#);
+
+    let names: Vec<_> = StripScripts { depth: 0 }
+        .filter_events(EventReader::from_str("<p><script/><i/></p>"))
+        .filter_map(|e| match e.unwrap() {
+            XmlEvent::StartElement { name, .. } => Some(name.local_name),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(names, ["p", "i"]);
+}
+
+#[test]
+fn namespace_rewriting_filter() {
+    use xml::filter::{self, NamespaceRewriter};
+    use xml::name::OwnedName;
+
+    let source = r#"<doc xmlns="urn:old" xmlns:a="urn:a" xmlns:b="urn:b" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+<a:item b:attr="1" xsi:type="a:T"/><item xsi:type="T"/></doc>"#;
+    let rewriter = NamespaceRewriter::new()
+        .rename_namespace("urn:old", "urn:new")
+        .rename_prefix("", "n")
+        .rename_prefix("a", "b")
+        .qname_attribute(OwnedName::qualified("type", "http://www.w3.org/2001/XMLSchema-instance", None::<&str>));
+
+    let mut b = Vec::new();
+    let mut w = EmitterConfig::new().write_document_declaration(false).create_writer(&mut b);
+    filter::copy(EventReader::from_str(source), &mut w, rewriter).unwrap();
+    assert_eq!(str::from_utf8(&b).unwrap(), r#"<?xml version="1.0" encoding="UTF-8"?><n:doc xmlns:b="urn:b" xmlns:b2="urn:a" xmlns:n="urn:new" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+<b2:item b:attr="1" xsi:type="b2:T" /><n:item xsi:type="n:T" /></n:doc>"#);
+}
+
+#[test]
+fn sanitizer_filter() {
+    use xml::filter::{self, Sanitizer};
+
+    let source = r#"<!DOCTYPE svg><svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" class="c" onload="x()">
+<!-- note --><?pi data?><foreignObject><p>html</p></foreignObject>
+<a xlink:href=" Java&#9;Script:x()"><image href="data:image/png;base64,AA" /></a>
+<a href="/rel?x=a:b" target="_top"><text>t</text></a><a href="HTTPS://example.com"/></svg>"#;
+    let sanitizer = Sanitizer::new()
+        .allow_element("svg", [])
+        .allow_element("a", ["href"])
+        .allow_element("image", ["href"])
+        .allow_element("text", [])
+        .allow_global_attribute("class")
+        .allow_url_scheme("DATA")
+        .keep_comments(true);
+
+    let mut b = Vec::new();
+    let mut w = EmitterConfig::new().create_writer(&mut b);
+    let reader = xml::ParserConfig::new().ignore_comments(false).create_reader(source.as_bytes());
+    filter::copy(reader, &mut w, sanitizer).unwrap();
+    assert_eq!(str::from_utf8(&b).unwrap(), r#"<?xml version="1.0" encoding="UTF-8"?><svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" class="c">
+<!-- note -->
+<a><image href="data:image/png;base64,AA" /></a>
+<a href="/rel?x=a:b"><text>t</text></a><a href="HTTPS://example.com" /></svg>"#);
+}
+
+#[test]
+fn transform_rules_filter() {
+    use xml::filter::{self, Action, TransformRules};
+
+    let rules: TransformRules = "
+        # comment
+        /a/b     wrap     group
+        b        rename   item
+        */c      unwrap
+        /a/*/d   set-attribute  k  v w
+        d        attribute-to-element  x
+    ".parse().unwrap();
+    let rules = rules.rule("e", Action::Drop);
+
+    let source = r#"<a xmlns="urn:x"><b><c>text<e>gone</e></c><d x="1" k="old"/></b><c/></a>"#;
+    let mut b = Vec::new();
+    let mut w = EmitterConfig::new().create_writer(&mut b);
+    filter::copy(EventReader::from_str(source), &mut w, rules).unwrap();
+    assert_eq!(str::from_utf8(&b).unwrap(), r#"<?xml version="1.0" encoding="UTF-8"?><a xmlns="urn:x"><group><item>text<d k="v w"><x>1</x></d></item></group></a>"#);
+
+    assert_eq!("b rename".parse::<TransformRules>().unwrap_err().to_string(), "line 1: wrong number of arguments");
+    assert_eq!("\nb\tcopy x".parse::<TransformRules>().unwrap_err().to_string(), "line 2: unknown action");
+    assert_eq!("b".parse::<TransformRules>().unwrap_err().to_string(), "line 1: expected a pattern and an action");
+}
+
+#[test]
+fn emitter_presets() {
+    use xml::writer::XmlEvent;
+
+    let write = |config: EmitterConfig| {
+        let mut b = Vec::new();
+        let mut w = config.create_writer(&mut b);
+        w.write(XmlEvent::start_element("html")).unwrap();
+        w.write(XmlEvent::start_element("p")).unwrap();
+        w.write(XmlEvent::end_element()).unwrap();
+        w.write(XmlEvent::start_element("BR")).unwrap();
+        w.write(XmlEvent::end_element()).unwrap();
+        w.write(XmlEvent::comment("c")).unwrap();
+        w.write(XmlEvent::cdata("<x>")).unwrap();
+        w.write(XmlEvent::end_element()).unwrap();
+        String::from_utf8(b).unwrap()
+    };
+
+    assert_eq!(write(EmitterConfig::pretty()), "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<html>\n  <p />\n  <BR />\n  <!-- c --><![CDATA[<x>]]></html>\n");
+    assert_eq!(write(EmitterConfig::compact()), "<?xml version=\"1.0\" encoding=\"utf-8\"?><html><p/><BR/><!--c--><![CDATA[<x>]]></html>");
+    assert_eq!(write(EmitterConfig::canonical()), "<html><p></p><BR></BR><!--c-->&lt;x&gt;</html>");
+    assert_eq!(write(EmitterConfig::html_polyglot()), "<html><p></p><BR /><!-- c -->&lt;x&gt;</html>");
+}
+
+#[test]
+fn scoped_config_overrides() {
+    use xml::writer::XmlEvent;
+
+    let mut b = Vec::new();
+    let mut w = EmitterConfig::pretty().write_document_declaration(false).create_writer(&mut b);
+    w.write(XmlEvent::start_element("doc")).unwrap();
+    w.write(XmlEvent::start_element("empty")).unwrap();
+    w.with_config(|c| c.perform_indent(false).normalize_empty_elements(false), |w| {
+        w.write(XmlEvent::end_element()).unwrap();
+        w.write(XmlEvent::start_element("signed")).unwrap();
+        w.write(XmlEvent::start_element("x")).unwrap();
+        w.write(XmlEvent::end_element()).unwrap();
+        w.write(XmlEvent::end_element()).unwrap();
+    });
+    w.write(XmlEvent::start_element("y")).unwrap();
+    w.write(XmlEvent::end_element()).unwrap();
+    w.with_config(|mut c| { c.perform_escaping = false; c }, |w| w.write(XmlEvent::characters("<&>"))).unwrap();
+    w.write(XmlEvent::characters("<")).unwrap();
+    w.write(XmlEvent::end_element()).unwrap();
+    assert_eq!(str::from_utf8(&b).unwrap(), "<doc>\n  <empty></empty><signed><x></x></signed>\n  <y /><&>&lt;</doc>\n");
+}
+
+#[test]
+fn prolog_order() {
+    use xml::common::XmlVersion;
+    use xml::writer::{Error, XmlEvent};
+
+    // the automatic declaration goes before comments
+    let mut b = Vec::new();
+    let mut w = EmitterConfig::new().create_writer(&mut b);
+    w.write(XmlEvent::comment("c")).unwrap();
+    w.write(XmlEvent::start_element("a")).unwrap();
+    w.write(XmlEvent::end_element()).unwrap();
+    assert_eq!(str::from_utf8(&b).unwrap(), r#"<?xml version="1.0" encoding="utf-8"?><!-- c --><a />"#);
+
+    let mut b = Vec::new();
+    let mut w = EmitterConfig::new().write_document_declaration(false).create_writer(&mut b);
+    w.write(XmlEvent::processing_instruction("pi", None)).unwrap();
+    let start = XmlEvent::StartDocument { version: XmlVersion::Version10, encoding: None, standalone: None };
+    assert!(matches!(w.write(start), Err(Error::DocumentStartAfterContent)));
+    assert_eq!(str::from_utf8(&b).unwrap(), "<?pi?>");
+}
+
+#[test]
+fn doctype_order() {
+    use xml::writer::{Error, XmlEvent};
+
+    let mut b = Vec::new();
+    let mut w = EmitterConfig::new().create_writer(&mut b);
+    w.write(XmlEvent::Doctype("<!DOCTYPE a>")).unwrap();
+    assert!(matches!(w.write(XmlEvent::Doctype("<!DOCTYPE a>")), Err(Error::DoctypeAlreadyEmitted)));
+    w.write(XmlEvent::start_element("a")).unwrap();
+    w.write(XmlEvent::end_element()).unwrap();
+    assert!(matches!(w.write(XmlEvent::Doctype("<!DOCTYPE b>")), Err(Error::DoctypeAfterRootElement)));
+
+    let check = |doctype| {
+        let mut w = EmitterConfig::new().write_document_declaration(false).create_writer(Vec::new());
+        match w.write(XmlEvent::Doctype(doctype)) {
+            Ok(()) => Ok(String::from_utf8(w.into_inner()).unwrap()),
+            Err(Error::MalformedDoctype(offset)) => Err(offset),
+            Err(e) => panic!("{e}"),
+        }
+    };
+    let valid = "<!DOCTYPE a PUBLIC \"-//A//[x]>\" 'a.dtd' [\n<!ENTITY e '<b>'><!-- ]> --><?pi ]>?>\n%p;]\n>";
+    assert_eq!(check(valid).as_deref(), Ok(valid));
+    assert_eq!(check("<!DOCTYPE a><a/>"), Err(12));
+    assert_eq!(check("<!DOCTYPE a [<!ENTITY e 'x'>] x>"), Err(30));
+    assert_eq!(check("<!DOCTYPE a [<!ENTITY e 'x'>"), Err(28));
+    assert_eq!(check("<!DOCTYPE a [<!ENTITY <e>]>"), Err(22));
+    assert_eq!(check("<!DOCTYPE a 'x>"), Err(12));
+    assert_eq!(check("<!DOCTYPE 1>"), Err(10));
+    assert_eq!(check("<!-- x --><!DOCTYPE a>"), Err(0));
+    assert_eq!(str::from_utf8(&b).unwrap(), r#"<?xml version="1.0" encoding="utf-8"?><!DOCTYPE a><a />"#);
+}
+
+#[test]
+fn single_root() {
+    use xml::writer::{Error, XmlEvent};
+
+    let mut b = Vec::new();
+    let mut w = EmitterConfig::new().write_document_declaration(false).single_root(true).create_writer(&mut b);
+    w.write(XmlEvent::characters("\n")).unwrap();
+    w.write(XmlEvent::start_element("a")).unwrap();
+    w.write(XmlEvent::start_element("b")).unwrap();
+    w.write(XmlEvent::end_element()).unwrap();
+    w.write(XmlEvent::start_element("b")).unwrap();
+    w.write(XmlEvent::end_element()).unwrap();
+    w.write(XmlEvent::end_element()).unwrap();
+    w.write(XmlEvent::comment("end")).unwrap();
+    assert!(matches!(w.write(XmlEvent::start_element("c")), Err(Error::MultipleRootElements)));
+    assert!(matches!(w.write(XmlEvent::characters("text")), Err(Error::TextOutsideRootElement)));
+    assert!(matches!(w.write(XmlEvent::cdata("text")), Err(Error::TextOutsideRootElement)));
+    assert_eq!(str::from_utf8(&b).unwrap(), "\n<a><b /><b /></a><!-- end -->");
+}
+
+#[test]
+fn fragment_builder() {
+    use xml::build::{Element, Node};
+    use xml::reader::XmlEvent;
+
+    let doc = Element::new("svg")
+        .default_ns("http://www.w3.org/2000/svg")
+        .ns("xl", "http://www.w3.org/1999/xlink")
+        .attr("width", "10")
+        .child(Element::new("a").attr("xl:href", "#x&y").child(Element::new("path").attr("d", "M0 0")))
+        .children(["t<1", "t2"])
+        .cdata("<raw>")
+        .child(Node::ProcessingInstruction("pi".into(), Some("data".into())));
+
+    let mut out = Vec::new();
+    let mut writer = EmitterConfig::new().write_document_declaration(false).create_writer(&mut out);
+    doc.write_to(&mut writer).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out, concat!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xl="http://www.w3.org/1999/xlink" width="10">"#,
+        r##"<a xl:href="#x&amp;y"><path d="M0 0" /></a>t&lt;1t2<![CDATA[<raw>]]><?pi data?></svg>"##,
+    ));
+
+    // reading it back declares the namespaces on the same elements
+    let read = Element::from_reader(EventReader::from_str(&out)).unwrap();
+    assert_eq!(read.children.len(), 4);
+    let mut rewritten = Vec::new();
+    read.write_to(&mut EmitterConfig::new().write_document_declaration(false).create_writer(&mut rewritten)).unwrap();
+    assert_eq!(String::from_utf8(rewritten).unwrap(), out);
+
+    // the same events as parsing the output
+    let parsed: Vec<_> = EventReader::from_str(&out).into_iter().map(Result::unwrap)
+        .filter(|e| !matches!(e, XmlEvent::StartDocument { .. } | XmlEvent::EndDocument))
+        .collect();
+    let mut built = doc.to_reader_events();
+    // the parser merges adjacent text
+    built.splice(5..7, [XmlEvent::Characters("t<1t2".into())]);
+    assert_eq!(parsed, built);
+}
+
+#[test]
+fn multiple_documents() {
+    use xml::reader::{DocumentDelimiter, ParserConfig2, XmlEvent as ReaderEvent};
+    use xml::writer::{Error, XmlEvent};
+
+    let mut out = Vec::new();
+    let mut w = EmitterConfig::new().single_root(true).create_writer(&mut out);
+    for name in ["a", "b"] {
+        unwrap_all! {
+            w.write(XmlEvent::StartDocument { version: xml::common::XmlVersion::Version10, encoding: None, standalone: None });
+            w.write(XmlEvent::Doctype("<!DOCTYPE x>"));
+            w.write(XmlEvent::start_element(name));
+            w.write(XmlEvent::end_element())
+        }
+        assert!(matches!(w.write(XmlEvent::start_element("c")), Err(Error::MultipleRootElements)));
+        w.start_new_document().unwrap();
+    }
+    w.write(XmlEvent::start_element("c")).unwrap();
+    assert!(matches!(w.start_new_document(), Err(Error::UnclosedElements)));
+    w.write(XmlEvent::end_element()).unwrap();
+    drop(w);
+
+    let names: Vec<_> = ParserConfig2::new()
+        .multiple_documents(Some(DocumentDelimiter::DeclarationOrElement))
+        .create_reader(&out[..])
+        .documents()
+        .filter_map(|e| match e.unwrap() {
+            ReaderEvent::StartElement { name, .. } => Some(name.local_name),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(names, ["a", "b", "c"]);
+
+    // a document that ends with a newline doesn't need a separator
+    let mut out = Vec::new();
+    let mut w = EmitterConfig::new().write_document_declaration(false).write_trailing_newline(true).create_writer(&mut out);
+    for name in ["a", "b"] {
+        unwrap_all! {
+            w.write(XmlEvent::start_element(name));
+            w.write(XmlEvent::end_element())
+        }
+        w.start_new_document().unwrap();
+    }
+    w.write(XmlEvent::characters("\n")).unwrap();
+    w.start_new_document().unwrap();
+    w.write(XmlEvent::start_element("c")).unwrap();
+    w.write(XmlEvent::end_element()).unwrap();
+    assert_eq!(std::str::from_utf8(&out).unwrap(), "<a />\n<b />\n\n<c />\n");
+}
+
+#[test]
+fn raw_markup() {
+    use xml::writer::{Error, XmlEvent};
+
+    let mut out = Vec::new();
+    let mut w = EmitterConfig::new().write_document_declaration(false).create_writer(&mut out);
+    unwrap_all! {
+        w.write(XmlEvent::start_element("html"));
+        w.write(XmlEvent::raw_markup(r#"<p class='x'>a &amp; b<br/><!-- c --></p><div id="d">"#));
+        w.write(XmlEvent::characters("<text>"));
+        w.write(XmlEvent::end_element());
+        w.write(XmlEvent::raw_markup("<?pi data?></html>"))
+    }
+    assert_eq!(w.write(XmlEvent::end_element()).unwrap_err().to_string(), "emitter error: last element name is not available");
+    drop(w);
+    assert_eq!(str::from_utf8(&out).unwrap(),
+        r#"<html><p class='x'>a &amp; b<br/><!-- c --></p><div id="d">&lt;text&gt;</div><?pi data?></html>"#);
+
+    let mut w = EmitterConfig::new().create_writer(Vec::new());
+    w.write(XmlEvent::start_element("a")).unwrap();
+    for (markup, offset) in [("<b>", None), ("<b></c>", Some(3)), ("<1/>", Some(0)), ("x & y", Some(2)),
+        ("<b x='<'/>", Some(6)), ("<b x=y/>", Some(5)), ("<!-- -- -->", Some(0)), ("a]]>", Some(1)),
+        ("<b x='1' x='2'/>", Some(9))] {
+        match w.write(XmlEvent::raw_markup(markup)) {
+            Err(Error::MalformedMarkup(o)) => assert_eq!(Some(o), offset, "{markup}"),
+            r => assert!(r.is_ok() && offset.is_none(), "{markup}"),
+        }
+    }
+    assert!(matches!(w.write(XmlEvent::raw_markup("</a>")), Err(Error::EndElementNameIsNotEqualToLastStartElementName)));
+    unwrap_all! {
+        w.write(XmlEvent::raw_markup("</b></a>"))
+    }
+
+    for markup in ["<a/>junk", "<a/><![CDATA[x]]>", "&amp;<a/>"] {
+        let mut w = EmitterConfig::new().single_root(true).create_writer(Vec::new());
+        assert!(matches!(w.write(XmlEvent::raw_markup(markup)), Err(Error::TextOutsideRootElement)), "{markup}");
+        let mut w = EmitterConfig::new().single_root(false).create_writer(Vec::new());
+        w.write(XmlEvent::raw_markup(markup)).unwrap();
+    }
+    let mut w = EmitterConfig::new().single_root(true).create_writer(Vec::new());
+    w.write(XmlEvent::raw_markup("<a>x</a>\n")).unwrap();
+}
+
+#[test]
+fn attribute_streamed() {
+    use xml::writer::{Error, XmlEvent};
+
+    let mut out = Vec::new();
+    let mut w = EmitterConfig::new().write_document_declaration(false).normalize_empty_elements(false).create_writer(&mut out);
+    let styles = ["color: red", "content: \"<>\""];
+    unwrap_all! {
+        w.write(XmlEvent::start_element("p"));
+        w.write_attribute_streamed("style", styles.iter().flat_map(|s| [*s, ";"]));
+        w.write_attribute_streamed("empty", []);
+        w.write(XmlEvent::characters("x"))
+    }
+    assert!(matches!(w.write_attribute_streamed("late", ["a"]), Err(Error::AttributeOutsideStartElement)));
+    w.write(XmlEvent::end_element()).unwrap();
+    drop(w);
+    assert_eq!(str::from_utf8(&out).unwrap(), r#"<p style="color: red;content: &quot;&lt;&gt;&quot;;" empty="">x</p>"#);
+}
+
+#[test]
+fn start_tag_closed_before_raw_output() {
+    use xml::writer::XmlEvent;
+
+    let config = EmitterConfig::new().write_document_declaration(false).normalize_empty_elements(false);
+    let mut w = config.clone().create_writer(Vec::new());
+    w.write(XmlEvent::start_element("a")).unwrap();
+    assert_eq!(w.into_inner(), b"<a>");
+
+    let mut w = config.create_writer(Vec::new());
+    w.write(XmlEvent::start_element("a")).unwrap();
+    w.inner_mut().write_all(b"raw").unwrap();
+    w.write(XmlEvent::end_element()).unwrap();
+    assert_eq!(w.into_inner(), b"<a>raw</a>");
+}
+
+#[test]
+fn text_writer() {
+    use xml::writer::XmlEvent;
+
+    let text = "ąę & €<".repeat(1000);
+    let mut out = Vec::new();
+    let mut w = EmitterConfig::new().write_document_declaration(false).create_writer(&mut out);
+    w.write(XmlEvent::start_element("a")).unwrap();
+    {
+        let mut t = w.text_writer();
+        // splits multi-byte characters between writes
+        for chunk in text.as_bytes().chunks(3) {
+            t.write_all(chunk).unwrap();
+        }
+        t.finish().unwrap();
+    }
+    std::io::copy(&mut "!".as_bytes(), &mut w.text_writer()).unwrap();
+    assert_eq!(w.stats().events.characters, 2);
+
+    let mut t = w.text_writer();
+    t.write_all(&"€".as_bytes()[..2]).unwrap();
+    assert!(t.finish().is_err());
+    assert!(w.text_writer().write_all(b"\xff").is_err());
+    w.write(XmlEvent::end_element()).unwrap();
+    drop(w);
+    assert_eq!(str::from_utf8(&out).unwrap(), format!("<a>{}!</a>", "ąę &amp; €&lt;".repeat(1000)));
+}
+
+#[test]
+fn binary_content() {
+    use xml::reader::XmlEvent as ReaderEvent;
+    use xml::writer::XmlEvent;
+
+    /// Returns a few bytes at a time
+    struct Trickle<'a>(&'a [u8]);
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(5);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    let blob: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+    let mut out = Vec::new();
+    let mut w = EmitterConfig::new().create_writer(&mut out);
+    unwrap_all! {
+        w.write(XmlEvent::start_element("doc"));
+        w.write(XmlEvent::start_element("b64"))
+    }
+    assert_eq!(w.write_base64(Trickle(&blob)).unwrap(), blob.len() as u64);
+    w.write(XmlEvent::end_element()).unwrap();
+    w.write(XmlEvent::start_element("hex")).unwrap();
+    assert_eq!(w.write_hex(&blob[..1000]).unwrap(), 1000);
+    unwrap_all! {
+        w.write(XmlEvent::end_element());
+        w.write(XmlEvent::start_element("bad"));
+        w.write(XmlEvent::characters("a!"));
+        w.write(XmlEvent::end_element());
+        w.write(XmlEvent::end_element())
+    }
+    assert_eq!(w.stats().events.characters, 3);
+    drop(w);
+
+    let mut r = EventReader::new(&out[..]);
+    let mut decoded = Vec::new();
+    let mut hex = Vec::new();
+    loop {
+        match r.next().unwrap() {
+            ReaderEvent::StartElement { name, .. } if name.local_name == "b64" => {
+                assert_eq!(r.read_base64_to(&mut decoded).unwrap(), blob.len() as u64);
+            },
+            ReaderEvent::StartElement { name, .. } if name.local_name == "hex" => {
+                r.read_hex_to(&mut hex).unwrap();
+            },
+            ReaderEvent::StartElement { name, .. } if name.local_name == "bad" => {
+                assert!(r.read_base64_to(std::io::sink()).unwrap_err().to_string().contains("Invalid base64 content"));
+                break;
+            },
+            _ => {},
+        }
+    }
+    assert!(decoded == blob);
+    assert!(hex == blob[..1000]);
+}
+
+#[test]
+fn cdata_writer() {
+    use xml::reader::XmlEvent as ReaderEvent;
+    use xml::writer::XmlEvent;
+
+    let script = "if (a[b[0]]>c) { x = ']]>'; }\n".repeat(500);
+    let mut out = Vec::new();
+    let mut w = EmitterConfig::new().create_writer(&mut out);
+    w.write(XmlEvent::start_element("a")).unwrap();
+    for chunk_len in [1, 2, 7, 4096] {
+        let mut cdata = w.cdata_writer();
+        for chunk in script.as_bytes().chunks(chunk_len) {
+            cdata.write_all(chunk).unwrap();
+        }
+    }
+    unwrap_all! {
+        w.write(XmlEvent::cdata("]]>"));
+        w.write(XmlEvent::end_element())
+    }
+    assert_eq!(w.stats().events.cdata, 5);
+    drop(w);
+
+    let mut text = String::new();
+    for e in EventReader::new(&out[..]) {
+        if let ReaderEvent::CData(s) = e.unwrap() {
+            text.push_str(&s);
+        }
+    }
+    assert_eq!(text, script.repeat(4) + "]]>");
+
+    let mut out = Vec::new();
+    let mut w = EmitterConfig::new().write_document_declaration(false).cdata_to_characters(true).create_writer(&mut out);
+    w.write(XmlEvent::start_element("a")).unwrap();
+    w.cdata_writer().write_all(b"]]>").unwrap();
+    w.write(XmlEvent::end_element()).unwrap();
+    drop(w);
+    assert_eq!(str::from_utf8(&out).unwrap(), "<a>]]&gt;</a>");
+}
+
+#[test]
+fn entity_map() {
+    use xml::writer::XmlEvent;
+
+    let mut out = Vec::new();
+    {
+        let mut w = EmitterConfig::new()
+            .write_document_declaration(false)
+            .entity_map(&[('\u{a0}', "&#160;"), ('<', "&#60;")])
+            .create_writer(&mut out);
+        unwrap_all! {
+            w.write(XmlEvent::start_element("a").attr("title", "x\u{a0}<y"));
+            w.write("1\u{a0}< 2 & 3");
+            w.write(XmlEvent::cdata("\u{a0}"));
+            w.write(XmlEvent::end_element())
+        }
+    }
+    assert_eq!(str::from_utf8(&out).unwrap(), "<a title=\"x&#160;&#60;y\">1&#160;&#60; 2 &amp; 3<![CDATA[\u{a0}]]></a>");
+
+    for valid in ["&nbsp;", "&#xA0;", "&#160;", "&a:b-c;"] {
+        let _ = EmitterConfig::new().entity_map(&[('\u{a0}', valid)]);
+    }
+    for invalid in ["nbsp", "&nbsp", "&#;", "&#x;", "&#+1;", "&#0;", "&#xD800;", "&1a;", "&a b;", "<b/>"] {
+        assert!(std::panic::catch_unwind(|| EmitterConfig::new().entity_map(&[('\u{a0}', invalid)])).is_err(), "{invalid}");
+    }
+}
+
+#[test]
+fn event_emitter_targets() {
+    use xml::writer::{EventEmitter, XmlEvent};
+
+    let mut emitter = EventEmitter::new(EmitterConfig::new().perform_indent(true));
+    let mut parts = vec![Vec::new(); 3];
+    emitter.write(&mut parts[0], XmlEvent::start_element("log").ns("x", "urn:x")).unwrap();
+    for (i, part) in parts.iter_mut().enumerate() {
+        emitter.write(part, XmlEvent::start_element("x:entry")).unwrap();
+        emitter.write(part, &*i.to_string()).unwrap();
+        emitter.write(part, XmlEvent::end_element()).unwrap();
+    }
+    assert_eq!(emitter.depth(), 1);
+    emitter.write(&mut parts[2], XmlEvent::end_element()).unwrap();
+    assert!(emitter.write(&mut parts[2], XmlEvent::end_element()).is_err());
+
+    let doc = parts.concat();
+    assert_eq!(str::from_utf8(&doc).unwrap(), "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<log xmlns:x=\"urn:x\">\n  <x:entry>0</x:entry>\n  <x:entry>1</x:entry>\n  <x:entry>2</x:entry>\n</log>");
+}
+
+#[test]
+fn replace_sink() {
+    use xml::writer::XmlEvent;
+
+    let mut w = EmitterConfig::new().perform_indent(true).create_writer(Vec::new());
+    w.write(XmlEvent::start_element("log")).unwrap();
+    w.write(XmlEvent::start_element("entry").attr("n", "1")).unwrap();
+    let first = w.replace_sink(Vec::new()).unwrap();
+    w.write(XmlEvent::end_element()).unwrap();
+    w.write(XmlEvent::start_element("entry")).unwrap();
+    w.write(XmlEvent::end_element()).unwrap();
+    w.inner_mut().flush().unwrap();
+    let second = w.replace_sink(Vec::new()).unwrap();
+    w.write(XmlEvent::end_element()).unwrap();
+    assert!(w.write(XmlEvent::end_element()).is_err());
+    let third = w.into_inner();
+
+    assert_eq!(str::from_utf8(&first).unwrap(), "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<log>\n  <entry n=\"1\">");
+    assert_eq!(str::from_utf8(&second).unwrap(), "\n  </entry>\n  <entry />");
+    assert_eq!(str::from_utf8(&third).unwrap(), "\n</log>");
+}
+
+#[test]
+#[cfg(any(feature = "flate2", feature = "zstd"))]
+fn compressing_writer() {
+    use xml::compression::{CompressingWriter, Compression, DecompressingReader};
+    use xml::writer::XmlEvent;
+
+    let mut formats = vec![Compression::None];
+    #[cfg(feature = "flate2")]
+    formats.push(Compression::Gzip);
+    #[cfg(feature = "zstd")]
+    formats.push(Compression::Zstd);
+
+    for compression in formats {
+        let sink = CompressingWriter::new(Vec::new(), compression).unwrap();
+        let mut w = EmitterConfig::new().auto_flush_depth(Some(2)).create_writer(sink);
+        w.write(XmlEvent::start_element("urlset")).unwrap();
+        for i in 0..100 {
+            w.write(XmlEvent::start_element("url")).unwrap();
+            w.write(&*format!("https://example.com/{i}")).unwrap();
+            w.write(XmlEvent::end_element()).unwrap();
+        }
+        w.write(XmlEvent::end_element()).unwrap();
+        let out = w.into_inner().finish().unwrap();
+
+        let source = DecompressingReader::new(&out[..]).unwrap();
+        assert_eq!(source.compression(), compression);
+        let urls = EventReader::new(source).into_iter().filter(|e| matches!(e.as_ref().unwrap(), xml::reader::XmlEvent::Characters(_))).count();
+        assert_eq!(urls, 100);
+    }
+}
+
+#[test]
+fn xop_include() {
+    use xml::writer::XmlEvent;
+
+    let mut w = EmitterConfig::new().write_document_declaration(false).create_writer(Vec::new());
+    w.write(XmlEvent::start_element("m")).unwrap();
+    w.write_xop_include("part 1@example.org").unwrap();
+    w.write(XmlEvent::start_element("x:a").ns("x", "http://www.w3.org/2004/08/xop/include")).unwrap();
+    w.write_xop_include("b").unwrap();
+    unwrap_all! {
+        w.write(XmlEvent::end_element());
+        w.write(XmlEvent::end_element())
+    }
+    let xml = String::from_utf8(w.into_inner()).unwrap();
+    assert_eq!(xml, concat!(r#"<m><xop:Include xmlns:xop="http://www.w3.org/2004/08/xop/include" href="cid:part%201@example.org" />"#,
+        r#"<x:a xmlns:x="http://www.w3.org/2004/08/xop/include"><xop:Include xmlns:xop="http://www.w3.org/2004/08/xop/include" href="cid:b" /></x:a></m>"#));
+
+    let mut reader = xml::reader::ParserConfig2::new().xop_includes(true).create_reader(xml.as_bytes());
+    let mut ids = Vec::new();
+    while reader.next().unwrap() != xml::reader::XmlEvent::EndDocument {
+        ids.extend(reader.xop_content_id().map(String::from));
+    }
+    assert_eq!(ids, ["part 1@example.org", "b"]);
+}
+
+#[test]
+fn prefix_undeclaration() {
+    use xml::common::XmlVersion;
+    use xml::writer::XmlEvent;
+
+    let mut w = EmitterConfig::new().create_writer(Vec::new());
+    unwrap_all! {
+        w.write(XmlEvent::StartDocument { version: XmlVersion::Version11, encoding: None, standalone: None });
+        w.write(XmlEvent::start_element("p:a").ns("p", "urn:p"));
+        w.write(XmlEvent::start_element("b").ns("p", ""));
+        w.write(XmlEvent::start_element("c").ns("p", ""));
+        w.write(XmlEvent::end_element());
+        w.write(XmlEvent::start_element("p:d").ns("p", "urn:p"));
+        w.write(XmlEvent::end_element());
+        w.write(XmlEvent::end_element());
+        w.write(XmlEvent::start_element("e").ns("q", ""));
+        w.write(XmlEvent::end_element());
+        w.write(XmlEvent::end_element())
+    }
+    assert_eq!(str::from_utf8(&w.into_inner()).unwrap(), concat!(r#"<?xml version="1.1" encoding="UTF-8"?><p:a xmlns:p="urn:p">"#,
+        r#"<b xmlns:p=""><c /><p:d xmlns:p="urn:p" /></b><e /></p:a>"#));
+
+    let mut w = EmitterConfig::new().create_writer(Vec::new());
+    w.write(XmlEvent::start_element("p:a").ns("p", "urn:p")).unwrap();
+    assert!(w.write(XmlEvent::start_element("b").ns("p", "")).is_err());
+}
+
+#[test]
+fn prolog_processing_instructions() {
+    use xml::writer::XmlEvent;
+
+    let mut w = EmitterConfig::new().create_writer(Vec::new());
+    unwrap_all! {
+        w.write(XmlEvent::xml_stylesheet("style.xsl?a=1&b=\"2\"", "text/xsl"));
+        w.write(XmlEvent::xml_model("schema.rng?>", "http://relaxng.org/ns/structure/1.0"));
+        w.write(XmlEvent::start_element("doc"));
+        w.write(XmlEvent::end_element())
+    }
+    let xml = String::from_utf8(w.into_inner()).unwrap();
+    assert_eq!(xml, concat!(r#"<?xml version="1.0" encoding="utf-8"?><?xml-stylesheet href="style.xsl?a=1&amp;b=&quot;2&quot;" type="text/xsl"?>"#,
+        r#"<?xml-model href="schema.rng?&gt;" schematypens="http://relaxng.org/ns/structure/1.0"?><doc />"#));
+
+    let mut reader = xml::EventReader::from_str(&xml);
+    reader.next().unwrap();
+    let stylesheet = reader.next().unwrap().xml_stylesheet().unwrap();
+    assert_eq!(stylesheet.href, "style.xsl?a=1&b=\"2\"");
+    assert_eq!(stylesheet.mime_type.as_deref(), Some("text/xsl"));
+}
+
+#[test]
+fn html_serialization() {
+    use xml::writer::XmlEvent;
+
+    let mut w = EmitterConfig::new().write_document_declaration(false).normalize_empty_elements(false)
+        .html_serialization(true).create_writer(Vec::new());
+    unwrap_all! {
+        w.write(XmlEvent::start_element("form"));
+        w.write(XmlEvent::start_element("div"));
+        w.write(XmlEvent::end_element());
+        w.write(XmlEvent::start_element("input").attr("disabled", "disabled").attr("checked", "").attr("value", ""));
+        w.write(XmlEvent::end_element());
+        w.write(XmlEvent::start_element("option").attr("selected", "no"));
+        w.write("a > b && c < d");
+        w.write(XmlEvent::end_element());
+        w.write(XmlEvent::end_element())
+    }
+    assert_eq!(String::from_utf8(w.into_inner()).unwrap(),
+        r#"<form><div></div><input disabled checked value="" /><option selected="no">a > b &amp;&amp; c &lt; d</option></form>"#);
+}
+
+#[test]
+fn writer_introspection() {
+    use xml::writer::XmlEvent;
+
+    let mut writer = EmitterConfig::new().create_writer(Vec::new());
+    assert!(!writer.has_open_document());
+    assert_eq!(format!("{writer:?}"), format!("EventWriter {{ path: /, depth: 0, has_open_document: false, stats: {:?}, .. }}", writer.stats()));
+    unwrap_all! {
+        writer.write(XmlEvent::start_element("feed").ns("x", "urn:x"));
+        writer.write(XmlEvent::start_element("x:entry"))
+    }
+    assert!(writer.has_open_document());
+    assert_eq!(writer.depth(), 2);
+    assert_eq!(writer.current_element_path()[1].prefix.as_deref(), Some("x"));
+    assert!(format!("{writer:?}").starts_with("EventWriter { path: /feed/x:entry, depth: 2, has_open_document: true,"));
+    unwrap_all! {
+        writer.write(XmlEvent::end_element());
+        writer.write(XmlEvent::end_element())
+    }
+    assert!(writer.current_element_path().is_empty());
+    assert!(writer.has_open_document());
+    writer.start_new_document().unwrap();
+    assert!(!writer.has_open_document());
+
+    let mut writer = EmitterConfig::new().keep_element_names_stack(false).create_writer(Vec::new());
+    writer.write(XmlEvent::start_element("a")).unwrap();
+    assert_eq!(writer.depth(), 1);
+    assert!(writer.current_element_path().is_empty());
+}
+
+#[test]
+fn error_positions() {
+    use xml::writer::{Error, XmlEvent};
+
+    let mut w = EmitterConfig::new().write_document_declaration(false).error_positions(true).create_writer(Vec::new());
+    unwrap_all! {
+        w.write(XmlEvent::start_element("export"));
+        w.write(XmlEvent::start_element("x:row").ns("x", "urn:x"));
+        w.write("data")
+    }
+    let err = w.write(XmlEvent::raw_markup("<a")).unwrap_err();
+    assert!(matches!(err.error(), Error::MalformedMarkup(2)));
+    let position = err.position().unwrap();
+    assert_eq!(position.bytes_written, 35);
+    assert_eq!(position.element_path.len(), 2);
+    assert_eq!(err.to_string(), "emitter error: raw markup is not well-formed at byte 2, at output byte 35 in /export/x:row");
+    assert!(std::error::Error::source(&err).is_some());
+
+    let mut w = EmitterConfig::new().create_writer(Vec::new());
+    let err = w.write(XmlEvent::end_element()).unwrap_err();
+    assert!(err.position().is_none());
+    assert!(matches!(err.error(), Error::LastElementNameNotAvailable));
+}
+
+#[test]
+fn normalize_text_newlines() {
+    use xml::writer::{NewlineStyle, XmlEvent};
+
+    let write = |style| {
+        let mut w = EmitterConfig::new()
+            .write_document_declaration(false)
+            .perform_indent(true)
+            .cdata_to_characters(true)
+            .normalize_text_newlines(style)
+            .create_writer(Vec::new());
+        unwrap_all! {
+            w.write(XmlEvent::start_element("a").attr("b", "1\n2"));
+            w.write(XmlEvent::start_element("p"));
+            w.write("x\ny\r\nz\r<");
+            w.write(XmlEvent::cdata("\r\n"));
+            w.write(XmlEvent::end_element());
+            w.write(XmlEvent::comment("c\nd"));
+            w.write(XmlEvent::end_element())
+        }
+        String::from_utf8(w.into_inner()).unwrap()
+    };
+    assert_eq!(write(NewlineStyle::Keep), "<a b=\"1&#xA;2\">\n  <p>x\ny\r\nz\r&lt;\r\n</p>\n  <!-- c\nd -->\n</a>");
+    assert_eq!(write(NewlineStyle::Lf), "<a b=\"1&#xA;2\">\n  <p>x\ny\nz\n&lt;\n</p>\n  <!-- c\nd -->\n</a>");
+    assert_eq!(write(NewlineStyle::CrLf), "<a b=\"1&#xA;2\">\n  <p>x\r\ny\r\nz\r\n&lt;\r\n</p>\n  <!-- c\nd -->\n</a>");
+
+    // `\r\n` split between events is one line break, but not if there's markup between them
+    let mut w = EmitterConfig::new().write_document_declaration(false).normalize_text_newlines(NewlineStyle::CrLf).create_writer(Vec::new());
+    unwrap_all! {
+        w.write(XmlEvent::start_element("p"));
+        w.write("a\r");
+        w.write("\nb\r");
+        w.write(XmlEvent::comment(""));
+        w.write("\nc");
+        w.write(XmlEvent::end_element())
+    }
+    assert_eq!(String::from_utf8(w.into_inner()).unwrap(), "<p>a\r\nb\r\n<!--  -->\r\nc</p>");
+}
+
+#[test]
+fn default_namespace_undeclaration() {
+    use xml::writer::XmlEvent;
+
+    let mut b = Vec::new();
+    let mut w = EmitterConfig::new().write_document_declaration(false).create_writer(&mut b);
+    unwrap_all! {
+        w.write(XmlEvent::start_element("a").ns("", ""));
+        w.write(XmlEvent::start_element("b").default_ns("urn:b"));
+        w.write(XmlEvent::start_element("c").default_ns(""));
+        w.write(XmlEvent::end_element());
+        w.write(XmlEvent::start_element("d").default_ns("urn:b"));
+        w.write(XmlEvent::end_element());
+        w.write(XmlEvent::end_element());
+        w.write(XmlEvent::end_element())
+    }
+    assert_eq!(str::from_utf8(&b).unwrap(), r#"<a><b xmlns="urn:b"><c xmlns="" /><d /></b></a>"#);
+}
+
+#[test]
+fn auto_flush_policy() {
+    use xml::writer::XmlEvent;
+
+    /// Remembers how much output there was at each flush
+    #[derive(Default)]
+    struct FlushLog {
+        data: Vec<u8>,
+        flushed_at: Vec<usize>,
+    }
+
+    impl Write for FlushLog {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.flushed_at.push(self.data.len());
+            Ok(())
+        }
+    }
+
+    let mut w = EmitterConfig::new()
+        .write_document_declaration(false)
+        .auto_flush_depth(Some(2))
+        .create_writer(FlushLog::default());
+    unwrap_all! {
+        w.write(XmlEvent::start_element("root"));
+        w.write(XmlEvent::start_element("a"));
+        w.write(XmlEvent::start_element("b"));
+        w.write(XmlEvent::end_element());
+        w.write(XmlEvent::end_element());
+        w.write(XmlEvent::start_element("c"));
+        w.write(XmlEvent::end_element());
+        w.write(XmlEvent::end_element())
+    }
+    let log = w.into_inner();
+    assert_eq!(str::from_utf8(&log.data).unwrap(), "<root><a><b /></a><c /></root>");
+    assert_eq!(log.flushed_at, [18, 23, 30]);
+
+    let mut w = EmitterConfig::new()
+        .write_document_declaration(false)
+        .auto_flush_bytes(Some(10))
+        .create_writer(FlushLog::default());
+    unwrap_all! {
+        w.write(XmlEvent::start_element("root"));
+        w.write(XmlEvent::characters("0123456789"));
+        w.write(XmlEvent::characters("x"));
+        w.write(XmlEvent::end_element())
+    }
+    assert_eq!(w.into_inner().flushed_at, [16]);
+}
+
+#[test]
+fn writer_stats() {
+    use xml::writer::XmlEvent;
+
+    let mut b = Vec::new();
+    let mut w = EmitterConfig::new().create_writer(&mut b);
+    unwrap_all! {
+        w.write(XmlEvent::start_element("root"));
+        w.write(XmlEvent::start_element("a"));
+        w.write(XmlEvent::characters("text"));
+        w.write(XmlEvent::end_element());
+        w.write(XmlEvent::comment("c"));
+        w.write(XmlEvent::cdata("d"));
+        w.write(XmlEvent::end_element())
+    }
+    let stats = w.stats();
+    drop(w);
+
+    assert_eq!(stats.bytes_written, b.len() as u64);
+    assert_eq!(stats.events.elements, 2);
+    assert_eq!(stats.events.characters, 1);
+    assert_eq!(stats.events.comments, 1);
+    assert_eq!(stats.events.cdata, 1);
+    assert_eq!(stats.max_depth, 2);
+}
+
+#[test]
+fn preserving_attribute_quotes() {
+    let source = r#"<a x='1' y="2" z='"'/>"#;
+    let mut reader = xml::ParserConfig::new()
+        .preserve_source_details(true)
+        .create_reader(source.as_bytes());
+    let mut b = Vec::new();
+    let mut w = EmitterConfig::new().create_writer(&mut b);
+    loop {
+        let e = reader.next().unwrap();
+        if let xml::reader::XmlEvent::EndDocument = e {
+            break;
+        }
+        w.set_next_attribute_quotes(reader.attribute_details().iter().map(|d| d.quote));
+        if let Some(e) = e.as_writer_event() {
+            w.write(e).unwrap();
+        }
+    }
+    assert_eq!(str::from_utf8(&b).unwrap(), r#"<?xml version="1.0" encoding="UTF-8"?><a x='1' y="2" z='&quot;' />"#);
+}
+
+#[test]
+fn sample_documents_roundtrip() {
+    for n in 1..=7 {
+        let path = format!("tests/documents/sample_{n}.xml");
+        let events = xml::ParserConfig::new()
+            .ignore_comments(false)
+            .create_reader(BufReader::new(File::open(&path).unwrap()))
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>();
+        // some samples are deliberately malformed
+        if let Ok(events) = events {
+            if let Err(e) = xml::testing::roundtrip(&events) {
+                panic!("{path}: {e}");
+            }
+        }
+    }
+}
+
+#[test]
+fn start_element_builder_invalid_names() {
+    use std::borrow::Cow;
+    use xml::attribute::Attribute;
+    use xml::name::{Name, NameError};
+    use xml::namespace::Namespace;
+    use xml::writer::{Error as EmitterError, XmlEvent};
+
+    let e = XmlEvent::start_element("a").try_attr("x:b", "1").unwrap().try_ns("x", "urn:x").unwrap().try_ns("", "urn:d").unwrap();
+    let mut b = Vec::new();
+    let mut w = EmitterConfig::new().write_document_declaration(false).create_writer(&mut b);
+    w.write(e).unwrap();
+    w.write(XmlEvent::end_element()).unwrap();
+    assert_eq!(str::from_utf8(&b).unwrap(), r#"<a xmlns="urn:d" xmlns:x="urn:x" x:b="1" />"#);
+
+    assert_eq!(XmlEvent::start_element("a").try_attr("b c", "1").err(), Some(NameError::InvalidChar(' ')));
+    assert_eq!(XmlEvent::start_element("a").try_attr("1b", "1").err(), Some(NameError::InvalidStartChar('1')));
+    assert_eq!(XmlEvent::start_element("a").try_attr("x:", "1").err(), Some(NameError::Empty));
+    assert_eq!(XmlEvent::start_element("a").try_ns("x:y", "urn:x").err(), Some(NameError::InvalidChar(':')));
+
+    let mut b = Vec::new();
+    let mut w = EmitterConfig::new().write_document_declaration(false).create_writer(&mut b);
+    w.write(XmlEvent::start_element("a")).unwrap();
+    match w.write(XmlEvent::start_element("b c")) {
+        Err(EmitterError::InvalidName(name, NameError::InvalidChar(' '))) => assert_eq!(name, "b c"),
+        r => panic!("{r:?}"),
+    }
+    let attributes = [Attribute::new(Name::local("1b"), "1")];
+    let e = XmlEvent::StartElement { name: Name::local("b"), attributes: Cow::Borrowed(&attributes), namespace: Cow::Owned(Namespace::empty()) };
+    assert!(matches!(w.write(e), Err(EmitterError::InvalidName(_, NameError::InvalidStartChar('1')))));
+    w.write(XmlEvent::start_element("b")).unwrap();
+    assert!(matches!(w.write_attribute_streamed("x y", ["1"]), Err(EmitterError::InvalidName(..))));
+    assert!(matches!(w.write(XmlEvent::end_element().name("c d")), Err(EmitterError::InvalidName(..))));
+    w.write(XmlEvent::end_element()).unwrap();
+    w.write(XmlEvent::end_element()).unwrap();
+    assert_eq!(str::from_utf8(&b).unwrap(), "<a><b /></a>");
+}
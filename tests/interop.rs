@@ -0,0 +1,51 @@
+#![cfg(any(feature = "minidom", feature = "roxmltree"))]
+
+use xml::{EmitterConfig, EventReader};
+
+const DOC: &str = r#"<a xmlns="urn:a" xmlns:x="urn:x" k="w" x:k="v"><x:b>t<c /></x:b><c xmlns="">&amp;</c></a>"#;
+
+fn write(f: impl FnOnce(&mut xml::EventWriter<&mut Vec<u8>>) -> xml::writer::Result<()>) -> String {
+    let mut out = Vec::new();
+    f(&mut EmitterConfig::new().write_document_declaration(false).create_writer(&mut out)).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+#[cfg(feature = "minidom")]
+fn minidom_round_trip() {
+    use xml::interop::{read_minidom, write_minidom};
+
+    let root = read_minidom(EventReader::from_str(DOC)).unwrap();
+    assert_eq!(root.name(), "a");
+    assert_eq!(root.ns(), "urn:a");
+    assert_eq!(root.prefixes.get(&Some("x".into())).map(String::as_str), Some("urn:x"));
+    assert_eq!(root.attr("k"), Some("w"));
+    let b = root.children().next().unwrap();
+    assert_eq!((b.name(), b.ns()), ("b", "urn:x".into()));
+    assert_eq!(b.children().next().unwrap().ns(), "urn:a");
+    let c = root.children().nth(1).unwrap();
+    assert_eq!(c.text(), "&");
+
+    let written = write(|w| write_minidom(&root, w));
+    assert_eq!(EventReader::from_str(&written).into_iter().collect::<Result<Vec<_>, _>>().unwrap(),
+        EventReader::from_str(DOC).into_iter().collect::<Result<Vec<_>, _>>().unwrap());
+
+    // namespaces without declared prefixes become default namespaces
+    let built = minidom::Element::builder("p", "urn:p").append(minidom::Element::bare("q", "")).build();
+    assert_eq!(write(|w| write_minidom(&built, w)), r#"<p xmlns="urn:p"><q xmlns="" /></p>"#);
+}
+
+#[test]
+#[cfg(feature = "roxmltree")]
+fn roxmltree_round_trip() {
+    use xml::interop::{roxmltree_source, write_roxmltree};
+
+    let source = roxmltree_source(EventReader::from_str(DOC)).unwrap();
+    let doc = roxmltree::Document::parse(&source).unwrap();
+    let c = doc.root_element().last_child().unwrap();
+    assert_eq!((c.tag_name().namespace(), c.text()), (Some(""), Some("&")));
+
+    assert_eq!(write(|w| write_roxmltree(doc.root(), w)), DOC);
+    let b = doc.root_element().first_child().unwrap();
+    assert_eq!(write(|w| write_roxmltree(b, w)), r#"<x:b xmlns="urn:a" xmlns:x="urn:x">t<c /></x:b>"#);
+}